@@ -0,0 +1,182 @@
+use anyhow::{Context, Result};
+use chrono::Utc;
+use colored::Colorize;
+use serde::Serialize;
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::Path,
+    sync::Mutex,
+};
+
+use crate::{
+    events::{Event, Events},
+    journal::Journal,
+    runid::RunId,
+};
+
+static LOG: Mutex<Option<File>> = Mutex::new(None);
+
+/// One line of the `--action-log`.
+#[derive(Debug, Serialize)]
+struct Entry<'a> {
+    time: String,
+    run_id: String,
+    /// `deleted`, `trashed`, `would-delete`, `moved`, `linked`, ..., `skipped` or `failed`
+    outcome: String,
+    path: &'a Path,
+    /// The copy kept, linked to or moved to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target: Option<&'a Path>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<&'a str>,
+}
+
+/// Every action taken on a file, in every mode: reported on stderr, so stdout only carries the
+/// report, appended as a JSON line to the `--action-log` for auditing, and journaled for
+/// `restore` when it can be undone.
+pub struct ActionLog;
+
+impl ActionLog {
+    /// Starts appending to `path`, for the rest of the run, in place of the log of an earlier
+    /// run of the process.
+    pub fn open(path: Option<&Path>) -> Result<()> {
+        let file = path
+            .map(|path| {
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .with_context(|| format!("unable to open action log {}", path.display()))
+            })
+            .transpose()?;
+        *LOG.lock().unwrap() = file;
+        Ok(())
+    }
+
+    /// `label` (e.g. `DELETED`, `WOULD LINK`) was done to `path`.
+    pub fn done(
+        events: &Events,
+        label: &str,
+        path: &Path,
+        target: Option<&Path>,
+        note: Option<&str>,
+    ) {
+        eprintln!("{}", Self::record(events, label, path, target, note));
+    }
+
+    pub fn skipped(events: &Events, path: &Path, target: Option<&Path>, reason: &str) {
+        eprintln!("{}", Self::record(events, "SKIPPED", path, target, Some(reason)));
+    }
+
+    pub fn failed(events: &Events, path: &Path, target: Option<&Path>, reason: &str) {
+        eprintln!("{}", Self::record(events, "FAILED", path, target, Some(reason)));
+    }
+
+    /// Appends the action to the log, tells the run's `events` subscribers, and returns the line
+    /// reporting it, for screens that show it later (see `Interactive`).
+    pub fn record(
+        events: &Events,
+        label: &str,
+        path: &Path,
+        target: Option<&Path>,
+        reason: Option<&str>,
+    ) -> String {
+        let outcome = label.to_lowercase().replace(' ', "-");
+        let target_display = target.map(|target| target.display().to_string());
+        match label {
+            "FAILED" => {
+                tracing::warn!(outcome, path = %path.display(), target = target_display, reason, "action")
+            }
+            _ => {
+                tracing::info!(outcome, path = %path.display(), target = target_display, reason, "action")
+            }
+        }
+        events.emit(|| Event::Action {
+            outcome: outcome.clone(),
+            path: path.to_path_buf(),
+            target: target.map(Path::to_path_buf),
+            reason: reason.map(str::to_string),
+        });
+        Journal::record(&outcome, path, target);
+        if let Some(log) = LOG.lock().unwrap().as_mut() {
+            let entry = Entry {
+                time: Utc::now().to_rfc3339(),
+                run_id: RunId::get(),
+                outcome,
+                path,
+                target,
+                reason,
+            };
+            if let Ok(line) = serde_json::to_string(&entry) {
+                let _ = writeln!(log, "{line}");
+            }
+        }
+
+        let mut line = format!("{}: {}", Self::colored(label), path.display());
+        if let Some(target) = target {
+            line.push_str(&format!(" -> {}", target.display()));
+        }
+        match (label, reason) {
+            ("FAILED", Some(reason)) => line.push_str(&format!(" - {reason}")),
+            (_, Some(reason)) => line.push_str(&format!(" ({reason})")),
+            (_, None) => {}
+        }
+        line
+    }
+
+    fn colored(label: &str) -> colored::ColoredString {
+        match label {
+            "SKIPPED" => label.yellow(),
+            "FAILED" => label.red(),
+            _ => label.green(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ActionLog;
+    use crate::events::Events;
+    use anyhow::Result;
+    use std::{fs, path::Path};
+    use tempfile::TempDir;
+
+    #[test]
+    fn actions_are_appended_as_json_lines() -> Result<()> {
+        let root = TempDir::new()?;
+        let log = root.path().join("actions.jsonl");
+        ActionLog::open(Some(&log))?;
+        let events = Events::default();
+
+        let line = ActionLog::record(&events, "WOULD DELETE", Path::new("/data/b.txt"), None, None);
+        assert!(line.ends_with(": /data/b.txt"));
+        let line = ActionLog::record(
+            &events,
+            "FAILED",
+            Path::new("/data/c.txt"),
+            Some(Path::new("/data/a.txt")),
+            Some("Permission denied"),
+        );
+        assert!(line.ends_with(": /data/c.txt -> /data/a.txt - Permission denied"));
+
+        let entries = fs::read_to_string(&log)?
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<Vec<serde_json::Value>, _>>()?
+            .into_iter()
+            // NOTE: the log is process-wide, other tests may act on files meanwhile.
+            .filter(|entry| {
+                entry["path"]
+                    .as_str()
+                    .is_some_and(|path| path.starts_with("/data/"))
+            })
+            .collect::<Vec<serde_json::Value>>();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["outcome"], "would-delete");
+        assert_eq!(entries[1]["target"], "/data/a.txt");
+        assert_eq!(entries[1]["reason"], "Permission denied");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,226 @@
+use anyhow::{Context, Result};
+use dashmap::DashMap;
+use globset::{Glob, GlobMatcher};
+use pathdiff::diff_paths;
+use serde::Deserialize;
+use std::{
+    cell::OnceCell,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{archive::Archive, fileinfo::FileInfo};
+
+pub const DEFAULT_ALLOWLIST_FILE: &str = "intentional.toml";
+
+/// On-disk representation of `intentional.toml`.
+///
+/// ```toml
+/// [[pair]]
+/// paths = ["vendor/jquery.js", "static/js/jquery.js"]
+///
+/// [[pattern]]
+/// path = "vendor/**"
+/// hash = "6a95..." # optional, BLAKE3 digest of the content as printed by `b3sum`
+/// ```
+#[derive(Debug, Default, Deserialize)]
+struct AllowlistFile {
+    #[serde(default)]
+    pair: Vec<PairEntry>,
+    #[serde(default)]
+    pattern: Vec<PatternEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PairEntry {
+    paths: Vec<PathBuf>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PatternEntry {
+    path: String,
+    hash: Option<String>,
+}
+
+struct Pattern {
+    matcher: GlobMatcher,
+    hash: Option<blake3::Hash>,
+}
+
+#[derive(Default)]
+pub struct Allowlist {
+    pairs: Vec<Vec<PathBuf>>,
+    patterns: Vec<Pattern>,
+}
+
+impl Allowlist {
+    pub fn load(path: &Path) -> Result<Self> {
+        let raw = fs::read_to_string(path)
+            .with_context(|| format!("unable to read allowlist {}", path.display()))?;
+        let parsed: AllowlistFile = toml::from_str(&raw)
+            .with_context(|| format!("invalid allowlist {}", path.display()))?;
+
+        let patterns = parsed
+            .pattern
+            .into_iter()
+            .map(|entry| {
+                let hash = entry
+                    .hash
+                    .map(|h| blake3::Hash::from_hex(h.trim()))
+                    .transpose()
+                    .with_context(|| format!("invalid hash for pattern {}", entry.path))?;
+                Ok(Pattern {
+                    matcher: Glob::new(&entry.path)?.compile_matcher(),
+                    hash,
+                })
+            })
+            .collect::<Result<Vec<Pattern>>>()?;
+
+        Ok(Self {
+            pairs: parsed.pair.into_iter().map(|pair| pair.paths).collect(),
+            patterns,
+        })
+    }
+
+    /// Loads the allowlist given on the command line, falling back to `intentional.toml` in the
+    /// scan directory when present.
+    pub fn discover(explicit: Option<&Path>, base_directory: &Path) -> Result<Self> {
+        match explicit {
+            Some(path) => Self::load(path),
+            None => {
+                let default_path = base_directory.join(DEFAULT_ALLOWLIST_FILE);
+                match default_path.is_file() {
+                    true => Self::load(&default_path),
+                    false => Ok(Self::default()),
+                }
+            }
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty() && self.patterns.is_empty()
+    }
+
+    fn relative(path: &Path, base_directory: &Path) -> PathBuf {
+        diff_paths(path, base_directory).unwrap_or_else(|| path.to_path_buf())
+    }
+
+    fn pair_covers(pair: &[PathBuf], relative: &Path, group: &[PathBuf]) -> bool {
+        pair.iter().any(|p| p == relative)
+            && pair.iter().all(|p| group.iter().any(|g| g == p))
+    }
+
+    /// BLAKE3 digest of the content, unlike the group hash the same on every run whatever
+    /// `--algorithm` & `--seed` are.
+    fn digest(file: &FileInfo) -> Result<blake3::Hash> {
+        let mut hasher = blake3::Hasher::new();
+        hasher.update_reader(Archive::open(&file.path)?)?;
+        Ok(hasher.finalize())
+    }
+
+    /// A group is intentional once every file in it is acknowledged by a pair or a pattern.
+    /// An unexpected extra copy keeps the group visible.
+    pub fn is_intentional(&self, group: &[Arc<FileInfo>], base_directory: &Path) -> bool {
+        let relatives = group
+            .iter()
+            .map(|file| Self::relative(&file.path, base_directory))
+            .collect::<Vec<PathBuf>>();
+        // NOTE: the content is only read for a pattern with a hash, and only once per group.
+        let digest = OnceCell::new();
+
+        relatives.iter().all(|relative| {
+            self.pairs
+                .iter()
+                .any(|pair| Self::pair_covers(pair, relative, &relatives))
+                || self.patterns.iter().any(|pattern| {
+                    pattern.matcher.is_match(relative)
+                        && pattern.hash.is_none_or(|hash| {
+                            digest.get_or_init(|| Self::digest(&group[0]).ok()) == &Some(hash)
+                        })
+                })
+        })
+    }
+
+    /// Removes acknowledged groups from the duplicate set, returning how many were hidden.
+    pub fn prune(&self, store: &DashMap<u128, Vec<Arc<FileInfo>>>, base_directory: &Path) -> usize {
+        if self.is_empty() {
+            return 0;
+        }
+
+        let before = store.len();
+        store.retain(|_, group| group.len() < 2 || !self.is_intentional(group, base_directory));
+
+        before - store.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Allowlist;
+    use crate::fixture::Fixture;
+    use anyhow::Result;
+    use dashmap::DashMap;
+
+    #[test]
+    fn prunes_groups_fully_covered_by_pairs_and_patterns() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let file = |name: &str| fixture.scanned(name, b"same content");
+        let allowlist_path = fixture.file(
+            "intentional.toml",
+            r#"
+[[pair]]
+paths = ["static/logo.png", "docs/logo.png"]
+
+[[pattern]]
+path = "vendor/**"
+
+[[pattern]]
+path = "assets/**"
+hash = "{digest}"
+"#
+            .replace("{digest}", &blake3::hash(b"same content").to_hex())
+            .as_bytes(),
+        )?;
+
+        let store = DashMap::new();
+        store.insert(
+            1u128,
+            vec![
+                file("static/logo.png")?,
+                file("docs/logo.png")?,
+            ],
+        );
+        store.insert(
+            2u128,
+            vec![
+                file("vendor/a/jquery.js")?,
+                file("vendor/b/jquery.js")?,
+            ],
+        );
+        store.insert(
+            3u128,
+            vec![
+                file("vendor/c/lib.js")?,
+                file("src/lib.js")?,
+            ],
+        );
+        store.insert(4u128, vec![file("assets/a.css")?, file("assets/b.css")?]);
+        store.insert(
+            5u128,
+            vec![
+                fixture.scanned("assets/c.css", b"other content")?,
+                fixture.scanned("assets/d.css", b"other content")?,
+            ],
+        );
+
+        let allowlist = Allowlist::load(&allowlist_path)?;
+        let hidden = allowlist.prune(&store, fixture.root());
+
+        assert_eq!(hidden, 3);
+        assert!(store.contains_key(&3u128));
+        assert!(store.contains_key(&5u128));
+
+        Ok(())
+    }
+}
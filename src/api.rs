@@ -0,0 +1,311 @@
+use anyhow::Result;
+use clap::Parser;
+use std::{
+    path::{Path, PathBuf},
+    sync::{mpsc::Receiver, Arc},
+};
+
+use crate::{
+    archive::Archive,
+    disk::DiskType,
+    events::Event,
+    fileinfo::FileInfo,
+    filetype::MediaType,
+    formatter::Formatter,
+    hasher::{Algorithm, ReadStrategy},
+    heartbeat::Heartbeat,
+    params::{GroupOrder, Params},
+    runid::RunId,
+    scanner::SymlinkMode,
+    server::Server,
+};
+
+/// Files found to hold the same content.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateGroup {
+    pub hash: u128,
+    /// Size of each file, in bytes.
+    pub size: u64,
+    /// Canonical paths of the copies, sorted.
+    pub files: Vec<PathBuf>,
+}
+
+impl DuplicateGroup {
+    pub(crate) fn new(hash: u128, files: &[Arc<FileInfo>]) -> Self {
+        let mut paths = files
+            .iter()
+            .map(|file| file.path.to_path_buf())
+            .collect::<Vec<PathBuf>>();
+        paths.sort();
+        Self {
+            hash,
+            size: files.first().map(|file| file.size).unwrap_or_default(),
+            files: paths,
+        }
+    }
+
+    /// Bytes that removing every copy but one would free.
+    pub fn wasted(&self) -> u64 {
+        self.size * (self.files.len() as u64).saturating_sub(1)
+    }
+}
+
+/// A duplicate scan set up by [`DeduplicatorBuilder`], the way the command line sets one up
+/// without an action: it reads and hashes files, but never changes them.
+#[derive(Debug, Clone)]
+pub struct Deduplicator {
+    params: Params,
+}
+
+impl Deduplicator {
+    pub fn builder() -> DeduplicatorBuilder {
+        DeduplicatorBuilder::default()
+    }
+
+    /// Scans the roots and returns every group of duplicates, in the `sort` order.
+    pub fn find(&self) -> Result<Vec<DuplicateGroup>> {
+        // NOTE: each call is a run of its own, it does not carry over what an earlier one read.
+        RunId::renew();
+        Archive::forget_indexes();
+        let server = Server::new(self.params.next_run());
+        server.start(&Heartbeat::default())?;
+        for warning in server.verification_warnings.lock().unwrap().iter() {
+            tracing::warn!(warning, "verification");
+        }
+
+        Ok(Formatter::ordered(&server.hw_duplicate_set, &self.params)
+            .into_iter()
+            .map(|(hash, files)| DuplicateGroup::new(hash, &files))
+            .collect())
+    }
+}
+
+/// Options of a [`Deduplicator`], named after the command line flags they stand for and with
+/// the same defaults.
+#[derive(Debug, Clone)]
+pub struct DeduplicatorBuilder {
+    params: Params,
+}
+
+impl Default for DeduplicatorBuilder {
+    fn default() -> Self {
+        Self {
+            params: Params::parse_from([env!("CARGO_PKG_NAME")]),
+        }
+    }
+}
+
+impl DeduplicatorBuilder {
+    /// Adds a directory to scan; the working directory is scanned when none is given.
+    pub fn root(mut self, dir: impl AsRef<Path>) -> Self {
+        let dir = dir.as_ref().to_path_buf();
+        match self.params.dir {
+            None => self.params.dir = Some(dir),
+            Some(_) => self.params.more_dirs.push(dir),
+        }
+        self
+    }
+
+    /// Smallest file to scan, in bytes (1 by default).
+    pub fn min_size(mut self, bytes: u64) -> Self {
+        self.params.min_size = Some(format!("{bytes}b"));
+        self
+    }
+
+    pub fn min_depth(mut self, depth: usize) -> Self {
+        self.params.min_depth = Some(depth);
+        self
+    }
+
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.params.max_depth = Some(depth);
+        self
+    }
+
+    /// Only scan files of these extensions, comma separated (see `--types`).
+    pub fn types(mut self, extensions: &str) -> Self {
+        self.params.types = Some(extensions.to_string());
+        self
+    }
+
+    /// Skip files of these extensions, comma separated (see `--exclude-types`).
+    pub fn exclude_types(mut self, extensions: &str) -> Self {
+        self.params.exclude_types = Some(extensions.to_string());
+        self
+    }
+
+    /// Only scan files of this kind, detected from their content (see `--type`).
+    pub fn media_type(mut self, media_type: MediaType) -> Self {
+        self.params.media_types.push(media_type);
+        self
+    }
+
+    /// Only scan paths matching `glob`, relative to their root (see `--include`).
+    pub fn include(mut self, glob: &str) -> Self {
+        self.params.include.push(glob.to_string());
+        self
+    }
+
+    /// Skip paths matching `glob`, relative to their root (see `--exclude`).
+    pub fn exclude(mut self, glob: &str) -> Self {
+        self.params.exclude.push(glob.to_string());
+        self
+    }
+
+    /// Skip directories of this name wherever they are (see `--exclude-dir`).
+    pub fn exclude_dir(mut self, name: &str) -> Self {
+        self.params.exclude_dir.push(name.to_string());
+        self
+    }
+
+    /// Whether dotfiles & dot-directories are scanned (they are by default).
+    pub fn hidden(mut self, hidden: bool) -> Self {
+        self.params.no_hidden = !hidden;
+        self
+    }
+
+    pub fn follow_links(mut self, follow: bool) -> Self {
+        self.params.follow_links = follow;
+        self
+    }
+
+    pub fn symlinks(mut self, mode: SymlinkMode) -> Self {
+        self.params.symlinks = mode;
+        self
+    }
+
+    pub fn one_file_system(mut self, one: bool) -> Self {
+        self.params.one_file_system = one;
+        self
+    }
+
+    /// Skip what `.gitignore` files ignore (see `--gitignore`).
+    pub fn gitignore(mut self, gitignore: bool) -> Self {
+        self.params.gitignore = gitignore;
+        self
+    }
+
+    /// Hash whole files rather than samples of them (see `--strict`).
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.params.strict = strict;
+        self
+    }
+
+    pub fn algorithm(mut self, algorithm: Algorithm) -> Self {
+        self.params.algorithm = algorithm;
+        self
+    }
+
+    /// How files hashed in full are read (see `--read-strategy`).
+    pub fn read_strategy(mut self, strategy: ReadStrategy) -> Self {
+        self.params.read_strategy = strategy;
+        self
+    }
+
+    /// Buffer of buffered reads, in bytes (see `--buffer-size`).
+    pub fn buffer_size(mut self, bytes: usize) -> Self {
+        self.params.buffer_size = Some(format!("{bytes}b"));
+        self
+    }
+
+    /// Whether files are hashed one at a time per spinning disk (see `--disk-type`).
+    pub fn disk_type(mut self, disk_type: DiskType) -> Self {
+        self.params.disk_type = disk_type;
+        self
+    }
+
+    /// Threads of each stage, one per CPU by default (see `--threads`).
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.params.threads = Some(threads);
+        self
+    }
+
+    /// Seed of the hashes, for hashes that compare across runs (see `--seed`).
+    pub fn seed(mut self, seed: i64) -> Self {
+        self.params.seed = Some(seed);
+        self
+    }
+
+    /// Reuse & keep the hashes of unchanged files in this database (see `--cache`).
+    pub fn cache(mut self, path: impl AsRef<Path>) -> Self {
+        self.params.cache = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn sort(mut self, order: GroupOrder) -> Self {
+        self.params.sort = order;
+        self
+    }
+
+    pub fn reverse(mut self, reverse: bool) -> Self {
+        self.params.reverse = reverse;
+        self
+    }
+
+    /// Calls `handler` with every event of the scans, on the thread the event happens on. It
+    /// holds up that stage of the scan while it runs.
+    pub fn on(self, handler: impl Fn(&Event) + Send + Sync + 'static) -> Self {
+        self.params.events.on(handler);
+        self
+    }
+
+    /// Every event of the scans, until the receiver is dropped.
+    pub fn subscribe(&self) -> Receiver<Event> {
+        self.params.events.subscribe()
+    }
+
+    /// Fails when a root does not exist or roots overlap.
+    pub fn build(self) -> Result<Deduplicator> {
+        self.params.scan_roots()?;
+        Ok(Deduplicator {
+            params: self.params,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Deduplicator;
+    use crate::{fixture::Fixture, Event, GroupOrder};
+    use anyhow::Result;
+
+    #[test]
+    fn embedded_scans_return_the_duplicate_groups() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let groups = fixture.distribution(&[3, 2, 1], 64)?;
+        let builder = Deduplicator::builder()
+            .root(fixture.root())
+            .sort(GroupOrder::Count)
+            .seed(42)
+            .threads(2);
+        let events = builder.subscribe();
+
+        let deduplicator = builder.build()?;
+        let found = deduplicator.find()?;
+
+        assert_eq!(found.len(), 2);
+        let mut expected = groups[0].clone();
+        expected.sort();
+        assert_eq!(found[0].files, expected);
+        assert_eq!(found[0].size, 64);
+        assert_eq!(found[0].wasted(), 128);
+        assert_eq!(found[1].files.len(), 2);
+        let mut announced = events
+            .try_iter()
+            .filter_map(|event| match event {
+                Event::GroupFound(group) => Some(group),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        announced.sort_by_key(|group| std::cmp::Reverse(group.files.len()));
+        assert_eq!(announced, found);
+        // NOTE: a second run starts afresh rather than from what the first one left behind.
+        assert_eq!(deduplicator.find()?, found);
+        assert!(Deduplicator::builder()
+            .root(fixture.path("missing"))
+            .build()
+            .is_err());
+
+        Ok(())
+    }
+}
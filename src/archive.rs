@@ -0,0 +1,279 @@
+use anyhow::{Context, Result};
+use flate2::read::DeflateDecoder;
+use std::{
+    collections::HashMap,
+    fs,
+    io::{Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use zip::CompressionMethod;
+
+use crate::fileinfo::{FileInfo, FileSource, FileState};
+
+/// Names archives end with, lowercase. A gzipped tarball cannot seek to a member, each read would
+/// unpack it again up to that member, so it is compared as a whole like any other file.
+const ARCHIVE_SUFFIXES: [&str; 2] = [".tar", ".zip"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Compression {
+    Stored,
+    Deflated,
+}
+
+/// Where the content of a regular file inside an archive lives.
+#[derive(Debug, Clone)]
+struct Member {
+    offset: u64,
+    stored_size: u64,
+    size: u64,
+    modified: SystemTime,
+    compression: Compression,
+}
+
+type Index = HashMap<PathBuf, Member>;
+
+/// Members are looked up for every read, so each archive is indexed once per run.
+static INDEXES: OnceLock<Mutex<HashMap<PathBuf, Arc<Index>>>> = OnceLock::new();
+
+/// Read-only view of `.tar` & `.zip` archives used as scan roots, or found while
+/// scanning with `--scan-archives`. A member is addressed by the archive path joined with its
+/// name, e.g. `backup.tar/photos/img.jpg`, so it can be read like a regular file but never
+/// changed.
+pub struct Archive;
+
+impl Archive {
+    pub fn is_archive(path: &Path) -> bool {
+        let supported = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map(str::to_ascii_lowercase)
+            .is_some_and(|name| ARCHIVE_SUFFIXES.iter().any(|suffix| name.ends_with(suffix)));
+        supported && path.is_file()
+    }
+
+    /// Splits a member path into the archive holding it and the name inside the archive.
+    pub fn locate(path: &Path) -> Option<(&Path, &Path)> {
+        path.ancestors()
+            .skip(1)
+            .find(|ancestor| Self::is_archive(ancestor))
+            .and_then(|archive| Some((archive, path.strip_prefix(archive).ok()?)))
+    }
+
+    /// Whether `path` lives inside an archive, and therefore cannot be deleted or moved.
+    pub fn contains(path: &Path) -> bool {
+        !path.exists() && Self::locate(path).is_some()
+    }
+
+    /// Opens a regular file, or an archive member when `path` points into an archive.
+    pub fn open(path: &Path) -> Result<Box<dyn Read + Send>> {
+        let error = match fs::File::open(path) {
+            Ok(file) => return Ok(Box::new(file)),
+            Err(error) => error,
+        };
+        let Some((archive, name)) = Self::locate(path) else {
+            return Err(error.into());
+        };
+
+        let member = Self::member(archive, name)?;
+        let mut file = fs::File::open(archive)?;
+        file.seek(SeekFrom::Start(member.offset))?;
+        let raw = file.take(member.stored_size);
+        Ok(match member.compression {
+            Compression::Deflated => Box::new(DeflateDecoder::new(raw)),
+            Compression::Stored => Box::new(raw),
+        })
+    }
+
+    /// Size of a regular file or archive member.
+    pub fn size(path: &Path) -> Result<u64> {
+        match fs::metadata(path) {
+            Ok(metadata) => Ok(metadata.len()),
+            Err(error) => match Self::locate(path) {
+                Some((archive, name)) => Ok(Self::member(archive, name)?.size),
+                None => Err(error.into()),
+            },
+        }
+    }
+
+    /// Every regular file inside the archive, with member paths as described on `Archive`.
+    pub fn files(archive: &Path, source: Option<FileSource>) -> Result<Vec<FileInfo>> {
+        let mut files = Self::index(archive)?
+            .iter()
+            .map(|(name, member)| FileInfo {
+                path: archive.join(name).into_boxed_path(),
+                size: member.size,
+                modified: member.modified,
+                state: Arc::new(Mutex::new(FileState::Unprocessed)),
+                source,
+                payload: None,
+            })
+            .collect::<Vec<FileInfo>>();
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(files)
+    }
+
+    fn member(archive: &Path, name: &Path) -> Result<Member> {
+        Self::index(archive)?
+            .get(name)
+            .cloned()
+            .with_context(|| format!("{} not found in {}", name.display(), archive.display()))
+    }
+
+    /// Drops the archives indexed so far, so that the next run of the process reads them afresh
+    /// (see `Deduplicator::find`).
+    pub fn forget_indexes() {
+        if let Some(indexes) = INDEXES.get() {
+            indexes.lock().unwrap().clear();
+        }
+    }
+
+    fn index(archive: &Path) -> Result<Arc<Index>> {
+        let indexes = INDEXES.get_or_init(Default::default);
+
+        if let Some(index) = indexes.lock().unwrap().get(archive) {
+            return Ok(Arc::clone(index));
+        }
+
+        let zip = archive
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("zip"));
+        let index = Arc::new(
+            match zip {
+                true => Self::index_zip(archive),
+                false => Self::index_tar(archive),
+            }
+            .with_context(|| format!("unable to read archive {}", archive.display()))?,
+        );
+
+        indexes
+            .lock()
+            .unwrap()
+            .insert(archive.to_path_buf(), Arc::clone(&index));
+        Ok(index)
+    }
+
+    fn index_tar(archive: &Path) -> Result<Index> {
+        let mut tar = tar::Archive::new(fs::File::open(archive)?);
+        let mut index = Index::new();
+        for entry in tar.entries_with_seek()? {
+            let entry = entry?;
+            if !entry.header().entry_type().is_file() {
+                continue;
+            }
+            let modified = UNIX_EPOCH + Duration::from_secs(entry.header().mtime().unwrap_or(0));
+            index.insert(
+                entry.path()?.into_owned(),
+                Member {
+                    offset: entry.raw_file_position(),
+                    stored_size: entry.size(),
+                    size: entry.size(),
+                    modified,
+                    compression: Compression::Stored,
+                },
+            );
+        }
+
+        Ok(index)
+    }
+
+    fn index_zip(archive: &Path) -> Result<Index> {
+        let mut zip = zip::ZipArchive::new(fs::File::open(archive)?)?;
+        let mut index = Index::new();
+
+        for position in 0..zip.len() {
+            let file = zip.by_index_raw(position)?;
+            // NOTE: names escaping the archive root cannot be addressed as member paths.
+            let (true, Some(name)) = (file.is_file(), file.enclosed_name()) else {
+                continue;
+            };
+            let compression = match file.compression() {
+                CompressionMethod::Stored => Compression::Stored,
+                CompressionMethod::Deflated => Compression::Deflated,
+                method => anyhow::bail!("{} uses unsupported compression {method}", name.display()),
+            };
+            let modified = file
+                .last_modified()
+                .and_then(|time| {
+                    chrono::NaiveDate::from_ymd_opt(
+                        time.year().into(),
+                        time.month().into(),
+                        time.day().into(),
+                    )?
+                    .and_hms_opt(
+                        time.hour().into(),
+                        time.minute().into(),
+                        time.second().into(),
+                    )
+                })
+                .and_then(|time| u64::try_from(time.and_utc().timestamp()).ok())
+                .map(|seconds| UNIX_EPOCH + Duration::from_secs(seconds))
+                .unwrap_or(UNIX_EPOCH);
+
+            index.insert(
+                name,
+                Member {
+                    offset: file.data_start(),
+                    stored_size: file.compressed_size(),
+                    size: file.size(),
+                    modified,
+                    compression,
+                },
+            );
+        }
+
+        Ok(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Archive;
+    use crate::fileinfo::FileInfo;
+    use anyhow::Result;
+    use std::{fs, io::Read, io::Write};
+    use tempfile::TempDir;
+    use zip::write::SimpleFileOptions;
+
+    #[test]
+    fn members_read_like_the_files_they_were_made_from() -> Result<()> {
+        let root = TempDir::new()?;
+        let content = (0..20_000).map(|i| (i % 251) as u8).collect::<Vec<u8>>();
+        let live = root.path().join("live.bin");
+        fs::write(&live, &content)?;
+
+        let tarball = root.path().join("backup.tar");
+        let mut tar = tar::Builder::new(fs::File::create(&tarball)?);
+        tar.append_path_with_name(&live, "photos/copy.bin")?;
+        tar.into_inner()?;
+
+        let zipfile = root.path().join("backup.zip");
+        let mut zip = zip::ZipWriter::new(fs::File::create(&zipfile)?);
+        zip.start_file("docs/copy.bin", SimpleFileOptions::default())?;
+        zip.write_all(&content)?;
+        zip.start_file(
+            "docs/stored.txt",
+            SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored),
+        )?;
+        zip.write_all(b"stored")?;
+        zip.finish()?;
+
+        let seed = 300;
+        let expected = FileInfo::new(live)?.hash(seed.into())?;
+        for archive in [&tarball, &zipfile] {
+            let files = Archive::files(archive, None)?;
+            let copy = files.iter().find(|f| f.path.ends_with("copy.bin")).unwrap();
+            assert_eq!(copy.size, content.len() as u64);
+            assert_eq!(copy.hash(seed.into())?, expected);
+            assert!(Archive::contains(&copy.path));
+        }
+
+        let mut stored = String::new();
+        Archive::open(&zipfile.join("docs/stored.txt"))?.read_to_string(&mut stored)?;
+        assert_eq!(stored, "stored");
+        assert!(Archive::open(&zipfile.join("docs/missing.txt")).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,221 @@
+use anyhow::{Context, Result};
+use clap::{Args, ValueEnum};
+use rusqlite::{params, Connection, OpenFlags, OptionalExtension};
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::{fileinfo::FileInfo, hasher::Algorithm};
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct CacheArgs {
+    /// Hash cache written by runs with --cache
+    #[arg(value_hint = clap::ValueHint::FilePath, value_name = "cache_path")]
+    pub path: PathBuf,
+    /// Drop every hash it holds, as --cache-invalidate does on the next run
+    #[arg(long)]
+    pub clear: bool,
+}
+
+/// Full-content hashes of earlier runs, keyed by (device, inode, size, mtime) so that unchanged
+/// files are not read again. Hashes depend on the seed, so the seed they were computed with is
+/// stored alongside and reused by every run sharing the cache. They also depend on the
+/// `--algorithm`; switching it drops every stored hash.
+pub struct HashCache {
+    connection: Mutex<Connection>,
+    seed: i64,
+}
+
+impl HashCache {
+    /// Opens (or creates) the cache at `path`. `seed` is only used for a new or invalidated
+    /// cache; otherwise the stored seed wins.
+    pub fn open(path: &Path, invalidate: bool, seed: i64, algorithm: Algorithm) -> Result<Self> {
+        let connection = Connection::open(path)
+            .with_context(|| format!("unable to open hash cache {}", path.display()))?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS meta (key TEXT PRIMARY KEY, value INTEGER NOT NULL);
+             CREATE TABLE IF NOT EXISTS hashes (
+                 device INTEGER NOT NULL,
+                 inode INTEGER NOT NULL,
+                 size INTEGER NOT NULL,
+                 mtime INTEGER NOT NULL,
+                 hash BLOB NOT NULL,
+                 PRIMARY KEY (device, inode, size, mtime)
+             );",
+        )?;
+
+        // NOTE: caches predating --algorithm hold gxhash hashes.
+        let stored_algorithm: i64 = connection
+            .query_row("SELECT value FROM meta WHERE key = 'algorithm'", [], |row| {
+                row.get(0)
+            })
+            .optional()?
+            .unwrap_or(Algorithm::Gxhash.id());
+        if invalidate || stored_algorithm != algorithm.id() {
+            connection.execute_batch("DELETE FROM hashes; DELETE FROM meta;")?;
+        }
+
+        let stored: Option<i64> = connection
+            .query_row("SELECT value FROM meta WHERE key = 'seed'", [], |row| {
+                row.get(0)
+            })
+            .optional()?;
+        let seed = match stored {
+            Some(stored) => stored,
+            None => {
+                connection.execute("INSERT INTO meta (key, value) VALUES ('seed', ?1)", [seed])?;
+                connection.execute(
+                    "INSERT INTO meta (key, value) VALUES ('algorithm', ?1)",
+                    [algorithm.id()],
+                )?;
+                seed
+            }
+        };
+
+        Ok(Self {
+            connection: Mutex::new(connection),
+            seed,
+        })
+    }
+
+    /// Like `open`, but a cache whose hashes were computed with another seed than `seed` is
+    /// started over (see `--seed`).
+    pub fn open_seeded(path: &Path, invalidate: bool, seed: i64, algorithm: Algorithm) -> Result<Self> {
+        let cache = Self::open(path, invalidate, seed, algorithm)?;
+        if cache.seed == seed {
+            return Ok(cache);
+        }
+        drop(cache);
+        Self::open(path, true, seed, algorithm)
+    }
+
+    pub fn seed(&self) -> i64 {
+        self.seed
+    }
+
+    /// Shows how many hashes the cache at `args.path` holds & what they were computed with, or
+    /// clears it. A missing cache is not created.
+    pub fn run(args: &CacheArgs) -> Result<()> {
+        let connection = Connection::open_with_flags(&args.path, OpenFlags::SQLITE_OPEN_READ_WRITE)
+            .with_context(|| format!("no hash cache at {}", args.path.display()))?;
+        if args.clear {
+            connection
+                .execute_batch("DELETE FROM hashes; DELETE FROM meta;")
+                .with_context(|| format!("{} is not a hash cache", args.path.display()))?;
+            println!("Cleared hash cache {}.", args.path.display());
+            return Ok(());
+        }
+
+        let hashes: i64 = connection
+            .query_row("SELECT COUNT(*) FROM hashes", [], |row| row.get(0))
+            .with_context(|| format!("{} is not a hash cache", args.path.display()))?;
+        let meta = |key: &str| -> Result<Option<i64>> {
+            Ok(connection
+                .query_row("SELECT value FROM meta WHERE key = ?1", [key], |row| row.get(0))
+                .optional()?)
+        };
+        let algorithm = meta("algorithm")?
+            .map_or(Some(Algorithm::Gxhash), Algorithm::from_id)
+            .and_then(|algorithm| algorithm.to_possible_value())
+            .map_or_else(|| "unknown".to_string(), |value| value.get_name().to_string());
+        println!(
+            "Hash cache {}: {hashes} hash(es), seed {}, algorithm {algorithm}.",
+            args.path.display(),
+            meta("seed")?.map_or_else(|| "-".to_string(), |seed| seed.to_string())
+        );
+        Ok(())
+    }
+
+    pub fn get(&self, file: &FileInfo) -> Option<u128> {
+        let (device, inode, size, mtime) = Self::identity(file)?;
+        let hash: Vec<u8> = self
+            .connection
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT hash FROM hashes WHERE device = ?1 AND inode = ?2 AND size = ?3 AND mtime = ?4",
+                params![device, inode, size, mtime],
+                |row| row.get(0),
+            )
+            .ok()?;
+
+        Some(u128::from_le_bytes(hash.try_into().ok()?))
+    }
+
+    /// Best effort: a file whose identity cannot be read, or a failed write, is simply not cached.
+    pub fn insert(&self, file: &FileInfo, hash: u128) {
+        if let Some((device, inode, size, mtime)) = Self::identity(file) {
+            let _ = self.connection.lock().unwrap().execute(
+                "INSERT OR REPLACE INTO hashes (device, inode, size, mtime, hash) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![device, inode, size, mtime, hash.to_le_bytes().to_vec()],
+            );
+        }
+    }
+
+    #[cfg(unix)]
+    fn identity(file: &FileInfo) -> Option<(i64, i64, i64, i64)> {
+        use std::{os::unix::fs::MetadataExt, time::UNIX_EPOCH};
+        let metadata = std::fs::metadata(&file.path).ok()?;
+        let mtime = file.modified.duration_since(UNIX_EPOCH).ok()?.as_nanos();
+        Some((
+            metadata.dev() as i64,
+            metadata.ino() as i64,
+            file.size as i64,
+            mtime as i64,
+        ))
+    }
+
+    #[cfg(not(unix))]
+    fn identity(_file: &FileInfo) -> Option<(i64, i64, i64, i64)> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HashCache;
+    use crate::{fileinfo::FileInfo, hasher::Algorithm};
+    use anyhow::Result;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn hashes_survive_reopening_until_invalidated() -> Result<()> {
+        let root = TempDir::new()?;
+        let database = root.path().join("cache.sqlite");
+        let path = root.path().join("a.bin");
+        fs::write(&path, b"content")?;
+        let file = FileInfo::new(path)?;
+
+        let cache = HashCache::open(&database, false, 42, Algorithm::Gxhash)?;
+        cache.insert(&file, 7);
+        drop(cache);
+
+        let cache = HashCache::open(&database, false, 99, Algorithm::Gxhash)?;
+        assert_eq!(cache.seed(), 42);
+        assert_eq!(cache.get(&file), Some(7));
+        drop(cache);
+
+        let cache = HashCache::open(&database, true, 99, Algorithm::Gxhash)?;
+        assert_eq!(cache.seed(), 99);
+        assert_eq!(cache.get(&file), None);
+        cache.insert(&file, 7);
+        drop(cache);
+
+        let cache = HashCache::open(&database, false, 5, Algorithm::Sha256)?;
+        assert_eq!(cache.seed(), 5);
+        assert_eq!(cache.get(&file), None);
+        cache.insert(&file, 7);
+        drop(cache);
+
+        let cache = HashCache::open_seeded(&database, false, 5, Algorithm::Sha256)?;
+        assert_eq!(cache.get(&file), Some(7));
+        drop(cache);
+        let cache = HashCache::open_seeded(&database, false, 6, Algorithm::Sha256)?;
+        assert_eq!(cache.seed(), 6);
+        assert_eq!(cache.get(&file), None);
+
+        Ok(())
+    }
+}
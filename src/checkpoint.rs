@@ -0,0 +1,281 @@
+use anyhow::{Context, Result};
+use crossbeam_channel::{Receiver, RecvTimeoutError};
+use dashmap::DashMap;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, UNIX_EPOCH},
+};
+
+use crate::fileinfo::FileInfo;
+
+/// A file as hashed: size, mtime in nanoseconds and hash.
+type Hashed = (u64, i64, u128);
+
+/// Progress of a long scan, saved to the `--checkpoint` database every
+/// `--checkpoint-interval`, so that a run stopped halfway is taken up again with `--resume`
+/// rather than started over: hashes computed before are reused for files unchanged since, and
+/// once the walk was complete, its files are listed again without walking the tree. Only a
+/// run with the same options can be resumed, as they decide which files are scanned and how
+/// they are hashed.
+pub struct Checkpoint {
+    connection: Mutex<Connection>,
+    seed: i64,
+    /// Hashes of the run resumed, by path.
+    resumed: HashMap<PathBuf, Hashed>,
+    /// Every file the run resumed scanned, if its walk was complete.
+    scanned: Option<Vec<PathBuf>>,
+    /// Hashes computed since the last save.
+    pending: Mutex<Vec<(PathBuf, Hashed)>>,
+    scan_saved: AtomicBool,
+}
+
+impl Checkpoint {
+    /// Starts a new checkpoint at `path` for a run hashing with `seed`, dropping what it held.
+    pub fn create(path: &Path, seed: i64, fingerprint: &str) -> Result<Self> {
+        let connection = Self::connect(path)?;
+        connection.execute_batch("DELETE FROM meta; DELETE FROM scanned; DELETE FROM hashes;")?;
+        connection.execute(
+            "INSERT INTO meta (key, value) VALUES ('fingerprint', ?1), ('seed', ?2)",
+            params![fingerprint, seed.to_string()],
+        )?;
+        Ok(Self::new(connection, seed, HashMap::new(), None))
+    }
+
+    /// Takes up the run saved at `path`, which must have been started with the same options.
+    pub fn resume(path: &Path, fingerprint: &str) -> Result<Self> {
+        let connection = Self::connect(path)?;
+        let meta = |key: &str| -> Result<Option<String>> {
+            Ok(connection
+                .query_row("SELECT value FROM meta WHERE key = ?1", [key], |row| row.get(0))
+                .optional()?)
+        };
+        let Some(stored) = meta("fingerprint")? else {
+            anyhow::bail!("no run to resume in checkpoint {}", path.display());
+        };
+        anyhow::ensure!(
+            stored == fingerprint,
+            "checkpoint {} was saved by a run with other options ({stored}), start it over without --resume",
+            path.display()
+        );
+        let seed = meta("seed")?.unwrap_or_default().parse()?;
+
+        let resumed = connection
+            .prepare("SELECT path, size, mtime, hash FROM hashes")?
+            .query_map([], |row| {
+                // NOTE: a hash that is not 16 bytes long is corrupt, its file is hashed again.
+                let Ok(hash) = <[u8; 16]>::try_from(row.get::<_, Vec<u8>>(3)?) else {
+                    return Ok(None);
+                };
+                Ok(Some((
+                    PathBuf::from(row.get::<_, String>(0)?),
+                    (row.get::<_, i64>(1)? as u64, row.get(2)?, u128::from_le_bytes(hash)),
+                )))
+            })?
+            .filter_map(rusqlite::Result::transpose)
+            .collect::<rusqlite::Result<HashMap<PathBuf, Hashed>>>()?;
+        let scanned = match meta("scan_complete")?.is_some() {
+            true => Some(
+                connection
+                    .prepare("SELECT path FROM scanned")?
+                    .query_map([], |row| row.get::<_, String>(0).map(PathBuf::from))?
+                    .collect::<rusqlite::Result<Vec<PathBuf>>>()?,
+            ),
+            false => None,
+        };
+
+        let checkpoint = Self::new(connection, seed, resumed, scanned);
+        checkpoint
+            .scan_saved
+            .store(checkpoint.scanned.is_some(), Ordering::Relaxed);
+        Ok(checkpoint)
+    }
+
+    /// The options a checkpoint is only resumed with: the command line, but for `--resume`.
+    pub fn fingerprint() -> String {
+        std::env::args_os()
+            .skip(1)
+            .filter(|arg| arg != "--resume")
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+
+    pub fn seed(&self) -> i64 {
+        self.seed
+    }
+
+    /// The files the resumed run scanned, if its walk was complete.
+    pub fn scanned(&self) -> Option<Vec<PathBuf>> {
+        self.scanned.clone()
+    }
+
+    /// The hash the resumed run computed for `file`, if the file has not changed since.
+    pub fn hash(&self, file: &FileInfo) -> Option<u128> {
+        let (size, mtime, hash) = self.resumed.get(&*file.path)?;
+        (*size == file.size && Some(*mtime) == Self::mtime(file)).then_some(*hash)
+    }
+
+    /// Notes the hash of `file`, saved along with the next checkpoint.
+    pub fn record(&self, file: &FileInfo, hash: u128) {
+        if let Some(mtime) = Self::mtime(file) {
+            self.pending
+                .lock()
+                .unwrap()
+                .push((file.path.to_path_buf(), (file.size, mtime, hash)));
+        }
+    }
+
+    /// Saves the hashes noted since the last save and, once `scanned` is set, every file of
+    /// the walk, which is then complete.
+    pub fn save(&self, sizes: &DashMap<u64, Vec<Arc<FileInfo>>>, scanned: bool) -> Result<()> {
+        let pending = std::mem::take(&mut *self.pending.lock().unwrap());
+        let mut connection = self.connection.lock().unwrap();
+        let transaction = connection.transaction()?;
+        {
+            let mut insert = transaction.prepare(
+                "INSERT OR REPLACE INTO hashes (path, size, mtime, hash) VALUES (?1, ?2, ?3, ?4)",
+            )?;
+            for (path, (size, mtime, hash)) in &pending {
+                insert.execute(params![
+                    path.to_string_lossy(),
+                    *size as i64,
+                    mtime,
+                    hash.to_le_bytes().to_vec()
+                ])?;
+            }
+        }
+        if scanned && !self.scan_saved.swap(true, Ordering::Relaxed) {
+            let mut insert =
+                transaction.prepare("INSERT OR REPLACE INTO scanned (path) VALUES (?1)")?;
+            for bucket in sizes.iter() {
+                for file in bucket.value() {
+                    insert.execute([file.path.to_string_lossy()])?;
+                }
+            }
+            drop(insert);
+            transaction.execute(
+                "INSERT OR REPLACE INTO meta (key, value) VALUES ('scan_complete', '1')",
+                [],
+            )?;
+        }
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// Saves every `interval` until `stop` hangs up, then once more. `scanned` is set once the
+    /// walk is complete and every file it found is in `sizes`.
+    pub fn keep_saving(
+        &self,
+        interval: Duration,
+        sizes: &DashMap<u64, Vec<Arc<FileInfo>>>,
+        scanned: &AtomicBool,
+        stop: Receiver<()>,
+    ) {
+        loop {
+            let stopped = !matches!(stop.recv_timeout(interval), Err(RecvTimeoutError::Timeout));
+            if let Err(error) = self.save(sizes, scanned.load(Ordering::Relaxed)) {
+                // NOTE: the run goes on, a later save may succeed.
+                eprintln!("Unable to save the checkpoint: {error:#}");
+            }
+            if stopped {
+                return;
+            }
+        }
+    }
+
+    fn connect(path: &Path) -> Result<Connection> {
+        let connection = Connection::open(path)
+            .with_context(|| format!("unable to open checkpoint {}", path.display()))?;
+        connection.execute_batch(
+            "CREATE TABLE IF NOT EXISTS meta (key TEXT PRIMARY KEY, value TEXT NOT NULL);
+             CREATE TABLE IF NOT EXISTS scanned (path TEXT PRIMARY KEY);
+             CREATE TABLE IF NOT EXISTS hashes (
+                 path TEXT PRIMARY KEY,
+                 size INTEGER NOT NULL,
+                 mtime INTEGER NOT NULL,
+                 hash BLOB NOT NULL
+             );",
+        )?;
+        Ok(connection)
+    }
+
+    fn new(
+        connection: Connection,
+        seed: i64,
+        resumed: HashMap<PathBuf, Hashed>,
+        scanned: Option<Vec<PathBuf>>,
+    ) -> Self {
+        Self {
+            connection: Mutex::new(connection),
+            seed,
+            resumed,
+            scanned,
+            pending: Mutex::new(vec![]),
+            scan_saved: AtomicBool::new(false),
+        }
+    }
+
+    fn mtime(file: &FileInfo) -> Option<i64> {
+        let nanos = file.modified.duration_since(UNIX_EPOCH).ok()?.as_nanos();
+        Some(nanos as i64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Checkpoint;
+    use crate::{fileinfo::FileInfo, fixture::Fixture};
+    use anyhow::Result;
+    use dashmap::DashMap;
+    use std::fs;
+
+    #[test]
+    fn a_saved_run_is_resumed_with_the_same_options_only() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let database = fixture.path("checkpoint.sqlite");
+        let (a, b) = (fixture.scanned("a.bin", b"same")?, fixture.scanned("b.bin", b"same")?);
+        let sizes = DashMap::new();
+        sizes.insert(4u64, vec![a.clone(), b.clone()]);
+
+        let checkpoint = Checkpoint::create(&database, 42, "/data --strict")?;
+        checkpoint.record(&a, 7);
+        checkpoint.save(&sizes, false)?;
+        drop(checkpoint);
+
+        let resumed = Checkpoint::resume(&database, "/data --strict")?;
+        assert_eq!(resumed.seed(), 42);
+        assert_eq!(resumed.hash(&a), Some(7));
+        assert_eq!(resumed.hash(&b), None);
+        assert_eq!(resumed.scanned(), None);
+        resumed.record(&b, 7);
+        resumed.save(&sizes, true)?;
+        drop(resumed);
+
+        // NOTE: a corrupt hash is dropped rather than read as another one.
+        let connection = rusqlite::Connection::open(&database)?;
+        connection.execute(
+            "UPDATE hashes SET hash = x'07' WHERE path = ?1",
+            [a.path.to_string_lossy()],
+        )?;
+        drop(connection);
+        let resumed = Checkpoint::resume(&database, "/data --strict")?;
+        assert_eq!(resumed.hash(&a), None);
+        assert_eq!(resumed.hash(&b), Some(7));
+        assert_eq!(resumed.scanned().map(|paths| paths.len()), Some(2));
+        fs::write(&a.path, b"edited")?;
+        assert_eq!(resumed.hash(&FileInfo::new(a.path.to_path_buf())?), None);
+        drop(resumed);
+
+        assert!(Checkpoint::resume(&database, "/data").is_err());
+        Checkpoint::create(&database, 5, "/data")?;
+        assert_eq!(Checkpoint::resume(&database, "/data")?.hash(&b), None);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,673 @@
+use crate::{
+    actionlog::ActionLog,
+    allowlist::Allowlist, archive::Archive, cache::HashCache, copychain::CopyChains, desktop::Desktop, doctor::Doctor, export::Export, fileinfo::FileInfo, formatter::Formatter,
+    gallery::Gallery,
+    heartbeat::{Heartbeat, Stage},
+    interactive::Interactive,
+    journal::Journal,
+    link::Linker,
+    linkcheck::LinkCheck,
+    location::SameLocation,
+    logging::Logging,
+    notes::Notes,
+    plan::{ApplyArgs, Plan},
+    processor::ConfirmedGroup,
+    project::Projects,
+    quarantine::{MoveOutcome, Quarantine},
+    report::Report,
+    rootusage::RootUsage,
+    rundir::RunDir,
+    runid::RunId,
+    server::Server,
+    sidecar::Sidecar,
+    similar::SimilarImages,
+    spotcheck::SpotCheck,
+    summary::RunSummary,
+    survivor::Survivor,
+    symlinkview::SymlinkView,
+    syspath::SystemPaths,
+    usage::UsageView,
+};
+use anyhow::Result;
+use colored::Colorize;
+use dashmap::DashMap;
+use crate::params::{Command, OutputFormat, Params};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The `deduplicator` command line: parses the arguments and carries out the run they ask for.
+pub fn run() -> Result<ExitCode> {
+    let mut app_args = Params::load();
+    Logging::init(app_args.log_file.as_deref(), app_args.log_level)?;
+    tracing::info!(
+        run_id = RunId::get(),
+        args = ?std::env::args_os().collect::<Vec<_>>(),
+        "run started"
+    );
+    ActionLog::open(app_args.action_log.as_deref())?;
+    Journal::open(Journal::default_dir());
+    // Whatever runs outside of the stage pools (reports, staging collapse) follows --threads too
+    if let Some(threads) = app_args.threads {
+        rayon::ThreadPoolBuilder::new().num_threads(threads).build_global()?;
+    }
+    match &app_args.command {
+        Some(Command::Doctor(args)) => return Doctor::run(args, &app_args).map(|_| ExitCode::SUCCESS),
+        Some(Command::SpotCheck(args)) => return SpotCheck::run(args).map(|_| ExitCode::SUCCESS),
+        Some(Command::View(args)) => return SymlinkView::run(args, &app_args).map(|_| ExitCode::SUCCESS),
+        Some(Command::Apply(args)) => return Plan::run(args, &app_args),
+        Some(Command::VerifyLinks(args)) => return LinkCheck::run(args, &app_args),
+        Some(Command::Cache(args)) => return HashCache::run(args).map(|_| ExitCode::SUCCESS),
+        Some(Command::Report(args)) => return Report::run(args, &app_args).map(|_| ExitCode::SUCCESS),
+        Some(Command::Restore(args)) => {
+            let restored = match args.quarantine {
+                Some(_) => Quarantine::run(args),
+                None => Journal::run(args),
+            };
+            return restored.map(|_| ExitCode::SUCCESS);
+        }
+        None => {}
+    }
+    if let Some(plan) = &app_args.apply_plan {
+        let args = ApplyArgs { plan: plan.clone(), ..Default::default() };
+        return Plan::run(&args, &app_args);
+    }
+
+    if app_args.comparison_mode {
+        SameLocation::guard(
+            &app_args.get_staging_directory()?,
+            &app_args.get_target_directory()?,
+        )?;
+    }
+    if app_args.destructive() && !app_args.allow_system_paths {
+        for root in app_args.scan_roots()? {
+            SystemPaths::guard(&root.path)?;
+        }
+    }
+
+    // Removed when the run ends; leftovers of crashed runs are cleared here on the next start
+    app_args.run_dir = Some(Arc::new(RunDir::create(app_args.tmpdir.as_deref())?));
+    // A run that returns early with an error leaves a `failed` beat behind
+    let heartbeat = Heartbeat::start(
+        app_args.heartbeat.as_deref(),
+        app_args.dbus,
+        Duration::from_secs(app_args.heartbeat_interval),
+    )?;
+    let server = Server::new(app_args.clone());
+    let base_directory = app_args.get_directory()?;
+    let allowlist = Allowlist::discover(app_args.intentional.as_deref(), &base_directory)?;
+    let mut notes = Notes::load(app_args.notes.as_deref())?;
+
+    // Interactive triage & --stream start with the first confirmed group instead of after the full scan
+    let streamed_groups = (app_args.stream
+        || app_args.interactive && !app_args.comparison_mode && !app_args.usage_view)
+        .then(|| server.stream_groups());
+    let streamed_deleted = std::thread::scope(|scope| -> Result<Option<u64>> {
+        let running = scope.spawn(|| server.start(&heartbeat));
+        let deleted = streamed_groups
+            .map(|groups| match app_args.stream {
+                true => stream_groups(groups, &allowlist, &notes, &base_directory, &app_args).map(|_| None),
+                false => Interactive::stream(groups, &allowlist, &mut notes, &app_args).map(Some),
+            })
+            .transpose()
+            .map(Option::flatten);
+        running.join().expect("server thread panicked.")?;
+        deleted
+    })?;
+
+    for warning in server.verification_warnings.lock().unwrap().iter() {
+        eprintln!("{}", warning.yellow());
+    }
+    // NOTE: measured before anything is hidden from the report, these bytes are on disk anyway.
+    let scan_roots = app_args
+        .scan_roots()?
+        .into_iter()
+        .map(|root| root.path)
+        .collect::<Vec<PathBuf>>();
+    let root_usage = app_args.root_usage.then(|| {
+        RootUsage::measure(
+            &server.sw_duplicate_set,
+            &server.hw_duplicate_set,
+            &app_args,
+            &scan_roots,
+        )
+    });
+
+    let hidden_groups = allowlist.prune(&server.hw_duplicate_set, &base_directory);
+    if hidden_groups > 0 {
+        eprintln!(
+            "\n{}",
+            format!("{hidden_groups} intentional duplicate group(s) hidden.").dimmed()
+        );
+    }
+    if app_args.cross_project {
+        let hidden_groups = Projects::prune(&server.hw_duplicate_set, &base_directory);
+        if hidden_groups > 0 {
+            eprintln!(
+                "\n{}",
+                format!("{hidden_groups} group(s) within a single project hidden.").dimmed()
+            );
+        }
+    }
+    let snoozed_groups = notes.prune_snoozed(&server.hw_duplicate_set);
+    if snoozed_groups > 0 {
+        eprintln!(
+            "\n{}",
+            format!("{snoozed_groups} snoozed group(s) hidden.").dimmed()
+        );
+    }
+
+    let mut summary = RunSummary::from_store(&server.hw_duplicate_set);
+    // NOTE: similar images are only reported, nothing acts on them.
+    let similar = match app_args.images_similar {
+        true => SimilarImages::find(
+            &server.sw_duplicate_set,
+            &server.hw_duplicate_set,
+            app_args.similarity_threshold,
+        ),
+        false => vec![],
+    };
+
+    // Analyze the results for comparison between staging and target
+    let comparison = match app_args.comparison_mode {
+        true => Some(crate::processor::Processor::analyze_comparison(
+            server.hw_duplicate_set.clone(),
+            &server.staging_files.lock().unwrap(),
+            &server.staging_copies.lock().unwrap(),
+            &app_args.get_staging_directory()?,
+            &app_args.get_target_directory()?,
+        )?),
+        false => None,
+    };
+
+    // NOTE: written once the run is over, so that the notes taken during triage are in it.
+    let export = app_args.export.as_ref().map(|export_path| {
+        let mut export = Export::from_store(&server.hw_duplicate_set)
+            .with_policy(&server.hw_duplicate_set, &app_args)
+            .with_labels(&app_args)
+            .with_similar(&similar);
+        if let Some(comparison) = comparison.as_ref().filter(|_| app_args.report_unique) {
+            export = export.with_unique(&comparison.unique);
+        }
+        (export_path, export)
+    });
+
+    if let Some(plan_path) = &app_args.review {
+        let plan = Plan::from_store(&server.hw_duplicate_set, &app_args);
+        plan.write(plan_path)?;
+        eprintln!(
+            "\n{}",
+            format!(
+                "{} proposed removal(s) written to {} for review, carry them out with `deduplicator apply`.",
+                plan.actions.len(),
+                plan_path.display()
+            )
+            .dimmed()
+        );
+    }
+
+    if let Some(gallery_path) = &app_args.gallery {
+        let proposed = Gallery::write(&server.hw_duplicate_set, &app_args, gallery_path)?;
+        eprintln!(
+            "\n{}",
+            format!("Gallery of {proposed} proposed removal(s) written to {}", gallery_path.display()).dimmed()
+        );
+    }
+
+    if let Some(comparison_result) = comparison {
+        let skipped_staging = server.skipped_staging.load(Ordering::Relaxed);
+        if skipped_staging > 0 {
+            eprintln!(
+                "\n{}",
+                format!("{skipped_staging} staging file(s) skipped without hashing: no target file has the same size.").dimmed()
+            );
+        }
+
+        // Print how much of each staging subtree already exists in target
+        if !comparison_result.directory_stats.is_empty() {
+            println!("\n{}", "Staging directories:".bold());
+            for stats in &comparison_result.directory_stats {
+                let line = format!(
+                    "  {}: {:.0}% already in target ({}/{})",
+                    stats.directory.display(),
+                    stats.percentage(),
+                    stats.matched,
+                    stats.total
+                );
+                match stats.matched == stats.total {
+                    true => println!("{}", line.green()),
+                    false => println!("{line}"),
+                }
+            }
+        }
+
+        // Print staging files that exist in target under another name or location
+        if !comparison_result.renames.is_empty() {
+            println!("\n{}", "Renamed or moved in target:".bold());
+            for rename in &comparison_result.renames {
+                println!(
+                    "  {} -> {}",
+                    rename.staging.display(),
+                    rename.target.display()
+                );
+            }
+        }
+
+        // Print identical files inside staging that have no copy in target
+        if !comparison_result.staging_duplicates.is_empty() {
+            println!("\n{}", "Duplicates within staging (no copy in target):".bold());
+            for set in &comparison_result.staging_duplicates {
+                for file in set {
+                    println!("  - {}", file.path.display());
+                }
+                println!();
+            }
+        }
+
+        // Print staging files whose content is nowhere in target
+        if app_args.report_unique && !comparison_result.unique.is_empty() {
+            println!(
+                "\n{}",
+                format!(
+                    "Only in staging ({} file(s), {} with no copy in target):",
+                    comparison_result.unique.len(),
+                    bytesize::ByteSize::b(comparison_result.unique.iter().map(|file| file.size).sum())
+                )
+                .bold()
+            );
+            for file in &comparison_result.unique {
+                println!("  - {}", file.path.display());
+            }
+        }
+
+        // Print warnings
+        if !comparison_result.warnings.is_empty() {
+            eprintln!("\n{}", "Warnings:".yellow().bold());
+            for warning in &comparison_result.warnings {
+                eprintln!("{}", warning.yellow());
+            }
+        }
+
+        // Delete (or quarantine) files from staging
+        if !comparison_result.files_to_delete.is_empty() {
+            let staging_root = app_args.get_staging_directory()?;
+            let quarantine = match app_args.dry_run {
+                true => app_args.move_to.as_deref().map(Quarantine::preview).transpose()?,
+                false => app_args.move_to.as_deref().map(Quarantine::new).transpose()?,
+            };
+            if let (Some(quarantine), false) = (&quarantine, app_args.dry_run) {
+                quarantine.ensure_free_space(&comparison_result.files_to_delete)?;
+            }
+            let heading = match &quarantine {
+                Some(q) => format!("Files to be moved from staging to {}:", q.root.display()),
+                None => "Files to be removed from staging:".to_string(),
+            };
+
+            println!("\n{}", heading.red().bold());
+            for file in &comparison_result.files_to_delete {
+                println!("  - {}", file.path.display());
+            }
+
+            // Files still being written by an import or download must not vanish silently
+            let recent_window = Duration::from_secs(app_args.recent_minutes * 60);
+            let (recent, settled): (Vec<&Arc<FileInfo>>, Vec<&Arc<FileInfo>>) = comparison_result
+                .files_to_delete
+                .iter()
+                .partition(|file| {
+                    app_args.recent_minutes > 0 && file.modified_within(recent_window)
+                });
+
+            let confirmed = match app_args.interactive {
+                true => Interactive::scan_group_confirmation()?,
+                // Non-interactive mode: delete files directly
+                false => true,
+            };
+
+            let include_recent = match (confirmed, recent.is_empty()) {
+                (true, false) => {
+                    eprintln!(
+                        "\n{}",
+                        format!(
+                            "Warning: {} file(s) were modified within the last {} minute(s) and may still be written to:",
+                            recent.len(),
+                            app_args.recent_minutes
+                        )
+                        .yellow()
+                        .bold()
+                    );
+                    for file in &recent {
+                        eprintln!("  - {}", file.path.display().to_string().yellow());
+                    }
+                    let include = app_args.dry_run || Interactive::scan_group_confirmation()?;
+                    if !include {
+                        eprintln!("{}", "Recently modified files left in staging.".yellow());
+                    }
+                    include
+                }
+                _ => false,
+            };
+
+            match confirmed {
+                true => {
+                    let mut cross_device = 0;
+                    let selected = match include_recent {
+                        true => comparison_result.files_to_delete.iter().collect(),
+                        false => settled,
+                    };
+                    // NOTE: a target nested in the staging folder may have its own copies planned for removal.
+                    let removals = comparison_result
+                        .kept_in_target
+                        .iter()
+                        .map(|(path, kept)| (path.as_path(), kept.iter().map(|kept| &*kept.path).collect()))
+                        .collect::<HashMap<&Path, Vec<&Path>>>();
+                    let orphaned = Survivor::orphaned(&removals);
+                    for file in selected {
+                        let kept = comparison_result
+                            .kept_in_target
+                            .get(&*file.path)
+                            .map(Vec::as_slice)
+                            .unwrap_or_default();
+                        if orphaned.contains(&*file.path) {
+                            let target = kept.first().map(|kept| &*kept.path);
+                            ActionLog::skipped(
+                                &app_args.events,
+                                &file.path,
+                                target,
+                                "no copy would be left in the target",
+                            );
+                            continue;
+                        }
+                        match dispose_staging_file(file, kept, &app_args, quarantine.as_ref(), &staging_root) {
+                            Some(Disposal::Moved(MoveOutcome::CopiedAcrossDevices)) => {
+                                cross_device += 1;
+                                summary.deleted += 1;
+                            }
+                            Some(_) => summary.deleted += 1,
+                            None => {}
+                        }
+                    }
+
+                    if cross_device > 0 {
+                        eprintln!(
+                            "\n{}",
+                            format!("{cross_device} file(s) were on another filesystem and were copied, verified and then removed.").yellow()
+                        );
+                    }
+                }
+                false => eprintln!("{}", "\nCancelled Delete Operation.".red()),
+            }
+        } else {
+            println!("\n{}", "No duplicates found between staging and target folders.".green());
+        }
+    } else if app_args.usage_view {
+        UsageView::init(server.hw_duplicate_set, &app_args)?;
+    } else {
+        match app_args.interactive {
+            false => {
+                if !app_args.stream {
+                    Formatter::print(
+                        server.hw_duplicate_set.clone(),
+                        server.max_file_path_len.load(Ordering::Acquire),
+                        &app_args,
+                    );
+                }
+                if !app_args.output.machine_readable() {
+                    print!("{}", Formatter::similar(&similar, &app_args));
+                }
+                if let Some(with) = app_args.replacement() {
+                    let replacements =
+                        Linker::replace_duplicates(&server.hw_duplicate_set, with, &app_args);
+                    Linker::report(&replacements, with, app_args.dry_run, &app_args.events);
+                }
+                if app_args.delete {
+                    summary.deleted += delete_duplicates(&server.hw_duplicate_set, &app_args);
+                }
+
+                let chains = CopyChains::find(&server.hw_duplicate_set);
+                let show_chains = app_args.replacement().is_none()
+                    && !app_args.delete
+                    && !app_args.output.machine_readable();
+                if show_chains && !chains.is_empty() {
+                    CopyChains::report(&chains, app_args.collapse_copies);
+                    if app_args.collapse_copies {
+                        summary.deleted += CopyChains::collapse(&chains, &app_args)?;
+                    }
+                }
+                let hint = app_args.preset.and_then(|preset| preset.hint());
+                if let Some(hint) = hint.filter(|_| show_chains && summary.groups > 0) {
+                    eprintln!("\n{}", hint.dimmed());
+                }
+            }
+            true => {
+                summary.deleted = match streamed_deleted {
+                    Some(deleted) => deleted,
+                    None => Interactive::init(server.hw_duplicate_set, &mut notes, &app_args)?,
+                };
+            }
+        }
+
+        if let Some(deferred_args) = app_args.deferred_pass() {
+            summary += run_deferred_pass(&deferred_args, &allowlist, &mut notes, &base_directory, &heartbeat)?;
+        }
+    }
+
+    if let Some((export_path, export)) = export {
+        export.with_notes(&notes).write(export_path)?;
+    }
+
+    if app_args.remove_empty_dirs {
+        let roots = match app_args.comparison_mode {
+            true => vec![app_args.get_staging_directory()?],
+            false => app_args.scan_roots()?.into_iter().map(|root| root.path).collect(),
+        };
+        app_args.empty_dirs.prune(&roots, app_args.dry_run, &app_args.events);
+    }
+
+    // NOTE: machine readable listings stay clean on stdout, the rest goes to stderr.
+    let left_out = [app_args.long_paths.report(), app_args.placeholders.report()];
+    let mode_comparison = app_args
+        .compare_modes
+        .then(|| *server.mode_comparison.lock().unwrap());
+    match app_args.output {
+        OutputFormat::Text | OutputFormat::Albums => {
+            mode_comparison.iter().for_each(|report| println!("{report}"));
+            root_usage.iter().for_each(|usages| println!("\n{}", RootUsage::report(usages)));
+            left_out.iter().flatten().for_each(|report| println!("{report}"));
+            println!("{summary}")
+        }
+        OutputFormat::UriList
+        | OutputFormat::Csv
+        | OutputFormat::Tsv
+        | OutputFormat::Fdupes
+        | OutputFormat::Jsonl => {
+            mode_comparison.iter().for_each(|report| eprintln!("{report}"));
+            root_usage.iter().for_each(|usages| eprintln!("\n{}", RootUsage::report(usages)));
+            left_out.iter().flatten().for_each(|report| eprintln!("{report}"));
+            eprintln!("{summary}")
+        }
+    }
+
+    heartbeat.finish(Stage::Done);
+    tracing::info!(%summary, "run finished");
+    if app_args.dbus {
+        if let Err(error) = Desktop::emit("Finished", &summary.to_string()) {
+            eprintln!("{}", format!("Unable to signal the result over D-Bus: {error}").yellow());
+        }
+    }
+    if app_args.notify {
+        if let Err(error) = Desktop::notify(&summary) {
+            eprintln!("{}", format!("Unable to show a desktop notification: {error}").yellow());
+        }
+    }
+    match app_args.dry_run {
+        true => Ok(app_args.dry_run_tally.finish()),
+        false => Ok(ExitCode::SUCCESS),
+    }
+}
+
+/// `--stream`: prints the groups the pipeline confirms, but for those the report would hide
+/// (intentional, within a single project or snoozed).
+fn stream_groups(
+    groups: std::sync::mpsc::Receiver<ConfirmedGroup>,
+    allowlist: &Allowlist,
+    notes: &Notes,
+    base_directory: &Path,
+    app_args: &Params,
+) -> Result<usize> {
+    let accept = |_, group: &[Arc<FileInfo>]| {
+        !allowlist.is_intentional(group, base_directory)
+            && (!app_args.cross_project || Projects::spans_projects(group, base_directory))
+            && !notes.is_snoozed(group)
+    };
+    Formatter::stream(groups, accept, app_args)
+}
+
+/// `--delete`: carries out at once the plan `--review` would write, every copy but the kept one
+/// approved, without reading the files again (see `Plan::confirmed`). Copies inside archives,
+/// which the plan leaves out, are listed as skipped.
+fn delete_duplicates(store: &DashMap<u128, Vec<Arc<FileInfo>>>, app_args: &Params) -> u64 {
+    eprintln!("\n{}", "Deleted duplicates:".bold());
+    let mut archived = store
+        .iter()
+        .filter(|group| group.value().len() > 1)
+        .flat_map(|group| group.value().clone())
+        .filter(|file| Archive::contains(&file.path))
+        .collect::<Vec<Arc<FileInfo>>>();
+    archived.sort_by(|a, b| a.path.cmp(&b.path));
+    for file in archived {
+        ActionLog::skipped(&app_args.events, &file.path, None, "inside a read-only archive");
+    }
+    Plan::confirmed(store, app_args).apply(false, app_args)
+}
+
+/// Second pass of `--defer-large`: hashes the large files the first pass left out and reports
+/// their duplicates the same way.
+fn run_deferred_pass(
+    app_args: &Params,
+    allowlist: &Allowlist,
+    notes: &mut Notes,
+    base_directory: &Path,
+    heartbeat: &Heartbeat,
+) -> Result<RunSummary> {
+    if !app_args.output.machine_readable() {
+        println!(
+            "\n{}",
+            format!(
+                "Large files ({} and above):",
+                bytesize::ByteSize::b(app_args.get_min_size().unwrap_or_default())
+            )
+            .bold()
+        );
+    }
+
+    let server = Server::new(app_args.clone());
+    server.start(heartbeat)?;
+    for warning in server.verification_warnings.lock().unwrap().iter() {
+        eprintln!("{}", warning.yellow());
+    }
+    allowlist.prune(&server.hw_duplicate_set, base_directory);
+    if app_args.cross_project {
+        Projects::prune(&server.hw_duplicate_set, base_directory);
+    }
+    notes.prune_snoozed(&server.hw_duplicate_set);
+
+    let mut summary = RunSummary::from_store(&server.hw_duplicate_set);
+    match app_args.interactive {
+        false => {
+            Formatter::print(
+                server.hw_duplicate_set.clone(),
+                server.max_file_path_len.load(Ordering::Acquire),
+                app_args,
+            );
+            if let Some(with) = app_args.replacement() {
+                let replacements =
+                    Linker::replace_duplicates(&server.hw_duplicate_set, with, app_args);
+                Linker::report(&replacements, with, app_args.dry_run, &app_args.events);
+            }
+            if app_args.delete {
+                summary.deleted += delete_duplicates(&server.hw_duplicate_set, app_args);
+            }
+        }
+        true => summary.deleted = Interactive::init(server.hw_duplicate_set, notes, app_args)?,
+    }
+
+    Ok(summary)
+}
+
+enum Disposal {
+    Deleted,
+    Moved(MoveOutcome),
+}
+
+/// Deletes a matched staging file, or moves it into the quarantine when `--move-to` is set, as
+/// long as one of its copies in target is still there, and unchanged with `--verify-keeper`.
+/// Returns `None` when the file could not be removed from staging.
+fn dispose_staging_file(
+    file: &FileInfo,
+    kept_in_target: &[Arc<FileInfo>],
+    app_args: &Params,
+    quarantine: Option<&Quarantine>,
+    staging_root: &Path,
+) -> Option<Disposal> {
+    if Archive::contains(&file.path) {
+        ActionLog::skipped(&app_args.events, &file.path, None, "inside a read-only archive");
+        return None;
+    }
+
+    let dispose = |path: &Path, kept: Option<&Path>| -> Result<Disposal> {
+        match quarantine {
+            Some(quarantine) if app_args.dry_run => {
+                app_args.dry_run_tally.record(path);
+                app_args.empty_dirs.record(path);
+                let destination = quarantine.destination(path, staging_root);
+                ActionLog::done(&app_args.events, "WOULD MOVE", path, Some(&destination), None);
+                Ok(Disposal::Deleted)
+            }
+            Some(quarantine) => {
+                let (destination, outcome) = quarantine.move_into(path, staging_root)?;
+                app_args.empty_dirs.record(path);
+                ActionLog::done(&app_args.events, "MOVED", path, Some(&destination), None);
+                Ok(Disposal::Moved(outcome))
+            }
+            None => {
+                let removal = app_args.removal();
+                removal.remove(path, app_args)?;
+                ActionLog::done(&app_args.events, removal.label(), path, kept, None);
+                Ok(Disposal::Deleted)
+            }
+        }
+    };
+
+    let first = kept_in_target.first().map(|kept| &*kept.path);
+    let kept = match Survivor::ensure(&file.path, kept_in_target.iter().map(|kept| &*kept.path)) {
+        Ok(kept) => kept,
+        Err(e) => {
+            ActionLog::skipped(&app_args.events, &file.path, first, &format!("{e:#}"));
+            return None;
+        }
+    };
+    if let Some(Err(e)) = app_args.verify_keeper.map(|check| check.verify_any(kept_in_target, file)) {
+        ActionLog::skipped(&app_args.events, &file.path, first, &format!("{e:#}"));
+        return None;
+    }
+    match dispose(&file.path, Some(kept)) {
+        Ok(outcome) => {
+            let sidecars = Sidecar::follow(&file.path, app_args.sidecars, |sidecar| {
+                dispose(sidecar, None).map(|_| ())
+            });
+            for (sidecar, outcome) in sidecars {
+                if let Err(e) = outcome {
+                    ActionLog::failed(&app_args.events, &sidecar, None, &format!("sidecar: {e:#}"));
+                }
+            }
+            Some(outcome)
+        }
+        Err(e) => {
+            ActionLog::failed(&app_args.events, &file.path, None, &format!("{e:#}"));
+            None
+        }
+    }
+}
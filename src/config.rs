@@ -0,0 +1,186 @@
+use anyhow::{Context, Result};
+use clap::Command;
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+use toml::{Table, Value};
+
+/// Options a config file cannot set, as they choose the config file itself.
+const RESERVED: [&str; 2] = ["config", "profile"];
+
+/// Default values for the options of the command line, read from `--config` or else from
+/// `~/.config/deduplicator/config.toml`, with named profiles on top chosen with `--profile`.
+/// Keys are option names, with dashes or underscores:
+///
+/// ```toml
+/// min-size = "1K"
+/// exclude-dir = ["node_modules", "target"]
+///
+/// [profiles.photos]
+/// preset = "photos"
+/// library = "/home/me/Pictures"
+/// ```
+///
+/// Both only change defaults: options given on the command line and the settings of a
+/// `--preset` win over them.
+#[derive(Debug, Default)]
+pub struct Config {
+    path: PathBuf,
+    defaults: Table,
+    profiles: HashMap<String, Table>,
+}
+
+impl Config {
+    /// `~/.config/deduplicator/config.toml`, honouring `$XDG_CONFIG_HOME`.
+    pub fn default_path() -> Option<PathBuf> {
+        env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .map(|dir| dir.join(env!("CARGO_PKG_NAME")).join("config.toml"))
+    }
+
+    /// The config at `path`, which has to exist, or else the one at `default` (see
+    /// `default_path`), if any.
+    pub fn locate(path: Option<&Path>, default: Option<PathBuf>) -> Result<Option<Self>> {
+        match path {
+            Some(path) => Self::load(path).map(Some),
+            None => default
+                .filter(|path| path.is_file())
+                .map(|path| Self::load(&path))
+                .transpose(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let raw = fs::read_to_string(path)
+            .with_context(|| format!("unable to read config {}", path.display()))?;
+        let mut defaults: Table =
+            toml::from_str(&raw).with_context(|| format!("invalid config {}", path.display()))?;
+        let profiles = match defaults.remove("profiles") {
+            None => HashMap::new(),
+            Some(Value::Table(profiles)) => profiles
+                .into_iter()
+                .map(|(name, profile)| match profile {
+                    Value::Table(profile) => Ok((name, profile)),
+                    _ => anyhow::bail!("profile {name} of config {} is not a table", path.display()),
+                })
+                .collect::<Result<HashMap<String, Table>>>()?,
+            Some(_) => anyhow::bail!("profiles of config {} is not a table", path.display()),
+        };
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            defaults,
+            profiles,
+        })
+    }
+
+    /// Makes the settings of the config, and of `profile` over them, the defaults of `command`.
+    pub fn apply(&self, mut command: Command, profile: Option<&str>) -> Result<Command> {
+        let mut settings = self.defaults.clone();
+        if let Some(name) = profile {
+            let Some(profile) = self.profiles.get(name) else {
+                let mut known = self.profiles.keys().map(String::as_str).collect::<Vec<&str>>();
+                known.sort_unstable();
+                anyhow::bail!(
+                    "no profile {name} in config {} (profiles: {})",
+                    self.path.display(),
+                    if known.is_empty() { "none".to_string() } else { known.join(", ") }
+                );
+            };
+            settings.extend(profile.clone());
+        }
+
+        for (key, value) in settings {
+            let id = command
+                .get_arguments()
+                .find(|arg| {
+                    arg.get_id() == key.replace('-', "_").as_str()
+                        || arg.get_long() == Some(key.as_str())
+                })
+                .map(|arg| arg.get_id().clone())
+                .filter(|id| !RESERVED.contains(&id.as_str()))
+                .with_context(|| format!("unknown option {key} in config {}", self.path.display()))?;
+            let values = Self::values(&value)
+                .with_context(|| format!("invalid value for {key} in config {}", self.path.display()))?;
+            command = command.mut_arg(id, |arg| arg.default_values(values));
+        }
+        Ok(command)
+    }
+
+    /// A value as the command line would give it, arrays as one value per item.
+    fn values(value: &Value) -> Result<Vec<String>> {
+        match value {
+            Value::String(value) => Ok(vec![value.clone()]),
+            Value::Integer(value) => Ok(vec![value.to_string()]),
+            Value::Float(value) => Ok(vec![value.to_string()]),
+            Value::Boolean(value) => Ok(vec![value.to_string()]),
+            Value::Datetime(value) => Ok(vec![value.to_string()]),
+            Value::Array(items) => items
+                .iter()
+                .map(|item| match item {
+                    Value::Array(_) | Value::Table(_) => anyhow::bail!("nested arrays & tables are not options"),
+                    item => Ok(Self::values(item)?.remove(0)),
+                })
+                .collect(),
+            Value::Table(_) => anyhow::bail!("tables are not options"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Config;
+    use crate::{keep::KeepPolicy, params::Params};
+    use anyhow::Result;
+    use clap::{CommandFactory, FromArgMatches};
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn profiles_override_the_defaults_and_the_command_line_both() -> Result<()> {
+        let root = TempDir::new()?;
+        let path = root.path().join("config.toml");
+        fs::write(
+            &path,
+            r#"
+min-size = "1K"
+exclude_dir = ["node_modules", "target"]
+keep = "newest"
+
+[profiles.photos]
+keep = "oldest"
+strict = true
+"#,
+        )?;
+        let config = Config::load(&path)?;
+        let parse = |profile: Option<&str>, args: &[&str]| -> Result<Params> {
+            let command = config.apply(Params::command(), profile)?;
+            let matches = command.get_matches_from(["deduplicator"].iter().chain(args));
+            Ok(Params::from_arg_matches(&matches)?)
+        };
+
+        let params = parse(None, &[])?;
+        assert_eq!(params.min_size.as_deref(), Some("1K"));
+        assert_eq!(params.exclude_dir, ["node_modules", "target"]);
+        assert_eq!(params.keep, KeepPolicy::Newest);
+        assert!(!params.strict);
+
+        let params = parse(Some("photos"), &[])?;
+        assert_eq!(params.keep, KeepPolicy::Oldest);
+        assert!(params.strict);
+        assert_eq!(params.min_size.as_deref(), Some("1K"));
+
+        let params = parse(Some("photos"), &["--keep", "shortest-path", "--min-size", "2M"])?;
+        assert_eq!(params.keep, KeepPolicy::ShortestPath);
+        assert_eq!(params.min_size.as_deref(), Some("2M"));
+
+        assert!(parse(Some("videos"), &[]).is_err());
+        fs::write(&path, "no-such-option = 1\n")?;
+        assert!(Config::load(&path)?.apply(Params::command(), None).is_err());
+
+        Ok(())
+    }
+}
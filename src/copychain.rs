@@ -0,0 +1,278 @@
+use anyhow::Result;
+use colored::Colorize;
+use dashmap::DashMap;
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
+
+use crate::{
+    actionlog::ActionLog, archive::Archive, fileinfo::FileInfo, interactive::Interactive,
+    params::Params, sidecar::Sidecar, survivor::Survivor, verify::Verifier,
+};
+
+/// Identical files next to each other where all but one carry a copy name derived from the other,
+/// e.g. `report.docx`, `report (1).docx` and `Copy of report.docx`.
+#[derive(Debug)]
+pub struct CopyChain {
+    pub canonical: Arc<FileInfo>,
+    pub copies: Vec<Arc<FileInfo>>,
+}
+
+pub struct CopyChains;
+
+impl CopyChains {
+    /// The name a copy was made from, or `None` if `name` is not a copy name. Understands the
+    /// naming of browsers & Windows (`x (1)`, `x - Copy`, `x - Copy (2)`, `Copy of x`) and of
+    /// macOS Finder (`x copy`, `x copy 2`), also when nested.
+    pub fn canonical_name(name: &str) -> Option<String> {
+        let (stem, extension) = match name.rsplit_once('.') {
+            Some((stem, extension)) if !stem.is_empty() => (stem, format!(".{extension}")),
+            _ => (name, String::new()),
+        };
+
+        let mut current = stem;
+        while let Some(original) = Self::strip_copy_marker(current) {
+            current = original;
+        }
+
+        match current == stem {
+            true => None,
+            false => Some(format!("{current}{extension}")),
+        }
+    }
+
+    fn strip_copy_marker(stem: &str) -> Option<&str> {
+        let is_number = |text: &str| !text.is_empty() && text.bytes().all(|b| b.is_ascii_digit());
+        let in_parens = |text: &str| {
+            text.strip_prefix('(')
+                .and_then(|text| text.strip_suffix(')'))
+                .is_some_and(is_number)
+        };
+
+        let original = stem
+            .strip_prefix("Copy of ")
+            .or_else(|| {
+                stem.split_once(" of ")
+                    .filter(|(marker, _)| marker.strip_prefix("Copy ").is_some_and(in_parens))
+                    .map(|(_, rest)| rest)
+            })
+            .or_else(|| {
+                stem.rsplit_once(' ')
+                    .filter(|(_, number)| in_parens(number))
+                    .map(|(rest, _)| rest)
+            })
+            .or_else(|| stem.strip_suffix(" - Copy"))
+            .or_else(|| stem.strip_suffix(" copy"))
+            .or_else(|| {
+                stem.rsplit_once(" copy ")
+                    .filter(|(_, number)| is_number(number))
+                    .map(|(rest, _)| rest)
+            })?;
+
+        (!original.trim().is_empty()).then_some(original)
+    }
+
+    /// Every copy chain among the duplicate groups, ordered by the canonical file's path.
+    pub fn find(store: &DashMap<u128, Vec<Arc<FileInfo>>>) -> Vec<CopyChain> {
+        let mut chains = store
+            .iter()
+            .filter(|group| group.value().len() > 1)
+            .flat_map(|group| {
+                let by_path: HashMap<PathBuf, &Arc<FileInfo>> = group
+                    .value()
+                    .iter()
+                    .map(|file| (file.path.to_path_buf(), file))
+                    .collect();
+
+                let mut chains: HashMap<PathBuf, Vec<Arc<FileInfo>>> = HashMap::new();
+                for file in group.value() {
+                    let canonical = file
+                        .path
+                        .file_name()
+                        .and_then(|name| Self::canonical_name(&name.to_string_lossy()))
+                        .map(|name| file.path.with_file_name(name))
+                        .filter(|canonical| by_path.contains_key(canonical));
+                    if let Some(canonical) = canonical {
+                        chains.entry(canonical).or_default().push(file.clone());
+                    }
+                }
+
+                chains
+                    .into_iter()
+                    .map(|(canonical, mut copies)| {
+                        copies.sort_by(|a, b| a.path.cmp(&b.path));
+                        CopyChain {
+                            canonical: by_path[&canonical].clone(),
+                            copies,
+                        }
+                    })
+                    .collect::<Vec<CopyChain>>()
+            })
+            .collect::<Vec<CopyChain>>();
+
+        chains.sort_by(|a, b| a.canonical.path.cmp(&b.canonical.path));
+        chains
+    }
+
+    pub fn report(chains: &[CopyChain], collapse: bool) {
+        println!(
+            "\n{}",
+            "Copy chains (numbered copies next to the original):".bold()
+        );
+        for chain in chains {
+            println!("  {}", chain.canonical.path.display());
+            for copy in &chain.copies {
+                println!("    - {}", copy.path.display());
+            }
+        }
+
+        if !collapse {
+            println!(
+                "{}",
+                "Run with --collapse-copies to keep the original names and delete the numbered copies."
+                    .dimmed()
+            );
+        }
+    }
+
+    /// One-shot cleanup of every chain: asks once, then deletes the copies that are confirmed
+    /// identical to their original. Returns the number of deleted copies.
+    pub fn collapse(chains: &[CopyChain], app_args: &Params) -> Result<u64> {
+        let count = chains.iter().map(|chain| chain.copies.len()).sum::<usize>();
+        println!(
+            "\n{}",
+            format!("{count} numbered cop(ies) will be deleted, original names are kept.")
+                .red()
+                .bold()
+        );
+        if !app_args.dry_run && !Interactive::scan_group_confirmation()? {
+            eprintln!("{}", "\nCancelled Delete Operation.".red());
+            return Ok(0);
+        }
+
+        let (removal, mut deleted) = (app_args.removal(), 0);
+        for chain in chains {
+            for copy in &chain.copies {
+                let original = Some(&*chain.canonical.path);
+                if Archive::contains(&copy.path) {
+                    ActionLog::skipped(
+                        &app_args.events,
+                        &copy.path,
+                        original,
+                        "inside a read-only archive",
+                    );
+                    continue;
+                }
+                if !Verifier::identical(&chain.canonical, copy, app_args.verification())
+                    .unwrap_or(false)
+                {
+                    ActionLog::skipped(
+                        &app_args.events,
+                        &copy.path,
+                        original,
+                        "contents differ from the original",
+                    );
+                    continue;
+                }
+                if let Err(e) = Survivor::ensure(&copy.path, [&*chain.canonical.path]) {
+                    ActionLog::skipped(&app_args.events, &copy.path, original, &format!("{e:#}"));
+                    continue;
+                }
+                if let Some(Err(e)) = app_args.verify_keeper.map(|check| check.verify(&chain.canonical, copy)) {
+                    ActionLog::skipped(&app_args.events, &copy.path, original, &format!("{e:#}"));
+                    continue;
+                }
+
+                match removal.remove(&copy.path, app_args) {
+                    Ok(_) => {
+                        deleted += 1;
+                        ActionLog::done(
+                            &app_args.events,
+                            removal.label(),
+                            &copy.path,
+                            original,
+                            None,
+                        );
+                        Sidecar::follow(&copy.path, app_args.sidecars, |sidecar| {
+                            removal.remove(sidecar, app_args)
+                        })
+                        .into_iter()
+                        .for_each(|(sidecar, outcome)| match outcome {
+                            Ok(_) => ActionLog::done(
+                                &app_args.events,
+                                removal.label(),
+                                &sidecar,
+                                None,
+                                Some("sidecar"),
+                            ),
+                            Err(e) => ActionLog::failed(
+                                &app_args.events,
+                                &sidecar,
+                                None,
+                                &format!("sidecar: {e:#}"),
+                            ),
+                        });
+                    }
+                    Err(e) => ActionLog::failed(
+                        &app_args.events,
+                        &copy.path,
+                        original,
+                        &format!("{e:#}"),
+                    ),
+                }
+            }
+        }
+
+        Ok(deleted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CopyChains;
+    use crate::fixture::Fixture;
+    use anyhow::Result;
+    use dashmap::DashMap;
+    use std::fs;
+
+    #[test]
+    fn finds_numbered_copies_next_to_their_original() -> Result<()> {
+        for (copy, original) in [
+            ("report (1).docx", Some("report.docx")),
+            ("Copy of report.docx", Some("report.docx")),
+            ("Copy (2) of report.docx", Some("report.docx")),
+            ("report - Copy (3).docx", Some("report.docx")),
+            ("report - Copy.docx", Some("report.docx")),
+            ("report copy 2.docx", Some("report.docx")),
+            ("Copy of report (1).docx", Some("report.docx")),
+            ("report (final).docx", None),
+            ("report.docx", None),
+            ("(1).txt", None),
+        ] {
+            assert_eq!(
+                CopyChains::canonical_name(copy).as_deref(),
+                original,
+                "{copy}"
+            );
+        }
+
+        let fixture = Fixture::new()?;
+        fs::create_dir(fixture.path("other"))?;
+        let file = |name: &str| fixture.scanned(name, b"same");
+        let store = DashMap::new();
+        store.insert(
+            1u128,
+            vec![
+                file("report.docx")?,
+                file("report (1).docx")?,
+                file("Copy of report.docx")?,
+                file("other/report (2).docx")?,
+            ],
+        );
+
+        let chains = CopyChains::find(&store);
+        assert_eq!(chains.len(), 1);
+        assert!(chains[0].canonical.path.ends_with("report.docx"));
+        assert_eq!(chains[0].copies.len(), 2);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,141 @@
+use anyhow::anyhow;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::protection::Protection;
+
+/// A mandatory access control system, which can refuse what the file permissions allow.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Mac {
+    /// Enforcing SELinux, with the security context of the file when it can be read
+    SELinux(Option<String>),
+    /// AppArmor, with the profile confining this process
+    AppArmor(String),
+}
+
+impl Mac {
+    /// The MAC system that may have refused an operation in this process, if any is active.
+    fn detect(path: &Path) -> Option<Self> {
+        let read = |file: &str| fs::read_to_string(file).map(|content| content.trim().to_string());
+
+        if read("/sys/fs/selinux/enforce").is_ok_and(|enforce| enforce == "1") {
+            return Some(Self::SELinux(Self::selinux_label(path)));
+        }
+
+        // NOTE: newer kernels stack LSMs and keep the AppArmor context apart.
+        let profile =
+            read("/proc/self/attr/apparmor/current").or_else(|_| read("/proc/self/attr/current"));
+        match profile {
+            Ok(profile)
+                if read("/sys/module/apparmor/parameters/enabled").is_ok_and(|on| on == "Y") =>
+            {
+                let profile = profile.trim_end_matches('\0').to_string();
+                (!profile.is_empty() && profile != "unconfined").then_some(Self::AppArmor(profile))
+            }
+            _ => None,
+        }
+    }
+
+    #[cfg(unix)]
+    fn selinux_label(path: &Path) -> Option<String> {
+        let label = xattr::get(path, "security.selinux").ok()??;
+        Some(
+            String::from_utf8_lossy(&label)
+                .trim_end_matches('\0')
+                .to_string(),
+        )
+    }
+
+    #[cfg(not(unix))]
+    fn selinux_label(_path: &Path) -> Option<String> {
+        None
+    }
+
+    /// What to tell the user, and where to look next.
+    fn hint(&self, path: &Path) -> String {
+        let directory = path
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|| PathBuf::from("."));
+        match self {
+            Self::SELinux(label) => format!(
+                "refused by SELinux policy, not by file permissions{}; see the denial with \
+                 `ausearch -m avc -ts recent`, and if the labels are wrong restore them with \
+                 `restorecon -Rv {}`",
+                label
+                    .as_ref()
+                    .map(|label| format!(" (file labelled {label})"))
+                    .unwrap_or_default(),
+                directory.display()
+            ),
+            Self::AppArmor(profile) => format!(
+                "refused by the AppArmor profile {profile}, not by file permissions; see the \
+                 denial with `journalctl -k | grep apparmor=\"DENIED\"` and allow {} in the \
+                 profile or run without it",
+                directory.display()
+            ),
+        }
+    }
+}
+
+/// Tells apart the reasons a file could not be removed when the OS only says "permission
+/// denied": file attributes, file permissions or a MAC policy (SELinux, AppArmor), each calling
+/// for a different fix.
+pub struct Denial;
+
+impl Denial {
+    /// `error` from removing or replacing `path`, with the reason it was refused when known.
+    pub fn explain(error: io::Error, path: &Path) -> anyhow::Error {
+        if !matches!(error.raw_os_error(), Some(code) if Self::is_denial(code)) {
+            return error.into();
+        }
+
+        let hint = match Protection::of(path) {
+            Some(Protection::LockedDirectory) => {
+                "the directory is immutable or not writable by this user".to_string()
+            }
+            Some(protection) => format!("the file is {protection}, see `lsattr` / `chattr`"),
+            None => match Mac::detect(path) {
+                Some(mac) => mac.hint(path),
+                None => return error.into(),
+            },
+        };
+        anyhow!(error).context(hint)
+    }
+
+    #[cfg(unix)]
+    fn is_denial(code: i32) -> bool {
+        code == libc::EACCES || code == libc::EPERM
+    }
+
+    #[cfg(not(unix))]
+    fn is_denial(_code: i32) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Denial, Mac};
+    use std::{io, path::Path};
+
+    #[test]
+    fn mac_denials_point_at_the_policy() {
+        let path = Path::new("/srv/share/report.pdf");
+        let missing = io::Error::from(io::ErrorKind::NotFound);
+        assert_eq!(
+            format!("{:#}", Denial::explain(missing, path)),
+            "entity not found"
+        );
+
+        let selinux = Mac::SELinux(Some("system_u:object_r:public_content_t:s0".to_string()));
+        let hint = selinux.hint(path);
+        assert!(hint.contains("labelled system_u:object_r:public_content_t:s0"));
+        assert!(hint.contains("restorecon -Rv /srv/share"));
+        assert!(Mac::AppArmor("smbd".to_string())
+            .hint(path)
+            .contains("profile smbd"));
+    }
+}
@@ -0,0 +1,114 @@
+use anyhow::{Context, Result};
+use bytesize::ByteSize;
+use std::process::Command;
+
+use crate::summary::RunSummary;
+
+/// Object the `--dbus` signals are emitted from, on the session bus.
+pub const OBJECT_PATH: &str = "/io/github/fc7/Deduplicator";
+/// Interface of the `--dbus` signals: `Progress(s status)` with every heartbeat, the same JSON
+/// as the `--heartbeat` file, and `Finished(s result)` with the `DEDUP_RESULT` line.
+pub const INTERFACE: &str = "io.github.fc7.Deduplicator";
+
+/// Desktop integration for long scans: progress signals on the D-Bus session bus (see `--dbus`),
+/// for system monitors & scripts (`dbus-monitor "interface='io.github.fc7.Deduplicator'"`), and
+/// a notification once the run is over (see `--notify`). Both go through the tools the desktop
+/// ships with (`gdbus`, `notify-send`, `osascript`), so nothing is linked against the bus.
+pub struct Desktop;
+
+impl Desktop {
+    /// Emits `signal` of `INTERFACE` with a single string argument.
+    pub fn emit(signal: &str, argument: &str) -> Result<()> {
+        let status = Command::new("gdbus")
+            .args([
+                "emit",
+                "--session",
+                "--object-path",
+                OBJECT_PATH,
+                "--signal",
+            ])
+            .arg(format!("{INTERFACE}.{signal}"))
+            .arg(Self::gvariant_string(argument))
+            .status()
+            .context("unable to run gdbus, is it installed?")?;
+        anyhow::ensure!(status.success(), "gdbus could not emit {signal}: {status}");
+        Ok(())
+    }
+
+    /// Shows the outcome of the run as a desktop notification.
+    pub fn notify(summary: &RunSummary) -> Result<()> {
+        let body = match summary.groups {
+            0 => "No duplicates found.".to_string(),
+            groups => format!(
+                "{groups} group(s) of duplicates, {} file(s), {} wasted, {} deleted.",
+                summary.files,
+                ByteSize::b(summary.wasted),
+                summary.deleted
+            ),
+        };
+        Self::show("Deduplicator finished", &body)
+    }
+
+    #[cfg(target_os = "macos")]
+    fn show(title: &str, body: &str) -> Result<()> {
+        let script = format!(
+            "display notification {} with title {}",
+            Self::applescript_string(body),
+            Self::applescript_string(title)
+        );
+        let status = Command::new("osascript")
+            .args(["-e", &script])
+            .status()
+            .context("unable to run osascript")?;
+        anyhow::ensure!(
+            status.success(),
+            "osascript could not show the notification: {status}"
+        );
+        Ok(())
+    }
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    fn show(title: &str, body: &str) -> Result<()> {
+        let status = Command::new("notify-send")
+            .args(["--app-name=deduplicator", title, body])
+            .status()
+            .context("unable to run notify-send, is libnotify installed?")?;
+        anyhow::ensure!(
+            status.success(),
+            "notify-send could not show the notification: {status}"
+        );
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn show(_title: &str, _body: &str) -> Result<()> {
+        anyhow::bail!("desktop notifications are not supported on this system")
+    }
+
+    /// `s` as a GVariant text literal, the way `gdbus` takes its arguments.
+    fn gvariant_string(s: &str) -> String {
+        format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"))
+    }
+
+    #[cfg(target_os = "macos")]
+    fn applescript_string(s: &str) -> String {
+        format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Desktop;
+
+    #[test]
+    fn arguments_are_quoted_as_gvariant_strings() {
+        assert_eq!(
+            Desktop::gvariant_string(r#"{"stage":"hashing"}"#),
+            r#"'{"stage":"hashing"}'"#
+        );
+        assert_eq!(
+            Desktop::gvariant_string(r"it's C:\data"),
+            r"'it\'s C:\\data'"
+        );
+    }
+}
@@ -0,0 +1,110 @@
+use clap::ValueEnum;
+use std::{collections::HashMap, path::Path, sync::Mutex};
+
+/// Whether each device was found to be rotational, by device id.
+static ROTATIONAL: Mutex<Option<HashMap<u64, bool>>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum DiskType {
+    /// Tell spinning disks from SSDs by what the system reports (Linux only, SSD elsewhere)
+    #[default]
+    Auto,
+    /// Hash in parallel everywhere
+    Ssd,
+    /// Hash one file at a time on each device
+    Hdd,
+}
+
+/// Spinning disks, where hashing many files at once makes the heads seek back and forth between
+/// them until throughput collapses. Their files are hashed one at a time per disk, in path order,
+/// while SSDs take as many threads as `--hash-threads` gives them.
+pub struct Disks;
+
+impl Disks {
+    /// The device to hash `path` on one file at a time, `None` to hash it in parallel.
+    pub fn spinning(path: &Path, disk_type: DiskType) -> Option<u64> {
+        match disk_type {
+            DiskType::Ssd => None,
+            DiskType::Hdd => Some(Self::device(path).unwrap_or_default()),
+            DiskType::Auto => Self::device(path).filter(|&device| Self::rotational(device)),
+        }
+    }
+
+    fn rotational(device: u64) -> bool {
+        let mut known = ROTATIONAL.lock().unwrap();
+        *known
+            .get_or_insert_with(HashMap::new)
+            .entry(device)
+            .or_insert_with(|| Self::detect(device))
+    }
+
+    #[cfg(unix)]
+    fn device(path: &Path) -> Option<u64> {
+        use std::os::unix::fs::MetadataExt;
+        std::fs::metadata(path).map(|meta| meta.dev()).ok()
+    }
+
+    #[cfg(not(unix))]
+    fn device(_path: &Path) -> Option<u64> {
+        None
+    }
+
+    /// Reads the `queue/rotational` flag of the block device, which a partition leaves to the
+    /// disk holding it. Devices without one (network & virtual file systems) count as SSDs.
+    #[cfg(target_os = "linux")]
+    fn detect(device: u64) -> bool {
+        let (major, minor) = (
+            ((device >> 8) & 0xfff) | ((device >> 32) & !0xfff),
+            (device & 0xff) | ((device >> 12) & !0xff),
+        );
+        std::fs::canonicalize(format!("/sys/dev/block/{major}:{minor}"))
+            .is_ok_and(|block| Self::rotational_in(&block))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn detect(_device: u64) -> bool {
+        false
+    }
+
+    #[cfg(any(test, target_os = "linux"))]
+    fn rotational_in(block: &Path) -> bool {
+        [Some(block), block.parent()]
+            .into_iter()
+            .flatten()
+            .find_map(|dir| std::fs::read_to_string(dir.join("queue/rotational")).ok())
+            .is_some_and(|flag| flag.trim() == "1")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DiskType, Disks};
+    use anyhow::Result;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn partitions_take_the_rotational_flag_of_their_disk() -> Result<()> {
+        let sys = TempDir::new()?;
+        let (disk, partition, ssd) = (
+            sys.path().join("sda"),
+            sys.path().join("sda/sda1"),
+            sys.path().join("nvme0n1"),
+        );
+        fs::create_dir_all(disk.join("queue"))?;
+        fs::create_dir_all(&partition)?;
+        fs::create_dir_all(ssd.join("queue"))?;
+        fs::write(disk.join("queue/rotational"), "1\n")?;
+        fs::write(ssd.join("queue/rotational"), "0\n")?;
+
+        assert!(Disks::rotational_in(&disk));
+        assert!(Disks::rotational_in(&partition));
+        assert!(!Disks::rotational_in(&ssd));
+        assert!(!Disks::rotational_in(&sys.path().join("missing")));
+
+        assert_eq!(Disks::spinning(sys.path(), DiskType::Ssd), None);
+        assert!(Disks::spinning(sys.path(), DiskType::Hdd).is_some());
+
+        Ok(())
+    }
+}
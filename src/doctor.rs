@@ -0,0 +1,237 @@
+use anyhow::{Context, Result};
+use chrono::Utc;
+use clap::Args;
+use colored::Colorize;
+use std::{
+    env, fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use crate::params::Params;
+
+const PROBE_DIR: &str = ".deduplicator-doctor";
+const LONG_NAME_LENGTH: usize = 255;
+const LONG_PATH_LENGTH: usize = 300;
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct DoctorArgs {
+    /// Directory whose filesystem is checked [default = pwd]
+    #[arg(value_hint = clap::ValueHint::DirPath, value_name = "check_dir_path")]
+    pub path: Option<PathBuf>,
+    /// Write a support bundle with the full report to this file
+    #[arg(long, value_hint = clap::ValueHint::FilePath, value_name = "bundle_path")]
+    pub bundle: Option<PathBuf>,
+}
+
+#[derive(Debug)]
+pub enum CheckStatus {
+    Supported,
+    Unsupported(String),
+}
+
+#[derive(Debug)]
+pub struct Check {
+    pub name: &'static str,
+    pub status: CheckStatus,
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.status {
+            CheckStatus::Supported => write!(f, "{:<12} ok", self.name),
+            CheckStatus::Unsupported(reason) => {
+                write!(f, "{:<12} unavailable: {reason}", self.name)
+            }
+        }
+    }
+}
+
+impl Check {
+    fn probe(name: &'static str, probe: impl FnOnce() -> Result<()>) -> Self {
+        let status = match probe() {
+            Ok(_) => CheckStatus::Supported,
+            Err(e) => CheckStatus::Unsupported(format!("{e:#}")),
+        };
+        Self { name, status }
+    }
+}
+
+/// Self-test of the filesystem capabilities deduplicator relies on, plus the environment details
+/// that platform-specific bug reports usually need.
+pub struct Doctor;
+
+impl Doctor {
+    pub fn run(args: &DoctorArgs, app_args: &Params) -> Result<()> {
+        let path = match &args.path {
+            Some(path) => fs::canonicalize(path)?,
+            None => env::current_dir()?,
+        };
+
+        let checks = Self::check_capabilities(&path)?;
+        let report = Self::report(&path, &checks, app_args);
+
+        println!("{}", "Environment:".bold());
+        Self::environment(app_args)
+            .iter()
+            .for_each(|line| println!("  {line}"));
+
+        println!(
+            "\n{}",
+            format!("Capabilities of {}:", path.display()).bold()
+        );
+        checks.iter().for_each(|check| match check.status {
+            CheckStatus::Supported => println!("  {}", check.to_string().green()),
+            CheckStatus::Unsupported(_) => println!("  {}", check.to_string().yellow()),
+        });
+
+        if let Some(bundle) = &args.bundle {
+            fs::write(bundle, report)
+                .with_context(|| format!("unable to write support bundle {}", bundle.display()))?;
+            println!("\nSupport bundle written to {}", bundle.display());
+        }
+
+        Ok(())
+    }
+
+    /// Runs every probe inside a scratch directory below `path`, which is removed afterwards.
+    pub fn check_capabilities(path: &Path) -> Result<Vec<Check>> {
+        let probe_dir = path.join(PROBE_DIR);
+        fs::create_dir_all(&probe_dir)
+            .with_context(|| format!("unable to write to {}", path.display()))?;
+        let source = probe_dir.join("source");
+        fs::write(&source, b"deduplicator doctor probe")?;
+
+        let checks = vec![
+            Check::probe("hardlink", || {
+                fs::hard_link(&source, probe_dir.join("hardlink"))?;
+                Ok(())
+            }),
+            Check::probe("reflink", || Self::probe_reflink(&probe_dir)),
+            Check::probe("xattr", || Self::probe_xattr(&source)),
+            Check::probe("trash", Self::probe_trash),
+            Check::probe("long paths", || Self::probe_long_paths(&probe_dir)),
+        ];
+
+        fs::remove_dir_all(&probe_dir)?;
+
+        Ok(checks)
+    }
+
+    /// Clones a scratch file inside `dir`, which only succeeds where the filesystem shares extents
+    /// between files (btrfs, XFS, APFS, ReFS, ...).
+    pub fn probe_reflink(dir: &Path) -> Result<()> {
+        let source = dir.join(".deduplicator-reflink-probe");
+        let clone = dir.join(".deduplicator-reflink-probe-clone");
+        fs::write(&source, b"deduplicator reflink probe")?;
+        let cloned = reflink_copy::reflink(&source, &clone);
+
+        let _ = fs::remove_file(&clone);
+        fs::remove_file(&source)?;
+        cloned.context("the filesystem does not support reflinks")
+    }
+
+    #[cfg(unix)]
+    fn probe_xattr(source: &Path) -> Result<()> {
+        xattr::set(source, "user.deduplicator.doctor", b"1")?;
+        match xattr::get(source, "user.deduplicator.doctor")? {
+            Some(value) if value == b"1" => Ok(()),
+            _ => anyhow::bail!("attribute was not stored"),
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn probe_xattr(_source: &Path) -> Result<()> {
+        anyhow::bail!("not supported on this platform")
+    }
+
+    fn probe_trash() -> Result<()> {
+        let trash = match env::consts::OS {
+            "macos" => env::var_os("HOME").map(|home| PathBuf::from(home).join(".Trash")),
+            "windows" => return Ok(()),
+            _ => env::var_os("XDG_DATA_HOME")
+                .map(PathBuf::from)
+                .or_else(|| {
+                    env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share"))
+                })
+                .map(|data| data.join("Trash")),
+        }
+        .context("no home directory to locate the trash")?;
+
+        match trash.is_dir() {
+            true => Ok(()),
+            false => anyhow::bail!("{} does not exist", trash.display()),
+        }
+    }
+
+    fn probe_long_paths(probe_dir: &Path) -> Result<()> {
+        fs::write(probe_dir.join("n".repeat(LONG_NAME_LENGTH)), b"")
+            .context("file names of 255 bytes are rejected")?;
+
+        let mut nested = probe_dir.to_path_buf();
+        while nested.as_os_str().len() < LONG_PATH_LENGTH {
+            nested.push("d".repeat(50));
+        }
+        fs::create_dir_all(&nested)
+            .with_context(|| format!("paths longer than {LONG_PATH_LENGTH} bytes are rejected"))?;
+        fs::write(nested.join("file"), b"")?;
+
+        Ok(())
+    }
+
+    fn environment(app_args: &Params) -> Vec<String> {
+        vec![
+            format!("version: {}", env!("CARGO_PKG_VERSION")),
+            format!("os: {} ({})", env::consts::OS, env::consts::ARCH),
+            format!("temp dir: {}", env::temp_dir().display()),
+            format!(
+                "threads: {}",
+                app_args.threads.unwrap_or_else(|| {
+                    std::thread::available_parallelism().map_or(1, |n| n.get())
+                })
+            ),
+            format!("strict: {}, verify: {:?}", app_args.strict, app_args.verification()),
+        ]
+    }
+
+    fn report(path: &Path, checks: &[Check], app_args: &Params) -> String {
+        let mut report = format!(
+            "deduplicator doctor report ({})\n\n",
+            Utc::now().to_rfc3339()
+        );
+        report.push_str("[environment]\n");
+        Self::environment(app_args)
+            .iter()
+            .for_each(|line| report.push_str(&format!("{line}\n")));
+
+        report.push_str(&format!("\n[capabilities of {}]\n", path.display()));
+        checks
+            .iter()
+            .for_each(|check| report.push_str(&format!("{check}\n")));
+
+        report.push_str(&format!("\n[config]\n{app_args:#?}\n"));
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CheckStatus, Doctor, PROBE_DIR};
+    use anyhow::Result;
+    use tempfile::TempDir;
+
+    #[test]
+    fn probes_clean_up_after_themselves() -> Result<()> {
+        let root = TempDir::new()?;
+
+        let checks = Doctor::check_capabilities(root.path())?;
+
+        let hardlink = checks
+            .iter()
+            .find(|check| check.name == "hardlink")
+            .unwrap();
+        assert!(matches!(hardlink.status, CheckStatus::Supported));
+        assert!(!root.path().join(PROBE_DIR).exists());
+
+        Ok(())
+    }
+}
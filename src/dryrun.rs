@@ -0,0 +1,77 @@
+use colored::Colorize;
+use std::{
+    fs,
+    path::Path,
+    process::ExitCode,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+/// Tally of the actions a `--dry-run` left out, for the closing summary and the exit code.
+/// Clones share the tally, the run carries one in `Params`.
+#[derive(Debug, Clone, Default)]
+pub struct DryRun {
+    actions: Arc<AtomicU64>,
+    reclaimed: Arc<AtomicU64>,
+}
+
+impl DryRun {
+    /// Exit code of a dry run that would have changed something. Errors keep exiting with 1.
+    pub const WOULD_ACT: u8 = 2;
+
+    /// Counts one action that would have freed the space `path` takes up now.
+    pub fn record(&self, path: &Path) {
+        let size = fs::symlink_metadata(path)
+            .map(|metadata| metadata.len())
+            .unwrap_or_default();
+        self.actions.fetch_add(1, Ordering::Relaxed);
+        self.reclaimed.fetch_add(size, Ordering::Relaxed);
+    }
+
+    pub fn actions(&self) -> u64 {
+        self.actions.load(Ordering::Relaxed)
+    }
+
+    /// Prints what the run would have done and returns the matching exit code.
+    pub fn finish(&self) -> ExitCode {
+        let actions = self.actions();
+        println!(
+            "\n{}",
+            format!(
+                "Dry run: {actions} action(s) would be taken, {} would be reclaimed. Nothing was changed.",
+                bytesize::ByteSize::b(self.reclaimed.load(Ordering::Relaxed))
+            )
+            .bold()
+        );
+
+        match actions {
+            0 => ExitCode::SUCCESS,
+            _ => ExitCode::from(Self::WOULD_ACT),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{params::Params, removal::Removal};
+    use anyhow::Result;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn dry_run_removal_counts_but_keeps_the_file() -> Result<()> {
+        let root = TempDir::new()?;
+        let file = root.path().join("duplicate.txt");
+        fs::write(&file, b"same")?;
+
+        let app_args = Params::default();
+        Removal::DryRun.remove(&file, &app_args)?;
+
+        assert!(file.exists());
+        assert_eq!(app_args.dry_run_tally.actions(), 1);
+
+        Ok(())
+    }
+}
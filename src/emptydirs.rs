@@ -0,0 +1,120 @@
+use std::{
+    collections::{BTreeSet, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use crate::{actionlog::ActionLog, events::Events};
+
+/// Directories left empty once their duplicates are gone (see `--remove-empty-dirs`), so a
+/// cleaned up staging folder does not end up as a skeleton of empty nested directories. Every
+/// file the run removes or moves away is recorded, by a dry run too, and their directories are
+/// pruned bottom-up at the end of the run. Clones share what was recorded, the run carries one
+/// in `Params`.
+#[derive(Debug, Clone, Default)]
+pub struct EmptyDirs {
+    removed: Arc<Mutex<Vec<PathBuf>>>,
+}
+
+impl EmptyDirs {
+    /// Notes that `path` was removed, or would have been by a dry run.
+    pub fn record(&self, path: &Path) {
+        self.removed.lock().unwrap().push(path.to_path_buf());
+    }
+
+    /// Removes the directories below `roots` that only held what was removed, deepest first, and
+    /// their parents once emptied in turn. A dry run only reports them. Returns how many there
+    /// were.
+    pub fn prune(&self, roots: &[PathBuf], dry_run: bool, events: &Events) -> usize {
+        let mut gone = std::mem::take(&mut *self.removed.lock().unwrap())
+            .into_iter()
+            .collect::<HashSet<PathBuf>>();
+        let below_root = |dir: &Path| {
+            roots
+                .iter()
+                .any(|root| dir.starts_with(root) && dir != root)
+        };
+        // NOTE: ordered by depth, so a directory is looked at after all of its subdirectories.
+        let mut pending = gone
+            .iter()
+            .filter_map(|path| path.parent())
+            .filter(|dir| below_root(dir))
+            .map(|dir| (dir.components().count(), dir.to_path_buf()))
+            .collect::<BTreeSet<(usize, PathBuf)>>();
+
+        let mut pruned = 0;
+        while let Some((_, dir)) = pending.pop_last() {
+            if !Self::emptied(&dir, &gone) {
+                continue;
+            }
+            match dry_run {
+                true => ActionLog::done(events, "WOULD DELETE DIR", &dir, None, None),
+                false => match fs::remove_dir(&dir) {
+                    Ok(()) => ActionLog::done(events, "DELETED DIR", &dir, None, None),
+                    Err(error) => {
+                        ActionLog::failed(events, &dir, None, &error.to_string());
+                        continue;
+                    }
+                },
+            }
+            pruned += 1;
+            if let Some(parent) = dir.parent().filter(|parent| below_root(parent)) {
+                pending.insert((parent.components().count(), parent.to_path_buf()));
+            }
+            gone.insert(dir);
+        }
+        pruned
+    }
+
+    /// Whether everything left in `dir` is gone.
+    fn emptied(dir: &Path, gone: &HashSet<PathBuf>) -> bool {
+        fs::read_dir(dir).is_ok_and(|mut entries| {
+            entries.all(|entry| entry.is_ok_and(|entry| gone.contains(&entry.path())))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{fixture::Fixture, params::Params, removal::Removal};
+    use anyhow::Result;
+    use std::fs;
+
+    #[test]
+    fn directories_are_pruned_bottom_up_below_the_root_only() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let root = fixture.root();
+        let file = |name: &str| fixture.file(name, b"same");
+        let removed = [
+            file("import/2024/05/a.jpg")?,
+            file("import/2024/06/b.jpg")?,
+            file("import/old/raw/c.jpg")?,
+            file("top.jpg")?,
+        ];
+        let kept = file("import/notes.txt")?;
+        fs::create_dir_all(root.join("import/2024/07"))?;
+        let roots = [root.to_path_buf()];
+        let app_args = Params::default();
+
+        for path in &removed {
+            Removal::DryRun.remove(path, &app_args)?;
+        }
+        assert_eq!(app_args.empty_dirs.prune(&roots, true, &app_args.events), 4);
+        assert!(root.join("import/2024/05").exists());
+
+        for path in &removed {
+            Removal::Delete.remove(path, &app_args)?;
+        }
+        assert_eq!(app_args.empty_dirs.prune(&roots, false, &app_args.events), 4);
+        assert!(!root.join("import/old").exists());
+        assert!(!root.join("import/2024/05").exists());
+        assert!(!root.join("import/2024/06").exists());
+        // NOTE: a directory that was empty before the run is not this run's to remove.
+        assert!(root.join("import/2024/07").exists());
+        assert!(kept.exists());
+        assert!(root.exists());
+
+        Ok(())
+    }
+}
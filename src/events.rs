@@ -0,0 +1,150 @@
+use std::{
+    fmt,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+};
+
+use crate::{api::DuplicateGroup, heartbeat::Stage};
+
+/// What happens during a run, for frontends that render their own progress.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Event {
+    /// The run entered `Stage`.
+    Stage(Stage),
+    /// A file the scan found and compares with the others.
+    FileScanned { path: PathBuf, size: u64 },
+    /// A file hashed; against the files scanned, how far hashing is.
+    FileHashed { path: PathBuf, size: u64 },
+    /// A group of duplicates confirmed while the scan goes on, before any is hidden from the
+    /// report (see `--intentional`, `--cross-project`).
+    GroupFound(DuplicateGroup),
+    /// An action taken on a file, as written to the `--action-log`: `outcome` is `deleted`,
+    /// `would-delete`, `moved`, `linked`, ..., `skipped` or `failed`.
+    Action {
+        outcome: String,
+        path: PathBuf,
+        target: Option<PathBuf>,
+        reason: Option<String>,
+    },
+}
+
+enum Subscriber {
+    Handler(Box<dyn Fn(&Event) + Send + Sync>),
+    Stream(Sender<Event>),
+}
+
+#[derive(Default)]
+struct Shared {
+    subscribers: Mutex<Vec<Arc<Subscriber>>>,
+    /// Set while anyone listens, so that events are not even built otherwise.
+    active: AtomicBool,
+}
+
+/// Subscribers to the events of one run, set up with the run (see `DeduplicatorBuilder::on`)
+/// for callers embedding the scan instead of reading the terminal progress bars. Clones share
+/// their subscribers. Events come from the threads of every stage, so they arrive in the order
+/// they happen rather than grouped by stage.
+#[derive(Clone, Default)]
+pub struct Events {
+    shared: Arc<Shared>,
+}
+
+impl fmt::Debug for Events {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Events").field("active", &self.active()).finish()
+    }
+}
+
+impl Events {
+    /// Every event from now on, until the receiver is dropped.
+    pub fn subscribe(&self) -> Receiver<Event> {
+        let (sender, receiver) = mpsc::channel();
+        self.add(Subscriber::Stream(sender));
+        receiver
+    }
+
+    /// Calls `handler` with every event from now on, on the thread the event happens on. It
+    /// holds up that stage of the scan while it runs.
+    pub fn on(&self, handler: impl Fn(&Event) + Send + Sync + 'static) {
+        self.add(Subscriber::Handler(Box::new(handler)));
+    }
+
+    /// Whether anyone listens.
+    pub(crate) fn active(&self) -> bool {
+        self.shared.active.load(Ordering::Relaxed)
+    }
+
+    /// Hands the event built by `event` to every subscriber, if there is any.
+    pub(crate) fn emit(&self, event: impl FnOnce() -> Event) {
+        if !self.active() {
+            return;
+        }
+        let event = event();
+        // NOTE: handlers run without the lock held, so that one may subscribe or emit in turn
+        // and one that panics leaves the others in place.
+        let subscribers = self.shared.subscribers.lock().unwrap().clone();
+        let gone = subscribers
+            .into_iter()
+            .filter(|subscriber| match subscriber.as_ref() {
+                Subscriber::Handler(handler) => {
+                    handler(&event);
+                    false
+                }
+                Subscriber::Stream(sender) => sender.send(event.clone()).is_err(),
+            })
+            .collect::<Vec<Arc<Subscriber>>>();
+        if !gone.is_empty() {
+            let mut subscribers = self.shared.subscribers.lock().unwrap();
+            subscribers.retain(|subscriber| !gone.iter().any(|gone| Arc::ptr_eq(gone, subscriber)));
+            self.shared.active.store(!subscribers.is_empty(), Ordering::Relaxed);
+        }
+    }
+
+    fn add(&self, subscriber: Subscriber) {
+        self.shared.subscribers.lock().unwrap().push(Arc::new(subscriber));
+        self.shared.active.store(true, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Event, Events};
+    use std::path::PathBuf;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    #[test]
+    fn subscribers_get_the_events_emitted_after_they_subscribed() {
+        let action = |name: &str| Event::Action {
+            outcome: "deleted".to_string(),
+            path: PathBuf::from(format!("/events-test/{name}")),
+            target: None,
+            reason: None,
+        };
+        let events = Events::default();
+        events.emit(|| action("before"));
+        let (stream, handled) = (events.subscribe(), Arc::new(AtomicUsize::new(0)));
+        let (counter, nested) = (Arc::clone(&handled), events.clone());
+        events.on(move |event| {
+            counter.fetch_add(1, Ordering::Relaxed);
+            // NOTE: a handler may emit in turn without deadlocking the run.
+            if matches!(event, Event::Action { .. }) {
+                nested.emit(|| Event::FileScanned { path: PathBuf::from("/events-test/nested"), size: 1 });
+            }
+        });
+
+        events.emit(|| action("after"));
+
+        let nested = Event::FileScanned { path: PathBuf::from("/events-test/nested"), size: 1 };
+        assert_eq!(stream.try_iter().collect::<Vec<Event>>(), [action("after"), nested]);
+        assert_eq!(handled.load(Ordering::Relaxed), 2);
+        // NOTE: each run has subscribers of its own.
+        assert!(!Events::default().active());
+    }
+}
@@ -0,0 +1,434 @@
+use anyhow::{Context, Result};
+use chrono::Utc;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    archive::Archive,
+    fileinfo::{FileInfo, FileSource},
+    filetype::FileType,
+    link::{LinkMode, Replace},
+    notes::Notes,
+    params::Params,
+    plan::Plan,
+    runid::RunId,
+    similar::SimilarGroup,
+};
+
+const EXPORT_VERSION: u32 = 1;
+
+/// Machine readable snapshot of a run's duplicate groups, written with `--export` and read back
+/// by commands that audit or act on an earlier run.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Export {
+    pub version: u32,
+    pub created: String,
+    /// The run that wrote the export (see `RunId`), empty in exports written before run IDs.
+    #[serde(default)]
+    pub run_id: String,
+    pub groups: Vec<ExportGroup>,
+    /// Comparison mode with --report-unique: staging files with no copy in target.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub unique: Vec<PathBuf>,
+    /// With --images-similar: images that look alike without being duplicates.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub similar: Vec<ExportSimilar>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportGroup {
+    pub hash: String,
+    pub size: u64,
+    /// MIME type sniffed from the content (see `FileType::mime_type`), if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    pub files: Vec<PathBuf>,
+    /// The --label root of each file, in the order of `files`, when the run names any.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<Option<String>>,
+    /// Note & tags attached during interactive triage (see `--notes`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// The copy the run keeps (see `Export::with_policy`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep: Option<PathBuf>,
+    /// What the run's mode does with every other copy.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub actions: Vec<ExportAction>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportSimilar {
+    pub files: Vec<PathBuf>,
+    /// Bits the perceptual hash of each file is off the first one's.
+    pub distances: Vec<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExportAction {
+    pub path: PathBuf,
+    /// `delete`, `trash`, `move`, `symlink`, `hardlink`, `reflink` or `skip`
+    pub action: String,
+    /// Why the copy is skipped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+impl Export {
+    pub fn from_store(store: &DashMap<u128, Vec<Arc<FileInfo>>>) -> Self {
+        let mut groups = store
+            .iter()
+            .filter(|group| group.value().len() > 1)
+            .map(|group| {
+                let mut files = group
+                    .value()
+                    .iter()
+                    .map(|file| file.path.to_path_buf())
+                    .collect::<Vec<PathBuf>>();
+                files.sort();
+                ExportGroup {
+                    hash: format!("{:032x}", group.key()),
+                    size: group.value().first().map(|f| f.size).unwrap_or_default(),
+                    content_type: group
+                        .value()
+                        .first()
+                        .and_then(|file| FileType::mime_type(&file.path))
+                        .map(str::to_string),
+                    files,
+                    labels: vec![],
+                    note: None,
+                    tags: vec![],
+                    keep: None,
+                    actions: vec![],
+                }
+            })
+            .collect::<Vec<ExportGroup>>();
+        groups.sort_by(|a, b| a.files.cmp(&b.files));
+
+        Self {
+            version: EXPORT_VERSION,
+            created: Utc::now().to_rfc3339(),
+            run_id: RunId::get(),
+            groups,
+            unique: vec![],
+            similar: vec![],
+        }
+    }
+
+    /// Attaches the note & tags of every group that has some.
+    pub fn with_notes(mut self, notes: &Notes) -> Self {
+        for group in &mut self.groups {
+            if let Some(note) = notes.find(&group.files) {
+                group.note = note.note.clone();
+                group.tags = note.tags.clone();
+            }
+        }
+        self
+    }
+
+    /// Attaches the --label root of every file, if the run names any.
+    pub fn with_labels(mut self, app_args: &Params) -> Self {
+        if app_args.label.is_empty() {
+            return self;
+        }
+        for group in &mut self.groups {
+            group.labels = group
+                .files
+                .iter()
+                .map(|file| app_args.label_of(file).map(str::to_string))
+                .collect();
+        }
+        self
+    }
+
+    /// Attaches to every group the copy kept and the action on every other copy, as the run's
+    /// mode would carry them out, so that automation can act on or audit an export without
+    /// re-implementing the policy: the copy --keep retains, the others deleted (or trashed,
+    /// linked, deduped), or in comparison mode a copy in target, the staging copies deleted or
+    /// moved to the --move-to quarantine. Protected copies and archive members are skipped, and
+    /// so are the other copies in target.
+    pub fn with_policy(
+        mut self,
+        store: &DashMap<u128, Vec<Arc<FileInfo>>>,
+        app_args: &Params,
+    ) -> Self {
+        let groups = store
+            .iter()
+            .map(|group| (format!("{:032x}", group.key()), group.value().clone()))
+            .collect::<HashMap<String, Vec<Arc<FileInfo>>>>();
+        let action = match app_args.replacement() {
+            Some(Replace::Link(LinkMode::Sym)) => "symlink",
+            Some(Replace::Link(LinkMode::Hard)) => "hardlink",
+            Some(Replace::Dedupe(_)) => "reflink",
+            None if app_args.comparison_mode && app_args.move_to.is_some() => "move",
+            None if app_args.trash => "trash",
+            None => "delete",
+        };
+
+        for group in &mut self.groups {
+            let Some(mut files) = groups.get(&group.hash).cloned() else {
+                continue;
+            };
+            files.sort_by(|a, b| a.path.cmp(&b.path));
+            let in_target = |file: &FileInfo| file.source == Some(FileSource::Target);
+            let (keep, protections) = match app_args.comparison_mode {
+                // NOTE: staging copies with no copy in target are left alone.
+                true => match files.iter().find(|file| in_target(file)) {
+                    Some(keeper) => (keeper.path.to_path_buf(), vec![None; files.len()]),
+                    None => continue,
+                },
+                false => {
+                    let (keeper, protections) = Plan::keeper(&files, app_args);
+                    (files[keeper].path.to_path_buf(), protections)
+                }
+            };
+
+            group.actions = files
+                .iter()
+                .zip(protections)
+                .filter(|(file, _)| *file.path != *keep)
+                .map(|(file, protection)| {
+                    let reason = match protection {
+                        _ if Archive::contains(&file.path) => {
+                            Some("inside a read-only archive".to_string())
+                        }
+                        _ if app_args.comparison_mode && in_target(file) => {
+                            Some("in the target folder".to_string())
+                        }
+                        Some(protection) => Some(protection.id().to_string()),
+                        None => None,
+                    };
+                    ExportAction {
+                        path: file.path.to_path_buf(),
+                        action: match reason {
+                            Some(_) => "skip".to_string(),
+                            None => action.to_string(),
+                        },
+                        reason,
+                    }
+                })
+                .collect();
+            group.keep = Some(keep);
+        }
+        self
+    }
+
+    pub fn with_unique(mut self, unique: &[Arc<FileInfo>]) -> Self {
+        self.unique = unique.iter().map(|file| file.path.to_path_buf()).collect();
+        self
+    }
+
+    pub fn with_similar(mut self, similar: &[SimilarGroup]) -> Self {
+        self.similar = similar
+            .iter()
+            .map(|group| ExportSimilar {
+                files: group.iter().map(|(file, _)| file.path.to_path_buf()).collect(),
+                distances: group.iter().map(|(_, distance)| *distance).collect(),
+            })
+            .collect();
+        self
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json).with_context(|| format!("unable to write export {}", path.display()))
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let raw = fs::read_to_string(path)
+            .with_context(|| format!("unable to read export {}", path.display()))?;
+        let export: Self = serde_json::from_str(&raw)
+            .with_context(|| format!("invalid export {}", path.display()))?;
+
+        if export.version != EXPORT_VERSION {
+            anyhow::bail!(
+                "export {} has version {}, expected {}",
+                path.display(),
+                export.version,
+                EXPORT_VERSION
+            );
+        }
+
+        Ok(export)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Export;
+    use crate::{
+        fileinfo::{FileInfo, FileSource},
+        fixture::Fixture,
+        params::{Params, RootLabel},
+    };
+    use anyhow::Result;
+    use dashmap::DashMap;
+    use std::sync::Arc;
+
+    #[test]
+    fn round_trips_duplicate_groups_only() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let file = |name: &str| fixture.scanned(name, b"same");
+
+        let store = DashMap::new();
+        store.insert(1u128, vec![file("b.txt")?, file("a.txt")?]);
+        store.insert(2u128, vec![file("c.txt")?]);
+
+        let destination = fixture.path("export.json");
+        Export::from_store(&store).write(&destination)?;
+        let export = Export::read(&destination)?;
+
+        assert_eq!(export.groups.len(), 1);
+        assert_eq!(export.groups[0].size, 4);
+        assert_eq!(
+            export.groups[0].files,
+            vec![fixture.path("a.txt"), fixture.path("b.txt")]
+        );
+        assert_eq!(export.groups[0].keep, None);
+        assert_eq!(export.groups[0].content_type, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn groups_are_labelled_with_their_content_type() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+        let files = ["a.bin", "b.bin"]
+            .iter()
+            .map(|name| fixture.scanned(name, png))
+            .collect::<Result<Vec<Arc<FileInfo>>>>()?;
+
+        let store = DashMap::new();
+        store.insert(1u128, files);
+        let export = Export::from_store(&store);
+
+        assert_eq!(export.groups[0].content_type.as_deref(), Some("image/png"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn files_are_labelled_with_their_root_when_the_run_names_any() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let file = |name: &str| fixture.scanned(name, b"same");
+
+        let store = DashMap::new();
+        store.insert(1u128, vec![file("backup/a.txt")?, file("loose.txt")?]);
+        let app_args = Params {
+            label: vec![RootLabel {
+                name: "backup".to_string(),
+                root: fixture.path("backup"),
+            }],
+            ..Default::default()
+        };
+
+        let export = Export::from_store(&store).with_labels(&app_args);
+        assert_eq!(export.groups[0].labels, vec![Some("backup".to_string()), None]);
+        let export = Export::from_store(&store).with_labels(&Params::default());
+        assert!(export.groups[0].labels.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn policy_names_the_kept_copy_and_the_action_on_the_others() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let file = |name: &str| fixture.scanned(name, b"same");
+
+        let store = DashMap::new();
+        store.insert(1u128, vec![file("b.txt")?, file("a.txt")?, file("c.txt")?]);
+        let app_args = Params {
+            trash: true,
+            ..Default::default()
+        };
+        let export = Export::from_store(&store).with_policy(&store, &app_args);
+
+        let group = &export.groups[0];
+        assert_eq!(group.keep, Some(fixture.path("a.txt")));
+        assert_eq!(
+            group
+                .actions
+                .iter()
+                .map(|action| (action.path.clone(), action.action.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                (fixture.path("b.txt"), "trash"),
+                (fixture.path("c.txt"), "trash")
+            ]
+        );
+
+        let export = Export::from_store(&store).with_policy(&store, &Params::default());
+        assert!(export.groups[0].actions.iter().all(|action| action.action == "delete"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn comparison_policy_keeps_the_target_copy_and_removes_or_moves_the_staging_ones() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let file = |name: &str, source: FileSource| fixture.sourced(name, b"same", source);
+
+        let store = DashMap::new();
+        store.insert(
+            1u128,
+            vec![
+                file("a-staging.txt", FileSource::Staging)?,
+                file("b-target.txt", FileSource::Target)?,
+                file("c-target.txt", FileSource::Target)?,
+            ],
+        );
+        store.insert(
+            2u128,
+            vec![file("d-staging.txt", FileSource::Staging)?, file("e-staging.txt", FileSource::Staging)?],
+        );
+        let policy = |app_args: &Params| {
+            let export = Export::from_store(&store).with_policy(&store, app_args);
+            export
+                .groups
+                .iter()
+                .map(|group| {
+                    let actions = group
+                        .actions
+                        .iter()
+                        .map(|action| (action.path.clone(), action.action.clone()))
+                        .collect::<Vec<_>>();
+                    (group.keep.clone(), actions)
+                })
+                .collect::<Vec<_>>()
+        };
+        let expected = |action: &str| {
+            vec![
+                (
+                    Some(fixture.path("b-target.txt")),
+                    vec![
+                        (fixture.path("a-staging.txt"), action.to_string()),
+                        (fixture.path("c-target.txt"), "skip".to_string()),
+                    ],
+                ),
+                (None, vec![]),
+            ]
+        };
+
+        let deleting = Params {
+            comparison_mode: true,
+            ..Default::default()
+        };
+        assert_eq!(policy(&deleting), expected("delete"));
+        let moving = Params {
+            move_to: Some(fixture.path("quarantine")),
+            ..deleting
+        };
+        assert_eq!(policy(&moving), expected("move"));
+
+        Ok(())
+    }
+}
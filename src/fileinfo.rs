@@ -1,14 +1,22 @@
 use anyhow::Result;
-use gxhash::gxhash128;
 use memmap2::Mmap;
 use std::{
     fs,
-    io::Read,
+    io::{self, Read, Seek, SeekFrom},
     path::{Path, PathBuf},
     sync::{Arc, Mutex},
-    time::SystemTime,
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
+use crate::{
+    archive::Archive,
+    hasher::{HashScheme, Hasher, ReadStrategy, GXHASH_BLOCK_SIZE},
+    payload::Payload,
+};
+
+/// Default size of each sample hashed in the default (non-strict) mode (see `prehash`).
+pub const PREHASH_SIZE: u64 = 16384;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum FileState {
     Unprocessed,
@@ -21,6 +29,8 @@ pub enum FileSource {
     Target,
 }
 
+/// A file found by the scan. Once scanned, it is shared as an `Arc<FileInfo>` by the size &
+/// hash stores, the report and the actions on it, rather than copied into each.
 #[derive(Debug, Clone)]
 pub struct FileInfo {
     pub path: Box<Path>,
@@ -28,30 +38,109 @@ pub struct FileInfo {
     pub modified: SystemTime,
     pub state: Arc<Mutex<FileState>>,
     pub source: Option<FileSource>,
+    /// Size of the image data alone, for photos compared without their metadata (see `Payload`).
+    pub payload: Option<u64>,
 }
 
 impl FileInfo {
-    pub fn hash(&self, seed: i64) -> Result<u128> {
+    pub fn hash(&self, scheme: HashScheme) -> Result<u128> {
         if self.size == 0 {
             return Ok(0u128);
         };
 
-        let file = fs::File::open(&self.path)?;
-        let mapper = unsafe { Mmap::map(&file)? };
-        let content_hash = mapper
-            .chunks(4096)
-            .fold(0u128, |acc, chunk: &[u8]| acc ^ gxhash128(chunk, seed));
+        let mut hasher = scheme.hasher();
+        let buffer_size = scheme.buffer_size;
+        match fs::File::open(&self.path) {
+            Ok(_) if self.payload.is_some() => {
+                Self::stream_into(Payload::open(&self.path)?, hasher.as_mut(), buffer_size)?
+            }
+            Ok(file) if scheme.read == ReadStrategy::Buffered => {
+                Self::stream_into(file, hasher.as_mut(), buffer_size)?
+            }
+            Ok(file) => {
+                let mapper = unsafe { Mmap::map(&file)? };
+                hasher.update(&mapper);
+            }
+            // NOTE: archive members cannot be mapped, they are streamed instead.
+            Err(_) => Self::stream_into(Archive::open(&self.path)?, hasher.as_mut(), buffer_size)?,
+        };
+
+        Ok(hasher.finish())
+    }
+
+    /// Hash of `length` bytes starting at `offset`, built like `hash` so that XORing the hashes
+    /// of consecutive chunks covering the file (in 4096 byte multiples) matches the gxhash
+    /// content hash. Only meaningful for a `chunkable` scheme.
+    pub fn chunk_hash(&self, offset: u64, length: usize, scheme: HashScheme) -> Result<u128> {
+        let mut buffer = vec![0u8; length];
+        match fs::File::open(&self.path) {
+            Ok(mut file) => {
+                file.seek(SeekFrom::Start(offset))?;
+                file.read_exact(&mut buffer)?;
+            }
+            Err(_) => {
+                let mut member = Archive::open(&self.path)?;
+                io::copy(&mut member.by_ref().take(offset), &mut io::sink())?;
+                member.read_exact(&mut buffer)?;
+            }
+        }
 
-        // NOTE: avoids collision bw an empty file & a file full of null bytes.
-        Ok(content_hash ^ gxhash128(&self.size.to_ne_bytes(), seed))
+        Ok(buffer
+            .chunks(GXHASH_BLOCK_SIZE)
+            .fold(0u128, |acc, chunk: &[u8]| acc ^ scheme.digest(chunk)))
     }
 
-    pub fn initpages_hash(&self, seed: i64) -> Result<u128> {
-        let mut file = fs::File::open(&self.path)?;
-        let mut buffer = [0; 16384];
-        let bytes_read = file.read(&mut buffer)?;
+    /// Hash of the first, middle & last `sample` bytes of the content, or of all of it when no
+    /// longer than that. Unlike the start alone, it tells apart files sharing their headers, as
+    /// media containers & disk images do, without reading them in full.
+    pub fn prehash(&self, scheme: HashScheme, sample: u64) -> Result<u128> {
+        let samples = Self::samples(self.content_size(), sample);
+        let mut content = Vec::new();
+        match (self.payload, fs::File::open(&self.path)) {
+            (None, Ok(mut file)) => {
+                for (offset, length) in samples {
+                    file.seek(SeekFrom::Start(offset))?;
+                    file.by_ref().take(length).read_to_end(&mut content)?;
+                }
+            }
+            // NOTE: payloads & archive members cannot seek, what lies between samples is skipped.
+            (Some(_), _) => Self::read_samples(Payload::open(&self.path)?, &samples, &mut content)?,
+            (None, Err(_)) => Self::read_samples(Archive::open(&self.path)?, &samples, &mut content)?,
+        }
+
+        Ok(scheme.digest(&content))
+    }
+
+    /// Offset & length of the samples `prehash` hashes out of `length` bytes.
+    fn samples(length: u64, sample: u64) -> Vec<(u64, u64)> {
+        match length <= sample.saturating_mul(3) {
+            true => vec![(0, length)],
+            false => vec![
+                (0, sample),
+                ((length - sample) / 2, sample),
+                (length - sample, sample),
+            ],
+        }
+    }
+
+    fn read_samples(mut reader: impl Read, samples: &[(u64, u64)], content: &mut Vec<u8>) -> Result<()> {
+        let mut position = 0;
+        for &(offset, length) in samples {
+            io::copy(&mut reader.by_ref().take(offset - position), &mut io::sink())?;
+            reader.by_ref().take(length).read_to_end(content)?;
+            position = offset + length;
+        }
+        Ok(())
+    }
 
-        Ok(gxhash128(&buffer[..bytes_read], seed))
+    fn stream_into(mut reader: impl Read, hasher: &mut dyn Hasher, buffer_size: usize) -> Result<()> {
+        let mut buffer = vec![0u8; buffer_size];
+        loop {
+            match reader.read(&mut buffer)? {
+                0 => break Ok(()),
+                read => hasher.update(&buffer[..read]),
+            }
+        }
     }
 
     pub fn new(path: PathBuf) -> Result<Self> {
@@ -62,9 +151,29 @@ impl FileInfo {
             modified: filemeta.modified()?,
             state: Arc::new(Mutex::new(FileState::Unprocessed)),
             source: None,
+            payload: None,
         })
     }
 
+    /// A file recorded by an earlier run (see `--export`), read again if it is still there, with
+    /// its recorded size and no modification time if it is not.
+    pub fn recorded(path: PathBuf, size: u64) -> Self {
+        Self::new(path.clone()).unwrap_or_else(|_| Self {
+            path: path.into_boxed_path(),
+            size,
+            modified: UNIX_EPOCH,
+            state: Arc::new(Mutex::new(FileState::Unprocessed)),
+            source: None,
+            payload: None,
+        })
+    }
+
+    /// The size that matters when comparing: the payload size for photos compared without their
+    /// metadata, the file size otherwise.
+    pub fn content_size(&self) -> u64 {
+        self.payload.unwrap_or(self.size)
+    }
+
     pub fn with_source(path: PathBuf, source: FileSource) -> Result<Self> {
         let mut file_info = Self::new(path)?;
         file_info.source = Some(source);
@@ -80,11 +189,21 @@ impl FileInfo {
         let self_state = self.state.lock().unwrap();
         *self_state == FileState::SwProcessed
     }
+
+    /// Whether the file was modified less than `window` ago. A modification time in the future
+    /// counts as recent.
+    pub fn modified_within(&self, window: Duration) -> bool {
+        match SystemTime::now().duration_since(self.modified) {
+            Ok(age) => age < window,
+            Err(_) => true,
+        }
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::{fixture::Fixture, hasher::Algorithm};
     use tempfile::TempDir;
     use std::fs::File;
     use std::io::Write;
@@ -109,10 +228,84 @@ mod test {
         let empty_file_info = FileInfo::new(empty_file_name)?;
         let file_with_empty_bytes_info = FileInfo::new(file_with_null_bytes_name)?;
 
-        let seed: i64 = 246910456374;
+        let seed = HashScheme::from(246910456374);
 
         assert_ne!(empty_file_info.hash(seed)?, file_with_empty_bytes_info.hash(seed)?);
 
         Ok(())
     }
+
+    #[test]
+    fn modified_within_flags_only_recent_files() -> Result<()> {
+        let root = TempDir::new()?;
+        let path = root.path().join("import.jpg");
+        File::create_new(&path)?;
+
+        let mut file = FileInfo::new(path)?;
+        assert!(file.modified_within(Duration::from_secs(600)));
+
+        file.modified = SystemTime::now() - Duration::from_secs(3600);
+        assert!(!file.modified_within(Duration::from_secs(600)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn chunk_hashes_combine_into_the_full_content_hash() -> Result<()> {
+        let root = TempDir::new()?;
+        let path = root.path().join("large.bin");
+        let content = (0..3 * 4096 + 100).map(|i| (i % 251) as u8).collect::<Vec<u8>>();
+        File::create_new(&path)?.write_all(&content)?;
+
+        let file = FileInfo::new(path)?;
+        let seed = HashScheme::from(246910456374);
+        let combined = file.chunk_hash(0, 2 * 4096, seed)?
+            ^ file.chunk_hash(2 * 4096, 4096 + 100, seed)?
+            ^ seed.digest(&file.size.to_ne_bytes());
+
+        assert_eq!(combined, file.hash(seed)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn prehash_samples_the_start_middle_and_end() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let file = |name: &str, edit: Option<usize>| -> Result<FileInfo> {
+            let mut content = vec![7u8; 64 * 1024];
+            if let Some(at) = edit {
+                content[at] ^= 0xff;
+            }
+            FileInfo::new(fixture.file(name, &content)?)
+        };
+        let (scheme, sample) = (HashScheme::from(3), 4096);
+        let prehash = |file: &FileInfo| file.prehash(scheme, sample).unwrap();
+
+        let original = prehash(&file("original.bin", None)?);
+        assert_ne!(prehash(&file("middle.bin", Some(32 * 1024))?), original);
+        assert_ne!(prehash(&file("tail.bin", Some(64 * 1024 - 1))?), original);
+        // NOTE: what lies between the samples is left to --strict & --verify.
+        assert_eq!(prehash(&file("between.bin", Some(8 * 1024))?), original);
+
+        let small = file("small.bin", None)?;
+        assert_eq!(small.prehash(scheme, 32 * 1024)?, scheme.digest(&fs::read(&small.path)?));
+
+        Ok(())
+    }
+
+    #[test]
+    fn buffered_reads_hash_like_mapped_ones() -> Result<()> {
+        let root = TempDir::new()?;
+        let path = root.path().join("file.bin");
+        fs::write(&path, (0..300_000u32).map(|n| (n % 251) as u8).collect::<Vec<u8>>())?;
+        let file = FileInfo::new(path)?;
+
+        for algorithm in [Algorithm::Gxhash, Algorithm::Blake3, Algorithm::Sha256] {
+            let mapped = HashScheme::new(algorithm, 9);
+            let buffered = mapped.reading(ReadStrategy::Buffered, 64 * 1024 + 100);
+            assert_eq!(file.hash(buffered)?, file.hash(mapped)?);
+        }
+
+        Ok(())
+    }
 }
@@ -0,0 +1,50 @@
+use clap::ValueEnum;
+use infer::MatcherType;
+use std::{io::Read, path::Path};
+
+use crate::archive::Archive;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum MediaType {
+    Image,
+    Video,
+    Audio,
+    Document,
+}
+
+pub struct FileType;
+
+impl FileType {
+    /// Sniffs the magic bytes at the start of the file, ignoring its extension.
+    pub fn detect(path: &Path) -> Option<infer::Type> {
+        let mut buffer = [0u8; 8192];
+        let read = Archive::open(path).ok()?.read(&mut buffer).ok()?;
+        infer::get(&buffer[..read])
+    }
+
+    /// MIME type of the content, e.g. `image/jpeg`, if its magic bytes are known.
+    pub fn mime_type(path: &Path) -> Option<&'static str> {
+        Self::detect(path).map(|detected| detected.mime_type())
+    }
+
+    pub fn media_type(path: &Path) -> Option<MediaType> {
+        let detected = Self::detect(path)?;
+        match detected.matcher_type() {
+            MatcherType::Image => Some(MediaType::Image),
+            MatcherType::Video => Some(MediaType::Video),
+            MatcherType::Audio => Some(MediaType::Audio),
+            MatcherType::Doc | MatcherType::Book => Some(MediaType::Document),
+            // NOTE: infer files pdf & rtf under archives.
+            MatcherType::Archive => match detected.mime_type() {
+                "application/pdf" | "application/rtf" => Some(MediaType::Document),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    pub fn matches_any(path: &Path, media_types: &[MediaType]) -> bool {
+        media_types.is_empty()
+            || Self::media_type(path).is_some_and(|kind| media_types.contains(&kind))
+    }
+}
@@ -0,0 +1,217 @@
+use anyhow::Result;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+use tempfile::TempDir;
+
+#[cfg(test)]
+use crate::fileinfo::{FileInfo, FileSource};
+#[cfg(test)]
+use std::sync::Arc;
+
+/// Synthetic trees to scan in tests, removed once dropped: duplicate groups of a chosen
+/// distribution, sparse files, symlinks and files the scan cannot read or remove. Available to
+/// other crates with the `test-util` feature.
+pub struct Fixture {
+    _dir: TempDir,
+    root: PathBuf,
+}
+
+impl Fixture {
+    pub fn new() -> Result<Self> {
+        let dir = TempDir::with_prefix("deduplicator_fixture")?;
+        // NOTE: canonical, as the scanner reports canonical paths (/tmp is a symlink on macOS).
+        let root = fs::canonicalize(dir.path())?;
+        Ok(Self { _dir: dir, root })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    pub fn path(&self, relative: impl AsRef<Path>) -> PathBuf {
+        self.root.join(relative)
+    }
+
+    /// Writes `content` to `relative`, creating the directories above it.
+    pub fn file(&self, relative: impl AsRef<Path>, content: &[u8]) -> Result<PathBuf> {
+        let path = self.path(relative);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, content)?;
+        Ok(path)
+    }
+
+    /// One group per entry of `copies`, each holding that many copies of a content of `size`
+    /// bytes (8 at least) that no other group shares: `[3, 2, 1]` makes a group of three, a
+    /// pair and a unique file. Copies are spread over `group-<n>/` and `copies/<n>/`.
+    pub fn distribution(&self, copies: &[usize], size: usize) -> Result<Vec<Vec<PathBuf>>> {
+        copies
+            .iter()
+            .enumerate()
+            .map(|(group, &count)| {
+                let content = Self::content(group, size);
+                (0..count)
+                    .map(|copy| {
+                        let relative = match copy % 2 {
+                            0 => format!("group-{group}/copy-{copy}.bin"),
+                            _ => format!("copies/{group}/copy-{copy}.bin"),
+                        };
+                        self.file(relative, &content)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// A file of `len` bytes that takes no space on disk, where the file system supports holes.
+    pub fn sparse(&self, relative: impl AsRef<Path>, len: u64) -> Result<PathBuf> {
+        let path = self.file(relative, b"")?;
+        fs::OpenOptions::new()
+            .write(true)
+            .open(&path)?
+            .set_len(len)?;
+        Ok(path)
+    }
+
+    /// A symlink at `relative` to `target`, itself relative to the root.
+    #[cfg(unix)]
+    pub fn symlink(&self, relative: impl AsRef<Path>, target: impl AsRef<Path>) -> Result<PathBuf> {
+        let path = self.path(relative);
+        std::os::unix::fs::symlink(self.path(target), &path)?;
+        Ok(path)
+    }
+
+    /// A symlink at `relative` to `target`, itself relative to the root. Creating one takes
+    /// developer mode or an elevated shell on Windows.
+    #[cfg(windows)]
+    pub fn symlink(&self, relative: impl AsRef<Path>, target: impl AsRef<Path>) -> Result<PathBuf> {
+        let (path, target) = (self.path(relative), self.path(target));
+        match target.is_dir() {
+            true => std::os::windows::fs::symlink_dir(&target, &path)?,
+            false => std::os::windows::fs::symlink_file(&target, &path)?,
+        }
+        Ok(path)
+    }
+
+    /// Makes `relative` read-only.
+    pub fn read_only(&self, relative: impl AsRef<Path>) -> Result<()> {
+        let path = self.path(relative);
+        let mut permissions = fs::metadata(&path)?.permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&path, permissions)?;
+        Ok(())
+    }
+
+    /// Takes every permission away from `relative`, a file that cannot be read or a directory
+    /// that cannot be listed (unless running as root).
+    #[cfg(unix)]
+    pub fn unreadable(&self, relative: impl AsRef<Path>) -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(self.path(relative), fs::Permissions::from_mode(0o000))?;
+        Ok(())
+    }
+
+    /// The group index in the first 8 bytes, then a filler that depends on it as well.
+    fn content(group: usize, size: usize) -> Vec<u8> {
+        let seed = group as u64;
+        seed.to_le_bytes()
+            .into_iter()
+            .chain((8..size as u64).map(|n| (n.wrapping_mul(31) ^ seed) as u8))
+            .collect()
+    }
+}
+
+/// Files as the scan finds them, for the tests of this crate.
+#[cfg(test)]
+impl Fixture {
+    /// Writes `content` to `relative` (see `file`) and returns it as the scan finds it.
+    pub(crate) fn scanned(
+        &self,
+        relative: impl AsRef<Path>,
+        content: &[u8],
+    ) -> Result<Arc<FileInfo>> {
+        FileInfo::new(self.file(relative, content)?).map(Arc::new)
+    }
+
+    /// `scanned`, found in the `source` tree of a comparison.
+    pub(crate) fn sourced(
+        &self,
+        relative: impl AsRef<Path>,
+        content: &[u8],
+        source: FileSource,
+    ) -> Result<Arc<FileInfo>> {
+        FileInfo::with_source(self.file(relative, content)?, source).map(Arc::new)
+    }
+}
+
+/// Permissions are handed back, so that the tree can be removed.
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            for entry in ignore::WalkBuilder::new(&self.root)
+                .standard_filters(false)
+                .build()
+                .filter_map(Result::ok)
+                .filter(|entry| !entry.path_is_symlink())
+            {
+                let _ = fs::set_permissions(entry.path(), fs::Permissions::from_mode(0o755));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Fixture;
+    use anyhow::Result;
+    use std::fs;
+
+    #[test]
+    fn distribution_makes_the_requested_groups() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let groups = fixture.distribution(&[3, 2, 1], 64)?;
+
+        assert_eq!(groups.iter().map(Vec::len).collect::<Vec<_>>(), [3, 2, 1]);
+        let contents = groups
+            .iter()
+            .map(|group| {
+                group
+                    .iter()
+                    .map(fs::read)
+                    .collect::<std::io::Result<Vec<Vec<u8>>>>()
+            })
+            .collect::<std::io::Result<Vec<_>>>()?;
+        assert!(contents
+            .iter()
+            .all(|group| group.iter().all(|c| c == &group[0])));
+        assert_ne!(contents[0][0], contents[1][0]);
+        assert_eq!(contents[0][0].len(), 64);
+
+        let sparse = fixture.sparse("sparse.img", 1 << 20)?;
+        assert_eq!(fs::metadata(sparse)?.len(), 1 << 20);
+        fixture.read_only("group-0/copy-0.bin")?;
+        assert!(fs::metadata(fixture.path("group-0/copy-0.bin"))?
+            .permissions()
+            .readonly());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let link = fixture.symlink("link.bin", "group-1/copy-0.bin")?;
+            assert!(link.starts_with(fixture.root()));
+            assert_eq!(fs::read(link)?, contents[1][0]);
+            fixture.unreadable("copies")?;
+            assert_eq!(
+                fs::metadata(fixture.path("copies"))?.permissions().mode() & 0o777,
+                0
+            );
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,303 @@
+use clap::{parser::ValueSource, ArgMatches, Command};
+
+/// Options of every run: what is scanned, how it is hashed and how the run is watched.
+const COMMON: &[&str] = &[
+    "dir",
+    "more_dirs",
+    "exclude_types",
+    "types",
+    "media_types",
+    "min_size",
+    "defer_large",
+    "max_depth",
+    "min_depth",
+    "hidden",
+    "no_hidden",
+    "follow_links",
+    "one_file_system",
+    "hydrate_and_hash",
+    "scan_archives",
+    "symlinks",
+    "gitignore",
+    "files_from",
+    "null_delimited",
+    "exclude_dir",
+    "include",
+    "exclude",
+    "strict",
+    "prehash_size",
+    "algorithm",
+    "read_strategy",
+    "buffer_size",
+    "threads",
+    "scan_threads",
+    "hash_threads",
+    "large_file_threads",
+    "disk_type",
+    "ignore_metadata",
+    "media_aware",
+    "same_name_only",
+    "intentional",
+    "verify",
+    "verify_threads",
+    "tmpdir",
+    "max_memory",
+    "cache",
+    "cache_invalidate",
+    "seed",
+    "checkpoint",
+    "resume",
+    "checkpoint_interval",
+    "preset",
+    "include_raw",
+    "config",
+    "profile",
+    "alias",
+    "time_format",
+    "log_file",
+    "log_level",
+    "heartbeat",
+    "heartbeat_interval",
+    "dbus",
+    "progress",
+];
+
+/// Options of `scan`: how the duplicates found are reported, nothing is changed.
+const SCAN: &[&str] = &[
+    "roots",
+    "cross_project",
+    "keep",
+    "label",
+    "library",
+    "gallery",
+    "output",
+    "stream",
+    "sort",
+    "reverse",
+    "notes",
+    "snooze",
+    "export",
+    "review",
+    "compare_modes",
+    "root_usage",
+    "usage_view",
+    "images_similar",
+    "similarity_threshold",
+    "notify",
+];
+
+/// Options of `clean`: which copies go and how.
+const CLEAN: &[&str] = &[
+    "roots",
+    "interactive",
+    "link",
+    "dedupe",
+    "delete",
+    "collapse_copies",
+    "trash",
+    "cross_project",
+    "dry_run",
+    "remove_empty_dirs",
+    "verify_keeper",
+    "allow_system_paths",
+    "keep",
+    "label",
+    "library",
+    "sidecars",
+    "output",
+    "sort",
+    "reverse",
+    "notes",
+    "snooze",
+    "export",
+    "review",
+    "apply_plan",
+    "action_log",
+    "notify",
+    "gallery",
+];
+
+/// Options of `compare`: what happens to the staging files already in the target.
+const COMPARE: &[&str] = &[
+    "target_dir",
+    "move_to",
+    "report_unique",
+    "recent_minutes",
+    "trash",
+    "dry_run",
+    "remove_empty_dirs",
+    "allow_system_paths",
+    "export",
+    "action_log",
+    "notify",
+];
+
+/// Subcommands that each show & accept the options of one kind of run, on top of the options
+/// every run shares. They parse into the same `Params` as the bare `deduplicator <dir>`, which
+/// still takes every option at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Focus {
+    Scan,
+    Clean,
+    Compare,
+}
+
+impl Focus {
+    pub const ALL: [Self; 3] = [Self::Scan, Self::Clean, Self::Compare];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Scan => "scan",
+            Self::Clean => "clean",
+            Self::Compare => "compare",
+        }
+    }
+
+    fn about(&self) -> &'static str {
+        match self {
+            Self::Scan => "Find and report duplicates without changing anything (what `deduplicator <dir>` does)",
+            Self::Clean => "Find duplicates and remove, link or clone every copy but one, interactively or by --keep",
+            Self::Compare => "Remove the files of a staging folder (scan_dir_path) that the --target-dir already holds",
+        }
+    }
+
+    fn own_options(&self) -> &'static [&'static str] {
+        match self {
+            Self::Scan => SCAN,
+            Self::Clean => CLEAN,
+            Self::Compare => COMPARE,
+        }
+    }
+
+    fn accepts(&self, id: &str) -> bool {
+        COMMON.contains(&id) || self.own_options().contains(&id)
+    }
+
+    /// Adds a subcommand per focus to `command`. Each holds every option of `command`, so that
+    /// it parses into the same `Params`, but hides & rejects the ones outside its focus.
+    pub fn attach(command: Command) -> Command {
+        let focused = Self::ALL.map(|focus| focus.command(&command));
+        command.subcommands(focused)
+    }
+
+    fn command(&self, full: &Command) -> Command {
+        let args = full
+            .get_arguments()
+            .map(|arg| arg.clone().hide(arg.is_hide_set() || !self.accepts(arg.get_id().as_str())));
+        let command = Command::new(self.name()).about(self.about()).args(args);
+        match self {
+            Self::Compare => command.mut_arg("target_dir", |arg| arg.required(true)),
+            _ => command,
+        }
+    }
+
+    /// The focus chosen on the command line, with the options given to it.
+    pub fn chosen(matches: &ArgMatches) -> Option<(Self, &ArgMatches)> {
+        let (name, options) = matches.subcommand()?;
+        let focus = Self::ALL.into_iter().find(|focus| focus.name() == name)?;
+        Some((focus, options))
+    }
+
+    /// Why the options given do not fit the focus, if they do not: options outside it, or
+    /// options of `command` given before the subcommand, which it would not see.
+    pub fn misfit(&self, command: &Command, top: &ArgMatches, options: &ArgMatches) -> Option<String> {
+        let given = |matches: &ArgMatches, id: &str| {
+            matches.value_source(id) == Some(ValueSource::CommandLine)
+        };
+        let flag = |id: &str| {
+            command
+                .get_arguments()
+                .find(|arg| arg.get_id() == id)
+                .and_then(|arg| arg.get_long())
+                .map_or_else(|| id.to_string(), |long| format!("--{long}"))
+        };
+        if let Some(id) = top.ids().find(|id| given(top, id.as_str())) {
+            return Some(format!(
+                "{} is given before `{}`, options go after the subcommand",
+                flag(id.as_str()),
+                self.name()
+            ));
+        }
+        options
+            .ids()
+            .find(|id| given(options, id.as_str()) && !self.accepts(id.as_str()))
+            .map(|id| {
+                let fits = Self::ALL
+                    .iter()
+                    .filter(|focus| focus.accepts(id.as_str()))
+                    .map(|focus| format!("`{}`", focus.name()))
+                    .collect::<Vec<String>>();
+                match fits.is_empty() {
+                    true => format!("{} is not an option of `{}`", flag(id.as_str()), self.name()),
+                    false => format!(
+                        "{} is not an option of `{}`, but of {}",
+                        flag(id.as_str()),
+                        self.name(),
+                        fits.join(" & ")
+                    ),
+                }
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Focus, CLEAN, COMMON, COMPARE, SCAN};
+    use crate::params::Params;
+    use clap::{error::ErrorKind, CommandFactory};
+
+    #[test]
+    fn every_option_belongs_to_a_focus() {
+        let command = Params::command();
+        let ids = command
+            .get_arguments()
+            .map(|arg| arg.get_id().as_str())
+            .collect::<Vec<&str>>();
+
+        for id in COMMON.iter().chain(SCAN).chain(CLEAN).chain(COMPARE) {
+            assert!(ids.contains(id), "{id} is not an option");
+        }
+        // NOTE: comparison_mode is what `compare` sets, the other foci reject it.
+        for id in ids.iter().filter(|id| **id != "comparison_mode") {
+            assert!(
+                Focus::ALL.iter().any(|focus| focus.accepts(id)),
+                "{id} belongs to no focus"
+            );
+        }
+        // NOTE: fields clap skips, like the event subscribers, are set through the API only.
+        assert!(!ids.contains(&"events"));
+    }
+
+    #[test]
+    fn focused_subcommands_parse_like_the_bare_command() {
+        let params = Params::load_from(["deduplicator", "scan", "/tmp", "--strict", "--sort", "size"], None);
+        assert_eq!(params.dir.as_deref(), Some(std::path::Path::new("/tmp")));
+        assert!(params.strict);
+        assert!(params.command.is_none());
+
+        let params = Params::load_from(["deduplicator", "compare", "/tmp/a", "--target-dir", "/tmp/b"], None);
+        assert!(params.comparison_mode);
+
+        let command = Focus::attach(Params::command());
+        let misfit = |args: &[&str]| {
+            let matches = command.clone().get_matches_from(args);
+            let (focus, options) = Focus::chosen(&matches).unwrap();
+            focus.misfit(&command, &matches, options)
+        };
+        assert_eq!(
+            misfit(&["deduplicator", "scan", "/tmp", "--delete"]).as_deref(),
+            Some("--delete is not an option of `scan`, but of `clean`")
+        );
+        assert!(misfit(&["deduplicator", "--strict", "clean", "/tmp"]).is_some());
+        assert_eq!(
+            misfit(&["deduplicator", "compare", "/tmp/a", "--target-dir", "/tmp/b", "--root", "/tmp"]).as_deref(),
+            Some("--root is not an option of `compare`, but of `scan` & `clean`")
+        );
+        let conflict = command
+            .clone()
+            .try_get_matches_from(["deduplicator", "/tmp/a", "--comparison-mode", "--root", "/tmp"])
+            .map_err(|e| e.kind());
+        assert_eq!(conflict.err(), Some(ErrorKind::ArgumentConflict));
+        assert_eq!(misfit(&["deduplicator", "clean", "/tmp", "--delete", "--keep", "newest"]), None);
+    }
+}
@@ -1,80 +1,812 @@
-use crate::{fileinfo::FileInfo, params::Params};
+use crate::{
+    fileinfo::FileInfo,
+    filetype::FileType,
+    params::{GroupOrder, OutputFormat, Params, TimeFormat},
+    processor::ConfirmedGroup,
+    similar::SimilarGroup,
+};
 use anyhow::Result;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, SecondsFormat, Utc};
 use dashmap::DashMap;
 use pathdiff::diff_paths;
 use rayon::prelude::*;
-use std::sync::atomic::AtomicU64;
-use std::{path::PathBuf, sync::Arc};
+use serde::Serialize;
+use std::{
+    borrow::Cow,
+    cmp::Reverse,
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{mpsc::Receiver, Arc},
+    time::SystemTime,
+};
 
 const YELLOW: &str = "\x1b[33m";
 const RESET: &str = "\x1b[0m";
+/// Columns of the csv & tsv listings.
+const DELIMITED_HEADER: [&str; 6] = ["group", "hash", "path", "size", "mtime", "label"];
+
+/// A duplicate group as a line of `--output jsonl`.
+#[derive(Serialize)]
+struct JsonGroup<'a> {
+    hash: String,
+    size: u64,
+    files: Vec<Cow<'a, str>>,
+    /// The --label root of each file, in the order of `files`, when the run names any.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    labels: Vec<Option<&'a str>>,
+}
 
 pub struct Formatter;
 impl Formatter {
+    /// Replaces the longest matching `--alias` prefix of `path` with its label.
+    pub fn aliased_path(path: &Path, aargs: &Params) -> Option<PathBuf> {
+        aargs
+            .alias
+            .iter()
+            .filter(|alias| path.starts_with(&alias.prefix))
+            .max_by_key(|alias| alias.prefix.components().count())
+            .and_then(|alias| {
+                path.strip_prefix(&alias.prefix)
+                    .ok()
+                    .map(|rest| Path::new(&alias.label).join(rest))
+            })
+    }
+
     pub fn human_path(file: &FileInfo, aargs: &Params, max_path_length: usize) -> Result<String> {
-        let base_directory: PathBuf = aargs.get_directory()?;
-        let relative_path = diff_paths(&file.path, base_directory).unwrap_or_default();
+        let display_path = match Self::aliased_path(&file.path, aargs) {
+            Some(aliased) => aliased,
+            None => {
+                let base_directory: PathBuf = match aargs.scan_root_of(&file.path) {
+                    Some(root) => root,
+                    None => aargs.get_directory()?,
+                };
+                diff_paths(&file.path, base_directory).unwrap_or_default()
+            }
+        };
 
         let formatted_path = format!(
             "{:<0width$}",
-            relative_path.to_str().unwrap_or_default().to_string(),
+            display_path.to_str().unwrap_or_default().to_string(),
             width = max_path_length
         );
 
         Ok(formatted_path)
     }
 
+    /// `[name]` of the `--label` root `file` is in, `-` outside of them. Without labels, the
+    /// `[dir]` of the scan root it came from when several are scanned, `None` otherwise.
+    pub fn human_label(file: &FileInfo, aargs: &Params) -> Option<String> {
+        if aargs.label.is_empty() {
+            return aargs
+                .scan_root_of(&file.path)
+                .map(|root| format!("[{}]", root.display()));
+        }
+        Some(match aargs.label_of(&file.path) {
+            Some(name) => format!("[{name}]"),
+            None => "-".to_string(),
+        })
+    }
+
     pub fn human_filesize(file: &FileInfo) -> Result<String> {
         Ok(format!("{:>12}", bytesize::ByteSize::b(file.size)))
     }
 
-    pub fn human_mtime(file: &FileInfo) -> Result<String> {
-        let modified_time: DateTime<Utc> = file.modified.into();
-        Ok(modified_time.format("%Y-%m-%d %H:%M:%S").to_string())
+    pub fn human_mtime(file: &FileInfo, aargs: &Params) -> Result<String> {
+        Ok(Self::format_time(file.modified, &aargs.time_format, SystemTime::now()))
+    }
+
+    pub fn format_time(time: SystemTime, format: &TimeFormat, now: SystemTime) -> String {
+        let datetime: DateTime<Utc> = time.into();
+        match format {
+            TimeFormat::Iso => datetime.to_rfc3339_opts(SecondsFormat::Secs, true),
+            TimeFormat::Strftime(pattern) => datetime.format(pattern).to_string(),
+            TimeFormat::Relative => match now.duration_since(time) {
+                Ok(age) => {
+                    let seconds = age.as_secs();
+                    let (amount, unit) = match seconds {
+                        0..60 => return "just now".to_string(),
+                        60..3600 => (seconds / 60, "minute"),
+                        3600..86400 => (seconds / 3600, "hour"),
+                        86400..2592000 => (seconds / 86400, "day"),
+                        2592000..31536000 => (seconds / 2592000, "month"),
+                        _ => (seconds / 31536000, "year"),
+                    };
+                    let plural = if amount == 1 { "" } else { "s" };
+                    format!("{amount} {unit}{plural} ago")
+                }
+                Err(_) => "in the future".to_string(),
+            },
+        }
+    }
+
+    /// `file://` URI of an absolute path, percent-encoding every byte outside the unreserved set.
+    pub fn file_uri(path: &Path) -> String {
+        let encoded = path
+            .as_os_str()
+            .as_encoded_bytes()
+            .iter()
+            .map(|byte| match byte {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                    (*byte as char).to_string()
+                }
+                _ => format!("%{byte:02X}"),
+            })
+            .collect::<String>();
+
+        format!("file://{encoded}")
+    }
+
+    /// `text/uri-list` of every duplicate group, each group introduced by a comment line.
+    pub fn uri_list(raw: &DashMap<u128, Vec<Arc<FileInfo>>>, aargs: &Params) -> String {
+        Self::ordered(raw, aargs)
+            .iter()
+            .map(|(hash, files)| Self::uri_group(*hash, files))
+            .collect()
+    }
+
+    fn uri_group(hash: u128, files: &[Arc<FileInfo>]) -> String {
+        let uris = files
+            .iter()
+            .map(|file| format!("{}\r\n", Self::file_uri(&file.path)))
+            .collect::<String>();
+        format!("# {hash:032x}\r\n{uris}")
+    }
+
+    /// Every duplicate group as its paths, one per line, followed by a blank line: what fdupes
+    /// & jdupes print, for the scripts written against them.
+    pub fn fdupes(raw: &DashMap<u128, Vec<Arc<FileInfo>>>, aargs: &Params) -> String {
+        Self::ordered(raw, aargs)
+            .iter()
+            .map(|(_hash, files)| Self::fdupes_group(files))
+            .collect()
+    }
+
+    fn fdupes_group(files: &[Arc<FileInfo>]) -> String {
+        let lines = files
+            .iter()
+            .map(|file| format!("{}\n", file.path.display()))
+            .collect::<String>();
+        format!("{lines}\n")
+    }
+
+    /// Every duplicate group as a JSON object of its hash, size in bytes & paths, one per line.
+    pub fn json_lines(raw: &DashMap<u128, Vec<Arc<FileInfo>>>, aargs: &Params) -> Result<String> {
+        Self::ordered(raw, aargs)
+            .iter()
+            .map(|(hash, files)| Self::json_line(*hash, files, aargs))
+            .collect()
+    }
+
+    fn json_line(hash: u128, files: &[Arc<FileInfo>], aargs: &Params) -> Result<String> {
+        let labels = match aargs.label.is_empty() {
+            true => vec![],
+            false => files.iter().map(|file| aargs.label_of(&file.path)).collect(),
+        };
+        let group = JsonGroup {
+            hash: format!("{hash:032x}"),
+            size: files.first().map(|file| file.size).unwrap_or_default(),
+            files: files.iter().map(|file| file.path.to_string_lossy()).collect(),
+            labels,
+        };
+        Ok(format!("{}\n", serde_json::to_string(&group)?))
+    }
+
+    /// Every duplicate group, files sorted by path, in the `--sort` order. Groups tied on the
+    /// sort key stay in the order of their paths.
+    pub fn ordered(
+        raw: &DashMap<u128, Vec<Arc<FileInfo>>>,
+        aargs: &Params,
+    ) -> Vec<(u128, Vec<Arc<FileInfo>>)> {
+        let mut groups = raw
+            .iter()
+            .filter(|group| group.value().len() > 1)
+            .map(|group| {
+                let mut files = group.value().clone();
+                files.sort_by(|a, b| a.path.cmp(&b.path));
+                (*group.key(), files)
+            })
+            .collect::<Vec<(u128, Vec<Arc<FileInfo>>)>>();
+        groups.sort_by(|a, b| a.1.iter().map(|f| &f.path).cmp(b.1.iter().map(|f| &f.path)));
+
+        let size = |files: &[Arc<FileInfo>]| files.first().map(|f| f.size).unwrap_or_default();
+        match aargs.sort {
+            GroupOrder::Path => {}
+            GroupOrder::Size => groups.sort_by_key(|(_, files)| Reverse(size(files))),
+            GroupOrder::Count => groups.sort_by_key(|(_, files)| Reverse(files.len())),
+            GroupOrder::Wasted => groups
+                .sort_by_key(|(_, files)| Reverse(size(files) * (files.len() as u64 - 1))),
+        }
+        if aargs.reverse {
+            groups.reverse();
+        }
+        groups
+    }
+
+    /// Every file of every duplicate group as a row of `separator`-separated columns, under a
+    /// header: group number, hash, path, size in bytes, ISO 8601 mtime and --label root (empty
+    /// outside of them), in the `ordered` order.
+    pub fn delimited(
+        raw: &DashMap<u128, Vec<Arc<FileInfo>>>,
+        separator: char,
+        aargs: &Params,
+    ) -> String {
+        let now = SystemTime::now();
+        let mut rows = Self::delimited_row(DELIMITED_HEADER, separator);
+        for (index, (hash, files)) in Self::ordered(raw, aargs).iter().enumerate() {
+            rows.push_str(&Self::delimited_group(index + 1, *hash, files, separator, now, aargs));
+        }
+        rows
+    }
+
+    fn delimited_group(
+        number: usize,
+        hash: u128,
+        files: &[Arc<FileInfo>],
+        separator: char,
+        now: SystemTime,
+        aargs: &Params,
+    ) -> String {
+        files
+            .iter()
+            .map(|file| {
+                Self::delimited_row(
+                    [
+                        &number.to_string(),
+                        &format!("{hash:032x}"),
+                        &file.path.to_string_lossy(),
+                        &file.size.to_string(),
+                        &Self::format_time(file.modified, &TimeFormat::Iso, now),
+                        aargs.label_of(&file.path).unwrap_or_default(),
+                    ],
+                    separator,
+                )
+            })
+            .collect()
+    }
+
+    fn delimited_row(fields: [&str; 6], separator: char) -> String {
+        let fields = fields.map(|field| Self::delimited_field(field, separator));
+        format!("{}\n", fields.join(&separator.to_string()))
+    }
+
+    /// CSV fields holding the separator, a quote or a line break are quoted (RFC 4180); TSV has
+    /// no quoting, so tabs, line breaks & backslashes are escaped with a backslash instead.
+    fn delimited_field(field: &str, separator: char) -> String {
+        match separator {
+            '\t' => field
+                .replace('\\', "\\\\")
+                .replace('\t', "\\t")
+                .replace('\n', "\\n")
+                .replace('\r', "\\r"),
+            _ if field.contains([separator, '"', '\n', '\r']) => {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            }
+            _ => field.to_string(),
+        }
+    }
+
+    /// The album a file belongs to: the two folders above it, e.g. `Artist / Album`.
+    pub fn album(path: &Path, aargs: &Params) -> Vec<String> {
+        let folder = path.parent().unwrap_or(Path::new(""));
+        let folder = aargs
+            .get_directory()
+            .ok()
+            .and_then(|base| diff_paths(folder, base))
+            .unwrap_or_else(|| folder.to_path_buf());
+        let names = folder
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy().to_string())
+            .collect::<Vec<String>>();
+
+        names[names.len().saturating_sub(2)..].to_vec()
+    }
+
+    /// Duplicate groups under a heading per album (see `album`), taken from the copy filed
+    /// deepest, as that is usually the one sorted into the library. The kept copy is listed
+    /// first.
+    pub fn albums(raw: &DashMap<u128, Vec<Arc<FileInfo>>>, aargs: &Params) -> String {
+        let mut albums: BTreeMap<Vec<String>, Vec<Vec<Arc<FileInfo>>>> = BTreeMap::new();
+        for group in raw.iter().filter(|group| group.value().len() > 1) {
+            let mut files = group.value().clone();
+            files.sort_by(|a, b| a.path.cmp(&b.path));
+            let kept = files.remove(aargs.keeper(&files).unwrap_or_default());
+            files.insert(0, kept);
+            let album = files
+                .iter()
+                .rev()
+                .map(|file| Self::album(&file.path, aargs))
+                .max_by_key(Vec::len)
+                .unwrap_or_default();
+            albums.entry(album).or_default().push(files);
+        }
+
+        albums
+            .into_iter()
+            .map(|(album, mut groups)| {
+                let heading = match album.is_empty() {
+                    true => "(top level)".to_string(),
+                    false => album.join(" / "),
+                };
+                groups.sort_by(|a, b| a[0].path.cmp(&b[0].path));
+                let listing = groups
+                    .iter()
+                    .map(|group| {
+                        group
+                            .iter()
+                            .enumerate()
+                            .map(|(i, file)| {
+                                let role = match i {
+                                    0 => "keep",
+                                    _ => "duplicate",
+                                };
+                                format!(
+                                    "  {}\t{}\t{}\t{role}{}\n",
+                                    if i == group.len() - 1 { "└─" } else { "├─" },
+                                    Self::aliased_path(&file.path, aargs)
+                                        .unwrap_or_else(|| file.path.to_path_buf())
+                                        .display(),
+                                    bytesize::ByteSize::b(file.size),
+                                    Self::human_label(file, aargs)
+                                        .map(|label| format!("\t{label}"))
+                                        .unwrap_or_default()
+                                )
+                            })
+                            .collect::<String>()
+                    })
+                    .collect::<Vec<String>>()
+                    .join("\n");
+                format!("{YELLOW}{heading}{RESET}\n{listing}\n")
+            })
+            .collect()
     }
 
-    pub fn print(raw: Arc<DashMap<u128, Vec<FileInfo>>>, max_path_len: u64, aargs: &Params) {
+    pub fn print(raw: Arc<DashMap<u128, Vec<Arc<FileInfo>>>>, max_path_len: u64, aargs: &Params) {
+        match aargs.output {
+            OutputFormat::UriList => return print!("{}", Self::uri_list(&raw, aargs)),
+            OutputFormat::Csv => return print!("{}", Self::delimited(&raw, ',', aargs)),
+            OutputFormat::Tsv => return print!("{}", Self::delimited(&raw, '\t', aargs)),
+            OutputFormat::Fdupes => return print!("{}", Self::fdupes(&raw, aargs)),
+            OutputFormat::Jsonl => {
+                return print!("{}", Self::json_lines(&raw, aargs).expect("JSON formatting failed."))
+            }
+            OutputFormat::Albums if raw.iter().any(|group| group.value().len() > 1) => {
+                return print!("\n{}", Self::albums(&raw, aargs))
+            }
+            _ => {}
+        }
+
         print!("{}", "\n".repeat(if aargs.progress { 2 } else { 1 })); // spacing
 
-        if raw.is_empty() {
+        let groups = Self::ordered(&raw, aargs);
+        if groups.is_empty() {
             println!("No duplicates found matching your search criteria.");
-        } else {
-            let printed_count: AtomicU64 = AtomicU64::new(0);
-
-            raw.par_iter().for_each(|sref| {
-                if sref.value().len() > 1 {
-                    printed_count.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-                    let mut ostring = format!("{}{:32x}{}\n", YELLOW, sref.key(), RESET);
-                    let subfields = sref
-                        .value()
-                        .par_iter()
-                        .enumerate()
-                        .map(|(i, finfo)| {
-                            let nodechar = if i == sref.value().len() - 1 {
-                                "└─"
-                            } else {
-                                "├─"
-                            };
-                            format!(
-                                "{}\t{}\t{}\t{}\n",
-                                nodechar,
-                                Self::human_path(finfo, aargs, max_path_len as usize)
-                                    .expect("path formatting failed."),
-                                Self::human_filesize(finfo).expect("filesize formatting failed."),
-                                Self::human_mtime(finfo).expect("modified time formatting failed.")
-                            )
-                        })
-                        .collect::<String>();
-
-                    ostring.push_str(&subfields);
-                    println!("{ostring}");
-                }
-            });
+            return;
+        }
+
+        // NOTE: formatted in parallel, printed in order.
+        let listings = groups
+            .par_iter()
+            .map(|(hash, files)| Self::text_group(*hash, files, max_path_len as usize, aargs))
+            .collect::<Vec<String>>();
+        listings.iter().for_each(|listing| println!("{listing}"));
+    }
+
+    fn text_group(
+        hash: u128,
+        files: &[Arc<FileInfo>],
+        max_path_len: usize,
+        aargs: &Params,
+    ) -> String {
+        let content_type = files
+            .first()
+            .and_then(|file| FileType::mime_type(&file.path))
+            .map(|mime| format!("\t{mime}"))
+            .unwrap_or_default();
+        let mut ostring = format!("{}{:32x}{}{content_type}\n", YELLOW, hash, RESET);
+        let subfields = files
+            .iter()
+            .enumerate()
+            .map(|(i, finfo)| {
+                let nodechar = if i == files.len() - 1 { "└─" } else { "├─" };
+                format!(
+                    "{}\t{}\t{}\t{}{}\n",
+                    nodechar,
+                    Self::human_path(finfo, aargs, max_path_len).expect("path formatting failed."),
+                    Self::human_filesize(finfo).expect("filesize formatting failed."),
+                    Self::human_mtime(finfo, aargs).expect("modified time formatting failed."),
+                    Self::human_label(finfo, aargs)
+                        .map(|label| format!("\t{label}"))
+                        .unwrap_or_default()
+                )
+            })
+            .collect::<String>();
 
-            if printed_count.load(std::sync::atomic::Ordering::Relaxed) < 1 {
-                println!("No duplicates found matching your search criteria.");
+        ostring.push_str(&subfields);
+        ostring
+    }
+
+    /// `--images-similar`: the groups of similar images, under a heading of their own so that
+    /// they are not taken for exact duplicates. Each image shows how many bits its perceptual
+    /// hash is off the first one's.
+    pub fn similar(groups: &[SimilarGroup], aargs: &Params) -> String {
+        if groups.is_empty() {
+            return String::new();
+        }
+        let width = groups
+            .iter()
+            .flatten()
+            .filter_map(|(file, _)| Self::human_path(file, aargs, 0).ok())
+            .map(|path| path.len())
+            .max()
+            .unwrap_or_default();
+        let listings = groups
+            .iter()
+            .map(|group| {
+                let files = group
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (file, distance))| {
+                        format!(
+                            "{}\t{}\t{}\t{distance:>2} bit(s) off\n",
+                            if i == group.len() - 1 { "└─" } else { "├─" },
+                            Self::human_path(file, aargs, width).expect("path formatting failed."),
+                            Self::human_filesize(file).expect("filesize formatting failed."),
+                        )
+                    })
+                    .collect::<String>();
+                format!("{YELLOW}similar images{RESET}\n{files}")
+            })
+            .collect::<Vec<String>>();
+        format!(
+            "\nSimilar images, not byte for byte duplicates (at most {} of 64 bits apart):\n\n{}\n",
+            aargs.similarity_threshold,
+            listings.join("\n")
+        )
+    }
+
+    /// `--stream`: prints every group `accept`ed as soon as the pipeline confirms it, in the
+    /// `--output` format, until hashing is over. Groups come in the order they are confirmed,
+    /// so `--sort` does not apply; the csv & tsv group numbers count them in that order.
+    /// Returns the number of groups printed.
+    pub fn stream(
+        groups: Receiver<ConfirmedGroup>,
+        accept: impl Fn(u128, &[Arc<FileInfo>]) -> bool,
+        aargs: &Params,
+    ) -> Result<usize> {
+        let now = SystemTime::now();
+        match aargs.output {
+            OutputFormat::Csv => print!("{}", Self::delimited_row(DELIMITED_HEADER, ',')),
+            OutputFormat::Tsv => print!("{}", Self::delimited_row(DELIMITED_HEADER, '\t')),
+            OutputFormat::Text | OutputFormat::Albums => println!(), // spacing
+            _ => {}
+        }
+
+        let mut printed = 0;
+        for (hash, mut files) in groups {
+            if !accept(hash, &files) {
+                continue;
+            }
+            printed += 1;
+            files.sort_by(|a, b| a.path.cmp(&b.path));
+            match aargs.output {
+                OutputFormat::UriList => print!("{}", Self::uri_group(hash, &files)),
+                OutputFormat::Csv => {
+                    print!("{}", Self::delimited_group(printed, hash, &files, ',', now, aargs))
+                }
+                OutputFormat::Tsv => {
+                    print!("{}", Self::delimited_group(printed, hash, &files, '\t', now, aargs))
+                }
+                OutputFormat::Fdupes => print!("{}", Self::fdupes_group(&files)),
+                OutputFormat::Jsonl => print!("{}", Self::json_line(hash, &files, aargs)?),
+                // NOTE: albums are only complete at the end of the run, `Params` rules them out.
+                OutputFormat::Text | OutputFormat::Albums => {
+                    let width = files
+                        .iter()
+                        .filter_map(|file| Self::human_path(file, aargs, 0).ok())
+                        .map(|path| path.chars().count())
+                        .max()
+                        .unwrap_or_default();
+                    println!("{}", Self::text_group(hash, &files, width, aargs));
+                }
             }
         }
+
+        if printed == 0 && !aargs.output.machine_readable() {
+            println!("No duplicates found matching your search criteria.");
+        }
+        Ok(printed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Formatter;
+    use crate::fileinfo::FileInfo;
+    use crate::fixture::Fixture;
+    use crate::params::{GroupOrder, Params, PathAlias, RootLabel, TimeFormat};
+    use dashmap::DashMap;
+    use std::{
+        path::{Path, PathBuf},
+        sync::Arc,
+        time::{Duration, SystemTime},
+    };
+
+    #[test]
+    fn longest_alias_prefix_wins() {
+        let params = Params {
+            alias: vec![
+                "/mnt/remote=REMOTE".parse::<PathAlias>().unwrap(),
+                "/mnt/remote/backup=NAS".parse::<PathAlias>().unwrap(),
+            ],
+            ..Default::default()
+        };
+
+        assert_eq!(
+            Formatter::aliased_path(Path::new("/mnt/remote/backup/2024/a.jpg"), &params),
+            Some(PathBuf::from("NAS/2024/a.jpg"))
+        );
+        assert_eq!(
+            Formatter::aliased_path(Path::new("/mnt/remote/other/a.jpg"), &params),
+            Some(PathBuf::from("REMOTE/other/a.jpg"))
+        );
+        assert_eq!(
+            Formatter::aliased_path(Path::new("/home/me/a.jpg"), &params),
+            None
+        );
+    }
+
+    #[test]
+    fn labelled_roots_tag_rows_and_choose_the_keeper() -> anyhow::Result<()> {
+        let fixture = Fixture::new()?;
+        let file = |name: &str| fixture.scanned(name, b"same");
+        let group = vec![file("a/photo.jpg")?, file("z/photo.jpg")?, file("loose.jpg")?];
+        let params = Params::load_from(
+            [
+                "deduplicator".to_string(),
+                "--label".to_string(),
+                format!("archive={}", fixture.path("a").display()),
+                "--label".to_string(),
+                format!("backup={}", fixture.path("z").display()),
+                "--keep".to_string(),
+                "label=backup".to_string(),
+            ],
+            None,
+        );
+
+        assert_eq!(params.keeper(&group), Some(1));
+        assert_eq!(Formatter::human_label(&group[0], &params).as_deref(), Some("[archive]"));
+        assert_eq!(Formatter::human_label(&group[2], &params).as_deref(), Some("-"));
+        assert_eq!(Formatter::human_label(&group[2], &Params::default()), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn files_of_several_roots_are_tagged_with_their_root() -> anyhow::Result<()> {
+        let fixture = Fixture::new()?;
+        let a = fixture.scanned("pictures/a.jpg", b"same")?;
+        let b = fixture.scanned("backup/a.jpg", b"same")?;
+        let (pictures, backup) = (fixture.path("pictures"), fixture.path("backup"));
+        let params = Params::load_from(
+            [
+                "deduplicator".into(),
+                pictures.clone().into_os_string(),
+                backup.clone().into_os_string(),
+            ],
+            None,
+        );
+
+        assert_eq!(params.scan_roots()?.len(), 2);
+        assert_eq!(
+            Formatter::human_label(&b, &params),
+            Some(format!("[{}]", backup.display()))
+        );
+        assert_eq!(Formatter::human_path(&a, &params, 0)?, "a.jpg");
+        assert_eq!(Formatter::human_path(&b, &params, 0)?, "a.jpg");
+
+        let nested = Params::load_from(
+            [
+                "deduplicator".into(),
+                pictures.clone().into_os_string(),
+                pictures.into_os_string(),
+            ],
+            None,
+        );
+        assert!(nested.scan_roots().is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn formats_times_as_iso_relative_or_strftime() {
+        let time = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let now = time + Duration::from_secs(3 * 86400 + 5);
+
+        assert_eq!(
+            Formatter::format_time(time, &TimeFormat::Iso, now),
+            "2023-11-14T22:13:20Z"
+        );
+        assert_eq!(
+            Formatter::format_time(time, &TimeFormat::Relative, now),
+            "3 days ago"
+        );
+        assert_eq!(
+            Formatter::format_time(time, &"%d/%m/%Y".parse::<TimeFormat>().unwrap(), now),
+            "14/11/2023"
+        );
+        assert!("%Q".parse::<TimeFormat>().is_err());
+    }
+
+    #[test]
+    fn delimited_rows_quote_or_escape_awkward_paths() -> anyhow::Result<()> {
+        let fixture = Fixture::new()?;
+        let file = |name: &str| fixture.scanned(name, b"same");
+
+        let store = DashMap::new();
+        store.insert(7u128, vec![file("b,\"c\".txt")?, file("a\tb.txt")?]);
+        store.insert(8u128, vec![file("single.txt")?]);
+
+        let csv = Formatter::delimited(&store, ',', &Params::default());
+        let lines = csv.lines().collect::<Vec<&str>>();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "group,hash,path,size,mtime,label");
+        assert!(lines[1].starts_with(&format!("1,{:032x},{}", 7u128, fixture.root().display())));
+        assert!(lines[2].contains("/b,\"\"c\"\".txt\",4,"));
+
+        let tsv = Formatter::delimited(&store, '\t', &Params::default());
+        assert_eq!(tsv.lines().next(), Some("group\thash\tpath\tsize\tmtime\tlabel"));
+        assert!(tsv.contains("/a\\tb.txt\t4\t"));
+        assert_eq!(tsv.lines().count(), 3);
+
+        let labelled = Params {
+            label: vec![RootLabel {
+                name: "root".to_string(),
+                root: fixture.root().to_path_buf(),
+            }],
+            ..Default::default()
+        };
+        let csv = Formatter::delimited(&store, ',', &labelled);
+        assert!(csv.lines().skip(1).all(|line| line.ends_with(",root")));
+        let json = Formatter::json_lines(&store, &labelled)?;
+        assert!(json.contains(r#""labels":["root","root"]"#));
+        assert!(!Formatter::json_lines(&store, &Params::default())?.contains("labels"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn groups_are_ordered_by_the_sort_key() -> anyhow::Result<()> {
+        let fixture = Fixture::new()?;
+        let group = |name: &str, copies: usize, size: usize| -> anyhow::Result<Vec<Arc<FileInfo>>> {
+            (0..copies)
+                .map(|copy| fixture.scanned(format!("{name}{copy}"), name.repeat(size).as_bytes()))
+                .collect()
+        };
+
+        let store = DashMap::new();
+        store.insert(1u128, group("a", 2, 100)?);
+        store.insert(2u128, group("b", 5, 10)?);
+        store.insert(3u128, group("c", 3, 60)?);
+        let order = |sort: GroupOrder, reverse: bool| {
+            let params = Params {
+                sort,
+                reverse,
+                ..Default::default()
+            };
+            Formatter::ordered(&store, &params)
+                .into_iter()
+                .map(|(hash, _)| hash)
+                .collect::<Vec<u128>>()
+        };
+
+        assert_eq!(order(GroupOrder::Path, false), [1, 2, 3]);
+        assert_eq!(order(GroupOrder::Size, false), [1, 3, 2]);
+        assert_eq!(order(GroupOrder::Count, false), [2, 3, 1]);
+        assert_eq!(order(GroupOrder::Wasted, false), [3, 1, 2]);
+        assert_eq!(order(GroupOrder::Wasted, true), [2, 1, 3]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn fdupes_output_separates_groups_with_blank_lines() -> anyhow::Result<()> {
+        let fixture = Fixture::new()?;
+
+        let store = DashMap::new();
+        let pair = |name: &str, other: &str, content: &[u8]| -> anyhow::Result<Vec<_>> {
+            Ok(vec![fixture.scanned(name, content)?, fixture.scanned(other, content)?])
+        };
+        store.insert(1u128, pair("d.txt", "c.txt", b"one")?);
+        store.insert(2u128, pair("a.txt", "b c.txt", b"two")?);
+        store.insert(3u128, vec![fixture.scanned("single.txt", b"three")?]);
+
+        let path = |name: &str| fixture.path(name).display().to_string();
+        assert_eq!(
+            Formatter::fdupes(&store, &Params::default()),
+            format!(
+                "{}\n{}\n\n{}\n{}\n\n",
+                path("a.txt"),
+                path("b c.txt"),
+                path("c.txt"),
+                path("d.txt")
+            )
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn uri_list_percent_encodes_paths_and_separates_groups() -> anyhow::Result<()> {
+        let fixture = Fixture::new()?;
+        let file = |name: &str| fixture.scanned(name, b"same");
+
+        let store = DashMap::new();
+        store.insert(1u128, vec![file("b c.txt")?, file("ä.txt")?]);
+        store.insert(2u128, vec![file("single.txt")?]);
+
+        let base = Formatter::file_uri(fixture.root());
+        assert_eq!(
+            Formatter::uri_list(&store, &Params::default()),
+            format!(
+                "# {:032x}\r\n{base}/b%20c.txt\r\n{base}/%C3%A4.txt\r\n",
+                1u128
+            )
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn albums_file_groups_under_the_deepest_folders() {
+        let params = Params {
+            dir: Some(PathBuf::from("/")),
+            ..Default::default()
+        };
+        let file = |path: &str| {
+            Arc::new(FileInfo {
+                path: PathBuf::from(path).into_boxed_path(),
+                size: 4,
+                modified: SystemTime::UNIX_EPOCH,
+                state: Arc::new(std::sync::Mutex::new(crate::fileinfo::FileState::Unprocessed)),
+                source: None,
+                payload: None,
+            })
+        };
+
+        let store = DashMap::new();
+        store.insert(
+            1u128,
+            vec![file("/music/Artist/Album/01.mp3"), file("/inbox/01 (1).mp3")],
+        );
+        let albums = Formatter::albums(&store, &params);
+
+        assert!(albums.starts_with("\x1b[33mArtist / Album\x1b[0m\n"));
+        assert!(albums.contains("/inbox/01 (1).mp3\t4 B\tkeep"));
+        assert!(Formatter::album(Path::new("/top.mp3"), &params).is_empty());
+    }
+
+    #[test]
+    fn json_lines_hold_one_group_each() -> anyhow::Result<()> {
+        let file = |path: &str| {
+            Arc::new(FileInfo {
+                path: PathBuf::from(path).into_boxed_path(),
+                size: 4,
+                modified: SystemTime::UNIX_EPOCH,
+                state: Arc::new(std::sync::Mutex::new(crate::fileinfo::FileState::Unprocessed)),
+                source: None,
+                payload: None,
+            })
+        };
+        let store = DashMap::new();
+        store.insert(0xabu128, vec![file("/b \"quoted\".txt"), file("/a.txt")]);
+        store.insert(2u128, vec![file("/lone.txt")]);
+
+        let lines = Formatter::json_lines(&store, &Params::default())?;
+
+        assert_eq!(
+            lines,
+            "{\"hash\":\"000000000000000000000000000000ab\",\"size\":4,\"files\":[\"/a.txt\",\"/b \\\"quoted\\\".txt\"]}\n"
+        );
+        Ok(())
     }
 }
@@ -0,0 +1,165 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use std::{fmt::Write, fs, path::Path, sync::Arc};
+
+use crate::{
+    fileinfo::FileInfo,
+    filetype::{FileType, MediaType},
+    formatter::Formatter,
+    params::Params,
+    protection::Protection,
+    runid::RunId,
+};
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em;background:#f4f4f4}\
+section{background:#fff;border-radius:6px;padding:1em;margin-bottom:1.5em}\
+.copies{display:flex;flex-wrap:wrap;gap:1em}\
+figure{margin:0;width:220px;border:3px solid #ccc;border-radius:4px;padding:6px}\
+figure.keep{border-color:#2e8b57}figure.remove{border-color:#c0392b;opacity:.75}\
+img,.placeholder{width:100%;height:160px;object-fit:contain;background:#eee}\
+.placeholder{display:flex;align-items:center;justify-content:center;color:#777}\
+figcaption{font-size:12px;word-break:break-all}\
+.label{font-weight:bold}.keep .label{color:#2e8b57}.remove .label{color:#c0392b}";
+
+/// Self-contained HTML page of the duplicate groups (see `--gallery`): every copy is shown as a
+/// thumbnail, the one `--keep` (and `--library`) would keep marked apart from those it would
+/// remove, for review in a browser before anything is deleted.
+pub struct Gallery;
+
+impl Gallery {
+    /// Writes the gallery to `path` and returns the number of copies proposed for removal.
+    pub fn write(
+        store: &DashMap<u128, Vec<Arc<FileInfo>>>,
+        app_args: &Params,
+        path: &Path,
+    ) -> Result<u64> {
+        let mut groups = store
+            .iter()
+            .filter(|group| group.value().len() > 1)
+            .map(|group| {
+                let mut files = group.value().clone();
+                files.sort_by(|a, b| a.path.cmp(&b.path));
+                files
+            })
+            .collect::<Vec<Vec<Arc<FileInfo>>>>();
+        groups.sort_by(|a, b| a[0].path.cmp(&b[0].path));
+
+        let (html, proposed) = Self::render(&groups, app_args);
+        fs::write(path, html)
+            .with_context(|| format!("unable to write gallery {}", path.display()))?;
+        Ok(proposed)
+    }
+
+    fn render(groups: &[Vec<Arc<FileInfo>>], app_args: &Params) -> (String, u64) {
+        let proposed = groups
+            .iter()
+            .map(|group| group.len() as u64 - 1)
+            .sum::<u64>();
+        let reclaimable = groups
+            .iter()
+            .map(|group| {
+                let locked = group
+                    .iter()
+                    .filter(|file| Protection::of(&file.path).is_some())
+                    .count() as u64;
+                Protection::removable_copies(group.len() as u64, locked) * group[0].size
+            })
+            .sum::<u64>();
+
+        let mut html = format!(
+            "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Duplicate gallery</title>\
+             <style>{STYLE}</style></head><body>\n<h1>Duplicate gallery</h1>\n\
+             <p>{} group(s), {proposed} cop(ies) proposed for removal, {} reclaimable.</p>\n\
+             <p>Run {}</p>\n",
+            groups.len(),
+            bytesize::ByteSize::b(reclaimable),
+            RunId::get()
+        );
+
+        for (index, group) in groups.iter().enumerate() {
+            let keeper = app_args.keeper(group).unwrap_or_default();
+            let _ = writeln!(
+                html,
+                "<section><h2>Group {} &middot; {} copies of {}</h2><div class=\"copies\">",
+                index + 1,
+                group.len(),
+                bytesize::ByteSize::b(group[0].size)
+            );
+            for (position, file) in group.iter().enumerate() {
+                let (class, label) = match position == keeper {
+                    true => ("keep", "KEEP"),
+                    false if Protection::of(&file.path).is_some() => ("remove", "LOCKED"),
+                    false => ("remove", "REMOVE"),
+                };
+                let preview = match FileType::media_type(&file.path) {
+                    Some(MediaType::Image) => format!(
+                        "<img loading=\"lazy\" src=\"{}\" alt=\"\">",
+                        Self::escape(&Formatter::file_uri(&file.path))
+                    ),
+                    _ => format!(
+                        "<div class=\"placeholder\">{}</div>",
+                        Self::escape(
+                            &file
+                                .path
+                                .extension()
+                                .map(|extension| extension.to_string_lossy().to_uppercase())
+                                .unwrap_or_default()
+                        )
+                    ),
+                };
+                let modified: DateTime<Utc> = file.modified.into();
+                let _ = writeln!(
+                    html,
+                    "<figure class=\"{class}\">{preview}<figcaption><span class=\"label\">{label}</span> \
+                     {}<br>{}</figcaption></figure>",
+                    Self::escape(&file.path.to_string_lossy()),
+                    modified.format("%Y-%m-%d %H:%M")
+                );
+            }
+            html.push_str("</div></section>\n");
+        }
+
+        html.push_str("</body></html>\n");
+        (html, proposed)
+    }
+
+    fn escape(text: &str) -> String {
+        text.chars()
+            .fold(String::with_capacity(text.len()), |mut escaped, c| {
+                match c {
+                    '&' => escaped.push_str("&amp;"),
+                    '<' => escaped.push_str("&lt;"),
+                    '>' => escaped.push_str("&gt;"),
+                    '"' => escaped.push_str("&quot;"),
+                    '\'' => escaped.push_str("&#39;"),
+                    c => escaped.push(c),
+                }
+                escaped
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Gallery;
+    use crate::{fixture::Fixture, params::Params};
+    use anyhow::Result;
+
+    #[test]
+    fn marks_the_kept_copy_and_escapes_paths() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let file = |name: &str| fixture.scanned(name, b"same");
+        let group = vec![file("a.txt")?, file("<b>.txt")?];
+
+        let (html, proposed) = Gallery::render(&[group], &Params::default());
+
+        assert_eq!(proposed, 1);
+        assert_eq!(html.matches("class=\"keep\"").count(), 1);
+        assert_eq!(html.matches("class=\"remove\"").count(), 1);
+        assert!(html.contains("&lt;b&gt;.txt"));
+        assert!(!html.contains("<b>.txt"));
+
+        Ok(())
+    }
+}
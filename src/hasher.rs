@@ -0,0 +1,215 @@
+use clap::ValueEnum;
+use gxhash::gxhash128;
+use sha2::Digest;
+
+/// Size of the blocks gxhash hashes one by one; their hashes are XORed together.
+pub const GXHASH_BLOCK_SIZE: usize = 4096;
+/// Default buffer of `ReadStrategy::Buffered` reads.
+pub const READ_BUFFER_SIZE: usize = 1 << 20;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Algorithm {
+    /// Fast non-cryptographic 128-bit hash, seeded anew every run unless --seed is given
+    #[default]
+    #[value(alias = "xxhash")]
+    Gxhash,
+    /// Cryptographic BLAKE3 digest
+    Blake3,
+    /// Cryptographic SHA-256 digest
+    Sha256,
+}
+
+/// How files hashed in full are read.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum ReadStrategy {
+    /// Map the whole file into memory, fastest on local SSDs
+    #[default]
+    Mmap,
+    /// Read the file from start to end through a buffer of --buffer-size, steadier on network
+    /// file systems & spinning disks, where mapped pages are faulted in one small read at a time
+    Buffered,
+}
+
+impl Algorithm {
+    /// Identifier stored with cached hashes, which are only comparable under one algorithm.
+    pub fn id(&self) -> i64 {
+        match self {
+            Self::Gxhash => 0,
+            Self::Blake3 => 1,
+            Self::Sha256 => 2,
+        }
+    }
+
+    pub fn from_id(id: i64) -> Option<Self> {
+        Self::value_variants().iter().copied().find(|algorithm| algorithm.id() == id)
+    }
+}
+
+/// Incremental content hash, fed the content of a file in order.
+pub trait Hasher: Send {
+    fn update(&mut self, bytes: &[u8]);
+    fn finish(self: Box<Self>) -> u128;
+}
+
+/// How contents are hashed in a run: the `--algorithm` and the per-run seed, and how files are
+/// read (see `--read-strategy`). Digests ignore the seed, so their hashes are the first 128 bits
+/// of the checksum any other tool computes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HashScheme {
+    pub algorithm: Algorithm,
+    pub seed: i64,
+    pub read: ReadStrategy,
+    pub buffer_size: usize,
+}
+
+impl From<i64> for HashScheme {
+    fn from(seed: i64) -> Self {
+        Self::new(Algorithm::Gxhash, seed)
+    }
+}
+
+impl HashScheme {
+    pub fn new(algorithm: Algorithm, seed: i64) -> Self {
+        Self {
+            algorithm,
+            seed,
+            read: ReadStrategy::default(),
+            buffer_size: READ_BUFFER_SIZE,
+        }
+    }
+
+    /// This scheme reading files with `read`, through a buffer of `buffer_size` bytes if buffered.
+    pub fn reading(self, read: ReadStrategy, buffer_size: usize) -> Self {
+        Self {
+            read,
+            buffer_size: buffer_size.max(GXHASH_BLOCK_SIZE),
+            ..self
+        }
+    }
+
+    pub fn hasher(&self) -> Box<dyn Hasher> {
+        match self.algorithm {
+            Algorithm::Gxhash => Box::new(GxHasher {
+                seed: self.seed,
+                pending: Vec::with_capacity(GXHASH_BLOCK_SIZE),
+                hash: 0,
+                length: 0,
+            }),
+            Algorithm::Blake3 => Box::new(blake3::Hasher::new()),
+            Algorithm::Sha256 => Box::new(sha2::Sha256::new()),
+        }
+    }
+
+    /// Hash of `bytes` on their own.
+    pub fn digest(&self, bytes: &[u8]) -> u128 {
+        match self.algorithm {
+            Algorithm::Gxhash => gxhash128(bytes, self.seed),
+            _ => {
+                let mut hasher = self.hasher();
+                hasher.update(bytes);
+                hasher.finish()
+            }
+        }
+    }
+
+    /// Whether the hash of a file is the XOR of the hashes of its blocks, so that a file can be
+    /// hashed chunk by chunk, in lockstep with others of the same size (see `chunk_hash`).
+    pub fn chunkable(&self) -> bool {
+        self.algorithm == Algorithm::Gxhash
+    }
+}
+
+/// XOR of the gxhash of every block, and of the content length, which tells an empty file
+/// apart from one full of null bytes.
+struct GxHasher {
+    seed: i64,
+    pending: Vec<u8>,
+    hash: u128,
+    length: u64,
+}
+
+impl Hasher for GxHasher {
+    fn update(&mut self, mut bytes: &[u8]) {
+        self.length += bytes.len() as u64;
+        while !bytes.is_empty() {
+            // NOTE: whole blocks are hashed in place, only a partial block is copied.
+            if self.pending.is_empty() && bytes.len() >= GXHASH_BLOCK_SIZE {
+                let (block, rest) = bytes.split_at(GXHASH_BLOCK_SIZE);
+                self.hash ^= gxhash128(block, self.seed);
+                bytes = rest;
+                continue;
+            }
+
+            let taken = bytes.len().min(GXHASH_BLOCK_SIZE - self.pending.len());
+            self.pending.extend_from_slice(&bytes[..taken]);
+            bytes = &bytes[taken..];
+            if self.pending.len() == GXHASH_BLOCK_SIZE {
+                self.hash ^= gxhash128(&self.pending, self.seed);
+                self.pending.clear();
+            }
+        }
+    }
+
+    fn finish(self: Box<Self>) -> u128 {
+        let mut hash = self.hash ^ gxhash128(&self.length.to_ne_bytes(), self.seed);
+        if !self.pending.is_empty() {
+            hash ^= gxhash128(&self.pending, self.seed);
+        }
+        hash
+    }
+}
+
+impl Hasher for blake3::Hasher {
+    fn update(&mut self, bytes: &[u8]) {
+        blake3::Hasher::update(self, bytes);
+    }
+
+    fn finish(self: Box<Self>) -> u128 {
+        truncated(blake3::Hasher::finalize(&self).as_bytes())
+    }
+}
+
+impl Hasher for sha2::Sha256 {
+    fn update(&mut self, bytes: &[u8]) {
+        Digest::update(self, bytes);
+    }
+
+    fn finish(self: Box<Self>) -> u128 {
+        truncated(&Digest::finalize(*self))
+    }
+}
+
+/// The first 128 bits of a digest, so its hex form is a prefix of the usual checksum.
+fn truncated(digest: &[u8]) -> u128 {
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&digest[..16]);
+    u128::from_be_bytes(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Algorithm, HashScheme};
+
+    #[test]
+    fn digests_match_the_usual_checksums() {
+        let sha256 = HashScheme::new(Algorithm::Sha256, 42);
+        assert_eq!(
+            format!("{:032x}", sha256.digest(b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223"
+        );
+        let blake3 = HashScheme::new(Algorithm::Blake3, 42);
+        assert_eq!(
+            format!("{:032x}", blake3.digest(b"abc")),
+            "6437b3ac38465133ffb63b75273a8db5"
+        );
+
+        // NOTE: fed in pieces or at once, a gxhash is the same.
+        let gxhash = HashScheme::from(42);
+        let bytes = vec![7u8; 3 * 4096 + 100];
+        let mut whole = gxhash.hasher();
+        whole.update(&bytes);
+        let mut pieces = gxhash.hasher();
+        bytes.chunks(1000).for_each(|piece| pieces.update(piece));
+        assert_eq!(whole.finish(), pieces.finish());
+    }
+}
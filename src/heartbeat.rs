@@ -0,0 +1,246 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::Serialize;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    desktop::Desktop,
+    fileinfo::FileInfo,
+    runid::RunId,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Stage {
+    Starting,
+    /// Walking the tree and grouping files by size
+    Scanning,
+    /// The tree is walked, the remaining candidates are being hashed
+    Hashing,
+    /// Hashing is over: reporting, interactive triage or acting on duplicates
+    Reporting,
+    Done,
+    /// The run ended with an error or a panic
+    Failed,
+}
+
+/// Content of the `--heartbeat` file, and of the `--dbus` progress signal.
+#[derive(Debug, Serialize)]
+struct Status {
+    run_id: String,
+    pid: u32,
+    started: String,
+    updated: String,
+    /// The next update is due by then; a file older than that belongs to a hung or killed run.
+    interval_seconds: u64,
+    stage: Stage,
+    files_scanned: u64,
+    candidates: u64,
+    hashed: u64,
+    groups: u64,
+    /// Estimated from the hashing rate so far, once every candidate is known.
+    eta_seconds: Option<u64>,
+}
+
+type SizeStore = Arc<DashMap<u64, Vec<Arc<FileInfo>>>>;
+type HashStore = Arc<DashMap<u128, Vec<Arc<FileInfo>>>>;
+
+struct Shared {
+    path: Option<PathBuf>,
+    dbus: bool,
+    interval: Duration,
+    started: (DateTime<Utc>, Instant),
+    stage: Mutex<Stage>,
+    stores: Mutex<Option<(SizeStore, HashStore, Arc<AtomicBool>)>>,
+    stopped: Mutex<bool>,
+    wake: Condvar,
+}
+
+/// Status file rewritten every `--heartbeat-interval` while the run lasts (see `--heartbeat`), so
+/// cron jobs & monitoring can tell a long scan from a hung or crashed one without parsing logs.
+/// The same status can go out as a D-Bus signal as well (see `--dbus`). A no-op without either.
+#[derive(Default)]
+pub struct Heartbeat {
+    shared: Option<Arc<Shared>>,
+    writer: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Heartbeat {
+    pub fn start(path: Option<&Path>, dbus: bool, interval: Duration) -> Result<Self> {
+        if path.is_none() && !dbus {
+            return Ok(Self::default());
+        }
+        let shared = Arc::new(Shared {
+            path: path.map(Path::to_path_buf),
+            dbus,
+            interval,
+            started: (Utc::now(), Instant::now()),
+            stage: Mutex::new(Stage::Starting),
+            stores: Mutex::new(None),
+            stopped: Mutex::new(false),
+            wake: Condvar::new(),
+        });
+        Self::write(&shared)?;
+
+        let writer_shared = Arc::clone(&shared);
+        let writer = thread::spawn(move || {
+            let mut stopped = writer_shared.stopped.lock().unwrap();
+            while !*stopped {
+                stopped = writer_shared
+                    .wake
+                    .wait_timeout(stopped, writer_shared.interval)
+                    .unwrap()
+                    .0;
+                if !*stopped {
+                    // NOTE: a failed write is retried at the next beat, monitoring sees it as late.
+                    let _ = Self::write(&writer_shared);
+                }
+            }
+        });
+
+        Ok(Self {
+            shared: Some(shared),
+            writer: Mutex::new(Some(writer)),
+        })
+    }
+
+    /// Counts files & groups from the stores of the scan now running, which moves on to hashing
+    /// once `scanned` is set.
+    pub fn watch(&self, sizes: SizeStore, hashes: HashStore, scanned: Arc<AtomicBool>) {
+        if let Some(shared) = &self.shared {
+            *shared.stores.lock().unwrap() = Some((sizes, hashes, scanned));
+        }
+    }
+
+    pub fn stage(&self, stage: Stage) {
+        tracing::info!(?stage, "stage");
+        if let Some(shared) = &self.shared {
+            *shared.stage.lock().unwrap() = stage;
+        }
+    }
+
+    /// Writes the last status and stops the updates.
+    pub fn finish(&self, stage: Stage) {
+        let Some(shared) = &self.shared else { return };
+        let Some(writer) = self.writer.lock().unwrap().take() else {
+            return;
+        };
+        self.stage(stage);
+        *shared.stopped.lock().unwrap() = true;
+        shared.wake.notify_all();
+        let _ = writer.join();
+        let _ = Self::write(shared);
+    }
+
+    fn write(shared: &Shared) -> Result<()> {
+        let mut stage = *shared.stage.lock().unwrap();
+        let (mut files_scanned, mut candidates, mut hashed, mut groups) = (0, 0, 0, 0);
+        if let Some((sizes, hashes, scanned)) = &*shared.stores.lock().unwrap() {
+            if stage == Stage::Scanning && scanned.load(Ordering::Relaxed) {
+                stage = Stage::Hashing;
+            }
+            sizes.iter().for_each(|bucket| {
+                let files = bucket.value().len() as u64;
+                files_scanned += files;
+                candidates += if files > 1 { files } else { 0 };
+            });
+            hashes.iter().for_each(|group| {
+                hashed += group.value().len() as u64;
+                groups += u64::from(group.value().len() > 1);
+            });
+        }
+        let elapsed = shared.started.1.elapsed().as_secs_f64();
+        let eta_seconds = (stage == Stage::Hashing && hashed > 0)
+            .then(|| (candidates.saturating_sub(hashed) as f64 * elapsed / hashed as f64) as u64);
+
+        let status = Status {
+            run_id: RunId::get(),
+            pid: std::process::id(),
+            started: shared.started.0.to_rfc3339(),
+            updated: Utc::now().to_rfc3339(),
+            interval_seconds: shared.interval.as_secs(),
+            stage,
+            files_scanned,
+            candidates,
+            hashed,
+            groups,
+            eta_seconds,
+        };
+
+        if let Some(path) = &shared.path {
+            // NOTE: renamed into place, so a monitor never reads half a file.
+            let mut staged = path.clone().into_os_string();
+            staged.push(".tmp");
+            fs::write(&staged, serde_json::to_string_pretty(&status)?)
+                .and_then(|_| fs::rename(&staged, path))
+                .with_context(|| format!("unable to write heartbeat {}", path.display()))?;
+        }
+        if shared.dbus {
+            Desktop::emit("Progress", &serde_json::to_string(&status)?)?;
+        }
+        Ok(())
+    }
+}
+
+/// A run that did not reach `finish` ended with an error: its last beat says so.
+impl Drop for Heartbeat {
+    fn drop(&mut self) {
+        self.finish(Stage::Failed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Heartbeat, Stage};
+    use crate::fixture::Fixture;
+    use anyhow::Result;
+    use dashmap::DashMap;
+    use std::{
+        fs,
+        sync::{atomic::AtomicBool, Arc},
+        time::Duration,
+    };
+
+    #[test]
+    fn heartbeat_reports_the_stage_and_counts() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let file = |name: &str| fixture.scanned(name, b"same");
+        let status = fixture.path("status.json");
+        let read = || -> Result<serde_json::Value> {
+            Ok(serde_json::from_str(&fs::read_to_string(&status)?)?)
+        };
+
+        let heartbeat = Heartbeat::start(Some(&status), false, Duration::from_secs(3600))?;
+        assert_eq!(read()?["stage"], "starting");
+
+        let sizes = Arc::new(DashMap::new());
+        sizes.insert(4, vec![file("a.txt")?, file("b.txt")?]);
+        sizes.insert(9, vec![file("c.txt")?]);
+        let hashes = Arc::new(DashMap::new());
+        hashes.insert(1, vec![file("a.txt")?, file("b.txt")?]);
+        heartbeat.watch(sizes, hashes, Arc::new(AtomicBool::new(true)));
+        heartbeat.finish(Stage::Done);
+
+        let beat = read()?;
+        assert_eq!(beat["stage"], "done");
+        assert_eq!(beat["files_scanned"], 3);
+        assert_eq!(beat["candidates"], 2);
+        assert_eq!(beat["groups"], 1);
+
+        // NOTE: finished runs are not reported as failed when dropped.
+        drop(heartbeat);
+        assert_eq!(read()?["stage"], "done");
+
+        Ok(())
+    }
+}
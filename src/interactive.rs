@@ -1,58 +1,86 @@
-use crate::{fileinfo::FileInfo, formatter::Formatter, params::Params};
+use crate::{
+    actionlog::ActionLog, allowlist::Allowlist, archive::Archive, fileinfo::FileInfo, formatter::Formatter, notes::Notes,
+    params::Params, processor::ConfirmedGroup, protection::Protection, selection::Selection,
+    sidecar::Sidecar, survivor::Survivor, verify::Verifier,
+};
 use anyhow::Result;
 use dashmap::DashMap;
-use prettytable::{format, row, Table};
-use std::sync::atomic::AtomicU64;
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind},
+    layout::{Constraint, Layout, Rect},
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{
+        Block, Borders, Cell, Clear, List, ListItem, ListState, Paragraph, Row, Table, TableState,
+    },
+    DefaultTerminal, Frame,
+};
 use std::{
+    collections::BTreeSet,
     io::{self, Write},
-    sync::Arc,
+    sync::{
+        mpsc::{Receiver, TryRecvError},
+        Arc,
+    },
+    time::Duration,
 };
 
+/// How often the screen is redrawn while groups are still streaming in.
+const TICK: Duration = Duration::from_millis(100);
+
 pub struct Interactive;
 
 impl Interactive {
-    pub fn init(result: Arc<DashMap<u128, Vec<FileInfo>>>, app_args: &Params) -> Result<()> {
-        let store = result.clone();
-        if store.is_empty() {
+    /// Opens the full-screen triage on every duplicate group, returning the number of files
+    /// deleted.
+    pub fn init(
+        result: Arc<DashMap<u128, Vec<Arc<FileInfo>>>>,
+        notes: &mut Notes,
+        app_args: &Params,
+    ) -> Result<u64> {
+        let mut groups = result
+            .iter()
+            .filter(|group| group.value().len() > 1)
+            .map(|group| group.value().clone())
+            .collect::<Vec<Vec<Arc<FileInfo>>>>();
+        if groups.is_empty() {
             println!("No duplicates found matching your search criteria.");
+            return Ok(0);
         }
+        groups.sort_by_key(|group| group.iter().map(|file| file.path.clone()).min());
 
-        let printed_count: AtomicU64 = AtomicU64::new(0);
+        let mut triage = Triage::new(app_args, false);
+        groups.into_iter().for_each(|group| triage.push(group));
+        Self::run(triage, None, notes)
+    }
 
-        store
-            .iter()
-            .filter(|i| i.value().len() > 1)
-            .enumerate()
-            .for_each(|(gindex, i)| {
-                printed_count.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-                let group = i.value();
-                let mut itable = Table::new();
-                itable.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
-                itable.set_titles(row!["index", "filename", "size", "updated_at"]);
-
-                let max_path_size = group
-                    .iter()
-                    .map(|f| f.path.iter().count())
-                    .max()
-                    .unwrap_or_default();
+    /// Like `init`, but groups are listed as the pipeline confirms them, so triage can begin
+    /// while the rest of the tree is still being hashed.
+    pub fn stream(
+        groups: Receiver<ConfirmedGroup>,
+        allowlist: &Allowlist,
+        notes: &mut Notes,
+        app_args: &Params,
+    ) -> Result<u64> {
+        let base_directory = app_args.get_directory()?;
+        let source = Source {
+            groups,
+            accept: &|_, group: &[Arc<FileInfo>]| !allowlist.is_intentional(group, &base_directory),
+        };
+        Self::run(Triage::new(app_args, true), Some(source), notes)
+    }
 
-                group.iter().enumerate().for_each(|(index, file)| {
-                    itable.add_row(row![
-                        index,
-                        Formatter::human_path(file, app_args, max_path_size).unwrap_or_default(),
-                        Formatter::human_filesize(file).unwrap_or_default(),
-                        Formatter::human_mtime(file).unwrap_or_default()
-                    ]);
-                });
+    fn run(mut triage: Triage, source: Option<Source>, notes: &mut Notes) -> Result<u64> {
+        let mut terminal = ratatui::try_init()?;
+        let outcome = triage.event_loop(&mut terminal, source, notes);
+        ratatui::try_restore()?;
+        outcome?;
 
-                Self::process_group_action(group, gindex, result.len(), itable);
-            });
-
-        if printed_count.load(std::sync::atomic::Ordering::Relaxed) < 1 {
+        if triage.presented == 0 {
             println!("No duplicates found matching your search criteria.");
         }
-
-        Ok(())
+        triage.log.iter().for_each(|line| eprintln!("{line}"));
+        Ok(triage.deleted)
     }
 
     pub fn scan_group_confirmation() -> Result<bool> {
@@ -67,71 +95,859 @@ impl Interactive {
         }
     }
 
-    pub fn scan_group_instruction() -> Result<String> {
-        println!("\nEnter the indices of the files you want to delete.");
-        println!("You can enter multiple files using commas to seperate file indices.");
-        println!("example: 1,2");
-        print!("\n> ");
-        std::io::stdout().flush()?;
-        let mut user_input = String::new();
-        io::stdin().read_line(&mut user_input)?;
+    fn verified_against_kept(
+        file: &FileInfo,
+        kept_files: &[Arc<FileInfo>],
+        app_args: &Params,
+    ) -> bool {
+        kept_files.iter().any(|kept| {
+            Verifier::identical(kept, file, app_args.verification()).unwrap_or(false)
+        })
+    }
 
-        Ok(user_input)
-    }
-
-    pub fn process_group_action(
-        duplicates: &Vec<FileInfo>,
-        dup_index: usize,
-        dup_size: usize,
-        table: Table,
-    ) {
-        println!("\nDuplicate Set {} of {}\n", dup_index + 1, dup_size);
-        table.printstd();
-        let files_to_delete = Self::scan_group_instruction().unwrap_or_default();
-        let parsed_file_indices = files_to_delete
-            .trim()
-            .split(',')
-            .filter(|element| !element.is_empty())
-            .map(|index| index.parse::<usize>().unwrap_or_default())
-            .collect::<Vec<usize>>();
-
-        if parsed_file_indices
-            .clone()
-            .into_iter()
-            .any(|index| index > (duplicates.len() - 1))
-        {
-            println!("Err: File Index Out of Bounds!");
-            return Self::process_group_action(duplicates, dup_index, dup_size, table);
+    /// Removes `file` (and its sidecars), logging the outcome. Returns whether it was removed.
+    fn remove(
+        file: &FileInfo,
+        kept_files: &[Arc<FileInfo>],
+        app_args: &Params,
+        log: &mut Vec<String>,
+    ) -> bool {
+        let kept = kept_files.first().map(|kept| &*kept.path);
+        if Archive::contains(&file.path) {
+            log.push(ActionLog::record(
+                &app_args.events,
+                "SKIPPED",
+                &file.path,
+                kept,
+                Some("inside a read-only archive"),
+            ));
+            return false;
+        }
+        if !Self::verified_against_kept(file, kept_files, app_args) {
+            log.push(ActionLog::record(
+                &app_args.events,
+                "SKIPPED",
+                &file.path,
+                kept,
+                Some("contents differ from the kept copies"),
+            ));
+            return false;
+        }
+        if let Err(e) = Survivor::ensure(&file.path, kept_files.iter().map(|kept| &*kept.path)) {
+            log.push(ActionLog::record(
+                &app_args.events,
+                "SKIPPED",
+                &file.path,
+                kept,
+                Some(&format!("{e:#}")),
+            ));
+            return false;
         }
+        if let Some(Err(e)) = app_args.verify_keeper.map(|check| check.verify_any(kept_files, file)) {
+            log.push(ActionLog::record(
+                &app_args.events,
+                "SKIPPED",
+                &file.path,
+                kept,
+                Some(&format!("{e:#}")),
+            ));
+            return false;
+        }
+
+        let removal = app_args.removal();
+        match removal.remove(&file.path, app_args) {
+            Ok(_) => {
+                log.push(ActionLog::record(
+                    &app_args.events,
+                    removal.label(),
+                    &file.path,
+                    kept,
+                    None,
+                ));
+                Sidecar::follow(&file.path, app_args.sidecars, |sidecar| {
+                    removal.remove(sidecar, app_args)
+                })
+                .into_iter()
+                .for_each(|(sidecar, outcome)| {
+                    log.push(match outcome {
+                        Ok(_) => ActionLog::record(
+                            &app_args.events,
+                            removal.label(),
+                            &sidecar,
+                            None,
+                            Some("sidecar"),
+                        ),
+                        Err(e) => ActionLog::record(
+                            &app_args.events,
+                            "FAILED",
+                            &sidecar,
+                            None,
+                            Some(&format!("sidecar: {e:#}")),
+                        ),
+                    })
+                });
+                true
+            }
+            Err(e) => {
+                log.push(ActionLog::record(
+                    &app_args.events,
+                    "FAILED",
+                    &file.path,
+                    kept,
+                    Some(&format!("{e:#}")),
+                ));
+                false
+            }
+        }
+    }
+}
+
+/// Groups confirmed by a scan still running, and which of them to list.
+struct Source<'a> {
+    groups: Receiver<ConfirmedGroup>,
+    accept: &'a dyn Fn(u128, &[Arc<FileInfo>]) -> bool,
+}
 
-        print!("{esc}[2J{esc}[1;1H", esc = 27 as char);
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Focus {
+    Groups,
+    Files,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Browse,
+    Filter,
+    /// Typing the text of a `note` or a `tag` (see `Notes::annotate`)
+    Annotate(&'static str),
+    /// Typing a pattern of the files to mark, or to keep when `false` (see `Selection`)
+    Select(bool),
+    Confirm,
+}
+
+/// State of the triage screen: the groups found so far, the files marked for deletion in each
+/// and what is shown of them.
+struct Triage<'a> {
+    app_args: &'a Params,
+    groups: Vec<Vec<Arc<FileInfo>>>,
+    marked: Vec<BTreeSet<usize>>,
+    /// Indices of the groups matching the filter, in the order listed.
+    visible: Vec<usize>,
+    filter: String,
+    input: String,
+    groups_state: ListState,
+    files_state: TableState,
+    focus: Focus,
+    mode: Mode,
+    status: String,
+    scanning: bool,
+    presented: usize,
+    deleted: u64,
+    log: Vec<String>,
+}
 
-        if parsed_file_indices.is_empty() {
+impl<'a> Triage<'a> {
+    fn new(app_args: &'a Params, scanning: bool) -> Self {
+        Self {
+            app_args,
+            groups: vec![],
+            marked: vec![],
+            visible: vec![],
+            filter: String::new(),
+            input: String::new(),
+            groups_state: ListState::default(),
+            files_state: TableState::default(),
+            focus: Focus::Groups,
+            mode: Mode::Browse,
+            status: String::new(),
+            scanning,
+            presented: 0,
+            deleted: 0,
+            log: vec![],
+        }
+    }
+
+    fn push(&mut self, mut group: Vec<Arc<FileInfo>>) {
+        group.sort_by(|a, b| a.path.cmp(&b.path));
+        self.presented += 1;
+        self.groups.push(group);
+        self.marked.push(BTreeSet::new());
+        if self.matches(self.groups.len() - 1) {
+            self.visible.push(self.groups.len() - 1);
+        }
+        if self.groups_state.selected().is_none() && !self.visible.is_empty() {
+            self.select_group(0);
+        }
+    }
+
+    /// Case-insensitive match of the filter against the paths of a group.
+    fn matches(&self, group: usize) -> bool {
+        let filter = self.filter.to_lowercase();
+        filter.is_empty()
+            || self.groups[group]
+                .iter()
+                .any(|file| file.path.to_string_lossy().to_lowercase().contains(&filter))
+    }
+
+    fn refilter(&mut self) {
+        let current = self.current();
+        self.visible = (0..self.groups.len())
+            .filter(|&group| self.matches(group))
+            .collect();
+        let position = current
+            .and_then(|current| self.visible.iter().position(|&group| group == current))
+            .unwrap_or_default();
+        match self.visible.is_empty() {
+            true => self.groups_state.select(None),
+            false => self.select_group(position),
+        }
+    }
+
+    /// Index in `groups` of the selected group.
+    fn current(&self) -> Option<usize> {
+        self.groups_state
+            .selected()
+            .and_then(|position| self.visible.get(position).copied())
+    }
+
+    fn select_group(&mut self, position: usize) {
+        self.groups_state.select(Some(position));
+        self.files_state.select(Some(0));
+    }
+
+    fn step(&mut self, delta: isize) {
+        match self.focus {
+            Focus::Groups if !self.visible.is_empty() => {
+                let position = self.groups_state.selected().unwrap_or_default();
+                let last = self.visible.len() - 1;
+                self.select_group(position.saturating_add_signed(delta).min(last));
+            }
+            Focus::Files => {
+                let Some(group) = self.current() else { return };
+                let row = self.files_state.selected().unwrap_or_default();
+                let last = self.groups[group].len() - 1;
+                self.files_state
+                    .select(Some(row.saturating_add_signed(delta).min(last)));
+            }
+            _ => {}
+        }
+    }
+
+    fn toggle(&mut self) {
+        let (Some(group), Some(row)) = (self.current(), self.files_state.selected()) else {
             return;
+        };
+        if !self.marked[group].remove(&row) {
+            self.marked[group].insert(row);
+        }
+        if self.marked[group].len() == self.groups[group].len() {
+            self.status = "Every copy of this group is marked, the one --keep chooses stays.".to_string();
+        }
+    }
+
+    /// Marks every copy of the selected group but the one --keep chooses.
+    fn mark_all_but_kept(&mut self) {
+        let Some(group) = self.current() else { return };
+        let keeper = self
+            .app_args
+            .keeper(&self.groups[group])
+            .unwrap_or_default();
+        self.marked[group] = (0..self.groups[group].len())
+            .filter(|&row| row != keeper)
+            .collect();
+    }
+
+    /// Marks the files matching the pattern typed, or unmarks them to keep them when `mark` is
+    /// `false`, in every group listed.
+    fn select(&mut self, mark: bool) {
+        let pattern = std::mem::take(&mut self.input);
+        let selection = match Selection::parse(&pattern) {
+            Ok(selection) => selection,
+            Err(e) => {
+                self.status = format!("invalid pattern {pattern}: {e:#}");
+                return;
+            }
+        };
+
+        let (mut files, mut groups, mut whole) = (0, 0, 0);
+        for &group in &self.visible {
+            let marked = &mut self.marked[group];
+            let changed = self.groups[group]
+                .iter()
+                .enumerate()
+                .filter(|(_, file)| selection.matches(&file.path))
+                .filter(|(row, _)| match mark {
+                    true => marked.insert(*row),
+                    false => marked.remove(row),
+                })
+                .count();
+            if changed > 0 {
+                files += changed;
+                groups += 1;
+            }
+            if marked.len() == self.groups[group].len() {
+                whole += 1;
+            }
+        }
+        self.status = format!(
+            "{files} file(s) {} in {groups} group(s).",
+            if mark { "marked" } else { "kept" }
+        );
+        if whole > 0 {
+            self.status.push_str(&format!(
+                " {whole} group(s) are marked as a whole, the copy --keep chooses stays."
+            ));
+        }
+    }
+
+    /// Files marked in every group and the space removing them would free. Protected files (see
+    /// `Protection`) cannot be removed and do not count.
+    fn projected(&self) -> (usize, u64) {
+        self.marked
+            .iter()
+            .zip(&self.groups)
+            .flat_map(|(marked, group)| {
+                // NOTE: a group marked as a whole keeps a copy, see `execute`.
+                let spared = (marked.len() == group.len())
+                    .then(|| self.app_args.keeper(group).unwrap_or_default());
+                marked
+                    .iter()
+                    .filter(move |&&row| Some(row) != spared)
+                    .map(|&row| &group[row])
+            })
+            .fold((0, 0), |(files, bytes), file| {
+                match Protection::of(&file.path) {
+                    Some(_) => (files + 1, bytes),
+                    None => (files + 1, bytes + file.size),
+                }
+            })
+    }
+
+    fn event_loop(
+        &mut self,
+        terminal: &mut DefaultTerminal,
+        mut source: Option<Source>,
+        notes: &mut Notes,
+    ) -> Result<()> {
+        loop {
+            if let Some(streaming) = &source {
+                loop {
+                    match streaming.groups.try_recv() {
+                        Ok((hash, group))
+                            if (streaming.accept)(hash, &group) && !notes.is_snoozed(&group) =>
+                        {
+                            self.push(group)
+                        }
+                        Ok(_) => {}
+                        Err(TryRecvError::Empty) => break,
+                        Err(TryRecvError::Disconnected) => {
+                            self.scanning = false;
+                            source = None;
+                            break;
+                        }
+                    }
+                }
+            }
+
+            terminal.draw(|frame| self.render(frame, notes))?;
+            if !event::poll(TICK)? {
+                continue;
+            }
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && self.handle(key, notes)? {
+                    return Ok(());
+                }
+            }
         }
+    }
 
-        let files_to_delete = parsed_file_indices
+    /// Acts on a key press. Returns `true` once the user quits.
+    fn handle(&mut self, key: KeyEvent, notes: &mut Notes) -> Result<bool> {
+        match (self.mode, key.code) {
+            (Mode::Filter, KeyCode::Esc) => {
+                self.filter.clear();
+                self.refilter();
+                self.mode = Mode::Browse;
+            }
+            (Mode::Filter, KeyCode::Enter) => self.mode = Mode::Browse,
+            (Mode::Filter, KeyCode::Backspace) => {
+                self.filter.pop();
+                self.refilter();
+            }
+            (Mode::Filter, KeyCode::Char(c)) => {
+                self.filter.push(c);
+                self.refilter();
+            }
+            (Mode::Annotate(_), KeyCode::Esc) => self.mode = Mode::Browse,
+            (Mode::Select(_), KeyCode::Esc) => {
+                self.input.clear();
+                self.mode = Mode::Browse;
+            }
+            (Mode::Annotate(_) | Mode::Select(_), KeyCode::Backspace) => {
+                self.input.pop();
+            }
+            (Mode::Annotate(_) | Mode::Select(_), KeyCode::Char(c)) => self.input.push(c),
+            (Mode::Select(mark), KeyCode::Enter) => {
+                self.select(mark);
+                self.mode = Mode::Browse;
+            }
+            (Mode::Annotate(kind), KeyCode::Enter) => {
+                if let Some(group) = self.current() {
+                    let input = format!("{kind} {}", std::mem::take(&mut self.input));
+                    self.status = match notes.annotate(&self.groups[group], &input) {
+                        Ok(_) => format!("{kind} saved."),
+                        Err(e) => format!("unable to save the {kind}: {e:#}"),
+                    };
+                }
+                self.mode = Mode::Browse;
+            }
+            (Mode::Confirm, KeyCode::Char('y' | 'Y')) => {
+                self.execute();
+                self.mode = Mode::Browse;
+            }
+            (Mode::Confirm, KeyCode::Char('n' | 'N') | KeyCode::Esc) => {
+                self.status = "Cancelled Delete Operation.".to_string();
+                self.mode = Mode::Browse;
+            }
+            (Mode::Browse, KeyCode::Char('q') | KeyCode::Esc) => return Ok(true),
+            (Mode::Browse, KeyCode::Up | KeyCode::Char('k')) => self.step(-1),
+            (Mode::Browse, KeyCode::Down | KeyCode::Char('j')) => self.step(1),
+            (Mode::Browse, KeyCode::PageUp) => self.step(-10),
+            (Mode::Browse, KeyCode::PageDown) => self.step(10),
+            (Mode::Browse, KeyCode::Tab | KeyCode::Left | KeyCode::Right) => {
+                self.focus = match self.focus {
+                    Focus::Groups => Focus::Files,
+                    Focus::Files => Focus::Groups,
+                };
+            }
+            (Mode::Browse, KeyCode::Enter) if self.focus == Focus::Groups => {
+                self.focus = Focus::Files
+            }
+            (Mode::Browse, KeyCode::Char(' ') | KeyCode::Enter) if self.focus == Focus::Files => {
+                self.toggle()
+            }
+            (Mode::Browse, KeyCode::Char('a')) => self.mark_all_but_kept(),
+            (Mode::Browse, KeyCode::Char('m')) => self.mode = Mode::Select(true),
+            (Mode::Browse, KeyCode::Char('u')) => self.mode = Mode::Select(false),
+            (Mode::Browse, KeyCode::Char('/')) => self.mode = Mode::Filter,
+            (Mode::Browse, KeyCode::Char('n')) if self.current().is_some() => {
+                self.mode = Mode::Annotate("note")
+            }
+            (Mode::Browse, KeyCode::Char('t')) if self.current().is_some() => {
+                self.mode = Mode::Annotate("tag")
+            }
+            (Mode::Browse, KeyCode::Char('s')) => self.snooze(notes),
+            (Mode::Browse, KeyCode::Char('d')) => match self.projected().0 {
+                0 => self.status = "No files marked, mark some with space first.".to_string(),
+                _ => self.mode = Mode::Confirm,
+            },
+            _ => {}
+        }
+        Ok(false)
+    }
+
+    /// Skips the selected group, which is no longer listed, nor shown by runs within `--snooze`.
+    fn snooze(&mut self, notes: &mut Notes) {
+        let Some(period) = self.app_args.snooze else {
+            self.status = "Run with --snooze <period> to skip groups across runs.".to_string();
+            return;
+        };
+        let Some(group) = self.current() else { return };
+        let Some(until) = period.end_from_now() else {
+            self.status = "--snooze is too long to snooze the group.".to_string();
+            return;
+        };
+        if let Err(e) = notes.snooze(&self.groups[group], until) {
+            self.status = format!("unable to snooze the group: {e:#}");
+            return;
+        }
+
+        self.groups.remove(group);
+        self.marked.remove(group);
+        self.refilter();
+        self.status = format!("Group snoozed until {}.", until.format("%Y-%m-%d %H:%M"));
+    }
+
+    /// Removes every marked file, keeping the groups that still hold duplicates afterwards. A
+    /// group marked as a whole keeps the copy --keep chooses (see `Survivor`).
+    fn execute(&mut self) {
+        let mut removed = 0;
+        for (group, marked) in self.groups.iter_mut().zip(self.marked.iter_mut()) {
+            if marked.is_empty() {
+                continue;
+            }
+            if marked.len() == group.len() {
+                let keeper = self.app_args.keeper(group).unwrap_or_default();
+                marked.remove(&keeper);
+                self.log.push(ActionLog::record(
+                    &self.app_args.events,
+                    "SKIPPED",
+                    &group[keeper].path,
+                    None,
+                    Some("the last copy of the group stays"),
+                ));
+            }
+            let kept_files = group
+                .iter()
+                .enumerate()
+                .filter(|(row, _)| !marked.contains(row))
+                .map(|(_, file)| file.clone())
+                .collect::<Vec<Arc<FileInfo>>>();
+            let gone = marked
+                .iter()
+                .filter(|&&row| {
+                    Interactive::remove(&group[row], &kept_files, self.app_args, &mut self.log)
+                })
+                .copied()
+                .collect::<BTreeSet<usize>>();
+            removed += gone.len() as u64;
+
+            let mut row = 0;
+            group.retain(|_| {
+                row += 1;
+                !gone.contains(&(row - 1))
+            });
+            marked.clear();
+        }
+
+        self.deleted += removed;
+        let (remaining, marks): (Vec<_>, Vec<_>) = std::mem::take(&mut self.groups)
             .into_iter()
-            .map(|index| duplicates[index].clone());
-
-        println!("\nThe following files will be deleted:");
-        files_to_delete
-            .clone()
-            .enumerate()
-            .for_each(|(index, file)| {
-                println!("{}: {}", index, file.path.display());
+            .zip(std::mem::take(&mut self.marked))
+            .filter(|(group, _)| group.len() > 1)
+            .unzip();
+        self.groups = remaining;
+        self.marked = marks;
+        self.refilter();
+        self.status = format!(
+            "{removed} file(s) {}, see the log once you quit.",
+            self.app_args.removal().label().to_lowercase()
+        );
+    }
+
+    fn render(&mut self, frame: &mut Frame, notes: &Notes) {
+        let [top, body, status, help] = Layout::vertical([
+            Constraint::Length(3),
+            Constraint::Min(5),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [groups_area, files_area] =
+            Layout::horizontal([Constraint::Percentage(35), Constraint::Percentage(65)])
+                .areas(body);
+
+        let (title, text) = match self.mode {
+            Mode::Annotate(kind) => (kind, self.input.as_str()),
+            Mode::Select(true) => ("mark files matching (glob, or re:regex)", self.input.as_str()),
+            Mode::Select(false) => ("keep files matching (glob, or re:regex)", self.input.as_str()),
+            _ => ("filter (/)", self.filter.as_str()),
+        };
+        let editing = matches!(self.mode, Mode::Filter | Mode::Annotate(_) | Mode::Select(_));
+        frame.render_widget(Paragraph::new(text).block(Self::block(title, editing)), top);
+
+        self.render_groups(frame, groups_area);
+        self.render_files(frame, files_area, notes);
+
+        let (files, bytes) = self.projected();
+        let mut line = format!(
+            " {} of {} group(s) | {files} file(s) marked | {} reclaimable",
+            self.visible.len(),
+            self.groups.len(),
+            bytesize::ByteSize::b(bytes)
+        );
+        if self.scanning {
+            line.push_str(" | scanning...");
+        }
+        if !self.status.is_empty() {
+            line.push_str(&format!(" | {}", self.status));
+        }
+        frame.render_widget(
+            Paragraph::new(line).style(Style::default().bg(Color::Blue).fg(Color::White)),
+            status,
+        );
+        frame.render_widget(
+            Paragraph::new(
+                " ↑↓ move  tab pane  space mark  a mark all but kept  m/u mark/keep matching  / filter  n note  t tag  s snooze  d delete marked  q quit",
+            )
+            .style(Style::default().add_modifier(Modifier::DIM)),
+            help,
+        );
+
+        if self.mode == Mode::Confirm {
+            self.render_confirm(frame);
+        }
+    }
+
+    fn render_groups(&mut self, frame: &mut Frame, area: Rect) {
+        let items = self
+            .visible
+            .iter()
+            .map(|&group| {
+                let files = &self.groups[group];
+                let name = files[0]
+                    .path
+                    .file_name()
+                    .map(|name| name.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                let marked = match self.marked[group].len() {
+                    0 => String::new(),
+                    count => format!(" [{count} marked]"),
+                };
+                ListItem::new(format!(
+                    "{} x {}  {name}{marked}",
+                    files.len(),
+                    bytesize::ByteSize::b(files[0].size)
+                ))
+            })
+            .collect::<Vec<ListItem>>();
+        let list = List::new(items)
+            .block(Self::block("groups", self.focus == Focus::Groups))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, area, &mut self.groups_state);
+    }
+
+    fn render_files(&mut self, frame: &mut Frame, area: Rect, notes: &Notes) {
+        let block = Self::block("files", self.focus == Focus::Files);
+        let Some(group) = self.current() else {
+            frame.render_widget(
+                Paragraph::new("No duplicate group to show.").block(block),
+                area,
+            );
+            return;
+        };
+        let files = &self.groups[group];
+
+        let annotation = notes.of(files).map(|note| {
+            let mut lines = vec![];
+            if let Some(text) = &note.note {
+                lines.push(Line::from(format!("Note: {text}")));
+            }
+            if !note.tags.is_empty() {
+                lines.push(Line::from(format!("Tags: {}", note.tags.join(", "))));
+            }
+            lines
+        });
+        let height = annotation.as_ref().map(Vec::len).unwrap_or_default() as u16;
+        let [table_area, note_area] =
+            Layout::vertical([Constraint::Min(3), Constraint::Length(height)]).areas(area);
+
+        let labelled = !self.app_args.label.is_empty();
+        let rows = files.iter().enumerate().map(|(row, file)| {
+            let mark = match (
+                self.marked[group].contains(&row),
+                Protection::of(&file.path),
+            ) {
+                (true, Some(_)) => "[!]",
+                (true, None) => "[x]",
+                (false, _) => "[ ]",
+            };
+            let mut cells = vec![
+                Cell::from(mark),
+                Cell::from(Formatter::human_path(file, self.app_args, 0).unwrap_or_default()),
+                Cell::from(Formatter::human_filesize(file).unwrap_or_default()),
+                Cell::from(Formatter::human_mtime(file, self.app_args).unwrap_or_default()),
+            ];
+            if let Some(label) = Formatter::human_label(file, self.app_args) {
+                cells.push(Cell::from(label));
+            }
+            Row::new(cells)
+        });
+        let mut widths = vec![
+            Constraint::Length(3),
+            Constraint::Fill(1),
+            Constraint::Length(12),
+            Constraint::Length(20),
+        ];
+        let mut titles = vec!["", "filename", "size", "updated_at"];
+        if labelled {
+            widths.push(Constraint::Length(12));
+            titles.push("label");
+        }
+        let table = Table::new(rows, widths)
+            .header(Row::new(titles).style(Style::default().add_modifier(Modifier::BOLD)))
+            .block(block)
+            .row_highlight_style(match self.focus {
+                Focus::Files => Style::default().add_modifier(Modifier::REVERSED),
+                Focus::Groups => Style::default(),
             });
+        frame.render_stateful_widget(table, table_area, &mut self.files_state);
+
+        if let Some(lines) = annotation {
+            frame.render_widget(Paragraph::new(lines), note_area);
+        }
+    }
 
-        match Self::scan_group_confirmation().unwrap() {
-            true => {
-                files_to_delete.into_iter().for_each(|file| {
-                    match std::fs::remove_file(file.path.clone()) {
-                        Ok(_) => println!("DELETED: {}", file.path.display()),
-                        Err(_) => println!("FAILED: {}", file.path.display()),
+    fn render_confirm(&self, frame: &mut Frame) {
+        let [_, middle, _] = Layout::vertical([
+            Constraint::Percentage(15),
+            Constraint::Percentage(70),
+            Constraint::Percentage(15),
+        ])
+        .areas(frame.area());
+        let [_, area, _] = Layout::horizontal([
+            Constraint::Percentage(10),
+            Constraint::Percentage(80),
+            Constraint::Percentage(10),
+        ])
+        .areas(middle);
+
+        let removal = self.app_args.removal();
+        let (files, bytes) = self.projected();
+        let mut lines = vec![
+            Line::from(format!(
+                "The following {files} file(s) will be {}, {} reclaimed:",
+                removal.label().to_lowercase(),
+                bytesize::ByteSize::b(bytes)
+            )),
+            Line::from(""),
+        ];
+        for (group, marked) in self.groups.iter().zip(&self.marked) {
+            for &row in marked {
+                let line = match Protection::of(&group[row].path) {
+                    Some(protection) => {
+                        format!("  {} ({protection}, will fail)", group[row].path.display())
                     }
-                });
+                    None => format!("  {}", group[row].path.display()),
+                };
+                lines.push(Line::from(line));
+            }
+            if !marked.is_empty() && marked.len() == group.len() {
+                lines.push(
+                    Line::from("  ! no copy of this group is kept")
+                        .style(Style::default().fg(Color::Red)),
+                );
             }
-            false => println!("\nCancelled Delete Operation."),
         }
+        lines.push(Line::from(""));
+        lines.push(
+            Line::from("confirm? [y/N]").style(Style::default().add_modifier(Modifier::BOLD)),
+        );
+
+        frame.render_widget(Clear, area);
+        frame.render_widget(
+            Paragraph::new(lines).block(Self::block("confirm", true)),
+            area,
+        );
+    }
+
+    fn block(title: &str, active: bool) -> Block<'static> {
+        let style = match active {
+            true => Style::default().fg(Color::Yellow),
+            false => Style::default(),
+        };
+        Block::default()
+            .borders(Borders::ALL)
+            .border_style(style)
+            .title(format!(" {title} "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Focus, Mode, Triage};
+    use crate::{fixture::Fixture, notes::Notes, params::Params};
+    use anyhow::Result;
+    use ratatui::{
+        backend::TestBackend,
+        crossterm::event::{KeyCode, KeyEvent},
+        Terminal,
+    };
+
+    #[test]
+    fn marked_files_are_deleted_after_confirmation() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let params = Params::default();
+        let mut notes = Notes::default();
+        let mut triage = Triage::new(&params, false);
+        triage.push(vec![
+            fixture.scanned("a.txt", b"same")?,
+            fixture.scanned("b.txt", b"same")?,
+            fixture.scanned("c.txt", b"same")?,
+        ]);
+        triage.push(vec![
+            fixture.scanned("photo.jpg", b"jpeg")?,
+            fixture.scanned("photo copy.jpg", b"jpeg")?,
+        ]);
+
+        let mut press =
+            |triage: &mut Triage, code: KeyCode| triage.handle(KeyEvent::from(code), &mut notes);
+        for c in "/COPY".chars() {
+            press(&mut triage, KeyCode::Char(c))?;
+        }
+        assert_eq!(triage.visible, vec![1]);
+        press(&mut triage, KeyCode::Esc)?;
+        assert_eq!(triage.visible, vec![0, 1]);
+        assert_eq!(triage.current(), Some(1));
+
+        press(&mut triage, KeyCode::Up)?;
+        press(&mut triage, KeyCode::Enter)?;
+        assert_eq!(triage.focus, Focus::Files);
+        press(&mut triage, KeyCode::Down)?;
+        press(&mut triage, KeyCode::Char(' '))?;
+        press(&mut triage, KeyCode::Down)?;
+        press(&mut triage, KeyCode::Enter)?;
+        assert_eq!(triage.projected(), (2, 8));
+
+        let mut terminal = Terminal::new(TestBackend::new(120, 20))?;
+        press(&mut triage, KeyCode::Char('d'))?;
+        assert_eq!(triage.mode, Mode::Confirm);
+        terminal.draw(|frame| triage.render(frame, &Notes::default()))?;
+        let screen = format!("{:?}", terminal.backend().buffer());
+        assert!(screen.contains("2 file(s) marked | 8 B reclaimable"));
+        assert!(screen.contains("confirm? [y/N]"));
+
+        press(&mut triage, KeyCode::Char('y'))?;
+        assert_eq!(triage.deleted, 2);
+        assert!(fixture.path("a.txt").exists());
+        assert!(!fixture.path("b.txt").exists());
+        assert!(!fixture.path("c.txt").exists());
+        // NOTE: the first group is down to one copy and no longer listed.
+        assert_eq!(triage.groups.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn files_are_marked_and_kept_by_pattern_across_groups() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let params = Params::default();
+        let mut notes = Notes::default();
+        let mut triage = Triage::new(&params, false);
+        let pair = |name: &str, other: &str, content: &[u8]| -> Result<Vec<_>> {
+            Ok(vec![fixture.scanned(name, content)?, fixture.scanned(other, content)?])
+        };
+        triage.push(pair("photos/a.jpg", "backup/a.jpg", b"jpeg")?);
+        triage.push(pair("photos/b.RAW", "backup/b.RAW", b"raw")?);
+        triage.push(pair("backup/c.txt", "backup/old/c.txt", b"text")?);
+
+        let mut typed = |triage: &mut Triage, keys: &str| -> Result<()> {
+            for c in keys.chars() {
+                triage.handle(KeyEvent::from(KeyCode::Char(c)), &mut notes)?;
+            }
+            triage.handle(KeyEvent::from(KeyCode::Enter), &mut notes)?;
+            Ok(())
+        };
+        typed(&mut triage, &format!("m{}", fixture.path("backup").display()))?;
+        assert!(triage.status.starts_with("4 file(s) marked in 3 group(s)."));
+        typed(&mut triage, "u*.RAW")?;
+        assert!(triage.status.starts_with("1 file(s) kept in 1 group(s)."));
+        assert_eq!(triage.marked, [[0].into(), [].into(), [0, 1].into()]);
+        // NOTE: the group marked as a whole keeps a copy.
+        assert_eq!(triage.projected(), (2, 8));
+
+        typed(&mut triage, "mre:(")?;
+        assert!(triage.status.starts_with("invalid pattern"));
+        assert_eq!(triage.mode, Mode::Browse);
+
+        triage.execute();
+        assert!(!fixture.path("backup/a.jpg").exists());
+        assert!(fixture.path("backup/b.RAW").exists());
+        assert_eq!(
+            ["backup/c.txt", "backup/old/c.txt"].map(|name| fixture.path(name).exists()),
+            [true, false]
+        );
+
+        Ok(())
     }
 }
@@ -0,0 +1,522 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use colored::Colorize;
+use serde::{Deserialize, Serialize};
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::{BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{Mutex, OnceLock},
+};
+
+use crate::{denial::Denial, quarantine::{Quarantine, RestoreArgs}, runid::RunId, verify::Verifier};
+
+const JOURNAL_EXTENSION: &str = "jsonl";
+const RESTORED_SUFFIX: &str = ".restored";
+/// Journals kept around, older ones are removed as new runs start theirs.
+const KEPT_JOURNALS: usize = 50;
+
+static DIR: OnceLock<PathBuf> = OnceLock::new();
+static FILE: OnceLock<Option<Mutex<File>>> = OnceLock::new();
+
+/// What a run did to a file, named like the outcomes of the `ActionLog`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum Action {
+    Deleted,
+    Trashed,
+    Moved,
+    Linked,
+    Cloned,
+    DeletedDir,
+}
+
+impl Action {
+    fn of(outcome: &str) -> Option<Self> {
+        match outcome {
+            "deleted" => Some(Self::Deleted),
+            "trashed" => Some(Self::Trashed),
+            "moved" => Some(Self::Moved),
+            "linked" => Some(Self::Linked),
+            "cloned" => Some(Self::Cloned),
+            "deleted-dir" => Some(Self::DeletedDir),
+            _ => None,
+        }
+    }
+
+    /// How the entry is listed by `restore`, before its target if any.
+    fn noun(&self) -> &'static str {
+        match self {
+            Self::Deleted => "deleted, kept",
+            Self::Trashed => "trashed, kept",
+            Self::Moved => "moved to",
+            Self::Linked => "linked to",
+            Self::Cloned => "cloned from",
+            Self::DeletedDir => "pruned",
+        }
+    }
+}
+
+/// One line of a journal.
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    time: String,
+    run_id: String,
+    action: Action,
+    path: PathBuf,
+    /// The copy kept in place of a deleted or linked file, or where a file was moved to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    target: Option<PathBuf>,
+    /// Size & modification time of `target` when the change was made, so that a kept copy
+    /// changed since is not copied back in place of the deleted file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    target_size: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    target_modified: Option<String>,
+}
+
+/// What undoing an entry came to, unless it failed.
+#[derive(Debug, PartialEq)]
+enum Undone {
+    Restored,
+    Skipped(String),
+}
+
+/// Every change a run makes to the files it was given (deletions, moves to the trash or the
+/// `--move-to` quarantine, links & clones, directories pruned) with what it takes to undo it,
+/// one journal per run under `~/.local/state/deduplicator/journal`. `deduplicator restore`
+/// replays the journal of the last run backwards: moved files go back, trashed ones come out of
+/// the trash, and deleted or linked files are copied back from the copy kept in their place.
+pub struct Journal;
+
+impl Journal {
+    /// `~/.local/state/deduplicator/journal`, honouring `$XDG_STATE_HOME`.
+    pub fn default_dir() -> Option<PathBuf> {
+        env::var_os("XDG_STATE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))
+            .map(|dir| dir.join(env!("CARGO_PKG_NAME")).join("journal"))
+    }
+
+    /// Journals the rest of the run under `dir`. The journal itself is only created by the first
+    /// change, runs that change nothing leave none behind.
+    pub fn open(dir: Option<PathBuf>) {
+        if let Some(dir) = dir {
+            let _ = DIR.set(dir);
+        }
+    }
+
+    /// `outcome` (see `ActionLog::record`) was done to `path`, with `target` as the copy kept or
+    /// the place it was moved to. Only changes that can be undone are journaled.
+    pub fn record(outcome: &str, path: &Path, target: Option<&Path>) {
+        let (Some(action), Some(dir)) = (Action::of(outcome), DIR.get()) else {
+            return;
+        };
+        let absolute = |path: &Path| std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf());
+        let stat = target.and_then(|target| fs::metadata(target).ok());
+        let entry = Entry {
+            time: Utc::now().to_rfc3339(),
+            run_id: RunId::get(),
+            action,
+            path: absolute(path),
+            target: target.map(absolute),
+            target_size: stat.as_ref().map(fs::Metadata::len),
+            target_modified: stat.and_then(|stat| stat.modified().ok()).map(Self::timestamp),
+        };
+
+        // NOTE: a journal that cannot be created is warned about once, not retried for every change.
+        let file = FILE.get_or_init(|| match Self::create(dir) {
+            Ok(file) => Some(Mutex::new(file)),
+            Err(e) => {
+                tracing::warn!(error = format!("{e:#}"), "no journal");
+                eprintln!("{} {e:#}, this run cannot be restored", "Warning:".yellow());
+                None
+            }
+        });
+        if let (Some(file), Ok(line)) = (file, serde_json::to_string(&entry)) {
+            let _ = writeln!(file.lock().unwrap(), "{line}");
+        }
+    }
+
+    /// The journal of this run in `dir`, named after the time it starts so journals sort in the
+    /// order of their runs.
+    fn create(dir: &Path) -> Result<File> {
+        fs::create_dir_all(dir).with_context(|| format!("unable to create journal dir {}", dir.display()))?;
+        let mut journals = Self::journals(dir);
+        while journals.len() >= KEPT_JOURNALS {
+            let _ = fs::remove_file(journals.remove(0));
+        }
+
+        let path = dir.join(format!(
+            "{}-{}.{JOURNAL_EXTENSION}",
+            Utc::now().format("%Y%m%dT%H%M%S%.6fZ"),
+            RunId::get()
+        ));
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .with_context(|| format!("unable to open journal {}", path.display()))
+    }
+
+    /// Journals in `dir`, restored ones included, oldest first.
+    fn journals(dir: &Path) -> Vec<PathBuf> {
+        let Ok(entries) = fs::read_dir(dir) else {
+            return vec![];
+        };
+        let mut journals = entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| {
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                name.ends_with(&format!(".{JOURNAL_EXTENSION}"))
+                    || name.ends_with(&format!(".{JOURNAL_EXTENSION}{RESTORED_SUFFIX}"))
+            })
+            .collect::<Vec<PathBuf>>();
+        journals.sort();
+        journals
+    }
+
+    /// The journal of the run `run_id`, or else of the last run not restored yet.
+    fn find(dir: &Path, run_id: Option<&str>) -> Result<PathBuf> {
+        let journals = Self::journals(dir);
+        match run_id {
+            Some(run_id) => journals
+                .into_iter()
+                .rev()
+                .find(|path| path.to_string_lossy().ends_with(&format!("-{run_id}.{JOURNAL_EXTENSION}")))
+                .with_context(|| format!("no journal of run {run_id} to restore in {}", dir.display())),
+            None => journals
+                .into_iter()
+                .rev()
+                .find(|path| path.extension().is_some_and(|extension| extension == JOURNAL_EXTENSION))
+                .with_context(|| format!("no run to restore, {} holds no journal", dir.display())),
+        }
+    }
+
+    fn read(path: &Path) -> Result<Vec<Entry>> {
+        let file = File::open(path).with_context(|| format!("unable to open journal {}", path.display()))?;
+        BufReader::new(file)
+            .lines()
+            .map(|line| {
+                let line = line?;
+                serde_json::from_str(&line).with_context(|| format!("invalid line in journal {}: {line}", path.display()))
+            })
+            .collect()
+    }
+
+    /// `deduplicator restore` without a quarantine folder: undoes the last run, or `--run`, from
+    /// its journal, which is then set aside so that the next restore undoes the run before. A
+    /// journal with changes that failed to be undone keeps those, for the next restore to retry.
+    pub fn run(args: &RestoreArgs) -> Result<()> {
+        let dir = Self::default_dir().context("no home directory to find the journals in")?;
+        let journal = Self::find(&dir, args.run.as_deref())?;
+        let entries = Self::read(&journal)?;
+        let run_id = entries.first().map_or("-", |entry| entry.run_id.as_str());
+
+        let undone = Self::undo(&entries, args.dry_run);
+        for (entry, outcome) in &undone {
+            let change = match &entry.target {
+                Some(target) => format!("{} ({} {})", entry.path.display(), entry.action.noun(), target.display()),
+                None => format!("{} ({})", entry.path.display(), entry.action.noun()),
+            };
+            match outcome {
+                Ok(Undone::Restored) if args.dry_run => println!("{} {change}", "WOULD RESTORE:".cyan()),
+                Ok(Undone::Restored) => println!("{} {change}", "RESTORED:".green()),
+                Ok(Undone::Skipped(reason)) => println!("{} {change}: {reason}", "SKIPPED:".yellow()),
+                Err(e) => println!("{} {change}: {e:#}", "FAILED:".red()),
+            }
+        }
+        println!(
+            "\n{} {} of {} change(s) of run {run_id}.",
+            if args.dry_run { "Would restore" } else { "Restored" },
+            undone.iter().filter(|(_, outcome)| matches!(outcome, Ok(Undone::Restored))).count(),
+            undone.len(),
+        );
+
+        if args.dry_run {
+            return Ok(());
+        }
+        let failed = Self::set_aside(&journal, &undone)?;
+        if failed > 0 {
+            println!(
+                "{}",
+                format!("{failed} failed change(s) left in {}, run restore again to retry.", journal.display()).yellow()
+            );
+        }
+        Ok(())
+    }
+
+    /// Sets `journal` aside once every change in it was `undone` or skipped; otherwise rewrites
+    /// it with the changes that failed alone. Returns how many failed.
+    fn set_aside(journal: &Path, undone: &[(&Entry, Result<Undone>)]) -> Result<usize> {
+        // NOTE: back in the order of the run, as they are undone last first.
+        let failed = undone
+            .iter()
+            .rev()
+            .filter(|(_, outcome)| outcome.is_err())
+            .map(|(entry, _)| *entry)
+            .collect::<Vec<&Entry>>();
+        if failed.is_empty() {
+            let mut name = journal.file_name().unwrap_or_default().to_os_string();
+            name.push(RESTORED_SUFFIX);
+            fs::rename(journal, journal.with_file_name(name))
+                .with_context(|| format!("unable to set aside journal {}", journal.display()))?;
+            return Ok(0);
+        }
+
+        let lines = failed
+            .iter()
+            .map(|entry| Ok(format!("{}\n", serde_json::to_string(entry)?)))
+            .collect::<Result<String>>()?;
+        fs::write(journal, lines).with_context(|| format!("unable to rewrite journal {}", journal.display()))?;
+        Ok(failed.len())
+    }
+
+    /// Undoes `entries` last first, so that a pruned directory is back before the files that
+    /// were in it. Each entry comes with what became of it.
+    fn undo(entries: &[Entry], dry_run: bool) -> Vec<(&Entry, Result<Undone>)> {
+        entries
+            .iter()
+            .rev()
+            .map(|entry| (entry, Self::undo_entry(entry, dry_run)))
+            .collect()
+    }
+
+    fn undo_entry(entry: &Entry, dry_run: bool) -> Result<Undone> {
+        let path = &entry.path;
+        match (entry.action, &entry.target) {
+            (Action::DeletedDir, _) => {
+                if !dry_run {
+                    fs::create_dir_all(path).map_err(|e| Denial::explain(e, path))?;
+                }
+                Ok(Undone::Restored)
+            }
+            (Action::Trashed, _) => {
+                anyhow::ensure!(!path.exists(), "{} exists again", path.display());
+                Self::untrash(path, dry_run)
+            }
+            (Action::Moved, Some(moved_to)) => {
+                anyhow::ensure!(!path.exists(), "{} exists again", path.display());
+                anyhow::ensure!(moved_to.is_file(), "{} is gone", moved_to.display());
+                if !dry_run {
+                    Quarantine::move_file(moved_to, path)?;
+                }
+                Ok(Undone::Restored)
+            }
+            (Action::Deleted, Some(kept)) => {
+                anyhow::ensure!(fs::symlink_metadata(path).is_err(), "{} exists again", path.display());
+                let stat = fs::metadata(kept)
+                    .ok()
+                    .filter(fs::Metadata::is_file)
+                    .with_context(|| format!("the kept copy {} is gone", kept.display()))?;
+                // NOTE: journals written before the kept copy's stat was recorded are trusted.
+                let unchanged = entry.target_size.is_none_or(|size| size == stat.len())
+                    && entry.target_modified.as_ref().is_none_or(|modified| {
+                        stat.modified().ok().map(Self::timestamp).as_ref() == Some(modified)
+                    });
+                anyhow::ensure!(
+                    unchanged,
+                    "the kept copy {} changed since, it no longer holds the deleted contents",
+                    kept.display()
+                );
+                if !dry_run {
+                    Self::copy_back(kept, path)?;
+                }
+                Ok(Undone::Restored)
+            }
+            (Action::Linked | Action::Cloned, Some(kept)) => {
+                // NOTE: a link that was replaced or written to since is not this run's to undo.
+                anyhow::ensure!(
+                    Verifier::same_bytes(kept, path).unwrap_or(false),
+                    "{} no longer matches the kept copy {}",
+                    path.display(),
+                    kept.display()
+                );
+                if !dry_run {
+                    Self::copy_back(kept, path)?;
+                }
+                Ok(Undone::Restored)
+            }
+            (Action::Deleted, None) => Ok(Undone::Skipped("deleted for good, no copy of it was kept".to_string())),
+            (Action::Moved | Action::Linked | Action::Cloned, None) => {
+                anyhow::bail!("the journal does not say where to restore it from")
+            }
+        }
+    }
+
+    fn timestamp(modified: std::time::SystemTime) -> String {
+        DateTime::<Utc>::from(modified).to_rfc3339_opts(SecondsFormat::Nanos, true)
+    }
+
+    /// A copy of `kept` at `path`, staged next to it and renamed over whatever is there, so that
+    /// a link is replaced in one step.
+    fn copy_back(kept: &Path, path: &Path) -> Result<()> {
+        let name = path.file_name().context("no file name")?.to_string_lossy();
+        let staged = path.with_file_name(format!(".{name}.deduplicator-restore"));
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let copied = fs::copy(kept, &staged).and_then(|_| fs::rename(&staged, path));
+        if copied.is_err() {
+            let _ = fs::remove_file(&staged);
+        }
+        copied.map_err(|e| Denial::explain(e, path))
+    }
+
+    /// Puts the file last trashed from `path` back.
+    #[cfg(any(
+        target_os = "windows",
+        all(unix, not(target_os = "macos"), not(target_os = "ios"), not(target_os = "android"))
+    ))]
+    fn untrash(path: &Path, dry_run: bool) -> Result<Undone> {
+        // NOTE: the trash records canonical paths, the directory of the file is still around.
+        let original = path
+            .parent()
+            .and_then(|parent| fs::canonicalize(parent).ok())
+            .zip(path.file_name())
+            .map_or_else(|| path.to_path_buf(), |(parent, name)| parent.join(name));
+        let item = trash::os_limited::list()
+            .context("unable to list the trash")?
+            .into_iter()
+            .filter(|item| item.original_path() == original)
+            .max_by_key(|item| item.time_deleted)
+            .context("no longer in the trash")?;
+        if !dry_run {
+            trash::os_limited::restore_all([item]).context("unable to restore from the trash")?;
+        }
+        Ok(Undone::Restored)
+    }
+
+    #[cfg(not(any(
+        target_os = "windows",
+        all(unix, not(target_os = "macos"), not(target_os = "ios"), not(target_os = "android"))
+    )))]
+    fn untrash(_path: &Path, _dry_run: bool) -> Result<Undone> {
+        Ok(Undone::Skipped("in the trash, put it back from there".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Action, Entry, Journal, Undone};
+    use anyhow::Result;
+    use std::{fs, path::Path};
+    use tempfile::TempDir;
+
+    fn entry(action: Action, path: &Path, target: Option<&Path>) -> Entry {
+        let stat = target.and_then(|target| fs::metadata(target).ok());
+        Entry {
+            time: String::new(),
+            run_id: "run".to_string(),
+            action,
+            path: path.to_path_buf(),
+            target: target.map(Path::to_path_buf),
+            target_size: stat.as_ref().map(fs::Metadata::len),
+            target_modified: stat.and_then(|stat| stat.modified().ok()).map(Journal::timestamp),
+        }
+    }
+
+    #[test]
+    fn the_changes_of_a_run_are_undone_last_first() -> Result<()> {
+        let root = TempDir::new()?;
+        let path = |name: &str| root.path().join(name);
+        fs::create_dir_all(path("quarantine/old"))?;
+        fs::write(path("kept.txt"), b"same")?;
+        fs::write(path("quarantine/old/moved.txt"), b"same")?;
+        fs::write(path("edited.txt"), b"changed since")?;
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(path("kept.txt"), path("linked.txt"))?;
+        #[cfg(not(unix))]
+        fs::copy(path("kept.txt"), path("linked.txt"))?;
+
+        let entries = [
+            entry(Action::Deleted, &path("import/deleted.txt"), Some(&path("kept.txt"))),
+            entry(Action::DeletedDir, &path("import"), None),
+            entry(Action::Moved, &path("old/moved.txt"), Some(&path("quarantine/old/moved.txt"))),
+            entry(Action::Linked, &path("linked.txt"), Some(&path("kept.txt"))),
+            entry(Action::Linked, &path("edited.txt"), Some(&path("kept.txt"))),
+            entry(Action::Deleted, &path("sidecar.xmp"), None),
+        ];
+
+        let preview = Journal::undo(&entries, true);
+        assert!(matches!(preview[0].1, Ok(Undone::Skipped(_))));
+        assert!(preview[1].1.is_err());
+        assert!(preview[2..].iter().all(|(_, outcome)| matches!(outcome, Ok(Undone::Restored))));
+        assert!(!path("import").exists());
+
+        let undone = Journal::undo(&entries, false);
+        assert_eq!(undone[0].0.path, path("sidecar.xmp"));
+        assert_eq!(fs::read(path("import/deleted.txt"))?, b"same");
+        assert_eq!(fs::read(path("old/moved.txt"))?, b"same");
+        assert!(!path("quarantine/old/moved.txt").exists());
+        assert!(!fs::symlink_metadata(path("linked.txt"))?.is_symlink());
+        assert_eq!(fs::read(path("linked.txt"))?, b"same");
+        assert_eq!(fs::read(path("edited.txt"))?, b"changed since");
+        assert!(path("kept.txt").exists());
+
+        // NOTE: twice is once, what is back already is not overwritten.
+        assert!(Journal::undo(&entries[..1], false)[0].1.is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn changes_that_failed_stay_in_the_journal_for_another_restore() -> Result<()> {
+        let root = TempDir::new()?;
+        let path = |name: &str| root.path().join(name);
+        fs::write(path("kept.txt"), b"same")?;
+        fs::write(path("edited.txt"), b"same")?;
+        let entries = [
+            entry(Action::Deleted, &path("deleted.txt"), Some(&path("kept.txt"))),
+            entry(Action::Deleted, &path("stale.txt"), Some(&path("edited.txt"))),
+        ];
+        // NOTE: the kept copy no longer holds what was deleted, it is not copied back.
+        let modified = fs::metadata(path("edited.txt"))?.modified()?;
+        fs::write(path("edited.txt"), b"changed since")?;
+
+        let journal = path("20260101T100000.000000Z-run.jsonl");
+        let undone = Journal::undo(&entries, false);
+        assert!(undone[0].1.is_err());
+        assert_eq!(Journal::set_aside(&journal, &undone)?, 1);
+        assert_eq!(fs::read(path("deleted.txt"))?, b"same");
+        assert!(!path("stale.txt").exists());
+        let left = Journal::read(&journal)?;
+        assert_eq!(left.len(), 1);
+        assert_eq!(left[0].path, path("stale.txt"));
+
+        // NOTE: once the kept copy is put back as it was, the retry goes through.
+        fs::write(path("edited.txt"), b"same")?;
+        fs::File::options().write(true).open(path("edited.txt"))?.set_modified(modified)?;
+        let undone = Journal::undo(&left, false);
+        assert_eq!(Journal::set_aside(&journal, &undone)?, 0);
+        assert_eq!(fs::read(path("stale.txt"))?, b"same");
+        assert!(!journal.exists());
+        assert!(path("20260101T100000.000000Z-run.jsonl.restored").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn the_last_run_not_restored_yet_is_found() -> Result<()> {
+        let root = TempDir::new()?;
+        for name in [
+            "20260101T100000.000000Z-first.jsonl",
+            "20260102T100000.000000Z-second.jsonl",
+            "20260103T100000.000000Z-third.jsonl.restored",
+        ] {
+            fs::write(root.path().join(name), "")?;
+        }
+
+        let found = Journal::find(root.path(), None)?;
+        assert!(found.ends_with("20260102T100000.000000Z-second.jsonl"));
+        let found = Journal::find(root.path(), Some("first"))?;
+        assert!(found.ends_with("20260101T100000.000000Z-first.jsonl"));
+        assert!(Journal::find(root.path(), Some("third")).is_err());
+        assert!(Journal::find(&root.path().join("none"), None).is_err());
+
+        Ok(())
+    }
+}
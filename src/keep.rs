@@ -0,0 +1,148 @@
+use std::{cmp::Ordering, path::Path, str::FromStr, sync::Arc};
+
+use crate::{fileinfo::FileInfo, payload::Payload};
+
+/// Audio formats that store the recording without loss.
+const LOSSLESS_EXTENSIONS: [&str; 7] = ["flac", "wav", "aif", "aiff", "alac", "ape", "wv"];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum KeepPolicy {
+    /// Keep the copy whose path sorts first
+    #[default]
+    FirstAlpha,
+    /// Keep the most recently modified copy
+    Newest,
+    /// Keep the least recently modified copy
+    Oldest,
+    /// Keep the copy with the shortest path, usually the original rather than a nested backup
+    ShortestPath,
+    /// Keep the copy with the longest path, usually the one filed away most carefully
+    LongestPath,
+    /// Keep the best sounding copy: lossless first, then the highest bitrate, then the one with
+    /// the most metadata (the largest file)
+    Quality,
+    /// Keep a copy below the root named by `--label`, the first by path
+    Label(String),
+}
+
+impl FromStr for KeepPolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "first-alpha" => Ok(Self::FirstAlpha),
+            "newest" => Ok(Self::Newest),
+            "oldest" => Ok(Self::Oldest),
+            "shortest-path" => Ok(Self::ShortestPath),
+            "longest-path" => Ok(Self::LongestPath),
+            "quality" => Ok(Self::Quality),
+            _ => match s.strip_prefix("label=") {
+                Some(label) if !label.is_empty() => Ok(Self::Label(label.to_string())),
+                _ => anyhow::bail!(
+                    "expected first-alpha, newest, oldest, shortest-path, longest-path, quality or label=<name>, got {s}"
+                ),
+            },
+        }
+    }
+}
+
+impl KeepPolicy {
+    /// Index of the copy to keep. Ties are broken by path, so the same group always gets the
+    /// same keeper.
+    pub fn keeper(&self, group: &[Arc<FileInfo>]) -> Option<usize> {
+        self.best(group.iter().enumerate())
+    }
+
+    /// Like `keeper`, but a copy below `root` (e.g. a photo library) is kept whenever the group
+    /// has one.
+    pub fn keeper_within(&self, group: &[Arc<FileInfo>], root: &Path) -> Option<usize> {
+        self.best(
+            group
+                .iter()
+                .enumerate()
+                .filter(|(_, file)| file.path.starts_with(root)),
+        )
+        .or_else(|| self.keeper(group))
+    }
+
+    fn best<'a>(
+        &self,
+        candidates: impl Iterator<Item = (usize, &'a Arc<FileInfo>)>,
+    ) -> Option<usize> {
+        candidates
+            .min_by(|(_, a), (_, b)| {
+                let preference = match self {
+                    // NOTE: labels are resolved to a root by `Params::keeper`.
+                    Self::FirstAlpha | Self::Label(_) => Ordering::Equal,
+                    Self::Newest => b.modified.cmp(&a.modified),
+                    Self::Oldest => a.modified.cmp(&b.modified),
+                    Self::ShortestPath => Self::length(a).cmp(&Self::length(b)),
+                    Self::LongestPath => Self::length(b).cmp(&Self::length(a)),
+                    Self::Quality => Self::quality(b).cmp(&Self::quality(a)),
+                };
+                preference.then_with(|| a.path.cmp(&b.path))
+            })
+            .map(|(index, _)| index)
+    }
+
+    fn length(file: &FileInfo) -> usize {
+        file.path.as_os_str().len()
+    }
+
+    fn quality(file: &FileInfo) -> (bool, Option<u32>, u64) {
+        let lossless = file
+            .path
+            .extension()
+            .map(|extension| extension.to_string_lossy().to_lowercase())
+            .is_some_and(|extension| LOSSLESS_EXTENSIONS.contains(&extension.as_str()));
+        (lossless, Payload::bitrate(&file.path), file.size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::KeepPolicy;
+    use crate::{fileinfo::FileInfo, fixture::Fixture};
+    use anyhow::Result;
+    use std::{
+        fs,
+        sync::Arc,
+        time::{Duration, SystemTime},
+    };
+
+    #[test]
+    fn keeper_follows_the_policy_and_breaks_ties_by_path() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let file = |name: &str, age: u64| -> Result<Arc<FileInfo>> {
+            let mut file = FileInfo::new(fixture.file(name, b"same")?)?;
+            file.modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000 - age);
+            Ok(Arc::new(file))
+        };
+        let group = vec![file("b.txt", 10)?, file("c.txt", 50)?, file("a.txt", 10)?];
+
+        assert_eq!(KeepPolicy::FirstAlpha.keeper(&group), Some(2));
+        assert_eq!(KeepPolicy::Newest.keeper(&group), Some(2));
+        assert_eq!(KeepPolicy::Oldest.keeper(&group), Some(1));
+        assert_eq!(KeepPolicy::Oldest.keeper(&[]), None);
+
+        fs::create_dir(fixture.path("backup"))?;
+        let nested = vec![file("backup/a.txt", 10)?, file("b.txt", 10)?, file("a.txt", 10)?];
+        assert_eq!(KeepPolicy::ShortestPath.keeper(&nested), Some(2));
+        assert_eq!(KeepPolicy::LongestPath.keeper(&nested), Some(0));
+        assert_eq!("shortest-path".parse::<KeepPolicy>()?, KeepPolicy::ShortestPath);
+
+        let songs = vec![file("song.mp3", 10)?, file("song.flac", 50)?];
+        assert_eq!(KeepPolicy::Quality.keeper(&songs), Some(1));
+
+        let library = fixture.path("library");
+        fs::create_dir(&library)?;
+        let group = vec![file("a.txt", 10)?, file("library/b.txt", 50)?];
+        assert_eq!(KeepPolicy::Newest.keeper_within(&group, &library), Some(1));
+        assert_eq!(KeepPolicy::Newest.keeper_within(&group, &fixture.path("none")), Some(0));
+
+        assert_eq!("label=archive".parse::<KeepPolicy>()?, KeepPolicy::Label("archive".to_string()));
+        assert!("label=".parse::<KeepPolicy>().is_err());
+
+        Ok(())
+    }
+}
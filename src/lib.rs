@@ -0,0 +1,94 @@
+//! Finds duplicate files, from the command line or embedded in other tools. A scan is set up
+//! with [`Deduplicator::builder`], whose options mirror the command line flags, and yields the
+//! [`DuplicateGroup`]s it found:
+//!
+//! ```no_run
+//! let groups = deduplicator::Deduplicator::builder()
+//!     .root("/home/me/Pictures")
+//!     .min_size(1024)
+//!     .strict(true)
+//!     .build()?
+//!     .find()?;
+//! for group in groups {
+//!     println!("{} copies of {} bytes", group.files.len(), group.size);
+//! }
+//! # Ok::<(), anyhow::Error>(())
+//! ```
+//!
+//! Progress of the scan and the groups as they are found are told to the subscribers of the
+//! builder (see [`DeduplicatorBuilder::on`]).
+
+mod actionlog;
+mod allowlist;
+mod api;
+mod archive;
+mod cache;
+mod checkpoint;
+pub mod cli;
+mod config;
+mod copychain;
+mod denial;
+mod desktop;
+mod disk;
+mod doctor;
+mod dryrun;
+mod emptydirs;
+mod events;
+mod export;
+mod fileinfo;
+mod filetype;
+#[cfg(any(test, feature = "test-util"))]
+pub mod fixture;
+mod focus;
+mod formatter;
+mod gallery;
+mod hasher;
+mod heartbeat;
+mod interactive;
+mod journal;
+mod keep;
+mod link;
+mod linkcheck;
+mod location;
+mod logging;
+mod longpath;
+mod modes;
+mod notes;
+mod params;
+mod payload;
+mod placeholder;
+mod plan;
+mod preset;
+mod processor;
+mod project;
+mod protection;
+mod quarantine;
+mod removal;
+mod report;
+mod rootusage;
+mod rundir;
+mod runid;
+mod scanner;
+mod selection;
+mod server;
+mod sidecar;
+mod similar;
+mod spill;
+mod spotcheck;
+mod summary;
+mod survivor;
+mod symlinkview;
+mod syspath;
+mod usage;
+mod verify;
+
+pub use self::{
+    api::{Deduplicator, DeduplicatorBuilder, DuplicateGroup},
+    disk::DiskType,
+    events::Event,
+    filetype::MediaType,
+    hasher::{Algorithm, ReadStrategy},
+    heartbeat::Stage,
+    params::GroupOrder,
+    scanner::SymlinkMode,
+};
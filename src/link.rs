@@ -0,0 +1,337 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use colored::Colorize;
+use dashmap::DashMap;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    actionlog::ActionLog, archive::Archive, denial::Denial, doctor::Doctor, events::Events,
+    fileinfo::FileInfo, params::Params, survivor::Survivor, verify::Verifier,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LinkMode {
+    /// Symbolic links, which also work across filesystems
+    Sym,
+    /// Hard links, which leave every path a regular file; within one filesystem only
+    Hard,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DedupeMode {
+    /// Copy-on-write clones sharing their extents with the kept copy (btrfs, XFS, APFS, ReFS)
+    Reflink,
+}
+
+/// What a duplicate is turned into, see `--link` and `--dedupe`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Replace {
+    Link(LinkMode),
+    Dedupe(DedupeMode),
+}
+
+impl Replace {
+    fn noun(&self) -> &'static str {
+        match self {
+            Self::Link(_) => "links",
+            Self::Dedupe(_) => "clones",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum LinkOutcome {
+    Linked,
+    Skipped(String),
+    Failed(String),
+}
+
+#[derive(Debug)]
+pub struct Replacement {
+    pub duplicate: PathBuf,
+    pub keeper: PathBuf,
+    pub size: u64,
+    pub outcome: LinkOutcome,
+}
+
+/// Replaces every duplicate with a link to, or a clone of, the copy `--keep` chooses in its group
+/// (see `--link` and `--dedupe`).
+pub struct Linker;
+
+impl Linker {
+    pub fn replace_duplicates(
+        store: &DashMap<u128, Vec<Arc<FileInfo>>>,
+        with: Replace,
+        app_args: &Params,
+    ) -> Vec<Replacement> {
+        let mut groups = store
+            .iter()
+            .filter(|group| group.value().len() > 1)
+            .map(|group| group.value().clone())
+            .collect::<Vec<Vec<Arc<FileInfo>>>>();
+        groups.sort_by(|a, b| a[0].path.cmp(&b[0].path));
+
+        // NOTE: reflink support is probed once per directory, not once per file.
+        let mut capabilities: HashMap<PathBuf, std::result::Result<(), String>> = HashMap::new();
+
+        groups
+            .iter()
+            .flat_map(|group| {
+                let keeper = app_args
+                    .keeper(group)
+                    .map(|index| &group[index])
+                    .expect("duplicate groups are never empty.");
+                group
+                    .iter()
+                    .filter(|file| file.path != keeper.path)
+                    .map(|duplicate| Replacement {
+                        duplicate: duplicate.path.to_path_buf(),
+                        keeper: keeper.path.to_path_buf(),
+                        size: duplicate.size,
+                        outcome: Self::replace(duplicate, keeper, with, &mut capabilities, app_args),
+                    })
+                    .collect::<Vec<Replacement>>()
+            })
+            .collect()
+    }
+
+    fn replace(
+        duplicate: &FileInfo,
+        keeper: &FileInfo,
+        with: Replace,
+        capabilities: &mut HashMap<PathBuf, std::result::Result<(), String>>,
+        app_args: &Params,
+    ) -> LinkOutcome {
+        if Archive::contains(&duplicate.path) || Archive::contains(&keeper.path) {
+            return LinkOutcome::Skipped("inside a read-only archive".to_string());
+        }
+        if let Replace::Dedupe(DedupeMode::Reflink) = with {
+            if let Err(reason) = Self::can_clone(&keeper.path, &duplicate.path, capabilities) {
+                return LinkOutcome::Skipped(reason);
+            }
+        }
+        if !Verifier::identical(keeper, duplicate, app_args.verification())
+            .unwrap_or(false)
+        {
+            return LinkOutcome::Skipped("contents differ from the kept copy".to_string());
+        }
+        if let Err(e) = Survivor::ensure(&duplicate.path, [&*keeper.path]) {
+            return LinkOutcome::Skipped(format!("{e:#}"));
+        }
+        if let Some(Err(e)) = app_args.verify_keeper.map(|check| check.verify(keeper, duplicate)) {
+            return LinkOutcome::Skipped(format!("{e:#}"));
+        }
+
+        if app_args.dry_run {
+            app_args.dry_run_tally.record(&duplicate.path);
+            return LinkOutcome::Linked;
+        }
+
+        let linked = match with {
+            Replace::Link(LinkMode::Sym) => Self::symlink(&keeper.path, &duplicate.path),
+            Replace::Link(LinkMode::Hard) => Self::hardlink(&keeper.path, &duplicate.path),
+            Replace::Dedupe(DedupeMode::Reflink) => Self::reflink(&keeper.path, &duplicate.path),
+        };
+        match linked {
+            Ok(_) => LinkOutcome::Linked,
+            Err(e) => LinkOutcome::Failed(format!("{e:#}")),
+        }
+    }
+
+    /// The link is created next to the duplicate and renamed over it, so the duplicate is never
+    /// missing, even if the run is interrupted.
+    fn symlink(keeper: &Path, duplicate: &Path) -> Result<()> {
+        let name = duplicate
+            .file_name()
+            .context("duplicate has no file name")?
+            .to_string_lossy();
+        let staged = duplicate.with_file_name(format!(".{name}.deduplicator-link"));
+
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(keeper, &staged)?;
+        #[cfg(windows)]
+        std::os::windows::fs::symlink_file(keeper, &staged)?;
+
+        fs::rename(&staged, duplicate).map_err(|e| {
+            let _ = fs::remove_file(&staged);
+            Denial::explain(e, duplicate)
+        })
+    }
+
+    /// Staged like `symlink`. Fails for a duplicate on another filesystem than the kept copy.
+    fn hardlink(keeper: &Path, duplicate: &Path) -> Result<()> {
+        let name = duplicate
+            .file_name()
+            .context("duplicate has no file name")?
+            .to_string_lossy();
+        let staged = duplicate.with_file_name(format!(".{name}.deduplicator-link"));
+
+        fs::hard_link(keeper, &staged)
+            .context("unable to hard link, is the kept copy on another filesystem?")?;
+        fs::rename(&staged, duplicate).map_err(|e| {
+            let _ = fs::remove_file(&staged);
+            Denial::explain(e, duplicate)
+        })
+    }
+
+    /// Clones only share extents within one filesystem, and only on filesystems built for it.
+    fn can_clone(
+        keeper: &Path,
+        duplicate: &Path,
+        capabilities: &mut HashMap<PathBuf, std::result::Result<(), String>>,
+    ) -> std::result::Result<(), String> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let device = |path: &Path| fs::metadata(path).map(|metadata| metadata.dev()).ok();
+            if device(keeper) != device(duplicate) {
+                return Err("on another filesystem than the kept copy".to_string());
+            }
+        }
+
+        let directory = duplicate.parent().unwrap_or(Path::new(".")).to_path_buf();
+        capabilities
+            .entry(directory)
+            .or_insert_with_key(|directory| {
+                Doctor::probe_reflink(directory).map_err(|e| format!("{e:#}"))
+            })
+            .clone()
+    }
+
+    /// The clone is created next to the duplicate, takes over its permissions & modification
+    /// time and is then renamed over it.
+    fn reflink(keeper: &Path, duplicate: &Path) -> Result<()> {
+        let name = duplicate
+            .file_name()
+            .context("duplicate has no file name")?
+            .to_string_lossy();
+        let staged = duplicate.with_file_name(format!(".{name}.deduplicator-clone"));
+        let metadata = fs::metadata(duplicate)?;
+
+        reflink_copy::reflink(keeper, &staged)?;
+        let cloned = fs::set_permissions(&staged, metadata.permissions())
+            .and_then(|_| fs::File::options().write(true).open(&staged))
+            .and_then(|file| file.set_modified(metadata.modified()?))
+            .and_then(|_| fs::rename(&staged, duplicate));
+        if cloned.is_err() {
+            let _ = fs::remove_file(&staged);
+        }
+        cloned.map_err(|e| Denial::explain(e, duplicate))
+    }
+
+    pub fn report(replacements: &[Replacement], with: Replace, dry_run: bool, events: &Events) {
+        if replacements.is_empty() {
+            return;
+        }
+
+        let label = match (with, dry_run) {
+            (Replace::Link(_), false) => "LINKED",
+            (Replace::Dedupe(_), false) => "CLONED",
+            (Replace::Link(_), true) => "WOULD LINK",
+            (Replace::Dedupe(_), true) => "WOULD CLONE",
+        };
+        let (replaced, reclaimed) = match dry_run {
+            true => ("would be replaced", "would be reclaimed"),
+            false => ("replaced", "reclaimed"),
+        };
+        eprintln!("\n{}", format!("Replaced with {}:", with.noun()).bold());
+        for replacement in replacements {
+            let (duplicate, keeper) = (&replacement.duplicate, Some(replacement.keeper.as_path()));
+            match &replacement.outcome {
+                LinkOutcome::Linked => ActionLog::done(events, label, duplicate, keeper, None),
+                LinkOutcome::Skipped(reason) => ActionLog::skipped(
+                    events,
+                    duplicate,
+                    keeper,
+                    reason,
+                ),
+                LinkOutcome::Failed(reason) => ActionLog::failed(events, duplicate, keeper, reason),
+            }
+        }
+
+        let linked = replacements
+            .iter()
+            .filter(|replacement| replacement.outcome == LinkOutcome::Linked);
+        println!(
+            "\n{} file(s) {replaced} with {}, {} {reclaimed}.",
+            linked.clone().count(),
+            with.noun(),
+            bytesize::ByteSize::b(linked.map(|replacement| replacement.size).sum())
+        );
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::{DedupeMode, LinkMode, LinkOutcome, Linker, Replace};
+    use crate::{doctor::Doctor, fixture::Fixture, params::Params};
+    use anyhow::Result;
+    use dashmap::DashMap;
+    use std::fs;
+
+    #[test]
+    fn duplicates_become_symlinks_to_the_keeper() -> Result<()> {
+        let fixture = Fixture::new()?;
+
+        let store = DashMap::new();
+        store.insert(
+            1u128,
+            vec![
+                fixture.scanned("b.txt", b"same")?,
+                fixture.scanned("a.txt", b"same")?,
+                fixture.scanned("c.txt", b"diff")?,
+            ],
+        );
+
+        let params = Params {
+            link: Some(LinkMode::Sym),
+            ..Default::default()
+        };
+        let replacements = Linker::replace_duplicates(&store, Replace::Link(LinkMode::Sym), &params);
+        let outcome = |name: &str| {
+            &replacements
+                .iter()
+                .find(|r| r.duplicate.ends_with(name))
+                .unwrap()
+                .outcome
+        };
+
+        assert_eq!(replacements.len(), 2);
+        assert_eq!(outcome("b.txt"), &LinkOutcome::Linked);
+        assert!(matches!(outcome("c.txt"), LinkOutcome::Skipped(_)));
+        assert_eq!(
+            fs::read_link(fixture.path("b.txt"))?,
+            fixture.path("a.txt")
+        );
+        assert_eq!(fs::read(fixture.path("c.txt"))?, b"diff");
+
+        Ok(())
+    }
+
+    #[test]
+    fn duplicates_become_clones_where_the_filesystem_supports_it() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let file = |name: &str| fixture.scanned(name, b"same");
+        let store = DashMap::new();
+        store.insert(1u128, vec![file("a.txt")?, file("b.txt")?]);
+
+        let with = Replace::Dedupe(DedupeMode::Reflink);
+        let replacements = Linker::replace_duplicates(&store, with, &Params::default());
+
+        assert_eq!(replacements.len(), 1);
+        match Doctor::probe_reflink(fixture.root()) {
+            Ok(_) => assert_eq!(replacements[0].outcome, LinkOutcome::Linked),
+            Err(_) => assert!(matches!(replacements[0].outcome, LinkOutcome::Skipped(_))),
+        }
+        assert!(!fs::symlink_metadata(fixture.path("b.txt"))?.is_symlink());
+        assert_eq!(fs::read(fixture.path("b.txt"))?, b"same");
+        assert_eq!(fs::read_dir(fixture.root())?.count(), 2);
+
+        Ok(())
+    }
+}
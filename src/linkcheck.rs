@@ -0,0 +1,263 @@
+use anyhow::{Context, Result};
+use clap::Args;
+use colored::Colorize;
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
+
+use crate::{fileinfo::FileInfo, hasher::HashScheme, params::Params};
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct VerifyLinksArgs {
+    /// Directory holding the links to check
+    #[arg(value_hint = clap::ValueHint::DirPath, value_name = "dir")]
+    pub dir: PathBuf,
+    /// --action-log of earlier --link or --dedupe runs: every file it records as linked or cloned below <dir> is checked against the copy it was linked to
+    #[arg(long, value_hint = clap::ValueHint::FilePath, value_name = "log_path")]
+    pub log: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkState {
+    /// A symlink whose target is gone.
+    Broken,
+    /// A file recorded as linked that is gone.
+    Missing,
+    /// Still the content of the copy it was linked to, but no longer sharing its storage.
+    Unlinked,
+    /// Another content than the copy it was linked to.
+    Diverged,
+}
+
+impl LinkState {
+    fn label(&self) -> colored::ColoredString {
+        match self {
+            Self::Broken => "BROKEN".red(),
+            Self::Missing => "MISSING".red(),
+            Self::Unlinked => "UNLINKED".yellow(),
+            Self::Diverged => "DIVERGED".red(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Finding {
+    pub state: LinkState,
+    pub path: PathBuf,
+    pub target: PathBuf,
+}
+
+/// An `--action-log` line, as far as links go.
+#[derive(Debug, Deserialize)]
+struct LoggedAction {
+    outcome: String,
+    path: PathBuf,
+    target: Option<PathBuf>,
+}
+
+/// Audits what earlier `--link` & `--dedupe` runs left behind: symlinks whose target is gone
+/// and, given their `--action-log`, replaced files that no longer match the copy they were
+/// linked to.
+pub struct LinkCheck;
+
+impl LinkCheck {
+    pub fn run(args: &VerifyLinksArgs, app_args: &Params) -> Result<ExitCode> {
+        let dir = fs::canonicalize(&args.dir)
+            .with_context(|| format!("no such directory: {}", args.dir.display()))?;
+        let scheme = app_args.hash_scheme(0);
+
+        let (symlinks, mut findings) = Self::broken_symlinks(&dir);
+        let recorded = match &args.log {
+            Some(log) => Self::recorded(log, &dir)?,
+            None => BTreeMap::new(),
+        };
+        findings.extend(
+            recorded
+                .iter()
+                .filter_map(|(path, (target, cloned))| Self::check(path, target, *cloned, scheme)),
+        );
+        findings.sort_by(|a, b| a.path.cmp(&b.path));
+        findings.dedup_by(|a, b| a.path == b.path);
+
+        for finding in &findings {
+            println!(
+                "{}: {} -> {}",
+                finding.state.label(),
+                finding.path.display(),
+                finding.target.display()
+            );
+        }
+        let line = format!(
+            "\nChecked {symlinks} symlink(s) and {} recorded link(s): {} problem(s) found.",
+            recorded.len(),
+            findings.len()
+        );
+        match findings.is_empty() {
+            true => println!("{}", line.green()),
+            false => println!("{}", line.red()),
+        }
+
+        match findings.is_empty() {
+            true => Ok(ExitCode::SUCCESS),
+            false => Ok(ExitCode::FAILURE),
+        }
+    }
+
+    /// Every symlink below `dir`, and those whose target is gone.
+    fn broken_symlinks(dir: &Path) -> (usize, Vec<Finding>) {
+        let symlinks = ignore::WalkBuilder::new(dir)
+            .standard_filters(false)
+            .build()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.path_is_symlink())
+            .map(|entry| entry.into_path())
+            .collect::<Vec<PathBuf>>();
+
+        let broken = symlinks
+            .iter()
+            .filter(|link| fs::metadata(link).is_err())
+            .map(|link| Finding {
+                state: LinkState::Broken,
+                path: link.clone(),
+                target: fs::read_link(link).unwrap_or_default(),
+            })
+            .collect();
+        (symlinks.len(), broken)
+    }
+
+    /// Files below `dir` the action log records as linked or cloned, with the copy they were
+    /// linked to and whether they were cloned. A file recorded more than once counts as its
+    /// latest entry says.
+    fn recorded(log: &Path, dir: &Path) -> Result<BTreeMap<PathBuf, (PathBuf, bool)>> {
+        let text = fs::read_to_string(log)
+            .with_context(|| format!("unable to read action log {}", log.display()))?;
+
+        let mut recorded = BTreeMap::new();
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            let action: LoggedAction = serde_json::from_str(line)
+                .with_context(|| format!("malformed action log line: {line}"))?;
+            let Some(target) = action.target else {
+                continue;
+            };
+            if !action.path.starts_with(dir) {
+                continue;
+            }
+            match action.outcome.as_str() {
+                "linked" => recorded.insert(action.path, (target, false)),
+                "cloned" => recorded.insert(action.path, (target, true)),
+                _ => recorded.remove(&action.path),
+            };
+        }
+        Ok(recorded)
+    }
+
+    fn check(path: &Path, target: &Path, cloned: bool, scheme: HashScheme) -> Option<Finding> {
+        let finding = |state| {
+            Some(Finding {
+                state,
+                path: path.to_path_buf(),
+                target: target.to_path_buf(),
+            })
+        };
+
+        let Ok(metadata) = fs::symlink_metadata(path) else {
+            return finding(LinkState::Missing);
+        };
+        if metadata.is_symlink() && fs::metadata(path).is_err() {
+            return finding(LinkState::Broken);
+        }
+        // NOTE: a replaced file outliving the copy it was linked to still holds the content.
+        if !target.exists() || Self::same_file(path, target) {
+            return None;
+        }
+
+        match Self::same_content(path, target, scheme) {
+            // NOTE: clones never share an inode, only their extents, which are not checked.
+            Ok(true) if metadata.is_symlink() || cloned => None,
+            Ok(true) => finding(LinkState::Unlinked),
+            Ok(false) => finding(LinkState::Diverged),
+            Err(_) => finding(LinkState::Missing),
+        }
+    }
+
+    fn same_content(path: &Path, target: &Path, scheme: HashScheme) -> Result<bool> {
+        let (file, other) = (
+            FileInfo::new(path.to_path_buf())?,
+            FileInfo::new(target.to_path_buf())?,
+        );
+        Ok(file.size == other.size && file.hash(scheme)? == other.hash(scheme)?)
+    }
+
+    #[cfg(unix)]
+    fn same_file(path: &Path, target: &Path) -> bool {
+        use std::os::unix::fs::MetadataExt;
+        let id = |path: &Path| fs::metadata(path).map(|meta| (meta.dev(), meta.ino())).ok();
+        id(path).is_some() && id(path) == id(target)
+    }
+
+    #[cfg(not(unix))]
+    fn same_file(path: &Path, target: &Path) -> bool {
+        fs::canonicalize(path).ok() == fs::canonicalize(target).ok()
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::{LinkCheck, LinkState};
+    use crate::hasher::HashScheme;
+    use anyhow::Result;
+    use std::{fs, os::unix::fs::symlink};
+    use tempfile::TempDir;
+
+    #[test]
+    fn broken_and_diverged_links_are_found() -> Result<()> {
+        let root = TempDir::new()?;
+        let dir = fs::canonicalize(root.path())?;
+        let keeper = dir.join("a.txt");
+        fs::write(&keeper, b"same")?;
+        symlink(&keeper, dir.join("good.txt"))?;
+        symlink(dir.join("gone.txt"), dir.join("broken.txt"))?;
+        fs::hard_link(&keeper, dir.join("hard.txt"))?;
+        fs::write(dir.join("copied.txt"), b"same")?;
+        fs::write(dir.join("edited.txt"), b"diff")?;
+
+        let log = dir.join("actions.jsonl");
+        let lines = ["hard.txt", "copied.txt", "edited.txt", "gone.txt"]
+            .map(|name| {
+                format!(
+                    r#"{{"outcome":"linked","path":"{}","target":"{}"}}"#,
+                    dir.join(name).display(),
+                    keeper.display()
+                )
+            })
+            .join("\n");
+        fs::write(&log, lines)?;
+
+        let (symlinks, broken) = LinkCheck::broken_symlinks(&dir);
+        assert_eq!(symlinks, 2);
+        assert_eq!(broken.len(), 1);
+        assert!(broken[0].path.ends_with("broken.txt"));
+
+        let recorded = LinkCheck::recorded(&log, &dir)?;
+        let state = |name: &str| {
+            let (target, cloned) = &recorded[&dir.join(name)];
+            LinkCheck::check(
+                &dir.join(name),
+                target,
+                *cloned,
+                HashScheme::new(Default::default(), 0),
+            )
+            .map(|finding| finding.state)
+        };
+        assert_eq!(state("hard.txt"), None);
+        assert_eq!(state("copied.txt"), Some(LinkState::Unlinked));
+        assert_eq!(state("edited.txt"), Some(LinkState::Diverged));
+        assert_eq!(state("gone.txt"), Some(LinkState::Missing));
+
+        Ok(())
+    }
+}
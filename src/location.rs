@@ -0,0 +1,80 @@
+use anyhow::Result;
+use std::path::Path;
+
+/// Guards `--comparison-mode` against a staging and a target directory that are one and the
+/// same place: every staging file would match itself in target, and all of staging would be
+/// deleted.
+pub struct SameLocation;
+
+impl SameLocation {
+    /// Fails when `staging` & `target` (both canonical) are the same directory, or one lies
+    /// within the other, directly or through a bind mount of it. Symlinks are already resolved
+    /// by canonicalizing.
+    pub fn guard(staging: &Path, target: &Path) -> Result<()> {
+        let relation = if staging == target || Self::same_dir(staging, target) {
+            "is the same directory as"
+        } else if Self::within(staging, target) {
+            "lies within"
+        } else if Self::within(target, staging) {
+            "holds"
+        } else {
+            return Ok(());
+        };
+        anyhow::bail!(
+            "staging {} {relation} target {}: every staging file would match itself and be \
+             deleted, compare two separate directories",
+            staging.display(),
+            target.display()
+        )
+    }
+
+    /// Whether `path` lies below `dir`, or below another mount of it.
+    fn within(path: &Path, dir: &Path) -> bool {
+        path.starts_with(dir)
+            || path
+                .ancestors()
+                .skip(1)
+                .any(|ancestor| Self::same_dir(ancestor, dir))
+    }
+
+    #[cfg(unix)]
+    fn same_dir(path: &Path, other: &Path) -> bool {
+        use std::os::unix::fs::MetadataExt;
+        let id = |path: &Path| {
+            std::fs::metadata(path)
+                .map(|meta| (meta.dev(), meta.ino()))
+                .ok()
+        };
+        id(path).is_some() && id(path) == id(other)
+    }
+
+    /// Bind mounts cannot be told apart from their source without inode numbers.
+    #[cfg(not(unix))]
+    fn same_dir(path: &Path, other: &Path) -> bool {
+        path == other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SameLocation;
+    use anyhow::Result;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn staging_and_target_must_be_separate_directories() -> Result<()> {
+        let root = TempDir::new()?;
+        let base = fs::canonicalize(root.path())?;
+        let (staging, target) = (base.join("staging"), base.join("target"));
+        fs::create_dir_all(staging.join("inbox"))?;
+        fs::create_dir_all(&target)?;
+
+        assert!(SameLocation::guard(&staging, &target).is_ok());
+        assert!(SameLocation::guard(&staging, &staging).is_err());
+        assert!(SameLocation::guard(&staging.join("inbox"), &staging).is_err());
+        assert!(SameLocation::guard(&base, &target).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,93 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use std::{fs::OpenOptions, path::Path, sync::Mutex};
+use tracing::level_filters::LevelFilter;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogLevel {
+    /// Errors that ended the run
+    Error,
+    /// Files & directories that could not be read, and failed actions
+    Warn,
+    /// Stages, scan roots and every action taken on a file
+    #[default]
+    Info,
+    /// Every hash computed
+    Debug,
+    Trace,
+}
+
+impl From<LogLevel> for LevelFilter {
+    fn from(level: LogLevel) -> Self {
+        match level {
+            LogLevel::Error => LevelFilter::ERROR,
+            LogLevel::Warn => LevelFilter::WARN,
+            LogLevel::Info => LevelFilter::INFO,
+            LogLevel::Debug => LevelFilter::DEBUG,
+            LogLevel::Trace => LevelFilter::TRACE,
+        }
+    }
+}
+
+/// Structured log of the run (see `--log-file`): scan progress, hashes, errors & actions as
+/// JSON lines, for auditing long unattended runs afterwards. Unlike the `--action-log`, which
+/// only records what was done to files, it tells how the run got there. Events are dropped
+/// without a log file.
+pub struct Logging;
+
+impl Logging {
+    /// Starts appending events at `level` and above to `path`, for the rest of the run.
+    pub fn init(path: Option<&Path>, level: LogLevel) -> Result<()> {
+        let Some(path) = path else {
+            return Ok(());
+        };
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("unable to open log file {}", path.display()))?;
+
+        tracing_subscriber::fmt()
+            .json()
+            .with_ansi(false)
+            .with_max_level(LevelFilter::from(level))
+            .with_writer(Mutex::new(file))
+            .try_init()
+            .map_err(|error| anyhow::anyhow!(error))
+            .context("unable to set up logging")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LogLevel, Logging};
+    use anyhow::Result;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn events_are_appended_as_json_lines() -> Result<()> {
+        let root = TempDir::new()?;
+        let log = root.path().join("run.log");
+        Logging::init(Some(&log), LogLevel::Info)?;
+
+        tracing::info!(path = "/data/a.jpg", "deleted");
+        tracing::debug!(hash = "00ff", "hashed");
+
+        let lines = fs::read_to_string(&log)?
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<serde_json::Result<Vec<serde_json::Value>>>()?;
+        let deleted = lines
+            .iter()
+            .find(|line| line["fields"]["message"] == "deleted")
+            .expect("the info event is logged");
+        assert_eq!(deleted["level"], "INFO");
+        assert_eq!(deleted["fields"]["path"], "/data/a.jpg");
+        assert!(!lines
+            .iter()
+            .any(|line| line["fields"]["message"] == "hashed"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,111 @@
+use colored::Colorize;
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+/// Longest path the system calls take, in bytes (`PATH_MAX` less the terminating NUL). Windows
+/// paths are handed to the OS in extended `\\?\` syntax by the standard library, which lifts
+/// `MAX_PATH` up to the limit of the file system itself.
+#[cfg(target_os = "linux")]
+const MAX_PATH_BYTES: usize = 4095;
+#[cfg(windows)]
+const MAX_PATH_BYTES: usize = 32766;
+#[cfg(not(any(target_os = "linux", windows)))]
+const MAX_PATH_BYTES: usize = 1023;
+/// Longest file or directory name on every common file system.
+const MAX_NAME_BYTES: usize = 255;
+
+/// Paths the OS cannot act on because they are too long. They are left out of the run as they
+/// are found, and listed in a report section of their own, rather than failing with a cryptic
+/// "File name too long" once a removal or a move reaches them. Clones share what was recorded,
+/// the run carries one in `Params`.
+#[derive(Debug, Clone, Default)]
+pub struct LongPaths {
+    found: Arc<Mutex<Vec<PathBuf>>>,
+}
+
+impl LongPaths {
+    pub fn too_long(path: &Path) -> bool {
+        path.as_os_str().len() > MAX_PATH_BYTES
+            || path
+                .components()
+                .any(|component| component.as_os_str().len() > MAX_NAME_BYTES)
+    }
+
+    /// Whether `path` can be acted on; a path too long is recorded for the report instead.
+    pub fn admit(&self, path: &Path) -> bool {
+        if !Self::too_long(path) {
+            return true;
+        }
+        self.record(path);
+        false
+    }
+
+    pub fn record(&self, path: &Path) {
+        self.found.lock().unwrap().push(path.to_path_buf());
+    }
+
+    /// The paths recorded so far, sorted, once each.
+    pub fn recorded(&self) -> Vec<PathBuf> {
+        let mut paths = self.found.lock().unwrap().clone();
+        paths.sort();
+        paths.dedup();
+        paths
+    }
+
+    /// The report section listing the paths left out, if any.
+    pub fn report(&self) -> Option<String> {
+        let paths = self.recorded();
+        if paths.is_empty() {
+            return None;
+        }
+
+        let mut report = format!(
+            "\n{}\n",
+            format!(
+                "Paths too long to act on ({}), left out of the run:",
+                paths.len()
+            )
+            .yellow()
+            .bold()
+        );
+        for path in &paths {
+            report.push_str(&format!("  - {}\n", path.display()));
+        }
+        report.push_str(
+            &format!(
+                "Shorten them to {MAX_PATH_BYTES} bytes at most, with names of {MAX_NAME_BYTES} bytes at most, to include them."
+            )
+            .dimmed()
+            .to_string(),
+        );
+        Some(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LongPaths, MAX_NAME_BYTES, MAX_PATH_BYTES};
+    use std::path::PathBuf;
+
+    #[test]
+    fn paths_over_the_limits_are_left_out_and_reported() {
+        let fine = PathBuf::from("/data/photos/a.jpg");
+        let long_name = PathBuf::from("/data").join("n".repeat(MAX_NAME_BYTES + 1));
+        let mut deep = PathBuf::from("/data");
+        while deep.as_os_str().len() <= MAX_PATH_BYTES {
+            deep.push("d".repeat(100));
+        }
+
+        let long_paths = LongPaths::default();
+        assert!(long_paths.admit(&fine));
+        assert!(!long_paths.admit(&long_name));
+        assert!(!long_paths.admit(&deep));
+        assert!(!long_paths.admit(&deep));
+
+        assert_eq!(long_paths.recorded(), [deep, long_name]);
+        assert!(long_paths.report().is_some_and(|report| report.contains("Paths too long")));
+        assert!(LongPaths::default().report().is_none());
+    }
+}
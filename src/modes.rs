@@ -0,0 +1,123 @@
+use bytesize::ByteSize;
+use colored::Colorize;
+use std::{fmt, sync::Arc};
+
+use crate::fileinfo::FileInfo;
+
+/// What `--compare-modes` found: the groups fast mode built from partial hashes, against the
+/// groups left once each was hashed in full, as `--strict` would have.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ModeComparison {
+    pub fast_groups: u64,
+    /// Fast-mode groups holding more than one content.
+    pub false_positives: u64,
+    /// False positives without any duplicate left in them at all.
+    pub dissolved: u64,
+    /// Files fast mode grouped with a content other than the one most of their group shares.
+    pub misgrouped_files: u64,
+    /// Size of the samples fast mode hashed (see `--prehash-size`).
+    pub sample: u64,
+}
+
+impl ModeComparison {
+    /// Counts a fast-mode group, given the groups its full hashes split it into.
+    pub fn tally(&mut self, group: &[Arc<FileInfo>], subgroups: &[Vec<Arc<FileInfo>>]) {
+        self.fast_groups += 1;
+        if subgroups.len() < 2 {
+            return;
+        }
+
+        let largest = subgroups.iter().map(Vec::len).max().unwrap_or_default();
+        self.false_positives += 1;
+        self.misgrouped_files += (group.len() - largest) as u64;
+        if largest < 2 {
+            self.dissolved += 1;
+        }
+    }
+
+    pub fn rate(&self) -> f64 {
+        match self.fast_groups {
+            0 => 0.0,
+            groups => self.false_positives as f64 / groups as f64 * 100.0,
+        }
+    }
+}
+
+impl fmt::Display for ModeComparison {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "\n{}", "Fast vs strict mode:".bold())?;
+        writeln!(
+            f,
+            "  {} group(s) found by hashing the first, middle & last {} of each file",
+            self.fast_groups,
+            ByteSize::b(self.sample)
+        )?;
+        writeln!(
+            f,
+            "  {} confirmed by full hashes",
+            self.fast_groups - self.false_positives
+        )?;
+        writeln!(
+            f,
+            "  {} false positive(s) ({:.1}%): {} file(s) grouped with a different content, {} group(s) without any duplicate",
+            self.false_positives,
+            self.rate(),
+            self.misgrouped_files,
+            self.dissolved
+        )?;
+        match self.false_positives {
+            0 => write!(f, "{}", "Fast mode is safe on this data.".green()),
+            _ => write!(
+                f,
+                "{}",
+                "Fast mode grouped different files on this data, use --strict (or keep --verify) before acting on its groups.".yellow()
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ModeComparison;
+    use crate::{fileinfo::FileInfo, fixture::Fixture};
+    use anyhow::Result;
+    use std::sync::Arc;
+
+    #[test]
+    fn split_groups_count_as_false_positives() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let files = (0..5)
+            .map(|index| fixture.scanned(format!("{index}.bin"), b""))
+            .collect::<Result<Vec<Arc<FileInfo>>>>()?;
+        let (a, b, c, d, e) = (
+            files[0].clone(),
+            files[1].clone(),
+            files[2].clone(),
+            files[3].clone(),
+            files[4].clone(),
+        );
+
+        let mut comparison = ModeComparison::default();
+        comparison.tally(&[a.clone(), b.clone()], &[vec![a.clone(), b.clone()]]);
+        comparison.tally(
+            &[a.clone(), b.clone(), c.clone()],
+            &[vec![a.clone(), b.clone()], vec![c.clone()]],
+        );
+        comparison.tally(&[d.clone(), e.clone()], &[vec![d], vec![e]]);
+
+        assert_eq!(
+            comparison,
+            ModeComparison {
+                fast_groups: 3,
+                false_positives: 2,
+                dissolved: 1,
+                misgrouped_files: 2,
+                sample: 0,
+            }
+        );
+        assert!((comparison.rate() - 200.0 / 3.0).abs() < 1e-9);
+        assert!(comparison.to_string().contains("use --strict"));
+
+        Ok(())
+    }
+}
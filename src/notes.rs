@@ -0,0 +1,223 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::fileinfo::FileInfo;
+
+const NOTES_VERSION: u32 = 1;
+
+/// On-disk representation of the `--notes` session file.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct NotesFile {
+    version: u32,
+    groups: Vec<GroupNote>,
+}
+
+/// A note and tags attached to a duplicate group during interactive triage. Groups are found
+/// again by their paths, as their hash changes with the seed of every run.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GroupNote {
+    pub paths: Vec<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Skipped during triage (see `--snooze`), the group is hidden from runs until then.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub snoozed_until: Option<String>,
+}
+
+/// Notes & tags of the groups triaged so far (see `--notes`), saved after every change so a
+/// shared drive can be cleaned up over several sessions, by several people.
+#[derive(Default)]
+pub struct Notes {
+    path: Option<PathBuf>,
+    groups: Vec<GroupNote>,
+}
+
+impl Notes {
+    /// Without a path, notes only last for this run. A file that does not exist yet is created
+    /// with the first note.
+    pub fn load(path: Option<&Path>) -> Result<Self> {
+        let Some(path) = path else {
+            return Ok(Self::default());
+        };
+        if !path.exists() {
+            return Ok(Self {
+                path: Some(path.to_path_buf()),
+                groups: vec![],
+            });
+        }
+
+        let raw = fs::read_to_string(path)
+            .with_context(|| format!("unable to read notes {}", path.display()))?;
+        let parsed: NotesFile = serde_json::from_str(&raw)
+            .with_context(|| format!("invalid notes {}", path.display()))?;
+        if parsed.version != NOTES_VERSION {
+            anyhow::bail!(
+                "notes {} have version {}, expected {}",
+                path.display(),
+                parsed.version,
+                NOTES_VERSION
+            );
+        }
+
+        Ok(Self {
+            path: Some(path.to_path_buf()),
+            groups: parsed.groups,
+        })
+    }
+
+    /// The note of the group holding any of `paths`.
+    pub fn find(&self, paths: &[PathBuf]) -> Option<&GroupNote> {
+        self.groups
+            .iter()
+            .find(|note| note.paths.iter().any(|path| paths.contains(path)))
+    }
+
+    pub fn of(&self, group: &[Arc<FileInfo>]) -> Option<&GroupNote> {
+        self.find(&Self::paths(group))
+    }
+
+    /// Applies `note <text>` or `tag <name>` to `group`. Returns `false` for any other input.
+    pub fn annotate(&mut self, group: &[Arc<FileInfo>], input: &str) -> Result<bool> {
+        let (command, text) = input.trim().split_once(' ').unwrap_or((input.trim(), ""));
+        let text = text.trim();
+        if text.is_empty() || !matches!(command, "note" | "tag") {
+            return Ok(false);
+        }
+
+        let entry = self.entry(group);
+        match command {
+            "note" => entry.note = Some(text.to_string()),
+            _ if !entry.tags.iter().any(|tag| tag == text) => entry.tags.push(text.to_string()),
+            _ => {}
+        }
+
+        self.save()?;
+        Ok(true)
+    }
+
+    /// Hides `group` from triage & reports until `until`.
+    pub fn snooze(&mut self, group: &[Arc<FileInfo>], until: DateTime<Utc>) -> Result<()> {
+        self.entry(group).snoozed_until = Some(until.to_rfc3339());
+        self.save()
+    }
+
+    pub fn is_snoozed(&self, group: &[Arc<FileInfo>]) -> bool {
+        self.of(group)
+            .and_then(|note| note.snoozed_until.as_deref())
+            .and_then(|until| DateTime::parse_from_rfc3339(until).ok())
+            .is_some_and(|until| until > Utc::now())
+    }
+
+    /// Drops the snoozed groups from `store`. Returns the number of groups hidden.
+    pub fn prune_snoozed(&self, store: &DashMap<u128, Vec<Arc<FileInfo>>>) -> usize {
+        let before = store.len();
+        store.retain(|_, group| group.len() < 2 || !self.is_snoozed(group));
+        before - store.len()
+    }
+
+    /// The note of `group`, created if it has none, which now knows every path of the group.
+    fn entry(&mut self, group: &[Arc<FileInfo>]) -> &mut GroupNote {
+        let paths = Self::paths(group);
+        let index = match self
+            .groups
+            .iter()
+            .position(|note| note.paths.iter().any(|path| paths.contains(path)))
+        {
+            Some(index) => index,
+            None => {
+                self.groups.push(GroupNote::default());
+                self.groups.len() - 1
+            }
+        };
+
+        let entry = &mut self.groups[index];
+        for path in paths {
+            if !entry.paths.contains(&path) {
+                entry.paths.push(path);
+            }
+        }
+        entry
+    }
+
+    fn paths(group: &[Arc<FileInfo>]) -> Vec<PathBuf> {
+        group.iter().map(|file| file.path.to_path_buf()).collect()
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let json = serde_json::to_string_pretty(&NotesFile {
+            version: NOTES_VERSION,
+            groups: self.groups.clone(),
+        })?;
+        fs::write(path, json).with_context(|| format!("unable to write notes {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Notes;
+    use crate::{fixture::Fixture, params::Period};
+    use anyhow::Result;
+    use chrono::Utc;
+    use dashmap::DashMap;
+
+    #[test]
+    fn notes_survive_the_session_and_follow_the_group() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let file = |name: &str| fixture.scanned(name, b"same");
+        let group = vec![file("a.pdf")?, file("b.pdf")?];
+        let session = fixture.path("notes.json");
+
+        let mut notes = Notes::load(Some(&session))?;
+        assert!(notes.annotate(&group, "note check with Anna")?);
+        assert!(notes.annotate(&group, "tag tax docs")?);
+        assert!(notes.annotate(&group, "tag tax docs")?);
+        assert!(!notes.annotate(&group, "1,2")?);
+        assert!(!notes.annotate(&group, "note ")?);
+
+        // NOTE: one copy was deleted, the group is still recognized by the other.
+        let notes = Notes::load(Some(&session))?;
+        let note = notes.of(&group[1..]).expect("note was not saved");
+        assert_eq!(note.note.as_deref(), Some("check with Anna"));
+        assert_eq!(note.tags, vec!["tax docs"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn snoozed_groups_stay_hidden_until_the_period_is_over() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let file = |name: &str| fixture.scanned(name, b"same");
+        let (skipped, fresh) = (
+            vec![file("a.pdf")?, file("b.pdf")?],
+            vec![file("c.pdf")?, file("d.pdf")?],
+        );
+        let session = fixture.path("notes.json");
+        let period: Period = "7d".parse()?;
+        assert!("7 days".parse::<Period>().is_err());
+        assert!("15250000w".parse::<Period>().is_err());
+
+        let mut notes = Notes::load(Some(&session))?;
+        notes.snooze(&skipped, Utc::now() + period.0)?;
+        notes.snooze(&fresh, Utc::now() - period.0)?;
+
+        let store = DashMap::new();
+        store.insert(1, skipped.clone());
+        store.insert(2, fresh.clone());
+        assert_eq!(Notes::load(Some(&session))?.prune_snoozed(&store), 1);
+        assert!(store.contains_key(&2));
+
+        Ok(())
+    }
+}
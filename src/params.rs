@@ -1,38 +1,343 @@
-use std::{fs, path::PathBuf};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
 
-use anyhow::Result;
-use clap::{Parser, ValueHint};
+use anyhow::{Context, Result};
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, TimeDelta, Utc,
+};
+use clap::{error::ErrorKind, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum, ValueHint};
+
+use crate::{
+    archive::Archive,
+    cache::CacheArgs,
+    config::Config,
+    disk::DiskType,
+    doctor::DoctorArgs,
+    dryrun::DryRun,
+    emptydirs::EmptyDirs,
+    events::Events,
+    fileinfo::{FileInfo, PREHASH_SIZE},
+    filetype::MediaType,
+    focus::Focus,
+    hasher::{Algorithm, HashScheme, ReadStrategy, READ_BUFFER_SIZE},
+    keep::KeepPolicy,
+    link::{DedupeMode, LinkMode, Replace},
+    linkcheck::VerifyLinksArgs,
+    logging::LogLevel,
+    longpath::LongPaths,
+    placeholder::Placeholders,
+    plan::ApplyArgs,
+    preset::Preset,
+    quarantine::RestoreArgs,
+    removal::Removal,
+    report::ReportArgs,
+    rundir::{RunDir, Scratch},
+    scanner::SymlinkMode,
+    sidecar::SidecarAction,
+    spotcheck::SpotCheckArgs,
+    symlinkview::SymlinkViewArgs,
+    verify::{KeeperCheck, VerifyMode},
+};
 
 #[derive(Parser, Debug, Default, Clone)]
 #[command(author, version, about, long_about = None)]
 pub struct Params {
+    #[command(subcommand)]
+    pub command: Option<Command>,
     /// Exclude Filetypes [default = none]
     #[arg(short = 'T', long)]
     pub exclude_types: Option<String>,
     /// Filetypes to deduplicate [default = all]
     #[arg(short, long)]
     pub types: Option<String>,
-    /// Run Deduplicator on dir different from pwd (e.g., ~/Pictures )
-    #[arg(value_hint = ValueHint::DirPath, value_name = "scan_dir_path")]
+    /// Only deduplicate files whose content is of these kinds, regardless of extension (e.g., image,video)
+    #[arg(long = "type", value_enum, value_delimiter = ',')]
+    pub media_types: Vec<MediaType>,
+    /// Run Deduplicator on dir different from pwd (e.g., ~/Pictures ). A .tar or .zip file is
+    /// scanned in place as a read-only root
+    #[arg(value_hint = ValueHint::AnyPath, value_name = "scan_dir_path")]
     pub dir: Option<PathBuf>,
+    /// More directories scanned along with scan_dir_path into one set of duplicates (e.g.,
+    /// ~/Pictures /mnt/backup/Pictures); each file is shown relative to its root, tagged with it
+    #[arg(value_hint = ValueHint::DirPath, value_name = "more_scan_dir_paths", conflicts_with_all = ["comparison_mode", "files_from"])]
+    pub more_dirs: Vec<PathBuf>,
+    /// Scan this directory as well, with options of its own: exclude=<glob> (repeatable) and threads=<n>
+    /// (e.g., /mnt/nas:exclude=tmp/**,threads=2). Only these roots are scanned unless scan_dir_path is given
+    #[arg(long = "root", value_name = "dir[:option=value,...]", conflicts_with = "comparison_mode")]
+    pub roots: Vec<ScanRoot>,
     /// Delete files interactively
     #[arg(long, short)]
     pub interactive: bool,
     /// Minimum filesize of duplicates to scan (e.g., 100B/1K/2M/3G/4T).
     #[arg(long, short = 'm', default_value = "1b")]
     pub min_size: Option<String>,
+    /// Report duplicates among smaller files first and hash files of this size and above in a second pass (e.g., 1G)
+    #[arg(long, value_name = "size")]
+    pub defer_large: Option<String>,
     /// Max Depth to scan while looking for duplicates
     #[arg(long, short = 'D')]
     pub max_depth: Option<usize>,
     /// Min Depth to scan while looking for duplicates
     #[arg(long, short = 'd')]
     pub min_depth: Option<usize>,
-    /// Follow links while scanning directories
-    #[arg(long, short)]
+    /// Scan dotfiles and dot-directories (the default)
+    #[arg(long, overrides_with = "no_hidden")]
+    pub hidden: bool,
+    /// Leave dotfiles and dot-directories (.git, .cache, ...) out of the scan
+    #[arg(long, overrides_with = "hidden")]
+    pub no_hidden: bool,
+    /// Follow symlinks to directories while scanning, walking each directory once however many
+    /// links lead to it (loops included)
+    #[arg(long, short, visible_alias = "follow-symlinks")]
     pub follow_links: bool,
+    /// Do not descend into directories on another filesystem than the scan root's (network shares, bind mounts, backup disks)
+    #[arg(long, short = 'x')]
+    pub one_file_system: bool,
+    /// Download and compare online-only files of cloud drives (OneDrive, Dropbox, iCloud), which are otherwise left out and listed apart
+    #[arg(long)]
+    pub hydrate_and_hash: bool,
+    /// Also scan the files inside the .zip and .tar archives found, as <archive>/<member> paths, to find copies kept both loose and archived or in two archives; archive members are never deleted, linked or moved. Gzipped tarballs are compared as a whole
+    #[arg(long)]
+    pub scan_archives: bool,
+    /// What to do with symlinks to files: compare them as the file they point to, or skip them
+    #[arg(long, value_enum, default_value_t = SymlinkMode::File, value_name = "mode")]
+    pub symlinks: SymlinkMode,
+    /// Skip files ignored by .gitignore, .ignore & .git/info/exclude files, as git would
+    #[arg(long)]
+    pub gitignore: bool,
+    /// Compare the files listed in this file, one per line (e.g. from find or fd), instead of walking scan_dir_path; - reads the list from stdin
+    #[arg(long, value_hint = ValueHint::FilePath, value_name = "list_path", conflicts_with_all = ["comparison_mode", "defer_large", "roots"])]
+    pub files_from: Option<PathBuf>,
+    /// The --files-from list is NUL-delimited (find -print0, fd -0)
+    #[arg(long = "null", short = '0', requires = "files_from")]
+    pub null_delimited: bool,
+    /// Skip directories with this name wherever they appear (e.g., node_modules)
+    #[arg(long, value_name = "name", value_delimiter = ',')]
+    pub exclude_dir: Vec<String>,
+    /// Only scan paths matching this glob, relative to scan_dir_path (e.g., '*.jpg', 'photos/**'); repeatable
+    #[arg(long, value_name = "glob")]
+    pub include: Vec<String>,
+    /// Skip paths matching this glob, relative to scan_dir_path (e.g., node_modules, '/build'); repeatable
+    #[arg(long, value_name = "glob")]
+    pub exclude: Vec<String>,
     /// Guarantees that two files are duplicate (performs a full hash)
     #[arg(long, short = 's', default_value = "false")]
     pub strict: bool,
+    /// Without --strict, files are grouped by hashing this much of their start, middle and end (e.g., 4K/64K/1M)
+    #[arg(long, default_value = "16KiB", value_name = "size", value_parser = sample_size)]
+    pub prehash_size: Option<String>,
+    /// Hash used to group files: gxhash (fast, seeded anew every run), or blake3 / sha256 for
+    /// cryptographic confidence, whose --strict group hashes are the first 128 bits of the
+    /// usual checksum
+    #[arg(long, value_enum, default_value_t = Algorithm::Gxhash, value_name = "algorithm")]
+    pub algorithm: Algorithm,
+    /// How files hashed in full are read: mmap maps them into memory, buffered reads them in --buffer-size pieces (for network file systems & spinning disks)
+    #[arg(long, value_enum, default_value_t = ReadStrategy::Mmap, value_name = "strategy")]
+    pub read_strategy: ReadStrategy,
+    /// Buffer of --read-strategy buffered reads (e.g., 256K/1MiB/8MiB)
+    #[arg(long, default_value = "1MiB", value_name = "size")]
+    pub buffer_size: Option<String>,
+    /// Group files by partial hashes as fast mode does, then hash every group in full and report
+    /// how many fast-mode groups were false positives on this data; nothing is changed
+    #[arg(long, conflicts_with_all = ["strict", "interactive", "comparison_mode", "usage_view", "link", "dedupe", "delete", "collapse_copies"])]
+    pub compare_modes: bool,
+    /// Threads for every stage not given a count of its own [default = one per CPU]
+    #[arg(long, short = 'j', value_name = "threads")]
+    pub threads: Option<usize>,
+    /// Threads reading the metadata (and --ignore-metadata payloads) of the files found while scanning [default = --threads]
+    #[arg(long, value_name = "threads")]
+    pub scan_threads: Option<usize>,
+    /// Threads hashing files below 64 MiB read per file [default = --threads]
+    #[arg(long, value_name = "threads")]
+    pub hash_threads: Option<usize>,
+    /// Threads hashing large files (64 MiB and more read per file), apart from the ones hashing smaller files
+    #[arg(long, default_value = "2", value_name = "threads")]
+    pub large_file_threads: usize,
+    /// Hash one file at a time on each spinning disk, in path order, and in parallel on SSDs: auto tells them apart (on Linux), ssd or hdd treat every disk as one
+    #[arg(long, value_enum, default_value_t = DiskType::Auto, value_name = "type")]
+    pub disk_type: DiskType,
+    /// Compare photos (JPEG, PNG, HEIC) & songs (MP3) by their content alone, ignoring EXIF, XMP & IPTC metadata and ID3 tags
+    #[arg(long)]
+    pub ignore_metadata: bool,
+    /// Compare songs (MP3, FLAC, M4A) by their audio frames alone, ignoring ID3 & Vorbis tags, iTunes metadata and cover art, so a re-tagged song still matches the original
+    #[arg(long)]
+    pub media_aware: bool,
+    /// Also group images (JPEG, PNG) that look the same although their bytes differ (another format, resolution, compression or metadata) by perceptual hash; they are listed apart from the exact duplicates (in the text listing & --export) and never deleted, linked or moved
+    #[arg(long, conflicts_with_all = ["comparison_mode", "interactive", "usage_view", "max_memory", "defer_large"])]
+    pub images_similar: bool,
+    /// Bits out of 64 the perceptual hashes of two images may differ by for --images-similar to group them (0 for images that look identical)
+    #[arg(long, default_value_t = 6, value_name = "bits", requires = "images_similar")]
+    pub similarity_threshold: u32,
+    /// Only compare files that share the same filename (skips hashing everything else)
+    #[arg(long, default_value = "false")]
+    pub same_name_only: bool,
+    /// What to do with sidecar files (.xmp, .srt, .nfo, .cue) of deleted duplicates
+    #[arg(long, value_enum, default_value_t = SidecarAction::Keep)]
+    pub sidecars: SidecarAction,
+    /// Report per root (each --label root, and the scan directory for the rest) its total size, the size of its distinct contents, and how much of it is duplicated within the root and in other roots
+    #[arg(long, conflicts_with = "comparison_mode")]
+    pub root_usage: bool,
+    /// Browse redundant bytes per directory (ncdu-style) instead of listing duplicate groups
+    #[arg(long, default_value = "false")]
+    pub usage_view: bool,
+    /// Allowlist of intentional duplicates to hide from reports [default = <scan_dir_path>/intentional.toml]
+    #[arg(long, value_hint = ValueHint::FilePath, value_name = "allowlist_path")]
+    pub intentional: Option<PathBuf>,
+    /// Display a path prefix as a short label in reports (e.g., /mnt/remote/backup=NAS)
+    #[arg(long, value_name = "path=label")]
+    pub alias: Vec<PathAlias>,
+    /// How modification times are shown: iso (ISO 8601), relative (e.g., "3 days ago") or a strftime pattern
+    #[arg(long, default_value = "iso", value_name = "iso|relative|strftime")]
+    pub time_format: TimeFormat,
+    /// How to double-check duplicates before deleting, linking or moving them (rehash is skipped in --strict mode) [default = rehash on runs that delete, link or move files, none otherwise]
+    #[arg(long, value_enum)]
+    pub verify: Option<VerifyMode>,
+    /// Before removing the other copies of a group, check that the copy kept is still there, readable and unchanged since the scan, by its size & modification time (stat) or by hashing it again (hash); copies are left alone otherwise, whether they are deleted, linked, moved from staging or removed in interactive mode
+    #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "hash", value_name = "stat|hash")]
+    pub verify_keeper: Option<KeeperCheck>,
+    /// Threads for the full-content verification stage [default = --threads]
+    #[arg(long, value_name = "threads")]
+    pub verify_threads: Option<usize>,
+    /// Directory for this run's temporary files, removed on exit [default = system temp dir]
+    #[arg(long, value_hint = ValueHint::DirPath, value_name = "tmp_dir_path")]
+    pub tmpdir: Option<PathBuf>,
+    /// Memory the files grouped by size may take (e.g., 512M/4G); the rest is spilled to sorted files in --tmpdir and only files sharing their size come back, a few sizes at a time dropped once hashed, so scans of tens of millions of files fit [default = no limit]
+    #[arg(long, value_name = "size", conflicts_with_all = ["comparison_mode", "root_usage", "checkpoint"])]
+    pub max_memory: Option<String>,
+    /// Reuse full-content hashes of unchanged files across runs from this SQLite database (with --strict)
+    #[arg(long, value_hint = ValueHint::FilePath, value_name = "cache_path")]
+    pub cache: Option<PathBuf>,
+    /// Drop every hash stored in the --cache before scanning
+    #[arg(long, requires = "cache")]
+    pub cache_invalidate: bool,
+    /// Seed content hashes with this number rather than a random one, so that two runs on the same files print the same hashes and groups; a --cache made with another seed is started over
+    #[arg(long, value_name = "n", allow_negative_numbers = true)]
+    pub seed: Option<i64>,
+    /// Save the progress of the scan (the files found and their hashes) to this file every --checkpoint-interval, so that a stopped run can be taken up again with --resume
+    #[arg(long, value_hint = ValueHint::FilePath, value_name = "checkpoint_path", conflicts_with_all = ["comparison_mode", "defer_large"])]
+    pub checkpoint: Option<PathBuf>,
+    /// Take up the run saved in the --checkpoint where it stopped; it must be given the same options
+    #[arg(long, requires = "checkpoint")]
+    pub resume: bool,
+    /// Seconds between two saves of the --checkpoint
+    #[arg(long, default_value_t = 60, value_name = "seconds", requires = "checkpoint")]
+    pub checkpoint_interval: u64,
+    /// Replace every duplicate with a link to the copy chosen by --keep, then report each replacement
+    #[arg(long, value_enum, value_name = "kind", conflicts_with_all = ["interactive", "comparison_mode", "usage_view"])]
+    pub link: Option<LinkMode>,
+    /// Turn every duplicate into a clone of the copy chosen by --keep that shares its storage, keeping both paths, then report each file
+    #[arg(long, value_enum, value_name = "kind", conflicts_with_all = ["interactive", "comparison_mode", "usage_view", "link"])]
+    pub dedupe: Option<DedupeMode>,
+    /// Delete every copy but the one chosen by --keep, checking each against it first, then report each file
+    #[arg(long, conflicts_with_all = ["interactive", "comparison_mode", "usage_view", "link", "dedupe", "review"])]
+    pub delete: bool,
+    /// Delete numbered copies (e.g., "report (1).docx", "Copy of report.docx") sitting next to an identical original, after one confirmation
+    #[arg(long, conflicts_with_all = ["interactive", "comparison_mode", "usage_view", "link", "dedupe", "delete"])]
+    pub collapse_copies: bool,
+    /// Only report groups whose copies sit in different projects (directories holding .git,
+    /// Cargo.toml, package.json, ...)
+    #[arg(long)]
+    pub cross_project: bool,
+    /// Run everything but only print what would be deleted, linked or moved. Exits with 2 if
+    /// anything would have been changed, 0 otherwise
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Once duplicates are deleted, trashed or moved out of the scan root (the staging folder in comparison mode), remove the directories they left empty, deepest first
+    #[arg(long, conflicts_with = "usage_view")]
+    pub remove_empty_dirs: bool,
+    /// Delete, move or link files even when the scan root is, holds or lies within a system directory such as /, /usr or C:\Windows
+    #[arg(long)]
+    pub allow_system_paths: bool,
+    /// Bundle of settings for a common cleanup; options given explicitly override it
+    #[arg(long, value_enum, value_name = "preset")]
+    pub preset: Option<Preset>,
+    /// Read default values of the options from this TOML file instead of ~/.config/deduplicator/config.toml
+    #[arg(long, value_hint = ValueHint::FilePath, value_name = "config_path")]
+    pub config: Option<PathBuf>,
+    /// Apply this profile of the config file ([profiles.<name>]) over its defaults (e.g., photos)
+    #[arg(long, value_name = "name")]
+    pub profile: Option<String>,
+    /// Which copy of a duplicate group is kept by --delete, --link, --dedupe, the gallery and the
+    /// symlink view: first-alpha, newest, oldest, shortest-path, longest-path, quality
+    /// (lossless, then highest bitrate) or label=<name> (a copy below the root given that --label)
+    #[arg(long, default_value = "first-alpha", value_name = "policy")]
+    pub keep: KeepPolicy,
+    /// Name a root (a drive, a backup) so that every report row shows it and --keep can prefer it (e.g., archive=/mnt/archive)
+    #[arg(long, value_name = "name=dir")]
+    pub label: Vec<RootLabel>,
+    /// Prefer keeping copies inside this directory (e.g., a photo library) over copies elsewhere
+    #[arg(long, value_hint = ValueHint::DirPath, value_name = "library_path")]
+    pub library: Option<PathBuf>,
+    /// Write an HTML gallery of the duplicate groups, marking the copies that would be removed
+    #[arg(long, value_hint = ValueHint::FilePath, value_name = "gallery_path")]
+    pub gallery: Option<PathBuf>,
+    /// Let --preset photos compare RAW camera files (.cr2, .nef, .dng, ...), which it leaves alone otherwise
+    #[arg(long)]
+    pub include_raw: bool,
+    /// How duplicate groups are listed: text, uri-list (file:// URIs for file managers & automation), csv / tsv (one row per file, for spreadsheets), fdupes (blank-line-separated path lists, as fdupes & jdupes print them) or jsonl (one JSON object per group and line)
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text, value_name = "format")]
+    pub output: OutputFormat,
+    /// Print each duplicate group in the --output format as soon as it is confirmed, instead of all of them once the scan is over; groups come in the order they are confirmed rather than the --sort order
+    #[arg(long, conflicts_with_all = ["interactive", "comparison_mode", "usage_view"])]
+    pub stream: bool,
+    /// Order of the duplicate groups: by path, or with the largest size, count or wasted space first; files within a group are listed by path
+    #[arg(long, value_enum, default_value_t = GroupOrder::Path, value_name = "order")]
+    pub sort: GroupOrder,
+    /// List the groups in the opposite --sort order
+    #[arg(long)]
+    pub reverse: bool,
+    /// Keep the notes & tags given to groups in interactive mode in this JSON file, to show them again in later sessions and include them in --export
+    #[arg(long, value_hint = ValueHint::FilePath, value_name = "notes_path")]
+    pub notes: Option<PathBuf>,
+    /// Let groups be snoozed with `s` in interactive mode: they are hidden from later runs for this long (e.g. 12h, 7d, 2w), remembered in the --notes file
+    #[arg(long, value_name = "period", requires = "notes")]
+    pub snooze: Option<Period>,
+    /// Write the duplicate groups found to this JSON file
+    #[arg(long, value_hint = ValueHint::FilePath, value_name = "export_path")]
+    pub export: Option<PathBuf>,
+    /// Write the removals --keep proposes to this JSON or CSV file instead of acting on them, for a reviewer to mark each approved or rejected (or drop it) before `deduplicator apply` or --apply-plan
+    #[arg(
+        long,
+        visible_alias = "export-plan",
+        value_hint = ValueHint::FilePath,
+        value_name = "plan_path",
+        conflicts_with_all = ["interactive", "comparison_mode", "link", "dedupe", "collapse_copies"]
+    )]
+    pub review: Option<PathBuf>,
+    /// Carry out a plan written by --export-plan instead of scanning, skipping files whose size, modification time or hash changed since
+    #[arg(
+        long,
+        value_hint = ValueHint::FilePath,
+        value_name = "plan_path",
+        conflicts_with_all = ["interactive", "comparison_mode", "usage_view", "link", "dedupe", "collapse_copies", "delete", "review"]
+    )]
+    pub apply_plan: Option<PathBuf>,
+    /// Append every action taken on a file (deleted, moved, linked, skipped or failed) to this file as a JSON line, for auditing
+    #[arg(long, value_hint = ValueHint::FilePath, value_name = "log_path")]
+    pub action_log: Option<PathBuf>,
+    /// Append a structured log of the run (stages, scan roots, hashes, errors & actions) to this file as JSON lines
+    #[arg(long, value_hint = ValueHint::FilePath, value_name = "log_path")]
+    pub log_file: Option<PathBuf>,
+    /// Least severe events written to the --log-file: debug adds every hash computed
+    #[arg(long, value_enum, default_value_t = LogLevel::Info, value_name = "level", requires = "log_file")]
+    pub log_level: LogLevel,
+    /// Rewrite this JSON status file (stage, counts, ETA) while the run lasts, for monitoring a scheduled run
+    #[arg(long, value_hint = ValueHint::FilePath, value_name = "status_path")]
+    pub heartbeat: Option<PathBuf>,
+    /// Seconds between two updates of the --heartbeat file or the --dbus progress signal
+    #[arg(long, default_value_t = 10, value_name = "seconds")]
+    pub heartbeat_interval: u64,
+    /// Emit progress (every --heartbeat-interval) and the result as signals of io.github.fc7.Deduplicator on the D-Bus session bus, for system monitors & scripts
+    #[arg(long)]
+    pub dbus: bool,
+    /// Show a desktop notification with the result once the run is over
+    #[arg(long)]
+    pub notify: bool,
     /// Show Progress spinners & metrics
     #[arg(long, short = 'p', default_value = "false")]
     pub progress: bool,
@@ -43,9 +348,276 @@ pub struct Params {
     #[arg(long)]
     pub comparison_mode: bool,
     /// Target folder for comparison mode (required when --comparison-mode is used).
-    /// The staging folder is specified as the scan_dir_path argument.
-    #[arg(long, value_hint = ValueHint::DirPath, value_name = "target_dir_path")]
+    /// The staging folder is specified as the scan_dir_path argument. Either folder may be a
+    /// .tar or .zip file, scanned in place as a read-only root.
+    #[arg(long, value_hint = ValueHint::AnyPath, value_name = "target_dir_path")]
     pub target_dir: Option<PathBuf>,
+    /// Comparison mode: move matched staging files into this quarantine folder instead of deleting them.
+    /// Moves across filesystems are copied, synced and verified before the original is removed.
+    #[arg(long, value_hint = ValueHint::DirPath, value_name = "quarantine_dir_path")]
+    pub move_to: Option<PathBuf>,
+    /// Comparison mode: also list the staging files with no copy in target, which wiping staging would lose (included in --export as "unique")
+    #[arg(long, requires = "comparison_mode")]
+    pub report_unique: bool,
+    /// Move deleted duplicates to the OS trash instead of removing them for good. Files that
+    /// cannot be trashed are kept
+    #[arg(long, conflicts_with = "move_to")]
+    pub trash: bool,
+    /// Comparison mode: ask before removing staging files modified within this many minutes (0 disables the check).
+    #[arg(long, default_value_t = 10, value_name = "minutes")]
+    pub recent_minutes: u64,
+    /// Subscribers to the events of the run, set up by the embedding caller (see `Events`).
+    #[arg(skip)]
+    pub events: Events,
+    /// What the run removed or moved away, for --remove-empty-dirs to prune (see `EmptyDirs`).
+    #[arg(skip)]
+    pub empty_dirs: EmptyDirs,
+    /// Actions a --dry-run left out (see `DryRun`).
+    #[arg(skip)]
+    pub dry_run_tally: DryRun,
+    /// Paths too long to act on, left out of the run (see `LongPaths`).
+    #[arg(skip)]
+    pub long_paths: LongPaths,
+    /// Online-only files left out of the run (see `Placeholders`).
+    #[arg(skip)]
+    pub placeholders: Placeholders,
+    /// Where the run keeps its temporary artifacts, set up by the command line (see `RunDir`).
+    #[arg(skip)]
+    pub run_dir: Option<Arc<RunDir>>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Tree of duplicate groups for reading in a terminal
+    #[default]
+    Text,
+    /// One file:// URI per line (RFC 2483), groups separated by a comment line
+    UriList,
+    /// Duplicate groups filed under the artist / album folders they were sorted into
+    Albums,
+    /// One row per file: group, hash, path, size in bytes, ISO 8601 mtime & --label, comma-separated
+    Csv,
+    /// The csv columns, tab-separated
+    Tsv,
+    /// One path per line, each group followed by a blank line, the way fdupes & jdupes print them
+    Fdupes,
+    /// One JSON object per group and line: hash, size in bytes, paths & their --label
+    Jsonl,
+}
+
+impl OutputFormat {
+    /// Whether the listing is meant for other programs, which keeps every other report off
+    /// stdout.
+    pub fn machine_readable(&self) -> bool {
+        matches!(self, Self::UriList | Self::Csv | Self::Tsv | Self::Fdupes | Self::Jsonl)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum GroupOrder {
+    /// By the paths of their files
+    #[default]
+    Path,
+    /// Largest files first
+    Size,
+    /// Most copies first
+    Count,
+    /// Most space taken by the extra copies first
+    Wasted,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Check filesystem capabilities at a path and optionally write a support bundle
+    Doctor(DoctorArgs),
+    /// Re-check a random sample of groups from an --export and estimate its reliability
+    SpotCheck(SpotCheckArgs),
+    /// Experimental: lay out a directory as it would look after cleanup, as a tree of symbolic links to its files
+    View(SymlinkViewArgs),
+    /// Carry out a plan written with --review, once it has been reviewed
+    Apply(ApplyArgs),
+    /// Audit earlier --link and --dedupe runs: find broken symlinks, and linked files that no longer match the copy they were linked to
+    VerifyLinks(VerifyLinksArgs),
+    /// Show how many hashes a --cache holds and what they were computed with, or clear it
+    Cache(CacheArgs),
+    /// List the duplicate groups of an --export again, in any --output format, without scanning
+    Report(ReportArgs),
+    /// Undo the last run from its journal, or move the files of a --move-to quarantine back to the folder they were moved out of
+    Restore(RestoreArgs),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PathAlias {
+    pub prefix: PathBuf,
+    pub label: String,
+}
+
+impl FromStr for PathAlias {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (prefix, label) = s
+            .rsplit_once('=')
+            .context("expected an alias of the form <path>=<label>")?;
+
+        if prefix.is_empty() || label.is_empty() {
+            anyhow::bail!("expected an alias of the form <path>=<label>");
+        }
+
+        // NOTE: scanned paths are canonical, so aliases have to be as well to match.
+        let prefix = fs::canonicalize(prefix).unwrap_or_else(|_| PathBuf::from(prefix));
+
+        Ok(Self {
+            prefix,
+            label: label.to_string(),
+        })
+    }
+}
+
+/// A directory to scan given with `--root`, with the options that apply below it alone.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ScanRoot {
+    pub path: PathBuf,
+    /// Globs skipped below this root, on top of `--exclude`.
+    pub exclude: Vec<String>,
+    /// Threads scanning this root, instead of `--scan-threads`.
+    pub threads: Option<usize>,
+}
+
+impl ScanRoot {
+    fn options(&mut self, options: &str) -> Result<()> {
+        for option in options.split(',') {
+            match option.split_once('=') {
+                Some(("exclude", glob)) if !glob.is_empty() => self.exclude.push(glob.to_string()),
+                Some(("threads", threads)) => {
+                    self.threads = Some(
+                        threads
+                            .parse()
+                            .with_context(|| format!("invalid thread count: {threads}"))?,
+                    )
+                }
+                _ => anyhow::bail!("unknown root option {option}, expected exclude=<glob> or threads=<n>"),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ScanRoot {
+    type Err = anyhow::Error;
+
+    /// `<dir>` or `<dir>:<option>=<value>,...`; a colon not followed by options is part of the
+    /// path (e.g., `C:\data`).
+    fn from_str(s: &str) -> Result<Self> {
+        let mut root = Self::default();
+        let path = match s.rsplit_once(':') {
+            Some((path, options)) if options.contains('=') => {
+                root.options(options)?;
+                path
+            }
+            _ => s,
+        };
+
+        root.path = fs::canonicalize(path).with_context(|| format!("no such directory: {path}"))?;
+        Ok(root)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RootLabel {
+    pub name: String,
+    pub root: PathBuf,
+}
+
+impl FromStr for RootLabel {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, root) = s
+            .split_once('=')
+            .context("expected a label of the form <name>=<dir>")?;
+
+        if name.is_empty() || root.is_empty() {
+            anyhow::bail!("expected a label of the form <name>=<dir>");
+        }
+
+        let root = fs::canonicalize(root).with_context(|| format!("no such directory: {root}"))?;
+
+        Ok(Self {
+            name: name.to_string(),
+            root,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum TimeFormat {
+    #[default]
+    Iso,
+    Relative,
+    Strftime(String),
+}
+
+impl FromStr for TimeFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "iso" => Ok(Self::Iso),
+            "relative" => Ok(Self::Relative),
+            pattern => {
+                if StrftimeItems::new(pattern).any(|item| item == Item::Error) {
+                    anyhow::bail!("invalid strftime pattern: {pattern}");
+                }
+                Ok(Self::Strftime(pattern.to_string()))
+            }
+        }
+    }
+}
+
+/// A length of time: a number followed by m (minutes), h (hours), d (days) or w (weeks).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Period(pub TimeDelta);
+
+impl Period {
+    /// The time this long after now, `None` past the last date chrono represents.
+    pub fn end_from_now(&self) -> Option<DateTime<Utc>> {
+        Utc::now().checked_add_signed(self.0)
+    }
+}
+
+impl FromStr for Period {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+        let (count, unit) = s.split_at(split);
+        let count: i64 = count
+            .parse()
+            .with_context(|| format!("invalid period {s:?}, expected e.g. 12h, 7d or 2w"))?;
+        let delta = match unit.trim() {
+            "m" => TimeDelta::try_minutes(count),
+            "h" => TimeDelta::try_hours(count),
+            "d" => TimeDelta::try_days(count),
+            "w" => TimeDelta::try_weeks(count),
+            other => anyhow::bail!("unknown unit {other:?} in {s:?}, expected m, h, d or w"),
+        };
+        delta
+            .map(Self)
+            .filter(|period| period.end_from_now().is_some())
+            .with_context(|| format!("period {s:?} is too long"))
+    }
+}
+
+/// `--prehash-size` is checked as it is parsed, as a sample of 0 bytes would put every file of a
+/// size in one group.
+fn sample_size(value: &str) -> std::result::Result<String, String> {
+    match value.parse::<bytesize::ByteSize>() {
+        Ok(size) if size.0 > 0 => Ok(value.to_string()),
+        Ok(_) => Err("the samples need to be at least 1 byte".to_string()),
+        Err(e) => Err(e),
+    }
 }
 
 impl Params {
@@ -59,6 +631,259 @@ impl Params {
         }
     }
 
+    /// How this run hashes files, given its seed.
+    pub fn hash_scheme(&self, seed: i64) -> HashScheme {
+        let buffer_size = self
+            .buffer_size
+            .as_ref()
+            .and_then(|size| size.parse::<bytesize::ByteSize>().ok())
+            .map(|size| size.0 as usize)
+            .unwrap_or(READ_BUFFER_SIZE);
+        HashScheme::new(self.algorithm, seed).reading(self.read_strategy, buffer_size)
+    }
+
+    pub fn get_max_memory(&self) -> Option<usize> {
+        self.max_memory
+            .as_ref()
+            .and_then(|size| size.parse::<bytesize::ByteSize>().ok())
+            .map(|size| size.0 as usize)
+    }
+
+    /// Size of each sample fast mode hashes, 16 KiB unless `--prehash-size` says otherwise.
+    pub fn get_prehash_size(&self) -> u64 {
+        self.prehash_size
+            .as_ref()
+            .and_then(|size| size.parse::<bytesize::ByteSize>().ok())
+            .map(|size| size.0)
+            .unwrap_or(PREHASH_SIZE)
+    }
+
+    /// Only listing and interactive mode run in passes; the other modes need every file at once.
+    pub fn get_defer_large(&self) -> Option<u64> {
+        if self.comparison_mode || self.usage_view {
+            return None;
+        }
+
+        self.defer_large
+            .as_ref()
+            .and_then(|size| size.parse::<bytesize::ByteSize>().ok())
+            .map(|size| size.0)
+    }
+
+    /// Arguments for the second pass of `--defer-large`: only the files the first pass left out.
+    pub fn deferred_pass(&self) -> Option<Self> {
+        let threshold = self.get_defer_large()?;
+        let min_size = self.get_min_size().unwrap_or_default().max(threshold);
+        Some(Self {
+            defer_large: None,
+            min_size: Some(format!("{min_size}b")),
+            ..self.clone()
+        })
+    }
+
+    /// Parses the command line, bare or after a `Focus` subcommand, over the defaults of the
+    /// config file & --profile, then applies the --preset, if any.
+    pub fn load() -> Self {
+        Self::load_from(std::env::args_os(), Config::default_path())
+    }
+
+    /// `load` from `args`, with the config file at `default_config` unless --config names one.
+    pub fn load_from<I, T>(args: I, default_config: Option<PathBuf>) -> Self
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<std::ffi::OsString> + Clone,
+    {
+        let args = args.into_iter().map(Into::into).collect::<Vec<std::ffi::OsString>>();
+        let command = Self::configured(&args, default_config)
+            .unwrap_or_else(|e| Self::command().error(ErrorKind::InvalidValue, format!("{e:#}")).exit());
+        let top = Focus::attach(command.clone()).get_matches_from(args);
+        let (matches, focus) = match Focus::chosen(&top) {
+            Some((focus, options)) => {
+                if let Some(misfit) = focus.misfit(&command, &top, options) {
+                    Self::command().error(ErrorKind::ArgumentConflict, misfit).exit();
+                }
+                (options, Some(focus))
+            }
+            None => (&top, None),
+        };
+        let mut params = Self::from_arg_matches(matches).unwrap_or_else(|e| e.exit());
+        params.comparison_mode |= focus == Some(Focus::Compare);
+        if let Some(preset) = params.preset {
+            preset.apply(&mut params, matches);
+        }
+        if params.stream && params.output == OutputFormat::Albums {
+            Self::command()
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    "--stream prints groups as they are confirmed, albums are only complete once the scan is over",
+                )
+                .exit();
+        }
+        if params.media_aware && !cfg!(feature = "media-aware") {
+            Self::command()
+                .error(
+                    ErrorKind::InvalidValue,
+                    "--media-aware (which --preset music turns on) needs a build with the media-aware feature",
+                )
+                .exit();
+        }
+        if let KeepPolicy::Label(name) = &params.keep {
+            if params.labelled(name).is_none() {
+                Self::command()
+                    .error(
+                        ErrorKind::ValueValidation,
+                        format!("--keep label={name} needs a matching --label {name}=<dir>"),
+                    )
+                    .exit();
+            }
+        }
+        params
+    }
+
+    /// The command line parser, with the config file's values as defaults. The config & profile
+    /// to read are looked up first, leniently, as they decide how the rest is parsed.
+    fn configured(args: &[std::ffi::OsString], default_config: Option<PathBuf>) -> Result<clap::Command> {
+        let located = Self::command()
+            .ignore_errors(true)
+            .try_get_matches_from(args)
+            .ok()
+            .and_then(|matches| Self::from_arg_matches(&matches).ok())
+            .unwrap_or_default();
+        match Config::locate(located.config.as_deref(), default_config)? {
+            Some(config) => config.apply(Self::command(), located.profile.as_deref()),
+            None if located.profile.is_some() => {
+                anyhow::bail!("--profile needs a config file, none found (see --config)")
+            }
+            None => Ok(Self::command()),
+        }
+    }
+
+    /// Runs that only report trust the hash, those that delete, link or move files (or show
+    /// what they would) rehash them unless --verify says otherwise. A full-content rehash adds
+    /// nothing once --strict already hashed the whole file, while --compare-modes needs one
+    /// whatever --verify says.
+    pub fn verification(&self) -> VerifyMode {
+        let verify = self.verify.unwrap_or(match self.changes_files() || self.apply_plan.is_some() {
+            true => VerifyMode::Rehash,
+            false => VerifyMode::None,
+        });
+        match (self.strict, self.compare_modes, verify) {
+            (true, _, VerifyMode::Rehash) => VerifyMode::None,
+            (_, true, VerifyMode::None) => VerifyMode::Rehash,
+            (_, _, mode) => mode,
+        }
+    }
+
+    /// Size of the pool of a stage given `stage` threads of its own, `--threads` otherwise; 0
+    /// leaves it to rayon (one thread per CPU).
+    pub fn stage_threads(&self, stage: Option<usize>) -> usize {
+        stage.or(self.threads).unwrap_or_default()
+    }
+
+    /// A scratch directory for the `name`d artifacts of a part of the run: in the run directory,
+    /// or for a run set up without one (through the library) in one of its own under --tmpdir.
+    pub fn scratch(&self, name: &str) -> Result<Scratch> {
+        let run_dir = match &self.run_dir {
+            Some(run_dir) => Arc::clone(run_dir),
+            None => Arc::new(RunDir::create(self.tmpdir.as_deref())?),
+        };
+        run_dir.scratch(name)
+    }
+
+    /// The same options for another run: what a run records (see `EmptyDirs`, `DryRun`,
+    /// `LongPaths` & `Placeholders`) starts out empty instead of shared with this one.
+    pub fn next_run(&self) -> Self {
+        Self {
+            empty_dirs: EmptyDirs::default(),
+            dry_run_tally: DryRun::default(),
+            long_paths: LongPaths::default(),
+            placeholders: Placeholders::default(),
+            ..self.clone()
+        }
+    }
+
+    pub fn removal(&self) -> Removal {
+        match (self.dry_run, self.trash) {
+            (true, _) => Removal::DryRun,
+            (false, true) => Removal::Trash,
+            (false, false) => Removal::Delete,
+        }
+    }
+
+    /// Index of the copy to keep in `group`, following --keep and --library. Copies inside
+    /// archives are only kept when the group has no other: they cannot be removed, and a loose
+    /// copy is not removed because an archive holds one too.
+    pub fn keeper(&self, group: &[Arc<FileInfo>]) -> Option<usize> {
+        let loose = group
+            .iter()
+            .filter(|file| !Archive::contains(&file.path))
+            .cloned()
+            .collect::<Vec<Arc<FileInfo>>>();
+        if loose.is_empty() || loose.len() == group.len() {
+            return self.policy_keeper(group);
+        }
+        let kept = &loose[self.policy_keeper(&loose)?];
+        group.iter().position(|file| file.path == kept.path)
+    }
+
+    fn policy_keeper(&self, group: &[Arc<FileInfo>]) -> Option<usize> {
+        if let KeepPolicy::Label(name) = &self.keep {
+            return match self.labelled(name) {
+                Some(root) => KeepPolicy::FirstAlpha.keeper_within(group, root),
+                None => KeepPolicy::FirstAlpha.keeper(group),
+            };
+        }
+
+        match &self.library {
+            Some(library) => {
+                let library = fs::canonicalize(library).unwrap_or_else(|_| library.clone());
+                self.keep.keeper_within(group, &library)
+            }
+            None => self.keep.keeper(group),
+        }
+    }
+
+    /// Root given the label `name` with --label.
+    pub fn labelled(&self, name: &str) -> Option<&Path> {
+        self.label
+            .iter()
+            .find(|label| label.name == name)
+            .map(|label| label.root.as_path())
+    }
+
+    /// Name of the innermost --label root `path` is below.
+    pub fn label_of(&self, path: &Path) -> Option<&str> {
+        self.label
+            .iter()
+            .filter(|label| path.starts_with(&label.root))
+            .max_by_key(|label| label.root.components().count())
+            .map(|label| label.name.as_str())
+    }
+
+    /// Whether the run may delete, move or link files.
+    pub fn destructive(&self) -> bool {
+        !self.dry_run && self.changes_files()
+    }
+
+    /// Whether the scan is followed by deleting, moving or linking files, or by showing what
+    /// would be with --dry-run.
+    fn changes_files(&self) -> bool {
+        self.delete
+            || self.replacement().is_some()
+            || self.collapse_copies
+            || self.interactive
+            || self.comparison_mode
+    }
+
+    /// What duplicates are replaced with, if --link or --dedupe is given.
+    pub fn replacement(&self) -> Option<Replace> {
+        match (self.link, self.dedupe) {
+            (Some(mode), _) => Some(Replace::Link(mode)),
+            (None, Some(mode)) => Some(Replace::Dedupe(mode)),
+            (None, None) => None,
+        }
+    }
+
     pub fn get_directory(&self) -> Result<PathBuf> {
         let current_dir = std::env::current_dir()?;
         let dir_path = self.dir.as_ref().unwrap_or(&current_dir).as_path();
@@ -66,6 +891,51 @@ impl Params {
         Ok(dir)
     }
 
+    /// Every directory the scan walks: scan_dir_path (the working directory by default) unless
+    /// only `--root`s are given, the other positional directories, then each `--root`. Roots
+    /// may not overlap, or the files below both would be found twice and match themselves.
+    pub fn scan_roots(&self) -> Result<Vec<ScanRoot>> {
+        let mut roots = vec![];
+        if self.dir.is_some() || self.roots.is_empty() {
+            roots.push(ScanRoot {
+                path: self.get_directory()?,
+                ..Default::default()
+            });
+        }
+        for dir in &self.more_dirs {
+            roots.push(ScanRoot {
+                path: fs::canonicalize(dir)
+                    .with_context(|| format!("no such directory: {}", dir.display()))?,
+                ..Default::default()
+            });
+        }
+        roots.extend(self.roots.iter().cloned());
+
+        for (index, root) in roots.iter().enumerate() {
+            if let Some(outer) = roots[..index]
+                .iter()
+                .find(|other| root.path.starts_with(&other.path) || other.path.starts_with(&root.path))
+            {
+                anyhow::bail!(
+                    "scan roots {} and {} overlap, scan only the outer one",
+                    outer.path.display(),
+                    root.path.display()
+                );
+            }
+        }
+        Ok(roots)
+    }
+
+    /// The innermost scan root `path` is below, when more than one root is scanned.
+    pub fn scan_root_of(&self, path: &Path) -> Option<PathBuf> {
+        let roots = self.scan_roots().ok().filter(|roots| roots.len() > 1)?;
+        roots
+            .into_iter()
+            .map(|root| root.path)
+            .filter(|root| path.starts_with(root))
+            .max_by_key(|root| root.components().count())
+    }
+
     pub fn get_staging_directory(&self) -> Result<PathBuf> {
         self.get_directory()
     }
@@ -0,0 +1,676 @@
+use anyhow::Result;
+use std::{
+    collections::{HashSet, VecDeque},
+    fs,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+const JPEG_SOI: [u8; 2] = [0xFF, 0xD8];
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+const FLAC_SIGNATURE: [u8; 4] = *b"fLaC";
+/// Extensions of the MPEG-4 files read as songs; videos share their `ftyp` signature.
+const M4A_EXTENSIONS: [&str; 3] = ["m4a", "m4b", "m4p"];
+const HEIC_EXTENSIONS: [&str; 3] = ["heic", "heif", "hif"];
+/// HEIC items holding metadata rather than pixels: EXIF, and XMP stored as a MIME item.
+const HEIC_METADATA_ITEMS: [&[u8; 4]; 2] = [b"Exif", b"mime"];
+/// Largest HEIC `meta` box read, which locates & describes the items but holds none of them.
+const HEIC_MAX_META: u64 = 16 << 20;
+/// JPEG segments holding metadata: APP1 (EXIF, XMP), APP13 (IPTC, Photoshop) and comments.
+const JPEG_METADATA_MARKERS: [u8; 3] = [0xE1, 0xED, 0xFE];
+/// PNG chunks holding metadata rather than pixels or how to render them.
+const PNG_METADATA_CHUNKS: [&[u8; 4]; 5] = [b"tEXt", b"zTXt", b"iTXt", b"eXIf", b"tIME"];
+const ID3V2_HEADER_SIZE: u64 = 10;
+const ID3V1_SIZE: u64 = 128;
+/// Bitrates in kbit/s by the 4 bit index of an MPEG audio frame header, for layer III.
+const MPEG1_BITRATES: [u32; 15] = [0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320];
+const MPEG2_BITRATES: [u32; 15] = [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160];
+
+/// Formats with a payload, told apart by their first bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Jpeg,
+    Png,
+    Heic,
+    Mp3,
+    Flac,
+    M4a,
+}
+
+impl Format {
+    /// Formats stripped of their metadata by `--ignore-metadata`.
+    pub const METADATA: [Self; 4] = [Self::Jpeg, Self::Png, Self::Heic, Self::Mp3];
+    /// Songs reduced to their audio frames by `--media-aware`.
+    pub const AUDIO: [Self; 3] = [Self::Mp3, Self::Flac, Self::M4a];
+}
+
+/// The content of a photo or song without its metadata (see `--ignore-metadata` and
+/// `--media-aware`), so a photo whose EXIF or XMP was rewritten by a sync tool, or a song that
+/// was re-tagged, still matches the original. Understands JPEG, PNG, HEIC and MP3 files on
+/// disk, and FLAC & M4A songs in builds with the `media-aware` feature; anything else, archive
+/// members included, has no payload and is compared byte for byte.
+pub struct Payload;
+
+impl Payload {
+    /// The format of the file at `path`, if it has a payload.
+    pub fn format(path: &Path) -> Option<Format> {
+        let mut magic = [0u8; 8];
+        fs::File::open(path).ok()?.read_exact(&mut magic).ok()?;
+        Self::format_of(path, &magic)
+    }
+
+    fn format_of(path: &Path, magic: &[u8; 8]) -> Option<Format> {
+        match *magic {
+            _ if magic[..2] == JPEG_SOI => Some(Format::Jpeg),
+            PNG_SIGNATURE => Some(Format::Png),
+            _ if magic[..4] == FLAC_SIGNATURE => Some(Format::Flac),
+            _ if magic[4..] == *b"ftyp" && Self::has_extension(path, &M4A_EXTENSIONS) => {
+                Some(Format::M4a)
+            }
+            _ if magic[4..] == *b"ftyp" && Self::has_extension(path, &HEIC_EXTENSIONS) => {
+                Some(Format::Heic)
+            }
+            _ if Self::is_mp3(magic) => Some(Format::Mp3),
+            _ => None,
+        }
+    }
+
+    fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| extensions.contains(&extension.to_ascii_lowercase().as_str()))
+    }
+
+    /// Byte ranges of the file that are kept, in file order.
+    pub fn ranges(path: &Path) -> Option<Vec<(u64, u64)>> {
+        let mut file = fs::File::open(path).ok()?;
+        let size = file.metadata().ok()?.len();
+        let mut magic = [0u8; 8];
+        file.read_exact(&mut magic).ok()?;
+
+        match Self::format_of(path, &magic)? {
+            Format::Jpeg => Self::jpeg_ranges(&mut file, size).ok(),
+            Format::Png => Self::png_ranges(&mut file, size).ok(),
+            Format::Heic => Self::heic_ranges(&mut file, size).ok(),
+            Format::Mp3 => Self::mp3_ranges(&mut file, size).ok(),
+            #[cfg(feature = "media-aware")]
+            Format::Flac => Self::flac_ranges(&mut file, size).ok(),
+            #[cfg(feature = "media-aware")]
+            Format::M4a => Self::m4a_ranges(&mut file, size).ok(),
+            #[cfg(not(feature = "media-aware"))]
+            Format::Flac | Format::M4a => None,
+        }
+    }
+
+    /// Bitrate of an MP3 in kbit/s, read from its first audio frame.
+    pub fn bitrate(path: &Path) -> Option<u32> {
+        let mut file = fs::File::open(path).ok()?;
+        let size = file.metadata().ok()?.len();
+        let mut magic = [0u8; 8];
+        file.read_exact(&mut magic).ok()?;
+        if !Self::is_mp3(&magic) {
+            return None;
+        }
+
+        let (start, _) = *Self::mp3_ranges(&mut file, size).ok()?.first()?;
+        let mut header = [0u8; 4];
+        file.seek(SeekFrom::Start(start)).ok()?;
+        file.read_exact(&mut header).ok()?;
+        if !Self::is_frame_sync(&header) {
+            return None;
+        }
+
+        let index = (header[2] >> 4) as usize;
+        // NOTE: version bits 11 are MPEG 1, everything else MPEG 2 & 2.5.
+        let table = match (header[1] >> 3) & 0b11 {
+            0b11 => MPEG1_BITRATES,
+            _ => MPEG2_BITRATES,
+        };
+        table.get(index).copied().filter(|bitrate| *bitrate > 0)
+    }
+
+    fn is_mp3(magic: &[u8; 8]) -> bool {
+        magic[..3] == *b"ID3" || Self::is_frame_sync(magic)
+    }
+
+    fn is_frame_sync(header: &[u8]) -> bool {
+        header[0] == 0xFF && header[1] & 0xE0 == 0xE0
+    }
+
+    /// Number of bytes left once the metadata is stripped, or `None` if `path` has no payload.
+    pub fn size(path: &Path) -> Option<u64> {
+        Self::ranges(path).map(|ranges| ranges.iter().map(|(start, end)| end - start).sum())
+    }
+
+    pub fn open(path: &Path) -> Result<Box<dyn Read + Send>> {
+        let ranges = Self::ranges(path).ok_or_else(|| {
+            anyhow::anyhow!("{} is not a photo or song with a payload", path.display())
+        })?;
+        Ok(Box::new(RangeReader {
+            inner: fs::File::open(path)?,
+            ranges: ranges.into(),
+            position: 0,
+        }))
+    }
+
+    fn jpeg_ranges(file: &mut fs::File, size: u64) -> io::Result<Vec<(u64, u64)>> {
+        let mut ranges = vec![(0, 2)];
+        let mut position = 2;
+
+        loop {
+            file.seek(SeekFrom::Start(position))?;
+            let mut marker = [0u8; 2];
+            file.read_exact(&mut marker)?;
+            if marker[0] != 0xFF {
+                return Err(io::ErrorKind::InvalidData.into());
+            }
+            // NOTE: markers may be padded with any number of 0xFF fill bytes.
+            if marker[1] == 0xFF {
+                position += 1;
+                continue;
+            }
+            if (0xD0..=0xD7).contains(&marker[1]) || marker[1] == 0x01 {
+                ranges.push((position, position + 2));
+                position += 2;
+                continue;
+            }
+            // NOTE: everything from the start of scan on is compressed image data.
+            if marker[1] == 0xDA {
+                ranges.push((position, size));
+                break;
+            }
+
+            let mut length = [0u8; 2];
+            file.read_exact(&mut length)?;
+            let end = position + 2 + u16::from_be_bytes(length) as u64;
+            if end > size {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            if !JPEG_METADATA_MARKERS.contains(&marker[1]) {
+                ranges.push((position, end));
+            }
+            position = end;
+        }
+
+        Ok(Self::merged(ranges))
+    }
+
+    fn png_ranges(file: &mut fs::File, size: u64) -> io::Result<Vec<(u64, u64)>> {
+        let mut ranges = vec![(0, PNG_SIGNATURE.len() as u64)];
+        let mut position = PNG_SIGNATURE.len() as u64;
+
+        while position < size {
+            file.seek(SeekFrom::Start(position))?;
+            let mut header = [0u8; 8];
+            file.read_exact(&mut header)?;
+            let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as u64;
+            // NOTE: length & type, the data, then a 4 byte CRC.
+            let end = position + 12 + length;
+            if end > size {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            if !PNG_METADATA_CHUNKS
+                .iter()
+                .any(|chunk| **chunk == header[4..])
+            {
+                ranges.push((position, end));
+            }
+            position = end;
+            if &header[4..] == b"IEND" {
+                break;
+            }
+        }
+
+        Ok(Self::merged(ranges))
+    }
+
+    /// The audio frames between a leading ID3v2 tag and a trailing ID3v1 tag.
+    fn mp3_ranges(file: &mut fs::File, size: u64) -> io::Result<Vec<(u64, u64)>> {
+        let mut header = [0u8; ID3V2_HEADER_SIZE as usize];
+        file.seek(SeekFrom::Start(0))?;
+        file.read_exact(&mut header)?;
+        let start = match header[..3] == *b"ID3" {
+            // NOTE: the tag size is stored in 4 "syncsafe" bytes of 7 bits each.
+            true => {
+                let tag = header[6..10]
+                    .iter()
+                    .fold(0u64, |size, byte| (size << 7) | (*byte & 0x7F) as u64);
+                let footer = match header[5] & 0x10 {
+                    0 => 0,
+                    _ => ID3V2_HEADER_SIZE,
+                };
+                ID3V2_HEADER_SIZE + tag + footer
+            }
+            false => 0,
+        };
+
+        let end = Self::id3v1_start(file, start, size)?;
+        match start <= end {
+            true => Ok(vec![(start, end)]),
+            false => Err(io::ErrorKind::UnexpectedEof.into()),
+        }
+    }
+
+    /// Where a trailing ID3v1 tag starts, the end of the file if there is none after `start`.
+    fn id3v1_start(file: &mut fs::File, start: u64, size: u64) -> io::Result<u64> {
+        if size < start + ID3V1_SIZE {
+            return Ok(size);
+        }
+        let mut marker = [0u8; 3];
+        file.seek(SeekFrom::Start(size - ID3V1_SIZE))?;
+        file.read_exact(&mut marker)?;
+        Ok(match marker == *b"TAG" {
+            true => size - ID3V1_SIZE,
+            false => size,
+        })
+    }
+
+    /// The audio frames after the metadata blocks (stream info, Vorbis comments, pictures,
+    /// padding), without the ID3v1 tag some taggers append.
+    #[cfg(feature = "media-aware")]
+    fn flac_ranges(file: &mut fs::File, size: u64) -> io::Result<Vec<(u64, u64)>> {
+        let mut position = FLAC_SIGNATURE.len() as u64;
+        loop {
+            file.seek(SeekFrom::Start(position))?;
+            let mut header = [0u8; 4];
+            file.read_exact(&mut header)?;
+            // NOTE: the high bit flags the last block, the length takes the next 3 bytes.
+            position += 4 + u32::from_be_bytes([0, header[1], header[2], header[3]]) as u64;
+            if position > size {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            if header[0] & 0x80 != 0 {
+                break;
+            }
+        }
+
+        Ok(vec![(position, Self::id3v1_start(file, position, size)?)])
+    }
+
+    /// The `mdat` boxes holding the encoded audio, leaving out the `moov` box with the sample
+    /// tables, iTunes tags & cover art, which taggers rewrite.
+    #[cfg(feature = "media-aware")]
+    fn m4a_ranges(file: &mut fs::File, size: u64) -> io::Result<Vec<(u64, u64)>> {
+        let ranges = Self::boxes(file, 0, size)?
+            .into_iter()
+            .filter(|(kind, ..)| kind == b"mdat")
+            .map(|(_, start, end)| (start, end))
+            .collect::<Vec<(u64, u64)>>();
+        match ranges.is_empty() {
+            true => Err(io::ErrorKind::InvalidData.into()),
+            false => Ok(Self::merged(ranges)),
+        }
+    }
+
+    /// The coded image data of every item but the EXIF & XMP ones, where the `iloc` box of the
+    /// `meta` box puts them. The `meta` box itself is left out too, as its offsets move whenever
+    /// the metadata before the image data grows or shrinks.
+    fn heic_ranges(file: &mut fs::File, size: u64) -> io::Result<Vec<(u64, u64)>> {
+        let invalid = || io::Error::from(io::ErrorKind::InvalidData);
+        let (_, start, end) = Self::boxes(file, 0, size)?
+            .into_iter()
+            .find(|(kind, ..)| kind == b"meta")
+            .ok_or_else(invalid)?;
+        if end - start > HEIC_MAX_META {
+            return Err(invalid());
+        }
+        let mut meta = vec![0u8; (end - start) as usize];
+        file.seek(SeekFrom::Start(start))?;
+        file.read_exact(&mut meta)?;
+
+        // NOTE: `meta` is a full box, its version & flags come before its children.
+        let children = Self::boxes(&mut io::Cursor::new(&meta), 4, meta.len() as u64)?;
+        let child = |wanted: &[u8; 4]| {
+            children
+                .iter()
+                .find(|(kind, ..)| kind == wanted)
+                .map(|&(_, start, end)| &meta[start as usize..end as usize])
+                .ok_or_else(invalid)
+        };
+        let metadata = Self::heic_metadata_items(child(b"iinf")?)?;
+        let mut ranges = Self::heic_extents(child(b"iloc")?)?
+            .into_iter()
+            .filter(|(item, ..)| !metadata.contains(item))
+            .map(|(_, start, end)| (start, end))
+            .collect::<Vec<(u64, u64)>>();
+        if ranges.is_empty() || ranges.iter().any(|(_, end)| *end > size) {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        ranges.sort_unstable();
+
+        Ok(Self::merged(ranges))
+    }
+
+    /// Ids of the items the `iinf` box declares as EXIF or XMP.
+    fn heic_metadata_items(iinf: &[u8]) -> io::Result<HashSet<u32>> {
+        // NOTE: the entry count takes 2 bytes in version 0 and 4 after.
+        let entries = match Self::be(iinf, 0, 1)? {
+            0 => 6,
+            _ => 8,
+        };
+        let mut items = HashSet::new();
+        for (kind, start, end) in
+            Self::boxes(&mut io::Cursor::new(iinf), entries, iinf.len() as u64)?
+        {
+            let infe = &iinf[start as usize..end as usize];
+            // NOTE: only versions 2 & 3 have an item type, after the id & a protection index.
+            let (item, at) = match (&kind, Self::be(infe, 0, 1)?) {
+                (b"infe", 2) => (Self::be(infe, 4, 2)?, 8),
+                (b"infe", 3) => (Self::be(infe, 4, 4)?, 10),
+                _ => continue,
+            };
+            let item_type = infe.get(at..at + 4).ok_or(io::ErrorKind::UnexpectedEof)?;
+            if HEIC_METADATA_ITEMS
+                .iter()
+                .any(|metadata| metadata[..] == *item_type)
+            {
+                items.insert(item as u32);
+            }
+        }
+        Ok(items)
+    }
+
+    /// Item id, start & end of each extent the `iloc` box places in the file.
+    fn heic_extents(iloc: &[u8]) -> io::Result<Vec<(u32, u64, u64)>> {
+        let version = Self::be(iloc, 0, 1)?;
+        let sizes = Self::be(iloc, 4, 1)?;
+        let (offset_size, length_size) = ((sizes >> 4) as usize, (sizes & 0xF) as usize);
+        let sizes = Self::be(iloc, 5, 1)?;
+        let base_size = (sizes >> 4) as usize;
+        let index_size = match version {
+            0 => 0,
+            _ => (sizes & 0xF) as usize,
+        };
+        let id_size = match version {
+            0 | 1 => 2,
+            _ => 4,
+        };
+
+        let mut at = 6;
+        let mut read = |length: usize| {
+            at += length;
+            Self::be(iloc, at - length, length)
+        };
+        let mut extents = vec![];
+        for _ in 0..read(id_size)? {
+            let item = read(id_size)? as u32;
+            let method = match version {
+                0 => 0,
+                _ => read(2)? & 0xF,
+            };
+            let _data_reference = read(2)?;
+            let base = read(base_size)?;
+            for _ in 0..read(2)? {
+                let _index = read(index_size)?;
+                let (offset, length) = (read(offset_size)?, read(length_size)?);
+                // NOTE: extents in the `idat` box or in other items describe how tiles form
+                // the image, only the ones at file offsets hold coded pixels.
+                if method == 0 {
+                    let start = base.saturating_add(offset);
+                    extents.push((item, start, start.saturating_add(length)));
+                }
+            }
+        }
+        Ok(extents)
+    }
+
+    /// The big-endian number of `length` bytes at `at`, 0 for none.
+    fn be(bytes: &[u8], at: usize, length: usize) -> io::Result<u64> {
+        let bytes = bytes
+            .get(at..at + length)
+            .ok_or(io::ErrorKind::UnexpectedEof)?;
+        Ok(bytes
+            .iter()
+            .fold(0, |number, byte| (number << 8) | *byte as u64))
+    }
+
+    /// Type, content start & end of the ISO base media boxes (MP4, M4A, HEIC) from `start` to
+    /// `end`.
+    fn boxes(
+        reader: &mut (impl Read + Seek),
+        start: u64,
+        end: u64,
+    ) -> io::Result<Vec<([u8; 4], u64, u64)>> {
+        let mut boxes = vec![];
+        let mut position = start;
+        while position + 8 <= end {
+            reader.seek(SeekFrom::Start(position))?;
+            let mut header = [0u8; 8];
+            reader.read_exact(&mut header)?;
+            let mut content = position + 8;
+            // NOTE: a size of 1 is followed by a 64 bit size, 0 runs to the end.
+            let length = match u32::from_be_bytes([header[0], header[1], header[2], header[3]]) {
+                0 => end - position,
+                1 => {
+                    let mut large = [0u8; 8];
+                    reader.read_exact(&mut large)?;
+                    content += 8;
+                    u64::from_be_bytes(large)
+                }
+                length => length as u64,
+            };
+            let box_end = position.saturating_add(length);
+            if box_end < content || box_end > end {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            boxes.push((
+                [header[4], header[5], header[6], header[7]],
+                content,
+                box_end,
+            ));
+            position = box_end;
+        }
+        Ok(boxes)
+    }
+
+    fn merged(ranges: Vec<(u64, u64)>) -> Vec<(u64, u64)> {
+        ranges.into_iter().fold(vec![], |mut merged, (start, end)| {
+            match merged.last_mut() {
+                Some((_, last_end)) if *last_end == start => *last_end = end,
+                _ => merged.push((start, end)),
+            }
+            merged
+        })
+    }
+}
+
+/// Reads only the given byte ranges of a file, as if they were one stream.
+struct RangeReader {
+    inner: fs::File,
+    ranges: VecDeque<(u64, u64)>,
+    position: u64,
+}
+
+impl Read for RangeReader {
+    fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+        while let Some(&(start, end)) = self.ranges.front() {
+            if self.position >= end {
+                self.ranges.pop_front();
+                continue;
+            }
+            if self.position < start {
+                self.position = self.inner.seek(SeekFrom::Start(start))?;
+            }
+
+            let wanted = buffer.len().min((end - self.position) as usize);
+            let read = self.inner.read(&mut buffer[..wanted])?;
+            self.position += read as u64;
+            return Ok(read);
+        }
+
+        Ok(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Payload;
+    use anyhow::Result;
+    use std::{fs, io::Read};
+    use tempfile::TempDir;
+
+    fn jpeg(exif: &[u8]) -> Vec<u8> {
+        let mut bytes = vec![0xFF, 0xD8];
+        let length = (exif.len() + 2) as u16;
+        bytes.extend([0xFF, 0xE1]);
+        bytes.extend(length.to_be_bytes());
+        bytes.extend(exif);
+        bytes.extend([0xFF, 0xDB, 0x00, 0x04, 0x01, 0x02]);
+        bytes.extend([0xFF, 0xDA, 0x00, 0x02, 0x11, 0x22, 0x33, 0xFF, 0xD9]);
+        bytes
+    }
+
+    /// A HEIC whose `iloc` places its EXIF item first in `mdat`, the image item after it.
+    fn heic(exif: &[u8], image: &[u8]) -> Vec<u8> {
+        let boxed = |kind: &[u8], content: &[u8]| {
+            [
+                &((content.len() + 8) as u32).to_be_bytes()[..],
+                kind,
+                content,
+            ]
+            .concat()
+        };
+        let infe = |item: u8, kind: &[u8]| {
+            boxed(
+                b"infe",
+                &[&[2, 0, 0, 0, 0, item, 0, 0][..], kind, b"\0"].concat(),
+            )
+        };
+        let extent = |item: u8, offset: u32, length: usize| {
+            [
+                &[0, item, 0, 0, 0, 1][..],
+                &offset.to_be_bytes(),
+                &(length as u32).to_be_bytes(),
+            ]
+            .concat()
+        };
+        let meta = |exif_offset: u32| {
+            let image_offset = exif_offset + exif.len() as u32;
+            let iinf = boxed(
+                b"iinf",
+                &[
+                    &[0, 0, 0, 0, 0, 2][..],
+                    &infe(1, b"hvc1"),
+                    &infe(2, b"Exif"),
+                ]
+                .concat(),
+            );
+            let iloc = [
+                &[0, 0, 0, 0, 0x44, 0x00, 0, 2][..],
+                &extent(1, image_offset, image.len()),
+                &extent(2, exif_offset, exif.len()),
+            ]
+            .concat();
+            boxed(
+                b"meta",
+                &[&[0, 0, 0, 0][..], &iinf, &boxed(b"iloc", &iloc)].concat(),
+            )
+        };
+        let ftyp = boxed(b"ftyp", b"heic\0\0\0\0mif1heic");
+        let mdat_offset = (ftyp.len() + meta(0).len() + 8) as u32;
+        [
+            ftyp,
+            meta(mdat_offset),
+            boxed(b"mdat", &[exif, image].concat()),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn photos_with_rewritten_exif_share_their_payload() -> Result<()> {
+        let root = TempDir::new()?;
+        let (original, synced) = (root.path().join("a.jpg"), root.path().join("b.jpg"));
+        fs::write(&original, jpeg(b"Exif\0\0camera"))?;
+        fs::write(&synced, jpeg(b"Exif\0\0camera, edited by a phone app"))?;
+
+        let payload = |path| -> Result<Vec<u8>> {
+            let mut bytes = vec![];
+            Payload::open(path)?.read_to_end(&mut bytes)?;
+            Ok(bytes)
+        };
+        assert_eq!(payload(&original)?, payload(&synced)?);
+        assert_eq!(
+            Payload::size(&original),
+            Some(payload(&original)?.len() as u64)
+        );
+        assert_ne!(fs::metadata(&original)?.len(), fs::metadata(&synced)?.len());
+
+        let (original, synced) = (root.path().join("a.heic"), root.path().join("b.heic"));
+        fs::write(&original, heic(b"Exif\0\0camera", b"coded tiles"))?;
+        fs::write(
+            &synced,
+            heic(b"Exif\0\0camera, edited by a phone app", b"coded tiles"),
+        )?;
+        assert_eq!(payload(&original)?, b"coded tiles");
+        assert_eq!(payload(&synced)?, b"coded tiles");
+
+        let frames = [0xFF, 0xFB, 0x90, 0x64, 1, 2, 3, 4];
+        let tagged = root.path().join("song.mp3");
+        let mut mp3 = b"ID3\x04\x00\x00\x00\x00\x00\x05title".to_vec();
+        mp3.extend(frames);
+        fs::write(&tagged, mp3)?;
+        assert_eq!(payload(&tagged)?, frames);
+        assert_eq!(Payload::bitrate(&tagged), Some(128));
+
+        let text = root.path().join("c.txt");
+        fs::write(&text, b"not an image")?;
+        assert_eq!(Payload::size(&text), None);
+
+        Ok(())
+    }
+
+    #[cfg(feature = "media-aware")]
+    #[test]
+    fn retagged_songs_share_their_audio_frames() -> Result<()> {
+        use super::Format;
+
+        let root = TempDir::new()?;
+        let payload = |name: &str, bytes: Vec<u8>| -> Result<Vec<u8>> {
+            let path = root.path().join(name);
+            fs::write(&path, bytes)?;
+            let mut content = vec![];
+            Payload::open(&path)?.read_to_end(&mut content)?;
+            Ok(content)
+        };
+        let frames = [0xFF, 0xF8, 0x69, 0x08, 1, 2, 3, 4];
+
+        let flac = |comment: &[u8], id3v1: bool| {
+            let mut bytes = b"fLaC".to_vec();
+            bytes.extend([0x00, 0x00, 0x00, 0x02, 0xAA, 0xBB]);
+            bytes.extend([0x84, 0x00, 0x00, comment.len() as u8]);
+            bytes.extend(comment);
+            bytes.extend(frames);
+            if id3v1 {
+                bytes.extend(b"TAG");
+                bytes.extend([0u8; 125]);
+            }
+            bytes
+        };
+        assert_eq!(payload("a.flac", flac(b"ARTIST=someone", false))?, frames);
+        assert_eq!(
+            payload("b.flac", flac(b"ARTIST=someone else", true))?,
+            frames
+        );
+
+        let m4a = |tags: &[u8]| {
+            let mut bytes = [&[0, 0, 0, 12][..], b"ftypM4A "].concat();
+            let moov = [&((tags.len() + 8) as u32).to_be_bytes()[..], b"moov", tags].concat();
+            bytes.extend(moov);
+            bytes.extend([0, 0, 0, 16]);
+            bytes.extend(b"mdat");
+            bytes.extend(frames);
+            bytes
+        };
+        assert_eq!(payload("c.m4a", m4a(b"covr and title"))?, frames);
+        assert_eq!(payload("d.m4a", m4a(b"another cover"))?, frames);
+        assert_eq!(
+            Payload::format(&root.path().join("d.m4a")),
+            Some(Format::M4a)
+        );
+
+        fs::write(root.path().join("video.mp4"), m4a(b""))?;
+        assert_eq!(Payload::format(&root.path().join("video.mp4")), None);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,116 @@
+use colored::Colorize;
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+/// File attributes of online-only files on Windows (OneDrive, Dropbox, iCloud for Windows, ...):
+/// offline, recalled on open & recalled on data access.
+#[cfg(windows)]
+const PLACEHOLDER_ATTRIBUTES: u32 = 0x1000 | 0x40000 | 0x400000;
+/// `SF_DATALESS`: the file has no data on disk, reading it asks the file provider for it.
+#[cfg(target_os = "macos")]
+const SF_DATALESS: u32 = 0x40000000;
+
+/// Online-only files of cloud storage clients: their content lives in the cloud, and reading it
+/// downloads it. They are left out of the run unless `--hydrate-and-hash` is given, so that a
+/// scan never triggers a download of the whole cloud drive, and listed in a report section of
+/// their own. Clones share what was recorded, the run carries one in `Params`.
+#[derive(Debug, Clone, Default)]
+pub struct Placeholders {
+    found: Arc<Mutex<Vec<PathBuf>>>,
+}
+
+impl Placeholders {
+    /// Whether `path` is an online-only file, judging by its attributes alone (which reading
+    /// does not download), or an iCloud `.<name>.icloud` stub standing in for one.
+    pub fn is_placeholder(path: &Path) -> bool {
+        let icloud_stub = path
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .is_some_and(|name| name.starts_with('.') && name.ends_with(".icloud"));
+        icloud_stub || Self::online_only(path)
+    }
+
+    /// Whether `path` takes part in the run; a placeholder is recorded for the report instead,
+    /// unless it is to be downloaded & hashed.
+    pub fn admit(&self, path: &Path, hydrate: bool) -> bool {
+        if hydrate || !Self::is_placeholder(path) {
+            return true;
+        }
+        self.found.lock().unwrap().push(path.to_path_buf());
+        false
+    }
+
+    /// The placeholders recorded so far, sorted, once each.
+    pub fn recorded(&self) -> Vec<PathBuf> {
+        let mut paths = self.found.lock().unwrap().clone();
+        paths.sort();
+        paths.dedup();
+        paths
+    }
+
+    /// The report section listing the placeholders left out, if any.
+    pub fn report(&self) -> Option<String> {
+        let paths = self.recorded();
+        if paths.is_empty() {
+            return None;
+        }
+
+        let mut report = format!(
+            "\n{}\n",
+            format!("Online-only files ({}), left out of the run:", paths.len())
+                .yellow()
+                .bold()
+        );
+        for path in &paths {
+            report.push_str(&format!("  - {}\n", path.display()));
+        }
+        report.push_str(
+            &"Pass --hydrate-and-hash to download and compare them."
+                .dimmed()
+                .to_string(),
+        );
+        Some(report)
+    }
+
+    #[cfg(windows)]
+    fn online_only(path: &Path) -> bool {
+        use std::os::windows::fs::MetadataExt;
+
+        std::fs::symlink_metadata(path)
+            .is_ok_and(|metadata| metadata.file_attributes() & PLACEHOLDER_ATTRIBUTES != 0)
+    }
+
+    #[cfg(target_os = "macos")]
+    fn online_only(path: &Path) -> bool {
+        use std::os::macos::fs::MetadataExt;
+
+        std::fs::symlink_metadata(path).is_ok_and(|metadata| metadata.st_flags() & SF_DATALESS != 0)
+    }
+
+    /// No cloud client on other systems leaves placeholders the filesystem tells apart.
+    #[cfg(not(any(windows, target_os = "macos")))]
+    fn online_only(_path: &Path) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Placeholders;
+    use std::path::Path;
+
+    #[test]
+    fn icloud_stubs_are_left_out_unless_hydrated() {
+        let stub = Path::new("/data/Photos/.IMG_0001.HEIC.icloud");
+        let photo = Path::new("/data/Photos/IMG_0002.HEIC");
+
+        let placeholders = Placeholders::default();
+        assert!(placeholders.admit(photo, false));
+        assert!(placeholders.admit(stub, true));
+        assert!(!placeholders.admit(stub, false));
+        assert_eq!(placeholders.recorded(), [stub.to_path_buf()]);
+        assert!(placeholders.report().is_some_and(|report| report.contains("Online-only")));
+    }
+}
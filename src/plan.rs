@@ -0,0 +1,707 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, SecondsFormat, Utc};
+use clap::Args;
+use colored::Colorize;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    sync::Arc,
+    time::SystemTime,
+};
+
+use crate::{
+    actionlog::ActionLog,
+    archive::Archive,
+    denial::Denial,
+    fileinfo::FileInfo,
+    hasher::{Algorithm, HashScheme},
+    params::Params,
+    protection::Protection,
+    runid::RunId,
+    survivor::Survivor,
+    syspath::SystemPaths,
+    verify::{KeeperCheck, Verifier, VerifyMode},
+};
+
+const PLAN_VERSION: u32 = 1;
+const CSV_HEADER: [&str; 6] = ["group", "action", "path", "keep", "size", "decision"];
+/// Optional columns: the protection, empty for files that can be removed, and what the file
+/// was like when the plan was made, empty to skip the check.
+const PROTECTION_COLUMN: &str = "protection";
+const MODIFIED_COLUMN: &str = "modified";
+const HASH_COLUMN: &str = "hash";
+const KEEP_MODIFIED_COLUMN: &str = "keep_modified";
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct ApplyArgs {
+    /// Plan written by an earlier run with --review (or --export-plan), with the reviewer's decisions
+    #[arg(value_hint = clap::ValueHint::FilePath, value_name = "plan_path")]
+    pub plan: PathBuf,
+    /// Only carry out actions marked approved; pending ones are left alone as well
+    #[arg(long)]
+    pub approved_only: bool,
+}
+
+/// A reviewer's verdict on one proposed action.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Decision {
+    #[default]
+    Pending,
+    Approved,
+    Rejected,
+}
+
+impl Decision {
+    fn parse(text: &str) -> Result<Self> {
+        match text.trim().to_lowercase().as_str() {
+            "" | "pending" => Ok(Self::Pending),
+            "approved" | "approve" | "yes" | "y" => Ok(Self::Approved),
+            "rejected" | "reject" | "no" | "n" => Ok(Self::Rejected),
+            other => anyhow::bail!("unknown decision {other:?}, expected approved or rejected"),
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Approved => "approved",
+            Self::Rejected => "rejected",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlannedAction {
+    /// Hash of the duplicate group, shared by the actions of one group.
+    pub group: String,
+    pub action: String,
+    pub path: PathBuf,
+    /// The copy that stays, which `path` is checked against before it is removed.
+    pub keep: PathBuf,
+    pub size: u64,
+    #[serde(default)]
+    pub decision: Decision,
+    /// Set for files the filesystem will not let go, which no decision can remove.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub protection: Option<Protection>,
+    /// Last modification of `path` when the plan was made; a file changed since is skipped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified: Option<String>,
+    /// BLAKE3 hash of `path` when the plan was made; a file changed since is skipped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    /// Last modification of `keep` when it was scanned, checked by `--verify-keeper`, along
+    /// with `size` and, hashed again, `hash`: both copies held the same contents.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keep_modified: Option<String>,
+}
+
+/// The removals a run proposes (see `--review`), written for someone else to approve or reject
+/// one by one, and carried out later by `deduplicator apply` or `--apply-plan`. JSON, or CSV for
+/// a spreadsheet, depending on the file extension.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Plan {
+    pub version: u32,
+    pub created: String,
+    /// The run that made the plan (see `RunId`); CSV plans do not keep it.
+    #[serde(default)]
+    pub run_id: String,
+    pub actions: Vec<PlannedAction>,
+    /// Set for a plan carried out by the run that made it (see `Plan::confirmed`), whose groups
+    /// were confirmed by this run's hashes: files are only checked to be unchanged since.
+    #[serde(skip)]
+    confirmed: bool,
+}
+
+impl Plan {
+    /// Proposes to remove every copy the --keep policy does not keep, for review. Archive
+    /// members cannot be removed and are left out. Protected files stay in the plan, marked so
+    /// the reviewer knows they will be skipped; when the kept copy is not protected but another
+    /// is, the protected copy is kept instead, as it would be left over anyway. Every file is
+    /// fingerprinted, so that the run carrying out the plan can tell whether it changed.
+    pub fn from_store(store: &DashMap<u128, Vec<Arc<FileInfo>>>, app_args: &Params) -> Self {
+        Self::propose(store, app_args, false)
+    }
+
+    /// The plan of `from_store`, carried out at once by the same run (see `--delete`): files
+    /// are not read again, as the hashes of this run stand for their contents as long as their
+    /// size & modification time did not change.
+    pub fn confirmed(store: &DashMap<u128, Vec<Arc<FileInfo>>>, app_args: &Params) -> Self {
+        Self::propose(store, app_args, true)
+    }
+
+    fn propose(store: &DashMap<u128, Vec<Arc<FileInfo>>>, app_args: &Params, confirmed: bool) -> Self {
+        let mut groups = store
+            .iter()
+            .filter(|group| group.value().len() > 1)
+            .map(|group| {
+                let mut files = group.value().clone();
+                files.sort_by(|a, b| a.path.cmp(&b.path));
+                (*group.key(), files)
+            })
+            .collect::<Vec<(u128, Vec<Arc<FileInfo>>)>>();
+        groups.sort_by(|a, b| a.1[0].path.cmp(&b.1[0].path));
+
+        let actions = groups
+            .iter()
+            .flat_map(|(hash, files)| {
+                let (keeper, protections) = Self::keeper(files, app_args);
+                let keep = &files[keeper];
+                files
+                    .iter()
+                    .zip(protections)
+                    .filter(|(file, _)| file.path != keep.path && !Archive::contains(&file.path))
+                    .map(|(file, protection)| PlannedAction {
+                        group: format!("{hash:032x}"),
+                        action: "delete".to_string(),
+                        path: file.path.to_path_buf(),
+                        keep: keep.path.to_path_buf(),
+                        size: file.size,
+                        decision: Decision::Pending,
+                        protection,
+                        modified: Some(Self::timestamp(file.modified)),
+                        hash: match confirmed {
+                            true => None,
+                            false => Self::fingerprint(&file.path).ok(),
+                        },
+                        keep_modified: Some(Self::timestamp(keep.modified)),
+                    })
+                    .collect::<Vec<PlannedAction>>()
+            })
+            .collect();
+
+        Self {
+            version: PLAN_VERSION,
+            created: Utc::now().to_rfc3339(),
+            run_id: RunId::get(),
+            actions,
+            confirmed,
+        }
+    }
+
+    /// Index of the copy kept in `files`, with the protection of every copy: the one --keep
+    /// chooses, unless it is not protected but another copy outside an archive is.
+    pub fn keeper(files: &[Arc<FileInfo>], app_args: &Params) -> (usize, Vec<Option<Protection>>) {
+        let protections = files
+            .iter()
+            .map(|file| Protection::of(&file.path))
+            .collect::<Vec<Option<Protection>>>();
+        let mut keeper = app_args.keeper(files).unwrap_or_default();
+        if protections[keeper].is_none() {
+            keeper = protections
+                .iter()
+                .zip(files)
+                .position(|(protection, file)| protection.is_some() && !Archive::contains(&file.path))
+                .unwrap_or(keeper);
+        }
+        (keeper, protections)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let contents = match Self::is_csv(path) {
+            true => self.to_csv(),
+            false => serde_json::to_string_pretty(self)?,
+        };
+        fs::write(path, contents)
+            .with_context(|| format!("unable to write plan {}", path.display()))
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let raw = fs::read_to_string(path)
+            .with_context(|| format!("unable to read plan {}", path.display()))?;
+        let plan = match Self::is_csv(path) {
+            true => Self::from_csv(&raw),
+            false => serde_json::from_str(&raw).map_err(anyhow::Error::from),
+        }
+        .with_context(|| format!("invalid plan {}", path.display()))?;
+
+        if plan.version != PLAN_VERSION {
+            anyhow::bail!(
+                "plan {} has version {}, expected {}",
+                path.display(),
+                plan.version,
+                PLAN_VERSION
+            );
+        }
+
+        Ok(plan)
+    }
+
+    fn is_csv(path: &Path) -> bool {
+        path.extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("csv"))
+    }
+
+    fn to_csv(&self) -> String {
+        let mut csv = format!(
+            "{},{PROTECTION_COLUMN},{MODIFIED_COLUMN},{HASH_COLUMN},{KEEP_MODIFIED_COLUMN}\r\n",
+            CSV_HEADER.join(",")
+        );
+        for action in &self.actions {
+            let row = [
+                action.group.clone(),
+                action.action.clone(),
+                action.path.to_string_lossy().to_string(),
+                action.keep.to_string_lossy().to_string(),
+                action.size.to_string(),
+                action.decision.as_str().to_string(),
+                action
+                    .protection
+                    .map(|protection| protection.id().to_string())
+                    .unwrap_or_default(),
+                action.modified.clone().unwrap_or_default(),
+                action.hash.clone().unwrap_or_default(),
+                action.keep_modified.clone().unwrap_or_default(),
+            ];
+            let row = row
+                .iter()
+                .map(|field| Self::csv_field(field))
+                .collect::<Vec<String>>();
+            csv.push_str(&row.join(","));
+            csv.push_str("\r\n");
+        }
+        csv
+    }
+
+    fn csv_field(field: &str) -> String {
+        match field.contains([',', '"', '\r', '\n']) {
+            true => format!("\"{}\"", field.replace('"', "\"\"")),
+            false => field.to_string(),
+        }
+    }
+
+    /// Reads a plan back from CSV (RFC 4180), as saved by a spreadsheet. Columns are found by
+    /// their header, so they may be reordered or added to.
+    fn from_csv(raw: &str) -> Result<Self> {
+        let mut rows = Self::csv_rows(raw).into_iter();
+        let header = rows.next().context("the plan is empty")?;
+        let column = |name: &str| -> Result<usize> {
+            header
+                .iter()
+                .position(|title| title.trim().eq_ignore_ascii_case(name))
+                .with_context(|| format!("missing column {name}"))
+        };
+        let columns = CSV_HEADER
+            .iter()
+            .map(|name| column(name))
+            .collect::<Result<Vec<usize>>>()?;
+        let protection_column = column(PROTECTION_COLUMN).ok();
+        let (modified_column, hash_column) = (column(MODIFIED_COLUMN).ok(), column(HASH_COLUMN).ok());
+        let keep_modified_column = column(KEEP_MODIFIED_COLUMN).ok();
+
+        let actions = rows
+            .enumerate()
+            .filter(|(_, row)| row.iter().any(|field| !field.is_empty()))
+            .map(|(line, row)| {
+                let field =
+                    |index: usize| row.get(columns[index]).map(String::as_str).unwrap_or("");
+                let optional = |column: Option<usize>| {
+                    column
+                        .and_then(|index| row.get(index))
+                        .map(|value| value.trim().to_string())
+                        .filter(|value| !value.is_empty())
+                };
+                Ok(PlannedAction {
+                    group: field(0).to_string(),
+                    action: field(1).to_string(),
+                    path: PathBuf::from(field(2)),
+                    keep: PathBuf::from(field(3)),
+                    size: field(4)
+                        .parse()
+                        .with_context(|| format!("row {}: invalid size", line + 2))?,
+                    decision: Decision::parse(field(5))
+                        .with_context(|| format!("row {}", line + 2))?,
+                    protection: protection_column
+                        .and_then(|index| row.get(index))
+                        .filter(|protection| !protection.trim().is_empty())
+                        .map(|protection| protection.parse())
+                        .transpose()
+                        .with_context(|| format!("row {}", line + 2))?,
+                    modified: optional(modified_column),
+                    hash: optional(hash_column),
+                    keep_modified: optional(keep_modified_column),
+                })
+            })
+            .collect::<Result<Vec<PlannedAction>>>()?;
+
+        Ok(Self {
+            version: PLAN_VERSION,
+            created: String::new(),
+            run_id: String::new(),
+            actions,
+            confirmed: false,
+        })
+    }
+
+    fn csv_rows(raw: &str) -> Vec<Vec<String>> {
+        let (mut rows, mut row, mut field) = (vec![], vec![], String::new());
+        let mut chars = raw.chars().peekable();
+        let mut quoted = false;
+
+        while let Some(c) = chars.next() {
+            match (quoted, c) {
+                (true, '"') if chars.peek() == Some(&'"') => {
+                    field.push('"');
+                    chars.next();
+                }
+                (true, '"') => quoted = false,
+                (true, c) => field.push(c),
+                (false, '"') => quoted = true,
+                (false, ',') => row.push(std::mem::take(&mut field)),
+                (false, '\r') => {}
+                (false, '\n') => {
+                    row.push(std::mem::take(&mut field));
+                    rows.push(std::mem::take(&mut row));
+                }
+                (false, c) => field.push(c),
+            }
+        }
+        if !field.is_empty() || !row.is_empty() {
+            row.push(field);
+            rows.push(row);
+        }
+
+        rows
+    }
+
+    pub fn run(args: &ApplyArgs, app_args: &Params) -> Result<ExitCode> {
+        let plan = Self::read(&args.plan)?;
+        plan.apply(args.approved_only, app_args);
+        match app_args.dry_run {
+            true => Ok(app_args.dry_run_tally.finish()),
+            false => Ok(ExitCode::SUCCESS),
+        }
+    }
+
+    /// Carries out the reviewed plan: approved actions, and pending ones too unless
+    /// `approved_only`. Actions that would leave no copy of their group, however the plan was
+    /// edited, are left out (see `Survivor`). Right before it is removed, every file is checked
+    /// for changes since the plan was made, against the copy that stays, which has to be still
+    /// there, and for a protection (see `Protection`). With `--verify-keeper`, the kept copy of
+    /// a group is checked once before its first removal, and the whole group is left alone if it
+    /// changed. Returns the number of files removed.
+    pub fn apply(&self, approved_only: bool, app_args: &Params) -> u64 {
+        let removal = app_args.removal();
+        let mut removed_paths: HashSet<&Path> = HashSet::new();
+        let mut skipped = 0;
+        let mut keepers = HashMap::<&str, Option<String>>::new();
+
+        let refusals = self
+            .actions
+            .iter()
+            .map(|action| match action.decision {
+                Decision::Rejected => Some("rejected".to_string()),
+                Decision::Pending if approved_only => Some("not approved".to_string()),
+                _ if action.action != "delete" => Some(format!("unknown action {}", action.action)),
+                _ => None,
+            })
+            .collect::<Vec<Option<String>>>();
+        let mut removals = HashMap::<&Path, Vec<&Path>>::new();
+        for (action, _) in self.actions.iter().zip(&refusals).filter(|(_, refusal)| refusal.is_none()) {
+            removals.entry(&action.path).or_default().push(&action.keep);
+        }
+        let orphaned = Survivor::orphaned(&removals);
+
+        for (action, refusal) in self.actions.iter().zip(refusals) {
+            let reason = match refusal {
+                Some(refusal) => Some(refusal),
+                None if orphaned.contains(action.path.as_path()) => {
+                    Some("no copy of the group would be left, the kept copy is removed too".to_string())
+                }
+                None if removed_paths.contains(action.keep.as_path()) => {
+                    Some("the kept copy was removed by this plan".to_string())
+                }
+                None => {
+                    let distrusted = app_args.verify_keeper.and_then(|check| {
+                        keepers
+                            .entry(&action.group)
+                            .or_insert_with(|| Self::distrust_keeper(action, check))
+                            .clone()
+                    });
+                    distrusted.or_else(|| {
+                        Self::check(action, self.confirmed, app_args)
+                            .and_then(|_| Survivor::ensure(&action.path, [action.keep.as_path()]).map(|_| ()))
+                            .err()
+                            .map(|e| format!("{e:#}"))
+                    })
+                }
+            };
+            if let Some(reason) = reason {
+                ActionLog::skipped(&app_args.events, &action.path, Some(&action.keep), &reason);
+                skipped += 1;
+                continue;
+            }
+
+            match removal.remove(&action.path, app_args) {
+                Ok(_) => {
+                    ActionLog::done(
+                        &app_args.events,
+                        removal.label(),
+                        &action.path,
+                        Some(&action.keep),
+                        None,
+                    );
+                    removed_paths.insert(&action.path);
+                }
+                Err(e) => ActionLog::failed(
+                    &app_args.events,
+                    &action.path,
+                    Some(&action.keep),
+                    &format!("{e:#}"),
+                ),
+            }
+        }
+
+        eprintln!(
+            "\n{}",
+            format!(
+                "{} of {} planned action(s) carried out, {skipped} skipped.",
+                removed_paths.len(),
+                self.actions.len()
+            )
+            .bold()
+        );
+        removed_paths.len() as u64
+    }
+
+    fn check(action: &PlannedAction, confirmed: bool, app_args: &Params) -> Result<()> {
+        let keep = FileInfo::new(action.keep.clone()).context("the kept copy is gone")?;
+        let duplicate = FileInfo::new(action.path.clone()).context("the file is gone")?;
+        if duplicate.size != action.size {
+            anyhow::bail!(
+                "the size changed since the plan was made ({} bytes, planned {})",
+                duplicate.size,
+                action.size
+            );
+        }
+        if let Some(modified) = &action.modified {
+            if Self::timestamp(duplicate.modified) != *modified {
+                anyhow::bail!("the file was modified since the plan was made");
+            }
+        }
+        if let Some(hash) = &action.hash {
+            if !Self::fingerprint(&action.path)?.eq_ignore_ascii_case(hash) {
+                anyhow::bail!("the contents changed since the plan was made");
+            }
+        }
+        if let Some(system) = SystemPaths::containing(&action.path) {
+            if !app_args.allow_system_paths {
+                anyhow::bail!(
+                    "within the system directory {}, see --allow-system-paths",
+                    system.display()
+                );
+            }
+        }
+        if let Some(protection) = Protection::of(&action.path) {
+            anyhow::bail!("{protection}, it cannot be removed");
+        }
+        match confirmed || Verifier::identical(&keep, &duplicate, app_args.verification())? {
+            true => Ok(()),
+            false => anyhow::bail!("contents differ from the kept copy"),
+        }
+    }
+
+    /// Why the copy `action` keeps is not to be relied on any more, if it is not: gone,
+    /// unreadable or changed since the scan (see `--verify-keeper`). Warns about it, as the
+    /// whole group is left alone.
+    fn distrust_keeper(action: &PlannedAction, check: KeeperCheck) -> Option<String> {
+        let verified = (|| -> Result<()> {
+            let keep = FileInfo::new(action.keep.clone()).context("the kept copy is gone")?;
+            fs::File::open(&action.keep)
+                .map_err(|e| Denial::explain(e, &action.keep))
+                .context("the kept copy cannot be read")?;
+            let modified = action.keep_modified.as_ref();
+            if keep.size != action.size || modified.is_some_and(|modified| Self::timestamp(keep.modified) != *modified) {
+                anyhow::bail!("the kept copy was modified since the scan");
+            }
+            if check == KeeperCheck::Hash {
+                let identical = match &action.hash {
+                    Some(hash) => Self::fingerprint(&action.keep)?.eq_ignore_ascii_case(hash),
+                    // NOTE: a plan without hashes is carried out by the run that made it (see
+                    // `Plan::confirmed`), the kept copy is compared with the one removed instead.
+                    None => {
+                        let duplicate = FileInfo::new(action.path.clone()).context("the file is gone")?;
+                        Verifier::identical(&keep, &duplicate, VerifyMode::Rehash)?
+                    }
+                };
+                if !identical {
+                    anyhow::bail!("the contents of the kept copy changed since the scan");
+                }
+            }
+            Ok(())
+        })();
+
+        verified.err().map(|e| {
+            eprintln!(
+                "{}",
+                format!("Warning: {e:#} ({}), its group is left alone.", action.keep.display()).yellow()
+            );
+            format!("{e:#}, the group is left alone")
+        })
+    }
+
+    fn timestamp(modified: SystemTime) -> String {
+        DateTime::<Utc>::from(modified).to_rfc3339_opts(SecondsFormat::Nanos, true)
+    }
+
+    /// Hash of the whole file, independent of the run's --algorithm and seed so that it can be
+    /// checked by a later run.
+    fn fingerprint(path: &Path) -> Result<String> {
+        let file = FileInfo::new(path.to_path_buf())?;
+        let hash = file.hash(HashScheme::new(Algorithm::Blake3, 0))?;
+        Ok(format!("{hash:032x}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Decision, Plan};
+    use crate::{
+        fixture::Fixture,
+        params::Params,
+        verify::{KeeperCheck, VerifyMode},
+    };
+    use anyhow::Result;
+    use dashmap::DashMap;
+    use std::{
+        fs,
+        time::{Duration, SystemTime},
+    };
+
+    #[test]
+    fn only_approved_actions_survive_a_csv_review() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let file = |name: &str| fixture.scanned(name, b"same");
+        let store = DashMap::new();
+        store.insert(
+            1,
+            vec![file("b, the copy.txt")?, file("a.txt")?, file("c.txt")?],
+        );
+
+        let plan = Plan::from_store(&store, &Params::default());
+        assert_eq!(plan.actions.len(), 2);
+        assert!(plan
+            .actions
+            .iter()
+            .all(|action| action.keep.ends_with("a.txt")));
+
+        let csv = fixture.path("plan.csv");
+        plan.write(&csv)?;
+        let reviewed = fs::read_to_string(&csv)?.replacen("pending", "approved", 1);
+        fs::write(&csv, reviewed)?;
+
+        let plan = Plan::read(&csv)?;
+        assert_eq!(plan.actions[0].decision, Decision::Approved);
+        assert_eq!(plan.actions[1].decision, Decision::Pending);
+        assert_eq!(plan.apply(true, &Params::default()), 1);
+
+        assert!(fixture.path("a.txt").exists());
+        assert!(!fixture.path("b, the copy.txt").exists());
+        assert!(fixture.path("c.txt").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn edited_plans_never_remove_the_last_copy() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let file = |name: &str| fixture.scanned(name, b"same");
+        let store = DashMap::new();
+        store.insert(1, vec![file("a.txt")?, file("b.txt")?]);
+        store.insert(2, vec![file("c.txt")?, file("d.txt")?]);
+
+        let mut plan = Plan::from_store(&store, &Params::default());
+        let mut swapped = plan.actions[0].clone();
+        (swapped.path, swapped.keep) = (swapped.keep, swapped.path);
+        swapped.modified = None;
+        swapped.hash = None;
+        plan.actions.push(swapped);
+        fs::remove_file(fixture.path("c.txt"))?;
+
+        assert_eq!(plan.apply(false, &Params::default()), 0);
+        assert!(fixture.path("a.txt").exists());
+        assert!(fixture.path("b.txt").exists());
+        assert!(fixture.path("d.txt").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn groups_whose_kept_copy_changed_are_left_alone() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let file = |name: &str| fixture.scanned(name, b"same");
+        let store = DashMap::new();
+        store.insert(1, vec![file("a.txt")?, file("b.txt")?, file("c.txt")?]);
+        store.insert(2, vec![file("d.txt")?, file("e.txt")?]);
+        store.insert(3, vec![file("f.txt")?, file("g.txt")?]);
+        // NOTE: trusting the grouping hash, only --verify-keeper looks at the kept copy again.
+        let params = Params {
+            verify: Some(VerifyMode::None),
+            verify_keeper: Some(KeeperCheck::Hash),
+            ..Params::default()
+        };
+        let plan = Plan::from_store(&store, &params);
+
+        // NOTE: rewritten in place with its modification time put back, only a hash tells.
+        let kept = fixture.path("a.txt");
+        let modified = fs::metadata(&kept)?.modified()?;
+        fs::write(&kept, b"SAME")?;
+        fs::File::options().write(true).open(&kept)?.set_modified(modified)?;
+        let touched = fixture.path("d.txt");
+        fs::File::options().write(true).open(&touched)?.set_modified(modified + Duration::from_secs(60))?;
+        let grown = fixture.path("f.txt");
+        fs::write(&grown, b"same, and more")?;
+        fs::File::options().write(true).open(&grown)?.set_modified(modified)?;
+
+        let stat = Params {
+            verify_keeper: Some(KeeperCheck::Stat),
+            ..params.clone()
+        };
+        assert_eq!(plan.apply(false, &params), 0);
+        assert_eq!(Plan::confirmed(&store, &params).apply(false, &params), 0);
+        assert!(fixture.path("b.txt").exists());
+        assert_eq!(plan.apply(false, &stat), 2);
+        assert!(!fixture.path("c.txt").exists());
+        assert!(fixture.path("e.txt").exists());
+        assert!(fixture.path("g.txt").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn files_changed_since_the_export_are_skipped() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let file = |name: &str| fixture.scanned(name, b"same");
+        let store = DashMap::new();
+        store.insert(1, vec![file("a.txt")?, file("b.txt")?, file("c.txt")?]);
+
+        let json = fixture.path("plan.json");
+        Plan::from_store(&store, &Params::default()).write(&json)?;
+        // Same size, other contents: only the hash tells.
+        fs::write(fixture.path("b.txt"), b"SAME")?;
+        let mut plan = Plan::read(&json)?;
+        plan.actions[0].modified = None;
+        // Same contents, touched since.
+        let c = fixture.path("c.txt");
+        fs::File::options()
+            .write(true)
+            .open(&c)?
+            .set_modified(SystemTime::UNIX_EPOCH)?;
+
+        assert_eq!(plan.apply(false, &Params::default()), 0);
+        assert!(fixture.path("b.txt").exists());
+        assert!(c.exists());
+
+        // Carried out by the run that made it, files are not read again, their stat tells.
+        let confirmed = Plan::confirmed(&store, &Params::default());
+        assert!(confirmed.actions.iter().all(|action| action.hash.is_none()));
+        assert_eq!(confirmed.apply(false, &Params::default()), 0);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,188 @@
+use clap::{parser::ValueSource, ArgMatches, ValueEnum};
+use std::path::PathBuf;
+
+use crate::{
+    filetype::MediaType,
+    keep::KeepPolicy,
+    params::{OutputFormat, Params},
+};
+
+/// Extensions browsers and download managers give files that are still being downloaded.
+pub const PARTIAL_DOWNLOADS: &str = "crdownload,part,partial,download,opdownload";
+/// Extensions of camera RAW files, the digital negatives a photo library is built from.
+pub const RAW_PHOTOS: &str = "cr2,cr3,crw,nef,nrw,arw,srf,sr2,dng,raf,orf,rw2,pef,srw,x3f,raw";
+/// Build output & dependency directories a developer workspace is full of.
+pub const BUILD_DIRS: [&str; 3] = ["target", "node_modules", "dist"];
+/// Smallest duplicate --preset dev reports unless --min-size says otherwise.
+pub const DEV_MIN_SIZE: &str = "100K";
+/// Where --preset photos writes its gallery unless --gallery says otherwise.
+pub const PHOTOS_GALLERY: &str = "deduplicator-photos.html";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Preset {
+    /// Downloads folder: skip partial downloads, keep the newest copy and move numbered copies
+    /// ("setup (1).exe") next to their original to the trash after one confirmation
+    Downloads,
+    /// Photo library: compare photos without their metadata, prefer copies inside --library,
+    /// leave RAW files alone unless --include-raw and write an HTML gallery of the proposed
+    /// removals
+    Photos,
+    /// Music library: compare songs without their tags (needs the media-aware feature), keep the
+    /// best sounding copy and list the groups by artist / album folder
+    Music,
+    /// Developer workspace: respect .gitignore, skip build directories (target, node_modules,
+    /// dist) and only report files above 100K duplicated across projects
+    Dev,
+}
+
+impl Preset {
+    /// Fills in the preset's settings. Options given on the command line always win over the
+    /// preset.
+    pub fn apply(&self, params: &mut Params, matches: &ArgMatches) {
+        let given = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+
+        match self {
+            Self::Downloads => {
+                Self::exclude(params, PARTIAL_DOWNLOADS);
+                if params.move_to.is_none() {
+                    params.trash = true;
+                }
+                if !given("keep") {
+                    params.keep = KeepPolicy::Newest;
+                }
+                if !params.interactive
+                    && !params.comparison_mode
+                    && !params.usage_view
+                    && params.replacement().is_none()
+                {
+                    params.collapse_copies = true;
+                }
+            }
+            Self::Photos => {
+                params.ignore_metadata = true;
+                if !params.include_raw {
+                    Self::exclude(params, RAW_PHOTOS);
+                }
+                params
+                    .gallery
+                    .get_or_insert_with(|| PathBuf::from(PHOTOS_GALLERY));
+            }
+            Self::Music => {
+                params.ignore_metadata = true;
+                params.media_aware = true;
+                if params.media_types.is_empty() {
+                    params.media_types = vec![MediaType::Audio];
+                }
+                if !given("keep") {
+                    params.keep = KeepPolicy::Quality;
+                }
+                // NOTE: albums are only complete once the scan is over, --stream lists groups.
+                if !given("output") && !params.stream {
+                    params.output = OutputFormat::Albums;
+                }
+            }
+            Self::Dev => {
+                params.gitignore = true;
+                params
+                    .exclude_dir
+                    .extend(BUILD_DIRS.iter().map(|dir| dir.to_string()));
+                params.cross_project = true;
+                if !given("min_size") {
+                    params.min_size = Some(DEV_MIN_SIZE.to_string());
+                }
+            }
+        }
+    }
+
+    /// Closing advice of the preset, shown when the duplicates were only listed.
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            Self::Dev => Some(
+                "Projects can share these files instead of deleting them: rerun with --link hard \
+                 to replace every copy with a hard link to one of them.",
+            ),
+            _ => None,
+        }
+    }
+
+    fn exclude(params: &mut Params, extensions: &str) {
+        params.exclude_types = Some(match params.exclude_types.take() {
+            Some(types) => format!("{types},{extensions}"),
+            None => extensions.to_string(),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BUILD_DIRS, DEV_MIN_SIZE, PARTIAL_DOWNLOADS, PHOTOS_GALLERY, RAW_PHOTOS};
+    use crate::{keep::KeepPolicy, params::Params};
+    use std::path::PathBuf;
+
+    #[test]
+    fn downloads_preset_fills_in_what_was_not_given() {
+        let params = Params::load_from(["deduplicator", "--preset", "downloads"], None);
+        assert_eq!(params.exclude_types.as_deref(), Some(PARTIAL_DOWNLOADS));
+        assert_eq!(params.keep, KeepPolicy::Newest);
+        assert!(params.collapse_copies);
+        assert!(params.trash);
+
+        let params = Params::load_from(
+            [
+                "deduplicator",
+                "--preset",
+                "downloads",
+                "--keep",
+                "oldest",
+                "-T",
+                "iso",
+                "-i",
+            ],
+            None,
+        );
+        assert_eq!(
+            params.exclude_types,
+            Some(format!("iso,{PARTIAL_DOWNLOADS}"))
+        );
+        assert_eq!(params.keep, KeepPolicy::Oldest);
+        assert!(!params.collapse_copies);
+    }
+
+    #[test]
+    fn photos_preset_leaves_raw_files_alone_unless_asked() {
+        let params = Params::load_from(["deduplicator", "--preset", "photos"], None);
+        assert!(params.ignore_metadata);
+        assert_eq!(params.exclude_types.as_deref(), Some(RAW_PHOTOS));
+        assert_eq!(params.gallery, Some(PathBuf::from(PHOTOS_GALLERY)));
+
+        let params = Params::load_from(["deduplicator", "--preset", "photos", "--include-raw"], None);
+        assert_eq!(params.exclude_types, None);
+    }
+
+    #[test]
+    #[cfg(feature = "media-aware")]
+    fn music_preset_keeps_an_explicit_output_format() {
+        use crate::{filetype::MediaType, params::OutputFormat};
+
+        let params = Params::load_from(["deduplicator", "--preset", "music"], None);
+        assert!(params.media_aware);
+        assert_eq!(params.media_types, vec![MediaType::Audio]);
+        assert_eq!(params.keep, KeepPolicy::Quality);
+        assert_eq!(params.output, OutputFormat::Albums);
+
+        let params = Params::load_from(["deduplicator", "--preset", "music", "--output", "text"], None);
+        assert_eq!(params.output, OutputFormat::Text);
+    }
+
+    #[test]
+    fn dev_preset_skips_build_directories_and_small_files() {
+        let params = Params::load_from(["deduplicator", "--preset", "dev"], None);
+        assert!(params.gitignore);
+        assert!(params.cross_project);
+        assert_eq!(params.exclude_dir, BUILD_DIRS);
+        assert_eq!(params.min_size.as_deref(), Some(DEV_MIN_SIZE));
+
+        let params = Params::load_from(["deduplicator", "--preset", "dev", "--min-size", "1M"], None);
+        assert_eq!(params.min_size.as_deref(), Some("1M"));
+    }
+}
@@ -1,92 +1,514 @@
 use anyhow::Result;
+use crossbeam_channel::Receiver;
 use dashmap::DashMap;
+use gxhash::{gxhash128, gxhash64};
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use rayon::iter::IntoParallelRefMutIterator;
+use rayon::iter::{IntoParallelRefMutIterator, ParallelBridge};
 use rayon::prelude::{IntoParallelIterator, ParallelIterator};
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
-use std::sync::{Arc, Mutex, TryLockError, TryLockResult};
+use std::collections::{hash_map::Entry, BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
 use std::time::Duration;
 use unicode_segmentation::UnicodeSegmentation;
 
+use crate::api::DuplicateGroup;
+use crate::cache::HashCache;
+use crate::checkpoint::Checkpoint;
+use crate::disk::Disks;
+use crate::events::Event;
 use crate::fileinfo::{FileInfo, FileSource};
+use crate::hasher::HashScheme;
+use crate::modes::ModeComparison;
 use crate::params::Params;
+use crate::spill::SizeSpill;
+use crate::verify::{Verifier, VerifyMode};
+
+/// Strict mode reads size buckets in chunks of this size, splitting them as soon as they diverge.
+const HASH_CHUNK_SIZE: u64 = 4 * 1024 * 1024;
+/// Buckets of files that take at least this many bytes each to hash go to the large-file pool.
+const LARGE_FILE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// A verified duplicate group and the hash it was found under.
+pub type ConfirmedGroup = (u128, Vec<Arc<FileInfo>>);
+/// Identical staging files, by the path of the one compared against target in their stead.
+pub type StagingCopies = HashMap<Box<Path>, Vec<Arc<FileInfo>>>;
 
 #[derive(Debug, Clone)]
 pub struct ComparisonResult {
-    pub files_to_delete: Vec<FileInfo>,
+    pub files_to_delete: Vec<Arc<FileInfo>>,
+    /// The copies in target of each file to delete, one of which has to be left when it goes.
+    pub kept_in_target: HashMap<PathBuf, Vec<Arc<FileInfo>>>,
     pub warnings: Vec<String>,
+    pub directory_stats: Vec<DirectoryMatchRate>,
+    /// Sets of identical staging files that have no copy in target.
+    pub staging_duplicates: Vec<Vec<Arc<FileInfo>>>,
+    pub renames: Vec<Rename>,
+    /// Staging files whose content is nowhere in target, lost if staging were wiped.
+    pub unique: Vec<Arc<FileInfo>>,
+}
+
+/// A staging file whose content exists in target, but under a different relative path.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rename {
+    pub staging: PathBuf,
+    pub target: PathBuf,
+}
+
+/// How much of a staging subtree already exists in the target folder.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DirectoryMatchRate {
+    pub directory: PathBuf,
+    pub total: u64,
+    pub matched: u64,
+}
+
+impl DirectoryMatchRate {
+    pub fn percentage(&self) -> f64 {
+        match self.total {
+            0 => 0.0,
+            total => self.matched as f64 * 100.0 / total as f64,
+        }
+    }
+}
+
+/// How file contents are hashed: the per-run seed, the optional `--cache` of full hashes and
+/// the optional `--checkpoint` of the run.
+#[derive(Clone, Default)]
+pub struct Hashing {
+    pub seed: i64,
+    pub cache: Option<Arc<HashCache>>,
+    pub checkpoint: Option<Arc<Checkpoint>>,
+}
+
+impl From<i64> for Hashing {
+    fn from(seed: i64) -> Self {
+        Self {
+            seed,
+            ..Default::default()
+        }
+    }
 }
 
 pub struct Processor;
 
 impl Processor {
+    /// Hashes every size bucket announced on `buckets` until the size bucketer hangs up. Files
+    /// of a bucket are hashed as they arrive, except in strict and comparison mode, which wait
+    /// for the final buckets (see below). Buckets of large files are hashed on a pool of
+    /// `--large-file-threads` of their own, alongside the small ones on `--hash-threads`, so that
+    /// a handful of huge files cannot take every thread while thousands of small files wait.
+    /// Buckets on a spinning disk go to a single thread for that disk instead (see `Disks`).
     pub fn hashwise(
         app_args: Arc<Params>,
-        sw_store: Arc<DashMap<u64, Vec<FileInfo>>>,
-        hw_store: Arc<DashMap<u128, Vec<FileInfo>>>,
+        sw_store: Arc<DashMap<u64, Vec<Arc<FileInfo>>>>,
+        hw_store: Arc<DashMap<u128, Vec<Arc<FileInfo>>>>,
         progress_bar_box: Arc<MultiProgress>,
         max_file_size: Arc<AtomicU64>,
-        seed: i64,
-        sw_sorting_finished: Arc<AtomicBool>,
+        hashing: Hashing,
+        buckets: Receiver<u64>,
     ) -> Result<()> {
+        let Hashing {
+            seed,
+            cache,
+            checkpoint,
+        } = hashing;
+        let scheme = app_args.hash_scheme(seed);
         let progress_bar = match app_args.progress {
             true => progress_bar_box.add(ProgressBar::new_spinner()),
             false => ProgressBar::hidden(),
         };
 
-        let progress_style = ProgressStyle::with_template("[{elapsed_precise}] {pos:>7} {msg}")?;
+        // NOTE: comparison mode knows every file up front, so it can report bytes & an ETA.
+        let measure_bytes = app_args.comparison_mode;
+        let progress_style = match measure_bytes {
+            true => ProgressStyle::with_template(
+                "[{elapsed_precise}] {bytes:>10}/{total_bytes} {binary_bytes_per_sec} eta {eta} {msg}",
+            )?,
+            false => ProgressStyle::with_template("[{elapsed_precise}] {pos:>7} {msg}")?,
+        };
         progress_bar.set_style(progress_style);
         progress_bar.enable_steady_tick(Duration::from_millis(50));
         progress_bar.set_message("files grouped by hash.");
 
-        loop {
-            let keys: Vec<u64> = sw_store
-                .clone()
-                .iter()
-                .filter(|i| !i.value().iter().all(|x| x.is_sw_processed()))
-                .filter(|i| i.value().len() > 1)
-                .map(|i| *i.key())
-                .collect();
+        let spilled = app_args.get_max_memory().is_some();
+        let hash_bucket = |key: u64| {
+            let Some(mut group) = sw_store.get(&key).map(|bucket| bucket.to_vec()) else {
+                return;
+            };
+            // NOTE: in path order, which is close to the order on disk for a spinning one.
+            group.sort_by(|a, b| a.path.cmp(&b.path));
+            // NOTE: files are marked processed only once their hash is stored, so a bucket
+            // whose files are all processed holds its final groups (see stream_confirmed).
+            if group.len() > 1 && app_args.strict {
+                group.iter().for_each(|file| {
+                    Self::compare_and_update_max_path_len(
+                        max_file_size.clone(),
+                        file.path.to_string_lossy().graphemes(true).count() as u64,
+                    );
+                });
 
-            if keys.is_empty() {
-                match sw_sorting_finished.load(std::sync::atomic::Ordering::Relaxed) {
-                    true => {
-                        progress_bar.finish_with_message("files grouped by hash.");
-                        break Ok(());
+                // NOTE: a bucket is taken from the resumed run only if it hashed all of it.
+                let resumed = checkpoint.as_deref().and_then(|checkpoint| {
+                    group
+                        .iter()
+                        .map(|file| checkpoint.hash(file).map(|fhash| (fhash, vec![file.clone()])))
+                        .collect::<Option<Vec<(u128, Vec<Arc<FileInfo>>)>>>()
+                });
+                resumed
+                    .unwrap_or_else(|| {
+                        Self::strict_groups(&group, scheme, cache.as_deref(), &progress_bar, measure_bytes)
+                    })
+                    .into_iter()
+                    .for_each(|(fhash, files)| {
+                        files.iter().for_each(|file| {
+                            if let Some(checkpoint) = &checkpoint {
+                                checkpoint.record(file, fhash);
+                            }
+                            Self::insert_hashed(&hw_store, &app_args, fhash, seed, file)
+                        });
+                    });
+                group.iter().for_each(|file| file.sw_processed());
+            } else if group.len() > 1 {
+                // NOTE: a bucket is announced again for every file joining it later on.
+                group.retain(|file| !file.is_sw_processed());
+                group.par_iter_mut().for_each(|file| {
+                    progress_bar.inc(match measure_bytes {
+                        true => Self::bytes_to_hash(&app_args, file),
+                        false => 1,
+                    });
+                    let resumed = checkpoint.as_deref().and_then(|checkpoint| checkpoint.hash(file));
+                    let fhash = match resumed {
+                        Some(fhash) => fhash,
+                        None => file
+                            .prehash(scheme, app_args.get_prehash_size())
+                            .expect("hashing file failed."),
+                    };
+                    if let Some(checkpoint) = &checkpoint {
+                        checkpoint.record(file, fhash);
                     }
-                    false => continue,
+
+                    Self::compare_and_update_max_path_len(
+                        max_file_size.clone(),
+                        file.path.to_string_lossy().graphemes(true).count() as u64,
+                    );
+
+                    Self::insert_hashed(&hw_store, &app_args, fhash, seed, file);
+                    file.sw_processed();
+                });
+            };
+            // NOTE: a spilled bucket is final once announced, it is dropped as soon as hashed so
+            // that `--max-memory` bounds the buckets held, not just the files scanned.
+            if spilled {
+                sw_store.remove(&key);
+            }
+        };
+
+        let small_pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(app_args.stage_threads(app_args.hash_threads))
+            .build()?;
+        let large_pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(app_args.large_file_threads.max(1))
+            .build()?;
+        let is_large = |key: &u64| {
+            sw_store
+                .get(key)
+                .and_then(|bucket| {
+                    bucket
+                        .first()
+                        .map(|file| Self::bytes_to_hash(&app_args, file) >= LARGE_FILE_SIZE)
+                })
+                .unwrap_or(false)
+        };
+        // NOTE: a bucket with a file on a spinning disk is queued on that disk (see `Disks`).
+        let spinning = |key: &u64| {
+            sw_store.get(key).and_then(|bucket| {
+                bucket
+                    .iter()
+                    .find_map(|file| Disks::spinning(&file.path, app_args.disk_type))
+            })
+        };
+        let hash_bucket = &hash_bucket;
+
+        // NOTE: strict mode compares the files of a bucket against each other, and comparison
+        // mode needs the total up front, so both wait until the buckets are final, unless they
+        // were spilled, which only announces final buckets.
+        match (app_args.strict && !spilled) || measure_bytes {
+            true => {
+                let keys = buckets.iter().collect::<HashSet<u64>>();
+                if measure_bytes {
+                    progress_bar.set_length(Self::candidate_bytes(&sw_store, &app_args));
                 }
-            } else {
-                keys.into_par_iter().for_each(|key| {
-                    let mut group: Vec<FileInfo> = sw_store.get(&key).unwrap().to_vec();
-                    if group.len() > 1 {
-                        group.par_iter_mut().for_each(|file| {
-                            progress_bar.inc(1);
-                            file.sw_processed();
-
-                            let fhash = match app_args.strict {
-                                true => file.hash(seed).expect("hashing file failed."),
-                                false => file.initpages_hash(seed).expect("hashing file failed."),
-                            };
-
-                            Self::compare_and_update_max_path_len(
-                                max_file_size.clone(),
-                                file.path.to_string_lossy().graphemes(true).count() as u64,
-                            );
-
-                            hw_store
-                                .entry(fhash)
-                                .and_modify(|fileset| {
-                                    // Only add if this path doesn't already exist in the fileset
-                                    if !fileset.iter().any(|f| f.path == file.path) {
-                                        fileset.push(file.clone());
-                                    }
-                                })
-                                .or_insert_with(|| vec![file.clone()]);
+                let mut disks: HashMap<u64, Vec<u64>> = HashMap::new();
+                let (large, small): (Vec<u64>, Vec<u64>) = keys
+                    .into_iter()
+                    .filter(|key| match spinning(key) {
+                        Some(device) => {
+                            disks.entry(device).or_default().push(*key);
+                            false
+                        }
+                        None => true,
+                    })
+                    .partition(is_large);
+                let disks = disks
+                    .into_values()
+                    .map(|mut keys| {
+                        keys.sort_by_cached_key(|key| Self::first_path(&sw_store, key));
+                        Ok((Self::disk_pool()?, keys))
+                    })
+                    .collect::<Result<Vec<(rayon::ThreadPool, Vec<u64>)>>>()?;
+                std::thread::scope(|scope| {
+                    for (disk_pool, keys) in disks {
+                        scope.spawn(move || {
+                            disk_pool.install(|| keys.into_iter().for_each(hash_bucket))
                         });
-                    };
+                    }
+                    scope.spawn(|| {
+                        large_pool.install(|| large.into_par_iter().for_each(hash_bucket))
+                    });
+                    small_pool.install(|| small.into_par_iter().for_each(hash_bucket));
                 });
             }
+            false => std::thread::scope(|scope| -> Result<()> {
+                let (small_sender, small_receiver) = crossbeam_channel::unbounded();
+                let (large_sender, large_receiver) = crossbeam_channel::unbounded();
+                scope.spawn(move || {
+                    small_pool.install(|| small_receiver.iter().par_bridge().for_each(hash_bucket))
+                });
+                scope.spawn(move || {
+                    large_pool.install(|| large_receiver.iter().par_bridge().for_each(hash_bucket))
+                });
+                // NOTE: the senders hang up when this returns, which ends the work of every pool.
+                let mut disks: HashMap<u64, crossbeam_channel::Sender<u64>> = HashMap::new();
+                for key in buckets.iter() {
+                    let _ = match (spinning(&key), is_large(&key)) {
+                        (Some(device), _) => match disks.entry(device) {
+                            Entry::Occupied(disk) => disk.get().send(key),
+                            Entry::Vacant(disk) => {
+                                let (disk_sender, disk_receiver) = crossbeam_channel::unbounded();
+                                let disk_pool = Self::disk_pool()?;
+                                scope.spawn(move || {
+                                    disk_pool.install(|| disk_receiver.iter().for_each(hash_bucket))
+                                });
+                                disk.insert(disk_sender).send(key)
+                            }
+                        },
+                        (None, true) => large_sender.send(key),
+                        (None, false) => small_sender.send(key),
+                    };
+                }
+                Ok(())
+            })?,
+        }
+
+        progress_bar.finish_with_message("files grouped by hash.");
+        Ok(())
+    }
+
+    /// A single thread, so that the files of a spinning disk are read one after the other.
+    fn disk_pool() -> Result<rayon::ThreadPool> {
+        Ok(rayon::ThreadPoolBuilder::new().num_threads(1).build()?)
+    }
+
+    fn first_path(sw_store: &DashMap<u64, Vec<Arc<FileInfo>>>, key: &u64) -> Option<Box<Path>> {
+        sw_store
+            .get(key)
+            .and_then(|bucket| bucket.iter().map(|file| file.path.clone()).min())
+    }
+
+    fn insert_hashed(
+        hw_store: &DashMap<u128, Vec<Arc<FileInfo>>>,
+        app_args: &Params,
+        mut fhash: u128,
+        seed: i64,
+        file: &Arc<FileInfo>,
+    ) {
+        if app_args.same_name_only {
+            fhash ^= gxhash128(Self::file_name_bytes(file), seed);
+        }
+        tracing::debug!(path = %file.path.display(), hash = format!("{fhash:032x}"), "hashed");
+        app_args.events.emit(|| Event::FileHashed {
+            path: file.path.to_path_buf(),
+            size: file.size,
+        });
+
+        hw_store
+            .entry(fhash)
+            .and_modify(|fileset| {
+                // Only add if this path doesn't already exist in the fileset
+                if !fileset.iter().any(|f| f.path == file.path) {
+                    fileset.push(Arc::clone(file));
+                }
+            })
+            .or_insert_with(|| vec![Arc::clone(file)]);
+    }
+
+    /// Strict mode hashing of a size bucket, consulting the `--cache` first. Without any cached
+    /// file the bucket is read chunk by chunk; otherwise only the uncached files are hashed, in
+    /// full, so they can be compared against the cached hashes. Full hashes end up in the cache.
+    /// Photos compared without their metadata are always hashed in full and never cached, and so
+    /// is everything hashed with a digest (see `HashScheme::chunkable`).
+    fn strict_groups(
+        group: &[Arc<FileInfo>],
+        scheme: HashScheme,
+        cache: Option<&HashCache>,
+        progress_bar: &ProgressBar,
+        measure_bytes: bool,
+    ) -> Vec<(u128, Vec<Arc<FileInfo>>)> {
+        let chunkable = scheme.chunkable() && group.iter().all(|file| file.payload.is_none());
+        let Some(cache) = cache.filter(|_| group.iter().all(|file| file.payload.is_none())) else {
+            return match chunkable {
+                true => Self::chunked_groups(group, scheme, progress_bar, measure_bytes),
+                false => Self::full_groups(group, scheme, None, progress_bar, measure_bytes),
+            };
+        };
+
+        if chunkable && group.iter().all(|file| cache.get(file).is_none()) {
+            let groups = Self::chunked_groups(group, scheme, progress_bar, measure_bytes);
+            // NOTE: only groups that were read to the end carry a full hash, lone files were
+            // set aside early under a partial one.
+            groups
+                .iter()
+                .filter(|(_, files)| files.len() > 1)
+                .for_each(|(fhash, files)| files.iter().for_each(|file| cache.insert(file, *fhash)));
+            return groups;
+        }
+
+        Self::full_groups(group, scheme, Some(cache), progress_bar, measure_bytes)
+    }
+
+    /// Groups files by their full hash, taken from the `cache` where possible.
+    fn full_groups(
+        group: &[Arc<FileInfo>],
+        scheme: HashScheme,
+        cache: Option<&HashCache>,
+        progress_bar: &ProgressBar,
+        measure_bytes: bool,
+    ) -> Vec<(u128, Vec<Arc<FileInfo>>)> {
+        let mut by_hash: HashMap<u128, Vec<Arc<FileInfo>>> = HashMap::new();
+        group.iter().for_each(|file| {
+            progress_bar.inc(match measure_bytes {
+                true => file.size,
+                false => 1,
+            });
+            let hash = match cache.and_then(|cache| cache.get(file)) {
+                Some(hash) => Some(hash),
+                None => {
+                    let hash = file.hash(scheme).ok();
+                    if let (Some(cache), Some(hash)) = (cache, hash) {
+                        cache.insert(file, hash);
+                    }
+                    hash
+                }
+            };
+            if let Some(hash) = hash {
+                by_hash.entry(hash).or_default().push(file.clone());
+            }
+        });
+
+        by_hash.into_iter().collect()
+    }
+
+    /// Strict mode: reads a size bucket chunk by chunk, in lockstep across its files, and splits
+    /// it as soon as chunks diverge. A file left without a partner is set aside right away, keyed
+    /// by the chunks read so far, instead of being read to the end. Groups that make it through
+    /// get the same key `FileInfo::hash` would give.
+    fn chunked_groups(
+        group: &[Arc<FileInfo>],
+        scheme: HashScheme,
+        progress_bar: &ProgressBar,
+        measure_bytes: bool,
+    ) -> Vec<(u128, Vec<Arc<FileInfo>>)> {
+        let size = group.first().map(|file| file.size).unwrap_or_default();
+        let size_hash = scheme.digest(&size.to_ne_bytes());
+        if !measure_bytes {
+            progress_bar.inc(group.len() as u64);
+        }
+        if size == 0 {
+            return vec![(0u128, group.to_vec())];
+        }
+
+        let mut pending: Vec<(u128, Vec<Arc<FileInfo>>)> = vec![(0u128, group.to_vec())];
+        let mut finished: Vec<(u128, Vec<Arc<FileInfo>>)> = vec![];
+        let mut offset = 0u64;
+
+        while offset < size && !pending.is_empty() {
+            let length = (size - offset).min(HASH_CHUNK_SIZE);
+
+            let (diverged, matching): (Vec<_>, Vec<_>) = pending
+                .into_par_iter()
+                .flat_map_iter(|(acc, files)| {
+                    let mut by_chunk: HashMap<u128, Vec<Arc<FileInfo>>> = HashMap::new();
+                    files
+                        .into_par_iter()
+                        .filter_map(|file| {
+                            let chunk = file.chunk_hash(offset, length as usize, scheme).ok()?;
+                            Some((chunk, file))
+                        })
+                        .collect::<Vec<(u128, Arc<FileInfo>)>>()
+                        .into_iter()
+                        .for_each(|(chunk, file)| by_chunk.entry(chunk).or_default().push(file));
+
+                    by_chunk
+                        .into_iter()
+                        .map(|(chunk, files)| (acc ^ chunk, files))
+                        .collect::<Vec<(u128, Vec<Arc<FileInfo>>)>>()
+                })
+                .partition(|(_, files)| files.len() < 2);
+
+            if measure_bytes {
+                progress_bar.inc(length * matching.iter().map(|(_, f)| f.len() as u64).sum::<u64>());
+                progress_bar.inc((size - offset) * diverged.len() as u64);
+            }
+
+            finished.extend(diverged);
+            pending = matching;
+            offset += length;
+        }
+
+        finished
+            .into_iter()
+            .chain(pending)
+            .map(|(acc, files)| (acc ^ size_hash, files))
+            .collect()
+    }
+
+    fn bytes_to_hash(app_args: &Params, file: &FileInfo) -> u64 {
+        match app_args.strict {
+            true => file.size,
+            false => file.size.min(app_args.get_prehash_size().saturating_mul(3)),
+        }
+    }
+
+    /// Total bytes hashwise will read: only files sharing a size bucket get hashed.
+    fn candidate_bytes(sw_store: &DashMap<u64, Vec<Arc<FileInfo>>>, app_args: &Params) -> u64 {
+        sw_store
+            .iter()
+            .filter(|bucket| bucket.value().len() > 1)
+            .map(|bucket| {
+                bucket
+                    .value()
+                    .iter()
+                    .map(|file| Self::bytes_to_hash(app_args, file))
+                    .sum::<u64>()
+            })
+            .sum()
+    }
+
+    fn file_name_bytes(file: &FileInfo) -> &[u8] {
+        file.path
+            .file_name()
+            .map(|name| name.as_encoded_bytes())
+            .unwrap_or_default()
+    }
+
+    /// Key used to bucket files before hashing. With `--same-name-only` the filename is folded
+    /// in so that files with different names never end up in the same bucket.
+    fn size_bucket_key(app_args: &Params, file: &FileInfo) -> u64 {
+        match app_args.same_name_only {
+            true => file.content_size() ^ gxhash64(Self::file_name_bytes(file), 0),
+            false => file.content_size(),
         }
     }
 
@@ -96,11 +518,13 @@ impl Processor {
         }
     }
 
+    /// Groups the files received on `files` by size and announces every bucket holding more
+    /// than one file on `buckets`. Returns once the scanner hangs up, which closes `buckets`.
     pub fn sizewise(
         app_args: Arc<Params>,
-        scanner_finished: Arc<AtomicBool>,
-        store: Arc<DashMap<u64, Vec<FileInfo>>>,
-        files: Arc<Mutex<Vec<FileInfo>>>,
+        files: Receiver<Arc<FileInfo>>,
+        store: Arc<DashMap<u64, Vec<Arc<FileInfo>>>>,
+        buckets: crossbeam_channel::Sender<u64>,
         progress_bar_box: Arc<MultiProgress>,
     ) -> Result<()> {
         let progress_bar = match app_args.progress {
@@ -113,63 +537,408 @@ impl Processor {
         progress_bar.enable_steady_tick(Duration::from_millis(50));
         progress_bar.set_message("files grouped by size");
 
-        loop {
-            let fileopt: Option<FileInfo> = {
-                match files.try_lock() {
-                    Ok(mut flist) => flist.pop(),
-                    TryLockResult::Err(TryLockError::WouldBlock) => None,
-                    _ => None,
+        if let Some(budget) = app_args.get_max_memory() {
+            Self::sizewise_spilled(&app_args, files, &store, &buckets, &progress_bar, budget)?;
+            progress_bar.finish_with_message("files grouped by size");
+            return Ok(());
+        }
+
+        for file in files.iter() {
+            progress_bar.inc(1);
+            let key = Self::size_bucket_key(&app_args, &file);
+            // NOTE: the entry is released before sending, the hasher reads the same bucket.
+            let bucket_len = {
+                let mut fileset = store.entry(key).or_default();
+                // Only add if this path doesn't already exist in the fileset
+                if !fileset.iter().any(|f| f.path == file.path) {
+                    fileset.push(file);
                 }
+                fileset.len()
             };
 
-            match fileopt {
-                Some(file) => {
-                    progress_bar.inc(1);
-                    store
-                        .entry(file.size)
-                        .and_modify(|fileset| {
-                            // Only add if this path doesn't already exist in the fileset
-                            if !fileset.iter().any(|f| f.path == file.path) {
-                                fileset.push(file.clone());
-                            }
-                        })
-                        .or_insert_with(|| vec![file]);
-                    continue;
+            if bucket_len > 1 && buckets.send(key).is_err() {
+                break;
+            }
+        }
+
+        progress_bar.finish_with_message("files grouped by size");
+        Ok(())
+    }
+
+    /// `sizewise` within `budget` bytes (see `--max-memory`): files are spilled to disk as they
+    /// come, and only buckets of more than one file are stored & announced, once the scanner
+    /// hung up, as a size is only final then. The hasher drops each bucket it hashed, and the
+    /// next one is only stored once those still waiting fit in the budget.
+    fn sizewise_spilled(
+        app_args: &Params,
+        files: Receiver<Arc<FileInfo>>,
+        store: &DashMap<u64, Vec<Arc<FileInfo>>>,
+        buckets: &crossbeam_channel::Sender<u64>,
+        progress_bar: &ProgressBar,
+        budget: usize,
+    ) -> Result<()> {
+        let mut spill = SizeSpill::new(app_args.scratch("spill")?, budget)?;
+        for file in files.iter() {
+            progress_bar.inc(1);
+            spill.push(Self::size_bucket_key(app_args, &file), file)?;
+        }
+
+        let mut waiting: Vec<(u64, usize)> = vec![];
+        for bucket in spill.buckets()? {
+            let (key, files) = bucket?;
+            let bytes = files.iter().map(|file| SizeSpill::held_bytes(file)).sum::<usize>();
+            loop {
+                waiting.retain(|(key, _)| store.contains_key(key));
+                let held = waiting.iter().map(|(_, bytes)| bytes).sum::<usize>();
+                // NOTE: a bucket larger than the budget goes on its own.
+                if waiting.is_empty() || held + bytes <= budget {
+                    break;
                 }
-                None => match scanner_finished.load(std::sync::atomic::Ordering::Relaxed) {
-                    true => {
-                        progress_bar.finish_with_message("files grouped by size");
-                        break Ok(());
+                std::thread::sleep(Duration::from_millis(10));
+            }
+            store.insert(key, files);
+            waiting.push((key, bytes));
+            if buckets.send(key).is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends hash groups to `sender`, and to event subscribers, as soon as they can no longer
+    /// change: sizes are final and every size bucket feeding the group has been hashed. Groups
+    /// are verified before they are sent and each file is sent at most once. Returns once hashing
+    /// has finished or no one listens any more.
+    pub fn stream_confirmed(
+        app_args: Arc<Params>,
+        sw_store: Arc<DashMap<u64, Vec<Arc<FileInfo>>>>,
+        hw_store: Arc<DashMap<u128, Vec<Arc<FileInfo>>>>,
+        sw_sorting_finished: Arc<AtomicBool>,
+        hw_sorting_finished: Arc<AtomicBool>,
+        mut sender: Option<Sender<ConfirmedGroup>>,
+    ) -> Result<()> {
+        let mut examined: HashMap<u128, usize> = HashMap::new();
+        let mut sent: HashSet<Box<Path>> = HashSet::new();
+
+        loop {
+            // NOTE: read before the pass, so the last pass runs over the complete store.
+            let hashing_finished = hw_sorting_finished.load(Ordering::Relaxed);
+
+            if sw_sorting_finished.load(Ordering::Relaxed) {
+                let ready = hw_store
+                    .iter()
+                    .filter(|group| group.value().len() > 1)
+                    .filter(|group| examined.get(group.key()) != Some(&group.value().len()))
+                    .filter(|group| {
+                        // NOTE: a bucket gone from the store was spilled and dropped once hashed.
+                        group.value().iter().all(|file| {
+                            sw_store
+                                .get(&Self::size_bucket_key(&app_args, file))
+                                .is_none_or(|bucket| bucket.iter().all(|f| f.is_sw_processed()))
+                        })
+                    })
+                    .map(|group| (*group.key(), group.value().clone()))
+                    .collect::<Vec<(u128, Vec<Arc<FileInfo>>)>>();
+
+                for (key, group) in ready {
+                    examined.insert(key, group.len());
+                    for subgroup in Verifier::split_group(&group, app_args.verification()) {
+                        if subgroup.len() < 2 || subgroup.iter().any(|f| sent.contains(&f.path)) {
+                            continue;
+                        }
+                        sent.extend(subgroup.iter().map(|f| f.path.clone()));
+                        app_args.events.emit(|| Event::GroupFound(DuplicateGroup::new(key, &subgroup)));
+                        if sender.as_ref().is_some_and(|to| to.send((key, subgroup)).is_err()) {
+                            sender = None;
+                        }
                     }
-                    false => continue,
-                },
+                }
             }
+
+            // NOTE: once the receiver hung up, groups are only found for event subscribers.
+            if hashing_finished || (sender.is_none() && !app_args.events.active()) {
+                break Ok(());
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    /// Confirms every hash group by full content (see `--verify`) as a stage of its own, on a
+    /// dedicated pool of `--verify-threads`, so long full-file reads never compete with the cheap
+    /// partial hashing. Groups holding different contents are split in place; returns a warning
+    /// for every group that had to be split, and how the groups fared (see `--compare-modes`).
+    pub fn verify_groups(
+        app_args: Arc<Params>,
+        hw_store: Arc<DashMap<u128, Vec<Arc<FileInfo>>>>,
+        progress_bar_box: Arc<MultiProgress>,
+    ) -> Result<(Vec<String>, ModeComparison)> {
+        let mut comparison = ModeComparison {
+            sample: app_args.get_prehash_size(),
+            ..Default::default()
+        };
+        let verify = app_args.verification();
+        if verify == VerifyMode::None {
+            return Ok((vec![], comparison));
         }
+
+        let keys: Vec<u128> = hw_store
+            .iter()
+            .filter(|group| group.value().len() > 1)
+            .map(|group| *group.key())
+            .collect();
+        let total_bytes: u64 = keys
+            .iter()
+            .filter_map(|key| hw_store.get(key))
+            .map(|group| group.iter().map(|file| file.size).sum::<u64>())
+            .sum();
+
+        let progress_bar = match app_args.progress {
+            true => progress_bar_box.add(ProgressBar::new(total_bytes)),
+            false => ProgressBar::hidden(),
+        };
+        progress_bar.set_style(ProgressStyle::with_template(
+            "[{elapsed_precise}] {bytes:>10}/{total_bytes} {binary_bytes_per_sec} eta {eta} {msg}",
+        )?);
+        progress_bar.enable_steady_tick(Duration::from_millis(50));
+        progress_bar.set_message("bytes verified.");
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(app_args.stage_threads(app_args.verify_threads))
+            .build()?;
+
+        let verified = pool.install(|| {
+            keys.into_par_iter()
+                .filter_map(|key| {
+                    let group = hw_store.get(&key)?.to_vec();
+                    let subgroups = Verifier::split_group(&group, verify);
+                    progress_bar.inc(group.iter().map(|file| file.size).sum());
+                    Some((key, group, subgroups))
+                })
+                .collect::<Vec<(u128, Vec<Arc<FileInfo>>, Vec<Vec<Arc<FileInfo>>>)>>()
+        });
+
+        let mut warnings = Vec::new();
+        verified.into_iter().for_each(|(key, group, subgroups)| {
+            comparison.tally(&group, &subgroups);
+            if subgroups.len() > 1 {
+                warnings.push(format!(
+                    "Warning: Hash {:32x} matched files with different contents, verification split it into {} groups.",
+                    key,
+                    subgroups.len()
+                ));
+            }
+
+            hw_store.remove(&key);
+            // NOTE: split-off groups take the neighbouring keys, a clash among 128 bit hashes is
+            // as unlikely as the collision that caused the split.
+            subgroups
+                .into_iter()
+                .enumerate()
+                .for_each(|(index, subgroup)| {
+                    hw_store.insert(key.wrapping_add(index as u128), subgroup);
+                });
+        });
+
+        progress_bar.finish_with_message("bytes verified.");
+        Ok((warnings, comparison))
+    }
+
+    /// Collapses identical files inside staging so each content is only compared against target
+    /// once. Returns one representative per content plus, keyed by representative path, the
+    /// copies it stands for.
+    pub fn collapse_staging(
+        staging_files: Vec<Arc<FileInfo>>,
+        app_args: &Params,
+        seed: i64,
+    ) -> (Vec<Arc<FileInfo>>, StagingCopies) {
+        let scheme = app_args.hash_scheme(seed);
+        let mut buckets: HashMap<u64, Vec<Arc<FileInfo>>> = HashMap::new();
+        staging_files.into_iter().for_each(|file| {
+            buckets
+                .entry(Self::size_bucket_key(app_args, &file))
+                .or_default()
+                .push(file);
+        });
+
+        let (singles, candidates): (Vec<_>, Vec<_>) =
+            buckets.into_values().partition(|bucket| bucket.len() < 2);
+
+        let sets = candidates
+            .into_par_iter()
+            .flat_map(|bucket| {
+                let mut by_hash: HashMap<u128, Vec<Arc<FileInfo>>> = HashMap::new();
+                bucket.into_iter().for_each(|file| {
+                    let fhash = match app_args.strict {
+                        true => file.hash(scheme),
+                        false => file.prehash(scheme, app_args.get_prehash_size()),
+                    };
+                    match fhash {
+                        Ok(fhash) => by_hash.entry(fhash).or_default().push(file),
+                        Err(_) => by_hash.entry(u128::MAX).or_default().push(file),
+                    }
+                });
+                by_hash
+                    .into_values()
+                    .flat_map(|set| Verifier::split_group(&set, app_args.verification()))
+                    .collect::<Vec<Vec<Arc<FileInfo>>>>()
+            })
+            .collect::<Vec<Vec<Arc<FileInfo>>>>();
+
+        let mut representatives = Vec::new();
+        let mut copies = HashMap::new();
+        singles.into_iter().chain(sets).for_each(|mut set| {
+            set.sort_by(|a, b| a.path.cmp(&b.path));
+            let representative = set.remove(0);
+            if !set.is_empty() {
+                copies.insert(representative.path.clone(), set);
+            }
+            representatives.push(representative);
+        });
+
+        (representatives, copies)
+    }
+
+    /// Drops the files of either tree whose size matches no file of the other: they cannot have
+    /// a copy there, so there is no point in opening them, not even to collapse staging copies.
+    /// Returns the remaining staging & target files and the number of staging files skipped.
+    pub fn retain_shared_sizes(
+        staging_files: Vec<Arc<FileInfo>>,
+        target_files: Vec<Arc<FileInfo>>,
+    ) -> (Vec<Arc<FileInfo>>, Vec<Arc<FileInfo>>, u64) {
+        let sizes = |files: &[Arc<FileInfo>]| -> HashSet<u64> {
+            files.iter().map(|file| file.content_size()).collect()
+        };
+        let (staging_sizes, target_sizes) = (sizes(&staging_files), sizes(&target_files));
+
+        let before = staging_files.len() as u64;
+        let staging_files = staging_files
+            .into_iter()
+            .filter(|file| target_sizes.contains(&file.content_size()))
+            .collect::<Vec<Arc<FileInfo>>>();
+        let target_files = target_files
+            .into_iter()
+            .filter(|file| staging_sizes.contains(&file.content_size()))
+            .collect::<Vec<Arc<FileInfo>>>();
+        let skipped = before - staging_files.len() as u64;
+
+        (staging_files, target_files, skipped)
+    }
+
+    /// Aggregates, for every directory below the staging root, how many of the files in its
+    /// subtree were matched in target.
+    pub fn directory_match_rates(
+        staging_files: &[Arc<FileInfo>],
+        matched: &[Arc<FileInfo>],
+        staging_root: &Path,
+    ) -> Vec<DirectoryMatchRate> {
+        let mut rates: BTreeMap<PathBuf, DirectoryMatchRate> = BTreeMap::new();
+        let matched_paths: HashSet<&Path> = matched.iter().map(|m| m.path.as_ref()).collect();
+
+        staging_files.iter().for_each(|file| {
+            let is_matched = matched_paths.contains(file.path.as_ref());
+            file.path
+                .parent()
+                .into_iter()
+                .flat_map(|parent| parent.ancestors())
+                .filter_map(|dir| dir.strip_prefix(staging_root).ok())
+                .filter(|relative| !relative.as_os_str().is_empty())
+                .for_each(|relative| {
+                    let rate = rates
+                        .entry(relative.to_path_buf())
+                        .or_insert_with(|| DirectoryMatchRate {
+                            directory: relative.to_path_buf(),
+                            total: 0,
+                            matched: 0,
+                        });
+                    rate.total += 1;
+                    rate.matched += is_matched as u64;
+                });
+        });
+
+        rates.into_values().collect()
+    }
+
+    /// Staging files of a matched group that have no target copy at the same relative path. Each
+    /// is paired with the first target copy (by path) so the report shows where it ended up.
+    pub fn detect_renames(
+        staging_files: &[Arc<FileInfo>],
+        target_files: &[&Arc<FileInfo>],
+        staging_root: &Path,
+        target_root: &Path,
+    ) -> Vec<Rename> {
+        let target_relative = target_files
+            .iter()
+            .filter_map(|f| f.path.strip_prefix(target_root).ok())
+            .collect::<HashSet<&Path>>();
+        let Some(target) = target_files.iter().map(|f| &f.path).min() else {
+            return vec![];
+        };
+
+        staging_files
+            .iter()
+            .filter(|f| {
+                f.path
+                    .strip_prefix(staging_root)
+                    .map_or(true, |relative| !target_relative.contains(relative))
+            })
+            .map(|f| Rename {
+                staging: f.path.to_path_buf(),
+                target: target.to_path_buf(),
+            })
+            .collect()
     }
 
     /// Analyzes the hash-wise duplicate set to find files that exist in both staging and target.
     /// This method operates on the already-processed hash groups from the Server pipeline.
     pub fn analyze_comparison(
-        hw_duplicate_set: Arc<DashMap<u128, Vec<FileInfo>>>,
+        hw_duplicate_set: Arc<DashMap<u128, Vec<Arc<FileInfo>>>>,
+        scanned_staging: &[Arc<FileInfo>],
+        staging_copies: &StagingCopies,
+        staging_root: &Path,
+        target_root: &Path,
     ) -> Result<ComparisonResult> {
         let mut files_to_delete = Vec::new();
+        let mut kept_in_target = HashMap::new();
         let mut warnings = Vec::new();
+        let mut renames = Vec::new();
 
-        for entry in hw_duplicate_set.iter() {
+        for entry in hw_duplicate_set.iter().filter(|entry| entry.value().len() > 1) {
             let group = entry.value();
-            let staging_files: Vec<&FileInfo> = group
+            let staging_files: Vec<&Arc<FileInfo>> = group
                 .iter()
                 .filter(|f| f.source == Some(FileSource::Staging))
                 .collect();
-            let target_files: Vec<&FileInfo> = group
+            let target_files: Vec<&Arc<FileInfo>> = group
                 .iter()
                 .filter(|f| f.source == Some(FileSource::Target))
                 .collect();
 
             // If file exists in both staging and target
             if !staging_files.is_empty() && !target_files.is_empty() {
-                // Remove all instances from staging
-                files_to_delete.extend(staging_files.iter().map(|f| (*f).clone()));
+                // Remove all instances from staging, including the collapsed copies
+                let matched_staging = staging_files
+                    .iter()
+                    .map(|f| (*f).clone())
+                    .chain(
+                        staging_files
+                            .iter()
+                            .filter_map(|f| staging_copies.get(&f.path))
+                            .flatten()
+                            .cloned(),
+                    )
+                    .collect::<Vec<Arc<FileInfo>>>();
+
+                renames.extend(Self::detect_renames(
+                    &matched_staging,
+                    &target_files,
+                    staging_root,
+                    target_root,
+                ));
+                let kept = target_files.iter().map(|f| (*f).clone()).collect::<Vec<Arc<FileInfo>>>();
+                for f in &matched_staging {
+                    kept_in_target.insert(f.path.to_path_buf(), kept.clone());
+                }
+                files_to_delete.extend(matched_staging);
 
                 // Warn if multiple instances in target
                 if target_files.len() > 1 {
@@ -184,9 +953,41 @@ impl Processor {
             }
         }
 
+        let directory_stats =
+            Self::directory_match_rates(scanned_staging, &files_to_delete, staging_root);
+
+        let matched_paths: HashSet<&Path> =
+            files_to_delete.iter().map(|f| f.path.as_ref()).collect();
+        let mut staging_duplicates = staging_copies
+            .iter()
+            .filter(|(representative, _)| !matched_paths.contains(representative.as_ref()))
+            .filter_map(|(representative, copies)| {
+                let representative = scanned_staging.iter().find(|f| &f.path == representative)?;
+                Some(
+                    std::iter::once(representative.clone())
+                        .chain(copies.iter().cloned())
+                        .collect::<Vec<Arc<FileInfo>>>(),
+                )
+            })
+            .collect::<Vec<Vec<Arc<FileInfo>>>>();
+        staging_duplicates.sort_by(|a, b| a[0].path.cmp(&b[0].path));
+        renames.sort_by(|a, b| a.staging.cmp(&b.staging));
+
+        let mut unique = scanned_staging
+            .iter()
+            .filter(|file| !matched_paths.contains(file.path.as_ref()))
+            .cloned()
+            .collect::<Vec<Arc<FileInfo>>>();
+        unique.sort_by(|a, b| a.path.cmp(&b.path));
+
         Ok(ComparisonResult {
             files_to_delete,
+            kept_in_target,
             warnings,
+            directory_stats,
+            staging_duplicates,
+            renames,
+            unique,
         })
     }
 }
@@ -197,21 +998,49 @@ mod tests {
     use dashmap::DashMap;
     use indicatif::MultiProgress;
     use rand::Rng;
+    use std::collections::HashMap;
     use std::fs::File;
     use std::io::Write;
+    use crossbeam_channel::Receiver;
     use std::sync::atomic::{AtomicBool, AtomicU64};
     use std::sync::{Arc, Mutex};
     use tempfile::TempDir;
 
-    use crate::{fileinfo::FileInfo, params::Params};
+    use crate::{
+        fileinfo::{FileInfo, FileSource},
+        fixture::Fixture,
+        params::Params,
+        verify::VerifyMode,
+    };
 
-    use super::Processor;
+    use super::{Processor, LARGE_FILE_SIZE};
 
     fn generate_bytes(size: usize) -> Vec<u8> {
         let mut rng = rand::rng();
         (0..size).map(|_| rng.random::<u8>()).collect::<Vec<u8>>()
     }
 
+    /// Runs the size bucketer over `files`, returning the bucket announcements meant for hashwise.
+    fn size_buckets(
+        app_args: Arc<Params>,
+        files: Vec<Arc<FileInfo>>,
+        store: Arc<DashMap<u64, Vec<Arc<FileInfo>>>>,
+    ) -> Result<Receiver<u64>> {
+        let (file_sender, file_receiver) = crossbeam_channel::unbounded();
+        files.into_iter().try_for_each(|file| file_sender.send(file))?;
+        drop(file_sender);
+
+        let (bucket_sender, bucket_receiver) = crossbeam_channel::unbounded();
+        Processor::sizewise(
+            app_args,
+            file_receiver,
+            store,
+            bucket_sender,
+            Arc::new(MultiProgress::new()),
+        )?;
+        Ok(bucket_receiver)
+    }
+
     #[test]
     fn hashwise_sorting_two_files_with_identical_init_pages_only_strict_mode() -> Result<()> {
         let root = TempDir::new()?;
@@ -234,21 +1063,13 @@ mod tests {
         }
 
         let dupstore = Arc::new(DashMap::new());
-        let file_queue = Arc::new(Mutex::new(
-            files
-                .iter()
-                .map(|f| FileInfo::new(f.0.clone()).unwrap())
-                .collect::<Vec<FileInfo>>(),
-        ));
+        let file_queue = files
+            .iter()
+            .map(|f| Arc::new(FileInfo::new(f.0.clone()).unwrap()))
+            .collect::<Vec<Arc<FileInfo>>>();
 
         let hw_dupstore = Arc::new(DashMap::new());
-        Processor::sizewise(
-            Arc::new(Params::default()),
-            Arc::new(AtomicBool::new(true)),
-            dupstore.clone(),
-            file_queue,
-            Arc::new(MultiProgress::new()),
-        )?;
+        let buckets = size_buckets(Arc::new(Params::default()), file_queue, dupstore.clone())?;
 
         let args = Params {
             strict: true,
@@ -261,8 +1082,8 @@ mod tests {
             hw_dupstore.clone(),
             Arc::new(MultiProgress::new()),
             Arc::new(AtomicU64::new(32)),
-            300,
-            Arc::new(AtomicBool::new(true)),
+            300.into(),
+            buckets,
         )?;
 
         assert_eq!(hw_dupstore.len(), 2);
@@ -292,21 +1113,13 @@ mod tests {
         }
 
         let dupstore = Arc::new(DashMap::new());
-        let file_queue = Arc::new(Mutex::new(
-            files
-                .iter()
-                .map(|f| FileInfo::new(f.0.clone()).unwrap())
-                .collect::<Vec<FileInfo>>(),
-        ));
+        let file_queue = files
+            .iter()
+            .map(|f| Arc::new(FileInfo::new(f.0.clone()).unwrap()))
+            .collect::<Vec<Arc<FileInfo>>>();
 
         let hw_dupstore = Arc::new(DashMap::new());
-        Processor::sizewise(
-            Arc::new(Params::default()),
-            Arc::new(AtomicBool::new(true)),
-            dupstore.clone(),
-            file_queue,
-            Arc::new(MultiProgress::new()),
-        )?;
+        let buckets = size_buckets(Arc::new(Params::default()), file_queue, dupstore.clone())?;
 
         Processor::hashwise(
             Arc::new(Params::default()),
@@ -314,11 +1127,12 @@ mod tests {
             hw_dupstore.clone(),
             Arc::new(MultiProgress::new()),
             Arc::new(AtomicU64::new(32)),
-            300,
-            Arc::new(AtomicBool::new(true)),
+            300.into(),
+            buckets,
         )?;
 
-        assert_eq!(hw_dupstore.len(), 1);
+        // NOTE: the middle & end samples tell them apart, the start alone did not.
+        assert_eq!(hw_dupstore.len(), 2);
 
         Ok(())
     }
@@ -338,33 +1152,115 @@ mod tests {
         }
 
         let dupstore = Arc::new(DashMap::new());
-        let file_queue = Arc::new(Mutex::new(
-            files
-                .iter()
-                .map(|f| FileInfo::new(f.0.clone()).unwrap())
-                .collect::<Vec<FileInfo>>(),
-        ));
+        let file_queue = files
+            .iter()
+            .map(|f| Arc::new(FileInfo::new(f.0.clone()).unwrap()))
+            .collect::<Vec<Arc<FileInfo>>>();
 
         let hw_dupstore = Arc::new(DashMap::new());
-        Processor::sizewise(
+        let buckets = size_buckets(Arc::new(Params::default()), file_queue, dupstore.clone())?;
+
+        Processor::hashwise(
             Arc::new(Params::default()),
-            Arc::new(AtomicBool::new(true)),
             dupstore.clone(),
-            file_queue,
+            hw_dupstore.clone(),
             Arc::new(MultiProgress::new()),
+            Arc::new(AtomicU64::new(32)),
+            300.into(),
+            buckets,
         )?;
 
+        assert_eq!(hw_dupstore.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn hashwise_hashes_large_and_small_buckets_side_by_side() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let mut file_queue = vec![];
+        let sizes = [
+            ("big1.img", LARGE_FILE_SIZE),
+            ("big2.img", LARGE_FILE_SIZE),
+            ("a.txt", 3),
+            ("b.txt", 3),
+        ];
+        for (name, size) in sizes {
+            // NOTE: sparse, so the large files cost no disk space.
+            file_queue.push(Arc::new(FileInfo::new(fixture.sparse(name, size)?)?));
+        }
+
+        let app_args = Arc::new(Params {
+            strict: true,
+            large_file_threads: 1,
+            ..Default::default()
+        });
+        let dupstore = Arc::new(DashMap::new());
+        let hw_dupstore = Arc::new(DashMap::new());
+        let buckets = size_buckets(app_args.clone(), file_queue, dupstore.clone())?;
+
         Processor::hashwise(
-            Arc::new(Params::default()),
-            dupstore.clone(),
+            app_args,
+            dupstore,
             hw_dupstore.clone(),
             Arc::new(MultiProgress::new()),
             Arc::new(AtomicU64::new(32)),
-            300,
-            Arc::new(AtomicBool::new(true)),
+            300.into(),
+            buckets,
         )?;
 
-        assert_eq!(hw_dupstore.len(), 1);
+        assert_eq!(hw_dupstore.len(), 2);
+        assert!(hw_dupstore.iter().all(|group| group.value().len() == 2));
+
+        Ok(())
+    }
+
+    #[test]
+    fn spilled_buckets_are_hashed_one_by_one_and_dropped() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let file_queue = [("a1", "a"), ("a2", "a"), ("b1", "bb"), ("b2", "bb"), ("c", "ccc")]
+            .iter()
+            .map(|(name, content)| fixture.scanned(name, content.as_bytes()))
+            .collect::<Result<Vec<Arc<FileInfo>>>>()?;
+        // NOTE: a budget below any bucket, each one waits for the last to be hashed.
+        let app_args = Arc::new(Params {
+            strict: true,
+            max_memory: Some("1B".to_string()),
+            tmpdir: Some(fixture.root().to_path_buf()),
+            ..Default::default()
+        });
+        let dupstore = Arc::new(DashMap::new());
+        let hw_dupstore = Arc::new(DashMap::new());
+
+        let (file_sender, file_receiver) = crossbeam_channel::unbounded();
+        file_queue.into_iter().try_for_each(|file| file_sender.send(file))?;
+        drop(file_sender);
+        let (bucket_sender, bucket_receiver) = crossbeam_channel::unbounded();
+        std::thread::scope(|scope| -> Result<()> {
+            let sizes = scope.spawn(|| {
+                Processor::sizewise(
+                    app_args.clone(),
+                    file_receiver,
+                    dupstore.clone(),
+                    bucket_sender,
+                    Arc::new(MultiProgress::new()),
+                )
+            });
+            Processor::hashwise(
+                app_args.clone(),
+                dupstore.clone(),
+                hw_dupstore.clone(),
+                Arc::new(MultiProgress::new()),
+                Arc::new(AtomicU64::new(32)),
+                300.into(),
+                bucket_receiver,
+            )?;
+            sizes.join().unwrap()
+        })?;
+
+        assert!(dupstore.is_empty());
+        assert_eq!(hw_dupstore.len(), 2);
+        assert!(hw_dupstore.iter().all(|group| group.value().len() == 2));
 
         Ok(())
     }
@@ -382,24 +1278,17 @@ mod tests {
             f.write_all(content)?;
         }
 
-        let file_queue = Arc::new(Mutex::new(
-            files
-                .iter()
-                .map(|f| FileInfo::new(f.0.clone()).unwrap())
-                .collect::<Vec<FileInfo>>(),
-        ));
+        let file_queue = files
+            .iter()
+            .map(|f| Arc::new(FileInfo::new(f.0.clone()).unwrap()))
+            .collect::<Vec<Arc<FileInfo>>>();
 
         let dupstore = Arc::new(DashMap::new());
 
-        Processor::sizewise(
-            Arc::new(Params::default()),
-            Arc::new(AtomicBool::new(true)),
-            dupstore.clone(),
-            file_queue,
-            Arc::new(MultiProgress::new()),
-        )?;
+        let buckets = size_buckets(Arc::new(Params::default()), file_queue, dupstore.clone())?;
 
         assert_eq!(dupstore.len(), 2);
+        assert!(buckets.is_empty());
 
         Ok(())
     }
@@ -417,24 +1306,308 @@ mod tests {
             f.write_all(content)?;
         }
 
-        let file_queue = Arc::new(Mutex::new(
-            files
-                .iter()
-                .map(|f| FileInfo::new(f.0.clone()).unwrap())
-                .collect::<Vec<FileInfo>>(),
-        ));
+        let file_queue = files
+            .iter()
+            .map(|f| Arc::new(FileInfo::new(f.0.clone()).unwrap()))
+            .collect::<Vec<Arc<FileInfo>>>();
 
         let dupstore = Arc::new(DashMap::new());
 
-        Processor::sizewise(
-            Arc::new(Params::default()),
-            Arc::new(AtomicBool::new(true)),
+        let buckets = size_buckets(Arc::new(Params::default()), file_queue, dupstore.clone())?;
+
+        assert_eq!(dupstore.len(), 1);
+        assert_eq!(buckets.iter().collect::<Vec<u64>>(), vec![282624]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn hashwise_same_name_only_ignores_identical_files_with_different_names() -> Result<()> {
+        let root = TempDir::new()?;
+        std::fs::create_dir(root.path().join("a"))?;
+        std::fs::create_dir(root.path().join("b"))?;
+
+        let content = generate_bytes(282624);
+        let files = [
+            root.path().join("a").join("report.pdf"),
+            root.path().join("b").join("report.pdf"),
+            root.path().join("b").join("report-final.pdf"),
+        ];
+
+        for fpath in files.iter() {
+            let mut f = File::create_new(fpath)?;
+            f.write_all(&content)?;
+        }
+
+        let args = Arc::new(Params {
+            same_name_only: true,
+            ..Default::default()
+        });
+
+        let dupstore = Arc::new(DashMap::new());
+        let file_queue = files
+            .iter()
+            .map(|f| Arc::new(FileInfo::new(f.clone()).unwrap()))
+            .collect::<Vec<Arc<FileInfo>>>();
+
+        let hw_dupstore = Arc::new(DashMap::new());
+        let buckets = size_buckets(args.clone(), file_queue, dupstore.clone())?;
+
+        Processor::hashwise(
+            args,
             dupstore.clone(),
-            file_queue,
+            hw_dupstore.clone(),
             Arc::new(MultiProgress::new()),
+            Arc::new(AtomicU64::new(32)),
+            300.into(),
+            buckets,
         )?;
 
-        assert_eq!(dupstore.len(), 1);
+        assert_eq!(hw_dupstore.len(), 1);
+        assert!(hw_dupstore
+            .iter()
+            .all(|group| group.value().iter().all(|f| f.path.ends_with("report.pdf"))));
+
+        Ok(())
+    }
+
+    #[test]
+    fn directory_match_rates_aggregate_staging_subtrees() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let staging = fixture.path("staging");
+
+        let files = ["Camera/2023/a.jpg", "Camera/2023/b.jpg", "Camera/2024/c.jpg", "Camera/2024/d.jpg"]
+            .iter()
+            .map(|name| {
+                fixture.sourced(staging.join(name), name.as_bytes(), FileSource::Staging)
+            })
+            .collect::<Result<Vec<Arc<FileInfo>>>>()?;
+
+        let matched = vec![files[0].clone(), files[1].clone(), files[2].clone()];
+        let rates = Processor::directory_match_rates(&files, &matched, &staging);
+
+        let rate_of = |dir: &str| {
+            rates
+                .iter()
+                .find(|r| r.directory == std::path::Path::new(dir))
+                .map(|r| (r.matched, r.total))
+        };
+
+        assert_eq!(rate_of("Camera"), Some((3, 4)));
+        assert_eq!(rate_of("Camera/2023"), Some((2, 2)));
+        assert_eq!(rate_of("Camera/2024"), Some((1, 2)));
+        assert_eq!(rates.len(), 3);
+
+        Ok(())
+    }
+
+    #[test]
+    fn analyze_comparison_reports_staging_files_missing_from_target() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let kept = fixture.sourced("staging/kept.jpg", b"kept", FileSource::Staging)?;
+        let unique = fixture.sourced("staging/new.jpg", b"new", FileSource::Staging)?;
+        let in_target = fixture.sourced("target/kept.jpg", b"kept", FileSource::Target)?;
+        let (staging, target) = (fixture.path("staging"), fixture.path("target"));
+
+        let store = Arc::new(DashMap::new());
+        store.insert(1, vec![kept.clone(), in_target]);
+        let result = Processor::analyze_comparison(
+            store,
+            &[kept, unique],
+            &HashMap::new(),
+            &staging,
+            &target,
+        )?;
+
+        assert_eq!(result.files_to_delete.len(), 1);
+        assert_eq!(result.unique.len(), 1);
+        assert!(result.unique[0].path.ends_with("new.jpg"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn files_without_a_size_in_the_other_tree_are_skipped() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let write = |name: &str, size: usize, source: FileSource| {
+            fixture.sourced(name, &generate_bytes(size), source)
+        };
+
+        let staging = vec![
+            write("staging-a.bin", 100, FileSource::Staging)?,
+            write("staging-b.bin", 200, FileSource::Staging)?,
+            write("staging-c.bin", 5000, FileSource::Staging)?,
+        ];
+        let target = vec![
+            write("target-a.bin", 100, FileSource::Target)?,
+            write("target-b.bin", 300, FileSource::Target)?,
+        ];
+
+        let (staging, target, skipped) = Processor::retain_shared_sizes(staging, target);
+
+        assert_eq!(skipped, 2);
+        assert_eq!(staging.len(), 1);
+        assert!(staging[0].path.ends_with("staging-a.bin"));
+        assert_eq!(target.len(), 1);
+        assert!(target[0].path.ends_with("target-a.bin"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn collapse_staging_keeps_one_representative_per_content() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let content = generate_bytes(4096);
+        let files = [
+            ("a.bin", content.clone()),
+            ("b.bin", content.clone()),
+            ("c.bin", generate_bytes(4096)),
+            ("d.bin", generate_bytes(100)),
+        ]
+        .iter()
+        .map(|(name, content)| fixture.sourced(name, content, FileSource::Staging))
+        .collect::<Result<Vec<Arc<FileInfo>>>>()?;
+
+        let (representatives, copies) =
+            Processor::collapse_staging(files, &Params::default(), 300);
+
+        assert_eq!(representatives.len(), 3);
+        assert_eq!(copies.len(), 1);
+
+        let collapsed = copies.get(fixture.path("a.bin").as_path()).unwrap();
+        assert_eq!(collapsed.len(), 1);
+        assert!(collapsed[0].path.ends_with("b.bin"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn detect_renames_ignores_files_at_the_same_relative_path() {
+        let staging_root = std::path::Path::new("/staging");
+        let target_root = std::path::Path::new("/target");
+        let file = |path: &str, source| {
+            Arc::new(FileInfo {
+                path: std::path::PathBuf::from(path).into_boxed_path(),
+                size: 1,
+                modified: std::time::SystemTime::UNIX_EPOCH,
+                state: Arc::new(Mutex::new(crate::fileinfo::FileState::Unprocessed)),
+                source: Some(source),
+                payload: None,
+            })
+        };
+
+        let staging = vec![
+            file("/staging/2024/a.jpg", FileSource::Staging),
+            file("/staging/import/IMG_001.jpg", FileSource::Staging),
+        ];
+        let in_target = [
+            file("/target/2024/a.jpg", FileSource::Target),
+            file("/target/2024/beach.jpg", FileSource::Target),
+        ];
+        let target = in_target.iter().collect::<Vec<&Arc<FileInfo>>>();
+
+        let renames = Processor::detect_renames(&staging, &target, staging_root, target_root);
+
+        assert_eq!(renames.len(), 1);
+        assert_eq!(
+            renames[0].staging,
+            std::path::PathBuf::from("/staging/import/IMG_001.jpg")
+        );
+        assert_eq!(renames[0].target, std::path::PathBuf::from("/target/2024/a.jpg"));
+    }
+
+    #[test]
+    fn verify_groups_splits_partial_hash_collisions_into_separate_groups() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let header = generate_bytes(crate::fileinfo::PREHASH_SIZE as usize);
+        let group = [("one.bin", 1u8), ("two.bin", 1u8), ("three.bin", 2u8)]
+            .iter()
+            .map(|(name, tail)| {
+                fixture.scanned(name, &[header.as_slice(), &[*tail; 4096]].concat())
+            })
+            .collect::<Result<Vec<Arc<FileInfo>>>>()?;
+
+        let hw_store = Arc::new(DashMap::new());
+        hw_store.insert(7u128, group);
+
+        let app_args = Params {
+            delete: true,
+            ..Default::default()
+        };
+        // NOTE: only runs that act on the groups check them by default.
+        assert_eq!(Params::default().verification(), VerifyMode::None);
+        let (warnings, comparison) = Processor::verify_groups(
+            Arc::new(app_args),
+            hw_store.clone(),
+            Arc::new(MultiProgress::new()),
+        )?;
+
+        let mut sizes = hw_store.iter().map(|g| g.value().len()).collect::<Vec<usize>>();
+        sizes.sort();
+        assert_eq!(sizes, vec![1, 2]);
+        assert_eq!(warnings.len(), 1);
+        assert_eq!((comparison.fast_groups, comparison.false_positives), (1, 1));
+
+        Ok(())
+    }
+
+    #[test]
+    fn chunked_groups_split_on_first_divergent_chunk() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let shared = generate_bytes(super::HASH_CHUNK_SIZE as usize + 4096);
+        let mut different = shared.clone();
+        different[0] ^= 0xff;
+
+        let group = [("one.bin", &shared), ("two.bin", &shared), ("three.bin", &different)]
+            .iter()
+            .map(|(name, content)| fixture.scanned(name, content))
+            .collect::<Result<Vec<Arc<FileInfo>>>>()?;
+
+        let groups =
+            Processor::chunked_groups(&group, 300.into(), &indicatif::ProgressBar::hidden(), true);
+
+        let pair = groups.iter().find(|(_, files)| files.len() == 2).unwrap();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(pair.0, group[0].hash(300.into())?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn stream_confirmed_sends_each_verified_group_once() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let content = generate_bytes(8192);
+        let files = ["one.bin", "two.bin", "three.bin"]
+            .iter()
+            .map(|name| fixture.scanned(name, &content))
+            .collect::<Result<Vec<Arc<FileInfo>>>>()?;
+
+        let (sw_store, hw_store) = (Arc::new(DashMap::new()), Arc::new(DashMap::new()));
+        let finished = Arc::new(AtomicBool::new(true));
+        let buckets = size_buckets(Arc::new(Params::default()), files, sw_store.clone())?;
+        Processor::hashwise(
+            Arc::new(Params::default()),
+            sw_store.clone(),
+            hw_store.clone(),
+            Arc::new(MultiProgress::new()),
+            Arc::new(AtomicU64::new(32)),
+            300.into(),
+            buckets,
+        )?;
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        Processor::stream_confirmed(
+            Arc::new(Params::default()),
+            sw_store,
+            hw_store,
+            finished.clone(),
+            finished,
+            Some(sender),
+        )?;
+
+        let groups = receiver.iter().collect::<Vec<super::ConfirmedGroup>>();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].1.len(), 3);
 
         Ok(())
     }
@@ -0,0 +1,113 @@
+use dashmap::DashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::fileinfo::FileInfo;
+
+/// Files marking the root of a project: a repository or a package manifest.
+const PROJECT_MARKERS: [&str; 8] = [
+    ".git",
+    "Cargo.toml",
+    "package.json",
+    "pyproject.toml",
+    "go.mod",
+    "pom.xml",
+    "build.gradle",
+    "Gemfile",
+];
+
+/// Groups duplicates by the project they live in (see `--cross-project`): a copy shared by two
+/// checkouts is worth linking, a fixture repeated within one project usually is not.
+pub struct Projects;
+
+impl Projects {
+    /// Removes groups whose copies all sit in the same project, returning how many were hidden.
+    pub fn prune(store: &DashMap<u128, Vec<Arc<FileInfo>>>, base_directory: &Path) -> usize {
+        // NOTE: every directory is looked up once, however many files it holds.
+        let mut roots: HashMap<PathBuf, Option<PathBuf>> = HashMap::new();
+        let before = store.len();
+        store.retain(|_, group| group.len() < 2 || Self::spans(group, base_directory, &mut roots));
+
+        before - store.len()
+    }
+
+    /// Whether the copies of `group` sit in more than one project, for groups judged one at a
+    /// time (see `--stream`).
+    pub fn spans_projects(group: &[Arc<FileInfo>], base_directory: &Path) -> bool {
+        Self::spans(group, base_directory, &mut HashMap::new())
+    }
+
+    fn spans(
+        group: &[Arc<FileInfo>],
+        base_directory: &Path,
+        roots: &mut HashMap<PathBuf, Option<PathBuf>>,
+    ) -> bool {
+        group
+            .iter()
+            .map(|file| Self::root_of(&file.path, base_directory, roots))
+            .collect::<HashSet<Option<PathBuf>>>()
+            .len()
+            > 1
+    }
+
+    /// The closest directory above `path`, up to `base_directory`, holding a project marker.
+    fn root_of(
+        path: &Path,
+        base_directory: &Path,
+        roots: &mut HashMap<PathBuf, Option<PathBuf>>,
+    ) -> Option<PathBuf> {
+        let directory = path.parent()?;
+        if let Some(root) = roots.get(directory) {
+            return root.clone();
+        }
+
+        let root = match PROJECT_MARKERS
+            .iter()
+            .any(|marker| directory.join(marker).exists())
+        {
+            true => Some(directory.to_path_buf()),
+            false if directory == base_directory => None,
+            false if !directory.starts_with(base_directory) => None,
+            false => Self::root_of(directory, base_directory, roots),
+        };
+        roots.insert(directory.to_path_buf(), root.clone());
+        root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Projects;
+    use crate::fixture::Fixture;
+    use anyhow::Result;
+    use dashmap::DashMap;
+    use std::fs;
+
+    #[test]
+    fn only_groups_spanning_projects_are_kept() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let file = |name: &str| fixture.scanned(name, b"same");
+        for project in ["app", "lib"] {
+            fs::create_dir_all(fixture.path(project))?;
+            fs::write(fixture.path(project).join("Cargo.toml"), b"")?;
+        }
+
+        let store = DashMap::new();
+        store.insert(1, vec![file("app/assets/logo.png")?, file("lib/logo.png")?]);
+        store.insert(
+            2,
+            vec![file("app/tests/a.json")?, file("app/tests/b.json")?],
+        );
+        store.insert(3, vec![file("notes.txt")?, file("app/notes.txt")?]);
+
+        assert_eq!(Projects::prune(&store, fixture.root()), 1);
+        assert!(store.contains_key(&1));
+        assert!(!store.contains_key(&2));
+        assert!(store.contains_key(&3));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,162 @@
+use serde::{Deserialize, Serialize};
+use std::{fmt, fs, path::Path, str::FromStr};
+
+/// Flags of `FS_IOC_GETFLAGS` (see `chattr(1)`), missing from libc.
+#[cfg(target_os = "linux")]
+const FS_IMMUTABLE_FL: libc::c_long = 0x10;
+#[cfg(target_os = "linux")]
+const FS_APPEND_FL: libc::c_long = 0x20;
+
+/// Why a duplicate cannot be removed, whatever is decided about it: the filesystem refuses to
+/// unlink it, even for root in the case of the immutable & append-only attributes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Protection {
+    /// `chattr +i` on Linux, `chflags uchg`/`schg` on macOS
+    Immutable,
+    /// `chattr +a` on Linux, `chflags uappnd`/`sappnd` on macOS
+    AppendOnly,
+    /// The read-only attribute, which blocks deletion on Windows
+    ReadOnly,
+    /// The directory holding the file is immutable or not writable by this user
+    LockedDirectory,
+}
+
+impl Protection {
+    /// The protection keeping `path` from being removed, if any. Files that cannot be inspected
+    /// are assumed removable; removing them reports the actual error.
+    pub fn of(path: &Path) -> Option<Self> {
+        if let Some(protection) = Self::flags(path) {
+            return Some(protection);
+        }
+        #[cfg(windows)]
+        if fs::metadata(path).is_ok_and(|metadata| metadata.permissions().readonly()) {
+            return Some(Self::ReadOnly);
+        }
+
+        let directory = path.parent().filter(|parent| !parent.as_os_str().is_empty())?;
+        match Self::flags(directory).is_some() || !Self::writable(directory) {
+            true => Some(Self::LockedDirectory),
+            false => None,
+        }
+    }
+
+    /// Name of the protection in plans, as serialized.
+    pub fn id(&self) -> &'static str {
+        match self {
+            Self::Immutable => "immutable",
+            Self::AppendOnly => "append-only",
+            Self::ReadOnly => "read-only",
+            Self::LockedDirectory => "locked-directory",
+        }
+    }
+
+    /// How many of `copies` copies can be removed once `locked` of them cannot be: a locked copy
+    /// makes a fine keeper, so one lock costs nothing.
+    pub fn removable_copies(copies: u64, locked: u64) -> u64 {
+        copies.saturating_sub(locked.max(1))
+    }
+
+    #[cfg(target_os = "linux")]
+    fn flags(path: &Path) -> Option<Self> {
+        use std::os::{fd::AsRawFd, unix::fs::OpenOptionsExt};
+
+        // NOTE: O_NONBLOCK keeps FIFOs & device nodes from blocking the open.
+        let file = fs::File::options()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(path)
+            .ok()?;
+        let mut flags: libc::c_long = 0;
+        // SAFETY: FS_IOC_GETFLAGS writes a single long into `flags`.
+        let status = unsafe { libc::ioctl(file.as_raw_fd(), libc::FS_IOC_GETFLAGS, &mut flags) };
+        match status {
+            0 if flags & FS_IMMUTABLE_FL != 0 => Some(Self::Immutable),
+            0 if flags & FS_APPEND_FL != 0 => Some(Self::AppendOnly),
+            _ => None,
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    fn flags(path: &Path) -> Option<Self> {
+        use std::os::macos::fs::MetadataExt;
+
+        let flags = fs::symlink_metadata(path).ok()?.st_flags();
+        match flags {
+            _ if flags & (libc::UF_IMMUTABLE | libc::SF_IMMUTABLE) != 0 => Some(Self::Immutable),
+            _ if flags & (libc::UF_APPEND | libc::SF_APPEND) != 0 => Some(Self::AppendOnly),
+            _ => None,
+        }
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    fn flags(_path: &Path) -> Option<Self> {
+        None
+    }
+
+    #[cfg(unix)]
+    fn writable(directory: &Path) -> bool {
+        use std::os::unix::ffi::OsStrExt;
+
+        let Ok(directory) = std::ffi::CString::new(directory.as_os_str().as_bytes()) else {
+            return true;
+        };
+        // SAFETY: `directory` is a valid NUL terminated string for the duration of the call.
+        unsafe { libc::access(directory.as_ptr(), libc::W_OK | libc::X_OK) == 0 }
+    }
+
+    #[cfg(not(unix))]
+    fn writable(_directory: &Path) -> bool {
+        true
+    }
+}
+
+impl fmt::Display for Protection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Immutable => "immutable",
+            Self::AppendOnly => "append-only",
+            Self::ReadOnly => "read-only",
+            Self::LockedDirectory => "in a locked directory",
+        })
+    }
+}
+
+impl FromStr for Protection {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        [
+            Self::Immutable,
+            Self::AppendOnly,
+            Self::ReadOnly,
+            Self::LockedDirectory,
+        ]
+        .into_iter()
+        .find(|protection| protection.id() == s.trim())
+        .ok_or_else(|| anyhow::anyhow!("unknown protection {s:?}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Protection;
+    use anyhow::Result;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn locked_copies_reduce_what_can_be_reclaimed() -> Result<()> {
+        let root = TempDir::new()?;
+        let file = root.path().join("a.txt");
+        fs::write(&file, b"same")?;
+        assert_eq!(Protection::of(&file), None);
+
+        assert_eq!(Protection::removable_copies(3, 0), 2);
+        assert_eq!(Protection::removable_copies(3, 1), 2);
+        assert_eq!(Protection::removable_copies(3, 2), 1);
+        assert_eq!(Protection::removable_copies(3, 3), 0);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,402 @@
+use anyhow::{Context, Result};
+use clap::Args;
+use colored::Colorize;
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    denial::Denial, fileinfo::FileInfo, longpath::LongPaths, verify::Verifier,
+};
+
+const PARTIAL_SUFFIX: &str = ".dedup-partial";
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct RestoreArgs {
+    /// Quarantine folder filled by earlier runs with --move-to. Without it, the last run is undone from its journal
+    #[arg(value_hint = clap::ValueHint::DirPath, value_name = "quarantine_dir_path", requires = "to")]
+    pub quarantine: Option<PathBuf>,
+    /// Folder the files were moved out of: the staging folder (scan_dir_path) of those runs
+    #[arg(long, value_hint = clap::ValueHint::DirPath, value_name = "dir", requires = "quarantine")]
+    pub to: Option<PathBuf>,
+    /// Undo this run instead of the last one, by the run ID of its --action-log or --export
+    #[arg(long, value_name = "run_id", conflicts_with = "quarantine")]
+    pub run: Option<String>,
+    /// Only print what would be restored
+    #[arg(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveOutcome {
+    /// Moved with a single rename on the same filesystem.
+    Renamed,
+    /// Destination lives on another filesystem: copied, synced, verified and then removed.
+    CopiedAcrossDevices,
+}
+
+/// Directory that receives moved duplicates instead of deleting them, mirroring their layout
+/// relative to the scanned root.
+pub struct Quarantine {
+    pub root: PathBuf,
+}
+
+impl Quarantine {
+    pub fn new(root: &Path) -> Result<Self> {
+        fs::create_dir_all(root)
+            .with_context(|| format!("unable to create quarantine dir {}", root.display()))?;
+        Ok(Self {
+            root: fs::canonicalize(root)?,
+        })
+    }
+
+    /// A quarantine that is only used to show where files would go, see `--dry-run`. Its root is
+    /// not created.
+    pub fn preview(root: &Path) -> Result<Self> {
+        Ok(Self {
+            root: std::path::absolute(root)?,
+        })
+    }
+
+    #[cfg(unix)]
+    fn same_device(&self, path: &Path) -> bool {
+        use std::os::unix::fs::MetadataExt;
+        match (fs::metadata(&self.root), fs::metadata(path)) {
+            (Ok(root), Ok(file)) => root.dev() == file.dev(),
+            _ => false,
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn same_device(&self, _path: &Path) -> bool {
+        false
+    }
+
+    /// Bytes the quarantine filesystem has to absorb: files on the same device are renamed in
+    /// place and need no extra space.
+    pub fn required_space(&self, files: &[Arc<FileInfo>]) -> u64 {
+        files
+            .iter()
+            .filter(|file| !self.same_device(&file.path))
+            .map(|file| file.size)
+            .sum()
+    }
+
+    /// Fails before anything is moved if the quarantine cannot hold the whole plan.
+    pub fn ensure_free_space(&self, files: &[Arc<FileInfo>]) -> Result<()> {
+        let required = self.required_space(files);
+        let available = fs4::available_space(&self.root).with_context(|| {
+            format!("unable to query free space of {}", self.root.display())
+        })?;
+
+        if required > available {
+            anyhow::bail!(
+                "not enough free space in {}: moving these files needs {} but only {} is available",
+                self.root.display(),
+                bytesize::ByteSize::b(required),
+                bytesize::ByteSize::b(available)
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Destination for `source`, keeping its path relative to `base`. Never overwrites: an
+    /// existing destination gets a numbered suffix.
+    pub fn destination(&self, source: &Path, base: &Path) -> PathBuf {
+        let relative = source
+            .strip_prefix(base)
+            .map(Path::to_path_buf)
+            .unwrap_or_else(|_| PathBuf::from(source.file_name().unwrap_or_default()));
+        let candidate = self.root.join(relative);
+
+        (1..)
+            .map(|attempt| match attempt {
+                1 => candidate.clone(),
+                n => {
+                    let mut name = candidate.file_name().unwrap_or_default().to_os_string();
+                    name.push(format!("~{n}"));
+                    candidate.with_file_name(name)
+                }
+            })
+            .find(|path| !path.exists())
+            .unwrap_or(candidate)
+    }
+
+    pub fn move_into(&self, source: &Path, base: &Path) -> Result<(PathBuf, MoveOutcome)> {
+        let destination = self.destination(source, base);
+        if LongPaths::too_long(&destination) {
+            anyhow::bail!(
+                "the quarantine path {} is too long, choose a shorter --move-to",
+                destination.display()
+            );
+        }
+        let outcome = Self::move_file(source, &destination)?;
+        Ok((destination, outcome))
+    }
+
+    pub fn move_file(source: &Path, destination: &Path) -> Result<MoveOutcome> {
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        match fs::rename(source, destination) {
+            Ok(_) => Ok(MoveOutcome::Renamed),
+            Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+                Self::copy_across_devices(source, destination)?;
+                Ok(MoveOutcome::CopiedAcrossDevices)
+            }
+            Err(e) => Err(Denial::explain(e, source)),
+        }
+    }
+
+    /// copy -> fsync -> verify -> rename -> delete. The source is only removed once a verified
+    /// copy sits at its final destination; any failure before that removes the partial copy.
+    pub fn copy_across_devices(source: &Path, destination: &Path) -> Result<()> {
+        let mut partial_name = destination.file_name().unwrap_or_default().to_os_string();
+        partial_name.push(PARTIAL_SUFFIX);
+        let partial = destination.with_file_name(partial_name);
+
+        let staged = (|| -> Result<()> {
+            fs::copy(source, &partial)?;
+            Self::preserve_metadata(source, &partial)?;
+            fs::File::open(&partial)?.sync_all()?;
+
+            if !Verifier::same_bytes(source, &partial)? {
+                anyhow::bail!("copy of {} does not match the original", source.display());
+            }
+
+            fs::rename(&partial, destination)?;
+            Self::sync_parent(destination);
+            Ok(())
+        })();
+
+        if let Err(e) = staged {
+            let _ = fs::remove_file(&partial);
+            return Err(e);
+        }
+
+        fs::remove_file(source)
+            .map_err(|e| Denial::explain(e, source))
+            .with_context(|| {
+                format!(
+                    "copied to {} but unable to remove {}",
+                    destination.display(),
+                    source.display()
+                )
+            })
+    }
+
+    /// Carries permissions, timestamps, ownership and extended attributes over to the copy so a
+    /// later restore is faithful. Ownership and xattrs are best effort: changing the owner needs
+    /// privileges and not every filesystem supports every attribute namespace.
+    fn preserve_metadata(source: &Path, copy: &Path) -> Result<()> {
+        let metadata = fs::metadata(source)?;
+
+        // NOTE: timestamps first, the copy may no longer be writable once permissions are applied.
+        let times = fs::FileTimes::new()
+            .set_accessed(metadata.accessed()?)
+            .set_modified(metadata.modified()?);
+        fs::File::options().write(true).open(copy)?.set_times(times)?;
+        fs::set_permissions(copy, metadata.permissions())?;
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let _ = std::os::unix::fs::chown(copy, Some(metadata.uid()), Some(metadata.gid()));
+
+            if let Ok(attributes) = xattr::list(source) {
+                for attribute in attributes {
+                    if let Ok(Some(value)) = xattr::get(source, &attribute) {
+                        let _ = xattr::set(copy, &attribute, &value);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// `deduplicator restore`: moves the files of a quarantine back to where they came from.
+    pub fn run(args: &RestoreArgs) -> Result<()> {
+        let (Some(root), Some(to)) = (&args.quarantine, &args.to) else {
+            anyhow::bail!("restoring a quarantine needs the quarantine folder and --to");
+        };
+        anyhow::ensure!(root.is_dir(), "no quarantine folder at {}", root.display());
+        let quarantine = Self::new(root)?;
+        let restored = quarantine.restore(to, args.dry_run);
+        for (file, outcome) in &restored {
+            match outcome {
+                Ok(place) if args.dry_run => {
+                    println!("{} {} -> {}", "WOULD RESTORE:".cyan(), file.display(), place.display())
+                }
+                Ok(place) => println!("{} {} -> {}", "RESTORED:".green(), file.display(), place.display()),
+                Err(e) => println!("{} {}: {e:#}", "SKIPPED:".yellow(), file.display()),
+            }
+        }
+        println!(
+            "\n{} {} of {} quarantined file(s) to {}.",
+            if args.dry_run { "Would restore" } else { "Restored" },
+            restored.iter().filter(|(_, outcome)| outcome.is_ok()).count(),
+            restored.len(),
+            to.display()
+        );
+        Ok(())
+    }
+
+    /// Moves every file of the quarantine back to its place below `base`, the other way round
+    /// from `move_into`. A numbered copy (`a.jpg~2`) goes back under its own name; a file whose
+    /// place is taken stays in the quarantine. Each file comes with where it went, or why not.
+    pub fn restore(&self, base: &Path, dry_run: bool) -> Vec<(PathBuf, Result<PathBuf>)> {
+        let mut files = ignore::WalkBuilder::new(&self.root)
+            .standard_filters(false)
+            .build()
+            .filter_map(Result::ok)
+            .filter(|entry| entry.file_type().is_some_and(|kind| kind.is_file()))
+            .map(|entry| entry.into_path())
+            .filter(|path| !path.to_string_lossy().ends_with(PARTIAL_SUFFIX))
+            .collect::<Vec<PathBuf>>();
+        files.sort();
+
+        let mut taken = HashSet::new();
+        files
+            .into_iter()
+            .map(|file| {
+                let place = base.join(Self::unnumbered(file.strip_prefix(&self.root).unwrap_or(&file)));
+                let outcome = match place.exists() || !taken.insert(place.clone()) {
+                    true => Err(anyhow::anyhow!("{} already exists", place.display())),
+                    false if dry_run => Ok(place),
+                    false => Self::move_file(&file, &place).map(|_| place),
+                };
+                (file, outcome)
+            })
+            .collect()
+    }
+
+    /// `relative` without the `~n` suffix `destination` numbers copies with.
+    fn unnumbered(relative: &Path) -> PathBuf {
+        let name = relative.file_name().unwrap_or_default().to_string_lossy();
+        match name.rsplit_once('~') {
+            Some((original, n)) if !original.is_empty() && n.parse::<u32>().is_ok_and(|n| n > 1) => {
+                relative.with_file_name(original)
+            }
+            _ => relative.to_path_buf(),
+        }
+    }
+
+    #[cfg(unix)]
+    fn sync_parent(path: &Path) {
+        if let Some(parent) = path.parent() {
+            let _ = fs::File::open(parent).and_then(|dir| dir.sync_all());
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn sync_parent(_path: &Path) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MoveOutcome, Quarantine};
+    use crate::fileinfo::FileInfo;
+    use anyhow::Result;
+    use std::fs;
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime};
+    use tempfile::TempDir;
+
+    #[test]
+    fn moves_preserve_relative_structure_without_overwriting() -> Result<()> {
+        let root = TempDir::new()?;
+        let staging = root.path().join("staging");
+        fs::create_dir_all(staging.join("Camera"))?;
+        fs::write(staging.join("Camera/a.jpg"), b"first")?;
+
+        let quarantine = Quarantine::new(&root.path().join("quarantine"))?;
+        let (destination, outcome) = quarantine.move_into(&staging.join("Camera/a.jpg"), &staging)?;
+
+        assert_eq!(outcome, MoveOutcome::Renamed);
+        assert_eq!(destination, quarantine.root.join("Camera/a.jpg"));
+        assert!(!staging.join("Camera/a.jpg").exists());
+
+        fs::write(staging.join("Camera/a.jpg"), b"second")?;
+        let (destination, _) = quarantine.move_into(&staging.join("Camera/a.jpg"), &staging)?;
+
+        assert_eq!(destination, quarantine.root.join("Camera/a.jpg~2"));
+        assert_eq!(fs::read(quarantine.root.join("Camera/a.jpg"))?, b"first");
+
+        let preview = quarantine.restore(&staging, true);
+        assert!(quarantine.root.join("Camera/a.jpg").exists());
+        let restored = quarantine.restore(&staging, false);
+        assert_eq!(
+            preview.iter().map(|(_, outcome)| outcome.is_ok()).collect::<Vec<bool>>(),
+            [true, false]
+        );
+        assert_eq!(restored[0].1.as_ref().ok(), Some(&staging.join("Camera/a.jpg")));
+        assert!(restored[1].1.is_err());
+        assert_eq!(fs::read(staging.join("Camera/a.jpg"))?, b"first");
+        assert_eq!(fs::read(quarantine.root.join("Camera/a.jpg~2"))?, b"second");
+
+        Ok(())
+    }
+
+    #[test]
+    fn same_device_moves_need_no_free_space() -> Result<()> {
+        let root = TempDir::new()?;
+        let source = root.path().join("a.bin");
+        fs::write(&source, vec![1u8; 4096])?;
+
+        let quarantine = Quarantine::new(&root.path().join("quarantine"))?;
+        let files = vec![Arc::new(FileInfo::new(source)?)];
+
+        assert_eq!(quarantine.required_space(&files), 0);
+        quarantine.ensure_free_space(&files)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn cross_device_copy_leaves_no_partial_files() -> Result<()> {
+        let root = TempDir::new()?;
+        let source = root.path().join("a.bin");
+        let destination = root.path().join("moved").join("a.bin");
+        fs::create_dir_all(destination.parent().unwrap())?;
+        fs::write(&source, vec![3u8; 100_000])?;
+
+        Quarantine::copy_across_devices(&source, &destination)?;
+
+        assert!(!source.exists());
+        assert_eq!(fs::read(&destination)?, vec![3u8; 100_000]);
+        assert_eq!(fs::read_dir(destination.parent().unwrap())?.count(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn cross_device_copy_preserves_mtime_and_permissions() -> Result<()> {
+        let root = TempDir::new()?;
+        let source = root.path().join("a.bin");
+        let destination = root.path().join("a-moved.bin");
+        fs::write(&source, b"content")?;
+
+        let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(1_500_000_000);
+        fs::File::options()
+            .write(true)
+            .open(&source)?
+            .set_times(fs::FileTimes::new().set_modified(mtime))?;
+        let mut permissions = fs::metadata(&source)?.permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&source, permissions)?;
+
+        Quarantine::copy_across_devices(&source, &destination)?;
+
+        let moved = fs::metadata(&destination)?;
+        assert_eq!(moved.modified()?, mtime);
+        assert!(moved.permissions().readonly());
+
+        Ok(())
+    }
+}
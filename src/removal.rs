@@ -0,0 +1,73 @@
+use anyhow::{Context, Result};
+use std::{fs, path::Path};
+
+use crate::{denial::Denial, params::Params};
+
+/// How duplicates are removed: deleted for good, moved to the OS trash (see `--trash`) or only
+/// counted (see `--dry-run`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Removal {
+    Delete,
+    Trash,
+    DryRun,
+}
+
+impl Removal {
+    /// A file that cannot be moved to the trash (no trash on its filesystem, no home directory,
+    /// ...) is left in place rather than deleted for good. Once removed, it is recorded in the
+    /// run's `empty_dirs`, and a dry run counts it in the run's `dry_run_tally`.
+    pub fn remove(&self, path: &Path, app_args: &Params) -> Result<()> {
+        match self {
+            Self::Delete => fs::remove_file(path).map_err(|e| Denial::explain(e, path))?,
+            Self::Trash => trash::delete(path).context("trash unavailable, the file was kept")?,
+            Self::DryRun => app_args.dry_run_tally.record(path),
+        }
+        app_args.empty_dirs.record(path);
+        Ok(())
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Delete => "DELETED",
+            Self::Trash => "TRASHED",
+            Self::DryRun => "WOULD DELETE",
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::Removal;
+    use crate::params::Params;
+    use anyhow::Result;
+    use std::{env, fs, path::PathBuf, process::Command};
+    use tempfile::TempDir;
+
+    /// Set only for the copy of the test binary that `trashed_files_end_up_in_the_trash` runs.
+    const TRASH_FILE: &str = "DEDUPLICATOR_TEST_TRASH_FILE";
+
+    // NOTE: the trash is found through XDG_DATA_HOME, which is set for a child test process
+    // rather than for every test running alongside this one.
+    #[test]
+    fn trashed_files_end_up_in_the_trash() -> Result<()> {
+        if let Some(file) = env::var_os(TRASH_FILE) {
+            return Removal::Trash.remove(&PathBuf::from(file), &Params::default());
+        }
+
+        let root = TempDir::new_in(env::current_dir()?)?;
+        let file = root.path().join("duplicate.txt");
+        fs::write(&file, b"same")?;
+
+        let status = Command::new(env::current_exe()?)
+            .args(["--exact", "removal::tests::trashed_files_end_up_in_the_trash"])
+            .env("XDG_DATA_HOME", root.path().join("data"))
+            .env(TRASH_FILE, &file)
+            .status()?;
+
+        assert!(status.success());
+        assert!(!file.exists());
+        assert!(root.path().join("data/Trash/files/duplicate.txt").exists());
+
+        Ok(())
+    }
+}
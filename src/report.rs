@@ -0,0 +1,124 @@
+use anyhow::{Context, Result};
+use clap::Args;
+use dashmap::DashMap;
+use std::{path::PathBuf, sync::Arc};
+
+use crate::{
+    export::Export,
+    fileinfo::FileInfo,
+    formatter::Formatter,
+    params::{GroupOrder, OutputFormat, Params},
+};
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct ReportArgs {
+    /// Export written by an earlier run with --export
+    #[arg(value_hint = clap::ValueHint::FilePath, value_name = "export_path")]
+    pub export: PathBuf,
+    /// How the duplicate groups are listed, as with --output of a scan
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text, value_name = "format")]
+    pub output: OutputFormat,
+    /// Order of the duplicate groups, as with --sort of a scan
+    #[arg(long, value_enum, default_value_t = GroupOrder::Path, value_name = "order")]
+    pub sort: GroupOrder,
+    /// List the groups in the opposite --sort order
+    #[arg(long)]
+    pub reverse: bool,
+}
+
+/// Lists the duplicate groups of an `--export` again, in any `--output` format, without
+/// scanning. Files gone since are listed with the size the export recorded.
+pub struct Report;
+
+impl Report {
+    pub fn run(args: &ReportArgs, app_args: &Params) -> Result<()> {
+        let export = Export::read(&args.export)?;
+        let params = Params {
+            output: args.output,
+            sort: args.sort,
+            reverse: args.reverse,
+            ..app_args.clone()
+        };
+        let store = Self::store(&export)?;
+        let max_path_len = store
+            .iter()
+            .flat_map(|group| {
+                group
+                    .value()
+                    .iter()
+                    .filter_map(|file| Formatter::human_path(file, &params, 0).ok())
+                    .map(|path| path.len() as u64)
+                    .collect::<Vec<u64>>()
+            })
+            .max()
+            .unwrap_or_default();
+        Formatter::print(Arc::new(store), max_path_len, &params);
+
+        if !params.output.machine_readable() {
+            let wasted = export
+                .groups
+                .iter()
+                .map(|group| group.size * (group.files.len() as u64).saturating_sub(1))
+                .sum::<u64>();
+            println!(
+                "{} group(s) of run {} ({}), {} wasted.",
+                export.groups.len(),
+                if export.run_id.is_empty() { "-" } else { &export.run_id },
+                export.created,
+                bytesize::ByteSize::b(wasted)
+            );
+        }
+        Ok(())
+    }
+
+    fn store(export: &Export) -> Result<DashMap<u128, Vec<Arc<FileInfo>>>> {
+        export
+            .groups
+            .iter()
+            .map(|group| {
+                let hash = u128::from_str_radix(&group.hash, 16)
+                    .with_context(|| format!("invalid hash {} in export", group.hash))?;
+                let files = group
+                    .files
+                    .iter()
+                    .map(|path| Arc::new(FileInfo::recorded(path.clone(), group.size)))
+                    .collect();
+                Ok((hash, files))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Report;
+    use crate::{export::Export, fileinfo::FileInfo};
+    use anyhow::Result;
+    use dashmap::DashMap;
+    use std::{fs, path::PathBuf, sync::Arc};
+    use tempfile::TempDir;
+
+    #[test]
+    fn exported_groups_come_back_with_the_files_gone_since() -> Result<()> {
+        let root = TempDir::new()?;
+        let kept = root.path().join("kept.txt");
+        fs::write(&kept, b"same")?;
+        let gone = PathBuf::from("/nonexistent/deleted.txt");
+        let files = vec![
+            Arc::new(FileInfo::new(kept.clone())?),
+            Arc::new(FileInfo::recorded(gone.clone(), 4)),
+        ];
+        let store = DashMap::new();
+        store.insert(0xfeedu128, files);
+        let export = Export::from_store(&store);
+
+        let reread = Report::store(&export)?;
+        let group = reread.get(&0xfeed).unwrap();
+        assert_eq!(
+            group.iter().map(|file| (file.path.to_path_buf(), file.size)).collect::<Vec<_>>(),
+            [(gone, 4), (kept, 4)]
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,187 @@
+use bytesize::ByteSize;
+use dashmap::DashMap;
+use prettytable::{format, row, Table};
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
+
+use crate::{fileinfo::FileInfo, params::Params};
+
+/// Disk usage of a scan root, as far as duplicates go (see `--root-usage`).
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RootUsage {
+    /// The `--label` of the root, or the scan root for files outside every labelled root.
+    pub name: String,
+    pub total: u64,
+    /// Extra copies of a content the root already holds.
+    pub within: u64,
+    /// Contents of the root, counted once, that another root holds as well.
+    pub across: u64,
+}
+
+impl RootUsage {
+    /// Bytes the root would take with a single copy of each of its contents.
+    pub fn unique(&self) -> u64 {
+        self.total - self.within
+    }
+
+    /// Usage of every root: the `--label` roots in the order given, then the scan `roots` for
+    /// the remaining files. `scanned` holds every file found, `groups` the duplicates.
+    pub fn measure(
+        scanned: &DashMap<u64, Vec<Arc<FileInfo>>>,
+        groups: &DashMap<u128, Vec<Arc<FileInfo>>>,
+        app_args: &Params,
+        roots: &[PathBuf],
+    ) -> Vec<Self> {
+        let mut usages = app_args
+            .label
+            .iter()
+            .map(|label| label.name.clone())
+            .chain(roots.iter().map(|root| root.display().to_string()))
+            .map(|name| Self {
+                name,
+                ..Default::default()
+            })
+            .collect::<Vec<Self>>();
+        let last = usages.len().saturating_sub(1);
+        let index = |file: &FileInfo| {
+            let name = match app_args.label_of(&file.path) {
+                Some(name) => name.to_string(),
+                None => roots
+                    .iter()
+                    .filter(|root| file.path.starts_with(root))
+                    .max_by_key(|root| root.components().count())
+                    .map(|root| root.display().to_string())
+                    .unwrap_or_default(),
+            };
+            usages
+                .iter()
+                .position(|usage| usage.name == name)
+                .unwrap_or(last)
+        };
+
+        let mut totals = vec![0u64; usages.len()];
+        scanned
+            .iter()
+            .flat_map(|bucket| bucket.value().to_vec())
+            .for_each(|file| totals[index(&file)] += file.size);
+
+        let mut shares = vec![(0u64, 0u64); usages.len()];
+        groups
+            .iter()
+            .filter(|group| group.value().len() > 1)
+            .for_each(|group| {
+                let size = group.value().first().map(|f| f.size).unwrap_or_default();
+                let mut copies: HashMap<usize, u64> = HashMap::new();
+                group
+                    .value()
+                    .iter()
+                    .for_each(|file| *copies.entry(index(file)).or_default() += 1);
+
+                let shared = copies.len() > 1;
+                copies.into_iter().for_each(|(root, count)| {
+                    shares[root].0 += (count - 1) * size;
+                    if shared {
+                        shares[root].1 += size;
+                    }
+                });
+            });
+
+        usages
+            .iter_mut()
+            .zip(totals.into_iter().zip(shares))
+            .for_each(|(usage, (total, (within, across)))| {
+                usage.total = total;
+                usage.within = within;
+                usage.across = across;
+            });
+        usages.retain(|usage| usage.total > 0);
+        usages
+    }
+
+    pub fn report(usages: &[Self]) -> String {
+        let mut table = Table::new();
+        table.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+        table.set_titles(row![
+            "root",
+            "total",
+            "unique",
+            "dup. within",
+            "dup. in other roots"
+        ]);
+        usages.iter().for_each(|usage| {
+            table.add_row(row![
+                usage.name,
+                ByteSize::b(usage.total),
+                ByteSize::b(usage.unique()),
+                ByteSize::b(usage.within),
+                ByteSize::b(usage.across)
+            ]);
+        });
+        table.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RootUsage;
+    use crate::{
+        fixture::Fixture,
+        params::{Params, RootLabel},
+    };
+    use anyhow::Result;
+    use dashmap::DashMap;
+
+    #[test]
+    fn duplicates_are_split_into_within_and_across_roots() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let shared = [
+            fixture.scanned("nas/a.bin", &[1; 100])?,
+            fixture.scanned("nas/a copy.bin", &[1; 100])?,
+            fixture.scanned("archive/a.bin", &[1; 100])?,
+        ];
+        let own = fixture.scanned("nas/b.bin", &[2; 10])?;
+        let loose = fixture.scanned("c.bin", &[3; 5])?;
+        let (nas, archive) = (fixture.path("nas"), fixture.path("archive"));
+        let base = fixture.root().to_path_buf();
+
+        let scanned = DashMap::new();
+        scanned.insert(100u64, shared.to_vec());
+        scanned.insert(10u64, vec![own]);
+        scanned.insert(5u64, vec![loose]);
+        let groups = DashMap::new();
+        groups.insert(1u128, shared.to_vec());
+
+        let app_args = Params {
+            label: vec![
+                RootLabel {
+                    name: "nas".to_string(),
+                    root: nas,
+                },
+                RootLabel {
+                    name: "archive".to_string(),
+                    root: archive,
+                },
+            ],
+            ..Default::default()
+        };
+        let usages = RootUsage::measure(&scanned, &groups, &app_args, &[base]);
+
+        assert_eq!(usages.len(), 3);
+        assert_eq!(
+            (
+                usages[0].total,
+                usages[0].unique(),
+                usages[0].within,
+                usages[0].across
+            ),
+            (210, 110, 100, 100)
+        );
+        assert_eq!(
+            (usages[1].total, usages[1].within, usages[1].across),
+            (100, 0, 100)
+        );
+        assert_eq!((usages[2].total, usages[2].across), (5, 0));
+        assert!(RootUsage::report(&usages).contains("archive"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,166 @@
+use anyhow::{Context, Result};
+use fs4::fs_std::FileExt;
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const RUN_DIR_PREFIX: &str = "deduplicator-run-";
+const LOCK_FILE: &str = ".lock";
+
+/// Scratch directory owned by a single invocation. Every temporary artifact of a run lives here
+/// so it can be removed in one go when the run ends. The directory holds a locked file for as
+/// long as the run is alive; directories whose lock is free belong to a run that crashed and are
+/// removed on the next start.
+#[derive(Debug)]
+pub struct RunDir {
+    path: PathBuf,
+    _lock: fs::File,
+}
+
+impl RunDir {
+    pub fn create(base: Option<&Path>) -> Result<Self> {
+        let base = base.map(Path::to_path_buf).unwrap_or_else(env::temp_dir);
+        fs::create_dir_all(&base)
+            .with_context(|| format!("unable to create temp dir {}", base.display()))?;
+        Self::recover(&base);
+
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.subsec_nanos())
+            .unwrap_or_default();
+        let path = base.join(format!("{RUN_DIR_PREFIX}{}-{nanos}", std::process::id()));
+        fs::create_dir(&path)
+            .with_context(|| format!("unable to create run dir {}", path.display()))?;
+
+        let lock = fs::File::create(path.join(LOCK_FILE))?;
+        lock.lock_exclusive()?;
+
+        Ok(Self { path, _lock: lock })
+    }
+
+    /// A new subdirectory for the `name`d artifacts of one part of the run, numbered as several
+    /// may be in use at once.
+    pub fn scratch(self: &Arc<Self>, name: &str) -> Result<Scratch> {
+        let mut number = 0;
+        loop {
+            let path = self.path.join(format!("{name}-{number}"));
+            match fs::create_dir(&path) {
+                Ok(()) => return Ok(Scratch { path, _run: Arc::clone(self) }),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => number += 1,
+                Err(e) => {
+                    return Err(e).with_context(|| format!("unable to create {}", path.display()))
+                }
+            }
+        }
+    }
+
+    /// Removes run directories under `base` left behind by runs that did not exit cleanly.
+    /// Returns how many were removed.
+    pub fn recover(base: &Path) -> usize {
+        let Ok(entries) = fs::read_dir(base) else {
+            return 0;
+        };
+
+        entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.is_dir()
+                    && path
+                        .file_name()
+                        .is_some_and(|name| name.to_string_lossy().starts_with(RUN_DIR_PREFIX))
+            })
+            .filter(|path| Self::is_abandoned(path))
+            .filter(|path| fs::remove_dir_all(path).is_ok())
+            .count()
+    }
+
+    fn is_abandoned(path: &Path) -> bool {
+        match fs::File::open(path.join(LOCK_FILE)) {
+            Ok(lock) => lock.try_lock_exclusive().unwrap_or(false),
+            // NOTE: a run that crashed between creating the dir and its lock file.
+            Err(_) => true,
+        }
+    }
+}
+
+impl Drop for RunDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+/// A subdirectory of a `RunDir`, removed once its artifacts are no longer needed, or along with
+/// the run directory at the latest.
+#[derive(Debug)]
+pub struct Scratch {
+    path: PathBuf,
+    _run: Arc<RunDir>,
+}
+
+impl Scratch {
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RunDir, RUN_DIR_PREFIX};
+    use anyhow::Result;
+    use std::{fs, sync::Arc};
+    use tempfile::TempDir;
+
+    #[test]
+    fn recovers_abandoned_run_dirs_but_not_live_ones() -> Result<()> {
+        let base = TempDir::new()?;
+        let crashed = base.path().join(format!("{RUN_DIR_PREFIX}1-1"));
+        fs::create_dir(&crashed)?;
+        fs::write(crashed.join("spill.bin"), b"leftover")?;
+
+        let live = RunDir::create(Some(base.path()))?;
+        let live_path = live.path.clone();
+
+        assert!(!crashed.exists());
+        assert_eq!(RunDir::recover(base.path()), 0);
+        assert!(live_path.exists());
+
+        drop(live);
+        assert!(!live_path.exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn scratch_dirs_live_inside_the_run_dir_until_dropped() -> Result<()> {
+        let base = TempDir::new()?;
+        let run_dir = Arc::new(RunDir::create(Some(base.path()))?);
+
+        let (first, second) = (run_dir.scratch("spill")?, run_dir.scratch("spill")?);
+        assert_ne!(first.path(), second.path());
+        assert!(first.path().starts_with(&run_dir.path));
+
+        let first_path = first.path().to_path_buf();
+        drop(first);
+        assert!(!first_path.exists());
+        assert!(second.path().exists());
+
+        // NOTE: the run dir stays as long as a scratch dir in it does.
+        let run_path = run_dir.path.clone();
+        drop(run_dir);
+        assert!(run_path.exists());
+        drop(second);
+        assert!(!run_path.exists());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,23 @@
+use std::sync::Mutex;
+use uuid::Uuid;
+
+static RUN_ID: Mutex<Option<String>> = Mutex::new(None);
+
+/// Random ID of this run, stamped on everything it writes (the `DEDUP_RESULT` line, exports,
+/// plans, the action log, the heartbeat) so that an audit can tie them back together.
+pub struct RunId;
+
+impl RunId {
+    pub fn get() -> String {
+        RUN_ID
+            .lock()
+            .unwrap()
+            .get_or_insert_with(|| Uuid::new_v4().to_string())
+            .clone()
+    }
+
+    /// Gives the next run of the process an ID of its own (see `Deduplicator::find`).
+    pub fn renew() {
+        *RUN_ID.lock().unwrap() = None;
+    }
+}
@@ -1,50 +1,194 @@
-use crate::{fileinfo::{FileInfo, FileSource}, params::Params};
-use anyhow::Result;
+use crate::{
+    archive::Archive,
+    events::{Event, Events},
+    fileinfo::{FileInfo, FileSource},
+    filetype::{FileType, MediaType},
+    longpath::LongPaths,
+    params::{Params, ScanRoot},
+    payload::{Format, Payload},
+    placeholder::Placeholders,
+};
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use crossbeam_channel::Sender;
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use rayon::iter::{ParallelBridge, ParallelIterator};
+use std::collections::HashSet;
+use std::{fs, io::{self, Read}};
 use std::sync::{Arc, Mutex};
 use std::{path::{Path, PathBuf}, time::Duration};
 
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
 use globwalk::{GlobWalker, GlobWalkerBuilder};
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum SymlinkMode {
+    /// Compare a symlink to a file like the file it points to
+    #[default]
+    File,
+    /// Leave symlinks to files out of the scan, only the files themselves are compared
+    Skip,
+}
+
+/// Directories walked so far, by device & inode (by resolved path where there are none), so that
+/// a directory reached again through a symlink, including one looping back up the tree, is walked
+/// a single time.
+#[derive(Debug, Clone, Default)]
+struct VisitedDirs(Arc<Mutex<HashSet<DirId>>>);
+
+#[cfg(unix)]
+type DirId = (u64, u64);
+#[cfg(not(unix))]
+type DirId = PathBuf;
+
+impl VisitedDirs {
+    /// Whether `path` is walked for the first time; unreadable directories are left to the walker.
+    fn first_visit(&self, path: &Path) -> bool {
+        match Self::id(path) {
+            Some(id) => self.0.lock().unwrap().insert(id),
+            None => true,
+        }
+    }
+
+    #[cfg(unix)]
+    fn id(path: &Path) -> Option<DirId> {
+        use std::os::unix::fs::MetadataExt;
+        std::fs::metadata(path).ok().map(|meta| (meta.dev(), meta.ino()))
+    }
+
+    #[cfg(not(unix))]
+    fn id(path: &Path) -> Option<DirId> {
+        std::fs::canonicalize(path).ok()
+    }
+}
+
+#[derive(Clone)]
 pub struct Scanner {
     pub directory: Box<Path>,
+    /// Every directory `scan` walks, with its own options (see `--root`).
+    pub roots: Vec<ScanRoot>,
     pub min_depth: Option<usize>,
     pub max_depth: Option<usize>,
     pub include_types: Option<String>,
     pub exclude_types: Option<String>,
+    pub media_types: Vec<MediaType>,
     pub min_size: Option<u64>,
+    /// Files of this size and above are left for a later pass (see `--defer-large`).
+    pub below_size: Option<u64>,
     pub follow_links: bool,
+    /// Walk dotfiles & dot-directories (see `--no-hidden`).
+    pub hidden: bool,
+    /// Stay on the filesystem of the scan root (see `--one-file-system`).
+    pub one_file_system: bool,
+    /// What becomes of symlinks to files (see `--symlinks`).
+    pub symlinks: SymlinkMode,
+    /// Read online-only files of cloud drives too, downloading them (see `--hydrate-and-hash`).
+    pub hydrate: bool,
+    /// Compare photos by their image data alone (see `--ignore-metadata`).
+    pub ignore_metadata: bool,
+    /// Compare songs by their audio frames alone (see `--media-aware`).
+    pub media_aware: bool,
+    /// Scan the members of the archives found too (see `--scan-archives`).
+    pub scan_archives: bool,
+    /// Skip what `.gitignore` files in the tree ignore (see `--gitignore`).
+    pub gitignore: bool,
+    /// List of the files to scan instead of walking the tree, `-` for stdin (see `--files-from`).
+    pub files_from: Option<PathBuf>,
+    /// Whether that list is NUL-delimited rather than one path per line.
+    pub null_delimited: bool,
+    /// Files a resumed run found before, scanned again instead of walking (see `--resume`).
+    pub listed: Option<Vec<PathBuf>>,
+    /// Directory names skipped wherever they appear (see `--exclude-dir`).
+    pub exclude_dirs: Vec<String>,
+    /// Paths relative to the scan root that are scanned (see `--include`), all if empty.
+    pub include_globs: Vec<String>,
+    /// Paths relative to the scan root that are skipped (see `--exclude`).
+    pub exclude_globs: Vec<String>,
+    /// Threads reading the metadata of the files found (see `--scan-threads`), 0 for one per CPU.
+    pub threads: usize,
     pub progress: bool,
+    /// Told about every file kept (see `Event::FileScanned`).
+    pub events: Events,
+    /// Where the paths too long to act on are recorded (see `LongPaths`).
+    pub long_paths: LongPaths,
+    /// Where the online-only files left out are recorded (see `Placeholders`).
+    pub placeholders: Placeholders,
 }
 
 impl Scanner {
     pub fn new(app_args: Arc<Params>) -> Result<Self> {
         Ok(Self {
             directory: app_args.get_directory()?.into_boxed_path(),
+            roots: app_args.scan_roots()?,
             include_types: app_args.types.clone(),
             exclude_types: app_args.exclude_types.clone(),
+            media_types: app_args.media_types.clone(),
             min_depth: app_args.min_depth,
             max_depth: app_args.max_depth,
             min_size: app_args.get_min_size(),
+            below_size: app_args.get_defer_large(),
             follow_links: app_args.follow_links,
+            hidden: !app_args.no_hidden,
+            one_file_system: app_args.one_file_system,
+            symlinks: app_args.symlinks,
+            hydrate: app_args.hydrate_and_hash,
+            ignore_metadata: app_args.ignore_metadata,
+            media_aware: app_args.media_aware,
+            scan_archives: app_args.scan_archives,
+            gitignore: app_args.gitignore,
+            files_from: app_args.files_from.clone(),
+            null_delimited: app_args.null_delimited,
+            listed: None,
+            exclude_dirs: app_args.exclude_dir.clone(),
+            include_globs: app_args.include.clone(),
+            exclude_globs: app_args.exclude.clone(),
+            threads: app_args.stage_threads(app_args.scan_threads),
             progress: app_args.progress,
+            events: app_args.events.clone(),
+            long_paths: app_args.long_paths.clone(),
+            placeholders: app_args.placeholders.clone(),
         })
     }
 
     pub fn build(app_args: &Params) -> Result<Self> {
         Ok(Self {
             directory: app_args.get_directory()?.into_boxed_path(),
+            roots: vec![],
             include_types: app_args.types.clone(),
             exclude_types: app_args.exclude_types.clone(),
+            media_types: app_args.media_types.clone(),
             min_depth: app_args.min_depth,
             max_depth: app_args.max_depth,
             min_size: app_args.get_min_size(),
+            below_size: app_args.get_defer_large(),
             follow_links: app_args.follow_links,
+            hidden: !app_args.no_hidden,
+            one_file_system: app_args.one_file_system,
+            symlinks: app_args.symlinks,
+            hydrate: app_args.hydrate_and_hash,
+            ignore_metadata: app_args.ignore_metadata,
+            media_aware: app_args.media_aware,
+            scan_archives: app_args.scan_archives,
+            gitignore: app_args.gitignore,
+            files_from: app_args.files_from.clone(),
+            null_delimited: app_args.null_delimited,
+            listed: None,
+            exclude_dirs: app_args.exclude_dir.clone(),
+            include_globs: app_args.include.clone(),
+            exclude_globs: app_args.exclude.clone(),
+            threads: app_args.stage_threads(app_args.scan_threads),
             progress: false,
+            events: app_args.events.clone(),
+            long_paths: app_args.long_paths.clone(),
+            placeholders: app_args.placeholders.clone(),
         })
     }
 
-    pub fn scan_with_source(&self, directory: PathBuf, source: FileSource) -> Result<Vec<FileInfo>> {
+    pub fn scan_with_source(
+        &self,
+        directory: PathBuf,
+        source: FileSource,
+    ) -> Result<Vec<Arc<FileInfo>>> {
         let progress_style = ProgressStyle::with_template("[{elapsed_precise}] {pos:>7} {msg}")?;
         let progress_bar = ProgressBar::new_spinner();
         progress_bar.set_style(progress_style);
@@ -55,33 +199,351 @@ impl Scanner {
         // Create a temporary scanner with the specified directory
         let temp_scanner = Self {
             directory: directory.into_boxed_path(),
+            roots: vec![],
             min_depth: self.min_depth,
             max_depth: self.max_depth,
             include_types: self.include_types.clone(),
             exclude_types: self.exclude_types.clone(),
+            media_types: self.media_types.clone(),
             min_size: self.min_size,
+            below_size: self.below_size,
             follow_links: self.follow_links,
+            hidden: self.hidden,
+            one_file_system: self.one_file_system,
+            symlinks: self.symlinks,
+            hydrate: self.hydrate,
+            ignore_metadata: self.ignore_metadata,
+            media_aware: self.media_aware,
+            scan_archives: self.scan_archives,
+            gitignore: self.gitignore,
+            files_from: self.files_from.clone(),
+            null_delimited: self.null_delimited,
+            listed: None,
+            exclude_dirs: self.exclude_dirs.clone(),
+            include_globs: self.include_globs.clone(),
+            exclude_globs: self.exclude_globs.clone(),
+            threads: self.threads,
             progress: self.progress,
+            events: self.events.clone(),
+            long_paths: self.long_paths.clone(),
+            placeholders: self.placeholders.clone(),
         };
 
-        let results = temp_scanner
-            .build_walker()?
-            .filter_map(Result::ok)
-            .map(|entity| entity.into_path())
-            .map(|path| {
-                progress_bar.inc(1);
-                path
-            })
-            .filter(|path| path.is_file())
-            .filter_map(|path| FileInfo::with_source(path, source).ok())
-            .filter(|file| file.size >= min_size)
-            .collect::<Vec<FileInfo>>();
+        let candidates = temp_scanner.with_members(
+            temp_scanner.candidates(Some(source), &progress_bar)?,
+            Some(source),
+        );
+        let results = self.pool()?.install(|| {
+            candidates
+                .par_bridge()
+                .filter(|file| file.size >= min_size)
+                .filter(|file| self.below_size.is_none_or(|below| file.size < below))
+                .filter(|file| FileType::matches_any(&file.path, &self.media_types))
+                .map(|file| self.with_payload(file))
+                .inspect(|file| self.announce(file))
+                .map(Arc::new)
+                .collect::<Vec<Arc<FileInfo>>>()
+        });
 
         progress_bar.finish_with_message("paths mapped");
 
         Ok(results)
     }
 
+    /// This scanner set up to scan `listed` rather than walk its roots, if any.
+    pub fn with_listed(self, listed: Option<Vec<PathBuf>>) -> Self {
+        Self { listed, ..self }
+    }
+
+    /// This scanner set up to walk `root`, with its excludes & thread count.
+    fn for_root(&self, root: &ScanRoot) -> Self {
+        Self {
+            directory: root.path.clone().into_boxed_path(),
+            exclude_globs: self.exclude_globs.iter().chain(&root.exclude).cloned().collect(),
+            threads: root.threads.unwrap_or(self.threads),
+            ..self.clone()
+        }
+    }
+
+    /// The walk itself is sequential, the files it yields are filtered and opened (see
+    /// `with_payload`) on this pool.
+    fn pool(&self) -> Result<rayon::ThreadPool> {
+        Ok(rayon::ThreadPoolBuilder::new()
+            .num_threads(self.threads)
+            .build()?)
+    }
+
+    fn with_payload(&self, mut file: FileInfo) -> FileInfo {
+        if !self.ignore_metadata && !self.media_aware {
+            return file;
+        }
+        let stripped = Payload::format(&file.path).is_some_and(|format| {
+            (self.ignore_metadata && Format::METADATA.contains(&format))
+                || (self.media_aware && Format::AUDIO.contains(&format))
+        });
+        if stripped {
+            file.payload = Payload::size(&file.path);
+        }
+        file
+    }
+
+    /// Every file below the scan root, which is either a directory or an archive (see `Archive`).
+    fn candidates<'a>(
+        &'a self,
+        source: Option<FileSource>,
+        progress_bar: &'a ProgressBar,
+    ) -> Result<Box<dyn Iterator<Item = FileInfo> + Send + 'a>> {
+        if Archive::is_archive(&self.directory) {
+            return Ok(Box::new(
+                self.archive_files(&self.directory, source)?
+                    .into_iter()
+                    .inspect(|_file| progress_bar.inc(1)),
+            ));
+        }
+
+        // NOTE: these passed every filter when they were first found.
+        if let Some(listed) = &self.listed {
+            return Ok(Box::new(
+                listed
+                    .iter()
+                    .inspect(|_path| progress_bar.inc(1))
+                    .filter(|path| path.is_file())
+                    .filter_map(move |path| Self::file_info(path.clone(), source)),
+            ));
+        }
+
+        if let Some(list) = &self.files_from {
+            let matches = self.pattern_filter()?;
+            // NOTE: a file listed twice, or through another path, is still scanned once.
+            let mut listed = HashSet::new();
+            return Ok(Box::new(
+                self.listed_files(list)?
+                    .into_iter()
+                    .inspect(|_path| progress_bar.inc(1))
+                    .filter(|path| self.long_paths.admit(path))
+                    .filter(|path| self.admits_link(path))
+                    .filter(|path| path.is_file())
+                    .filter_map(|path| fs::canonicalize(path).ok())
+                    .filter(move |path| listed.insert(path.clone()))
+                    .filter(move |path| matches(path))
+                    .filter(|path| self.placeholders.admit(path, self.hydrate))
+                    .filter_map(move |path| Self::file_info(path, source)),
+            ));
+        }
+
+        // NOTE: only this walker can prune a directory it already walked (see `VisitedDirs`) or
+        // one on another filesystem.
+        if self.gitignore || self.follow_links || self.one_file_system {
+            return Ok(Box::new(
+                self.ignore_walk()?
+                    .inspect(|_path| progress_bar.inc(1))
+                    .filter(|path| self.long_paths.admit(path))
+                    .filter(|path| self.admits_link(path))
+                    .filter(|path| path.is_file())
+                    .filter(|path| self.placeholders.admit(path, self.hydrate))
+                    .filter_map(move |path| Self::file_info(path, source)),
+            ));
+        }
+
+        let globs = self.glob_filter()?;
+        Ok(Box::new(
+            self.build_walker()?
+                .filter_map(|entity| match entity {
+                    Ok(entity) => Some(entity.into_path()),
+                    Err(e) => {
+                        tracing::warn!(error = %e, "unable to walk an entry");
+                        // NOTE: a directory too deep to read fails here rather than being listed.
+                        if let Some(path) = e.path().filter(|path| LongPaths::too_long(path)) {
+                            self.long_paths.record(path);
+                        }
+                        None
+                    }
+                })
+                .inspect(|_path| progress_bar.inc(1))
+                .filter(|path| self.long_paths.admit(path))
+                .filter(|path| self.admits_link(path))
+                .filter(|path| path.is_file())
+                .filter(move |path| globs(path))
+                .filter(|path| self.placeholders.admit(path, self.hydrate))
+                .filter_map(move |path| Self::file_info(path, source)),
+        ))
+    }
+
+    /// Tells event subscribers about a file the scan keeps.
+    fn announce(&self, file: &FileInfo) {
+        self.events.emit(|| Event::FileScanned {
+            path: file.path.to_path_buf(),
+            size: file.size,
+        });
+    }
+
+    /// The file at `path`, or `None` when its metadata cannot be read (which is logged).
+    fn file_info(path: PathBuf, source: Option<FileSource>) -> Option<FileInfo> {
+        let file = match source {
+            Some(source) => FileInfo::with_source(path.clone(), source),
+            None => FileInfo::new(path.clone()),
+        };
+        file.inspect_err(|error| {
+            tracing::warn!(path = %path.display(), error = format!("{error:#}"), "unable to read file")
+        })
+        .ok()
+    }
+
+    /// Archive members, filtered by type & depth the way the walker filters a directory.
+    fn archive_files(&self, archive: &Path, source: Option<FileSource>) -> Result<Vec<FileInfo>> {
+        let matches = self.pattern_filter()?;
+        Ok(Archive::files(archive, source)?
+            .into_iter()
+            .filter(|file| matches(&file.path))
+            .collect())
+    }
+
+    /// `candidates`, each archive among them followed by its members with `--scan-archives`.
+    /// Archives inside archives are not opened, their members are listed as files.
+    fn with_members<'a>(
+        &'a self,
+        candidates: Box<dyn Iterator<Item = FileInfo> + Send + 'a>,
+        source: Option<FileSource>,
+    ) -> Box<dyn Iterator<Item = FileInfo> + Send + 'a> {
+        if !self.scan_archives {
+            return candidates;
+        }
+        Box::new(candidates.flat_map(move |file| {
+            let members = match Archive::is_archive(&file.path) {
+                true => self.archive_files(&file.path, source).unwrap_or_else(|error| {
+                    let error = format!("{error:#}");
+                    tracing::warn!(path = %file.path.display(), error, "unable to read archive");
+                    vec![]
+                }),
+                false => vec![],
+            };
+            std::iter::once(file).chain(members)
+        }))
+    }
+
+    /// The paths of a `--files-from` list, relative ones taken from the working directory.
+    fn listed_files(&self, list: &Path) -> Result<Vec<PathBuf>> {
+        let mut contents = Vec::new();
+        match list == Path::new("-") {
+            true => io::stdin().lock().read_to_end(&mut contents)?,
+            false => fs::File::open(list)
+                .with_context(|| format!("unable to read file list {}", list.display()))?
+                .read_to_end(&mut contents)?,
+        };
+
+        let delimiter = match self.null_delimited {
+            true => b'\0',
+            false => b'\n',
+        };
+        Ok(contents
+            .split(|byte| *byte == delimiter)
+            .map(|entry| match self.null_delimited {
+                true => entry,
+                false => entry.strip_suffix(b"\r").unwrap_or(entry),
+            })
+            .filter(|entry| !entry.is_empty())
+            .map(Self::path_from_bytes)
+            .collect())
+    }
+
+    #[cfg(unix)]
+    fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+        use std::os::unix::ffi::OsStrExt;
+        PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+    }
+
+    #[cfg(not(unix))]
+    fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+        PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+    }
+
+    fn admits_link(&self, path: &Path) -> bool {
+        self.symlinks != SymlinkMode::Skip || !path.is_symlink()
+    }
+
+    /// Paths below the scan root, each directory walked once however many symlinks lead to it,
+    /// and none beyond a mount point with `--one-file-system`. With `--gitignore` they are listed the way git sees them: whatever a `.gitignore`,
+    /// `.ignore` or `.git/info/exclude` in the tree (or above it) ignores is left out, as is
+    /// `.git` itself.
+    fn ignore_walk(&self) -> Result<impl Iterator<Item = PathBuf> + Send + '_> {
+        let matches = self.pattern_filter()?;
+        let (gitignore, visited) = (self.gitignore, VisitedDirs::default());
+        visited.first_visit(&self.directory);
+        let walker = ignore::WalkBuilder::new(&self.directory)
+            .standard_filters(gitignore)
+            .hidden(!self.hidden)
+            .require_git(false)
+            .follow_links(self.follow_links)
+            .same_file_system(self.one_file_system)
+            .max_depth(self.max_depth)
+            .filter_entry(move |entry| {
+                let is_dir = entry.file_type().is_some_and(|kind| kind.is_dir());
+                !(gitignore && entry.file_name() == ".git")
+                    && (!is_dir || visited.first_visit(entry.path()))
+            })
+            .build();
+
+        Ok(walker
+            .filter_map(|entry| {
+                entry
+                    .inspect_err(|error| tracing::warn!(%error, "unable to walk an entry"))
+                    .ok()
+            })
+            .map(|entry| entry.into_path())
+            .filter(move |path| matches(path)))
+    }
+
+    /// Type, directory, glob & depth filters of the walker, for paths it does not walk itself.
+    fn pattern_filter(&self) -> Result<impl Fn(&Path) -> bool + '_> {
+        let globs = self.glob_filter()?;
+        let (mut include, mut exclude) = (GlobSetBuilder::new(), GlobSetBuilder::new());
+        for pattern in self.scan_patterns()? {
+            match pattern.strip_prefix('!') {
+                Some(pattern) => exclude.add(Glob::new(pattern)?),
+                None => include.add(Glob::new(&pattern)?),
+            };
+        }
+        let (include, exclude) = (include.build()?, exclude.build()?);
+
+        Ok(move |path: &Path| {
+            let name = path.strip_prefix(&self.directory).unwrap_or(path);
+            let depth = name.components().count();
+            include.is_match(name)
+                && !exclude.is_match(name)
+                && globs(path)
+                && self.min_depth.is_none_or(|min_depth| depth >= min_depth)
+                && self.max_depth.is_none_or(|max_depth| depth <= max_depth)
+        })
+    }
+
+    /// The `--include` & `--exclude` globs, checked against the path relative to the scan root.
+    fn glob_filter(&self) -> Result<impl Fn(&Path) -> bool + '_> {
+        let include = Self::path_globs(&self.include_globs)?;
+        let exclude = Self::path_globs(&self.exclude_globs)?;
+
+        Ok(move |path: &Path| {
+            let name = path.strip_prefix(&self.directory).unwrap_or(path);
+            (self.include_globs.is_empty() || include.is_match(name)) && !exclude.is_match(name)
+        })
+    }
+
+    /// Globs match the way .gitignore patterns do: one without a `/` (e.g., `*.jpg`,
+    /// `node_modules`) matches at any depth, and one matching a directory covers all below it.
+    fn path_globs(globs: &[String]) -> Result<GlobSet> {
+        let mut set = GlobSetBuilder::new();
+        for glob in globs {
+            let glob = glob.trim_end_matches('/');
+            let glob = match glob.contains('/') {
+                true => glob.trim_start_matches('/').to_string(),
+                false => format!("**/{glob}"),
+            };
+            for pattern in [glob.clone(), format!("{glob}/**")] {
+                set.add(GlobBuilder::new(&pattern).literal_separator(true).build()?);
+            }
+        }
+        Ok(set.build()?)
+    }
+
     fn scan_patterns(&self) -> Result<Vec<String>> {
         let include_types = match &self.include_types {
             Some(ftypes) => Some(format!("**/*.{{{ftypes}}}")),
@@ -93,9 +555,20 @@ impl Scanner {
             .as_ref()
             .map(|ftypes| format!("!**/*.{{{ftypes}}}"));
 
-        Ok(vec![include_types, exclude_types]
+        let exclude_dirs = (!self.exclude_dirs.is_empty())
+            .then(|| format!("!**/{{{}}}/**", self.exclude_dirs.join(",")));
+
+        // NOTE: the walker skips a dot-directory as a whole, the second pattern does it for
+        // filters checking paths one by one.
+        let exclude_hidden = match self.hidden {
+            true => vec![],
+            false => vec!["!**/.*".to_string(), "!**/.*/**".to_string()],
+        };
+
+        Ok(vec![include_types, exclude_types, exclude_dirs]
             .into_iter()
             .flatten()
+            .chain(exclude_hidden)
             .collect())
     }
 
@@ -131,7 +604,7 @@ impl Scanner {
 
     pub fn scan(
         &self,
-        files: Arc<Mutex<Vec<FileInfo>>>,
+        files: Sender<Arc<FileInfo>>,
         progress_bar_box: Arc<MultiProgress>,
     ) -> Result<()> {
         let progress_bar = match self.progress {
@@ -145,19 +618,33 @@ impl Scanner {
         progress_bar.set_message("paths mapped");
         let min_size = self.min_size.unwrap_or(0);
 
-        self.build_walker()?
-            .filter_map(Result::ok)
-            .map(|entity| entity.into_path())
-            .inspect(|_path| progress_bar.inc(1))
-            .filter(|path| path.is_file())
-            .map(FileInfo::new)
-            .filter_map(Result::ok)
-            .filter(|file| file.size >= min_size)
-            .for_each(|file| {
-                let mut flock = files.lock().unwrap();
-                flock.push(file);
+        // NOTE: a list of files is scanned once, whatever the roots.
+        let roots = match self.listed.is_some() {
+            true => &self.roots[..self.roots.len().min(1)],
+            false => &self.roots[..],
+        };
+        for root in roots {
+            tracing::info!(root = %root.path.display(), "scanning");
+            let scanner = self.for_root(root);
+            let candidates = scanner.with_members(scanner.candidates(None, &progress_bar)?, None);
+            let sent = scanner.pool()?.install(|| {
+                candidates
+                    .par_bridge()
+                    .filter(|file| file.size >= min_size)
+                    .filter(|file| self.below_size.is_none_or(|below| file.size < below))
+                    .filter(|file| FileType::matches_any(&file.path, &self.media_types))
+                    .map(|file| self.with_payload(file))
+                    .inspect(|file| self.announce(file))
+                    .map(Arc::new)
+                    // NOTE: blocks while the size bucketer is behind; stops once it hung up.
+                    .try_for_each(|file| files.send(file))
             });
+            if sent.is_err() {
+                break;
+            }
+        }
 
+        tracing::info!(paths = progress_bar.position(), "scan finished");
         progress_bar.finish_with_message("paths mapped");
         Ok(())
     }
@@ -169,9 +656,10 @@ mod tests {
     use crate::params::Params;
     use std::fs::File;
     use std::io::Write;
-    use std::sync::{Arc, Mutex};
+    use std::sync::Arc;
 
-    use super::Scanner;
+    use super::{Scanner, SymlinkMode};
+    use crate::filetype::MediaType;
     use indicatif::MultiProgress;
     use tempfile::TempDir;
 
@@ -203,14 +691,14 @@ mod tests {
         };
 
         let progress = Arc::new(MultiProgress::new());
-        let scanlist = Arc::new(Mutex::<Vec<FileInfo>>::new(vec![]));
+        let (sender, scanlist) = crossbeam_channel::unbounded();
         let scanner = Scanner::new(Arc::new(params)).expect("scanner initialization failed");
 
         scanner
-            .scan(scanlist.clone(), progress)
+            .scan(sender, progress)
             .expect("scanning failed.");
 
-        let scan_list_mg = scanlist.lock().unwrap();
+        let scan_list_mg = scanlist.iter().collect::<Vec<Arc<FileInfo>>>();
         
         let expected_js = std::fs::canonicalize(root.path().join("this-is-a-js-file.js")).unwrap();
         let expected_csv = std::fs::canonicalize(root.path().join("this-is-a-csv-file.csv")).unwrap();
@@ -254,14 +742,14 @@ mod tests {
         };
 
         let progress = Arc::new(MultiProgress::new());
-        let scanlist = Arc::new(Mutex::<Vec<FileInfo>>::new(vec![]));
+        let (sender, scanlist) = crossbeam_channel::unbounded();
         let scanner = Scanner::new(Arc::new(params)).expect("scanner initialization failed");
 
         scanner
-            .scan(scanlist.clone(), progress)
+            .scan(sender, progress)
             .expect("scanning failed.");
 
-        let scan_list_mg = scanlist.lock().unwrap();
+        let scan_list_mg = scanlist.iter().collect::<Vec<Arc<FileInfo>>>();
 
         let expected_js = std::fs::canonicalize(root.path().join("this-is-a-js-file.js")).unwrap();
         let expected_csv = std::fs::canonicalize(root.path().join("this-is-a-csv-file.csv")).unwrap();
@@ -306,14 +794,14 @@ mod tests {
         };
 
         let progress = Arc::new(MultiProgress::new());
-        let scanlist = Arc::new(Mutex::<Vec<FileInfo>>::new(vec![]));
+        let (sender, scanlist) = crossbeam_channel::unbounded();
         let scanner = Scanner::new(Arc::new(params)).expect("scanner initialization failed");
 
         scanner
-            .scan(scanlist.clone(), progress)
+            .scan(sender, progress)
             .expect("scanning failed.");
 
-        let scan_list_mg = scanlist.lock().unwrap();
+        let scan_list_mg = scanlist.iter().collect::<Vec<Arc<FileInfo>>>();
 
         let expected_js = std::fs::canonicalize(root.path().join("this-is-a-js-file.js")).unwrap();
         let expected_csv = std::fs::canonicalize(root.path().join("this-is-a-csv-file.csv")).unwrap();
@@ -325,4 +813,386 @@ mod tests {
 
         assert!(scan_list_mg.iter().any(|f| f.path.as_ref() == expected_rs.as_path()));
     }
+
+    #[test]
+    fn media_type_filter_detects_misnamed_files_by_content() {
+        let root =
+            TempDir::with_prefix("deduplicator_test_root").expect("unable to create tempdir");
+        let png_magic: &[u8] = b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR";
+        [
+            ("holiday.png", png_magic),
+            ("holiday-copy.txt", png_magic),
+            ("notes.txt", b"plain old text".as_slice()),
+        ]
+        .iter()
+        .for_each(|(path, content)| {
+            let mut file = File::create_new(root.path().join(path)).unwrap_or_else(|_| {
+                panic!("unable to create file {path}");
+            });
+            file.write_all(content).unwrap_or_else(|_| {
+                panic!("unable to write to file {path}");
+            });
+        });
+
+        let params = Params {
+            media_types: vec![MediaType::Image],
+            dir: Some(root.path().into()),
+            min_size: Some("0b".to_string()),
+            ..Default::default()
+        };
+
+        let progress = Arc::new(MultiProgress::new());
+        let (sender, scanlist) = crossbeam_channel::unbounded();
+        let scanner = Scanner::new(Arc::new(params)).expect("scanner initialization failed");
+
+        scanner
+            .scan(sender, progress)
+            .expect("scanning failed.");
+
+        let scan_list_mg = scanlist.iter().collect::<Vec<Arc<FileInfo>>>();
+
+        let expected_png = std::fs::canonicalize(root.path().join("holiday.png")).unwrap();
+        let expected_misnamed = std::fs::canonicalize(root.path().join("holiday-copy.txt")).unwrap();
+        let expected_txt = std::fs::canonicalize(root.path().join("notes.txt")).unwrap();
+
+        assert!(scan_list_mg.iter().any(|f| f.path.as_ref() == expected_png.as_path()));
+
+        assert!(scan_list_mg.iter().any(|f| f.path.as_ref() == expected_misnamed.as_path()));
+
+        assert!(scan_list_mg.iter().all(|f| f.path.as_ref() != expected_txt.as_path()));
+    }
+
+    #[test]
+    fn deferred_large_files_are_only_scanned_in_the_second_pass() {
+        let root =
+            TempDir::with_prefix("deduplicator_test_root").expect("unable to create tempdir");
+        [("small.bin", 10usize), ("large.bin", 2048usize)]
+            .iter()
+            .for_each(|(path, size)| {
+                let mut file = File::create_new(root.path().join(path)).unwrap_or_else(|_| {
+                    panic!("unable to create file {path}");
+                });
+                file.write_all(&vec![0u8; *size]).unwrap_or_else(|_| {
+                    panic!("unable to write to file {path}");
+                });
+            });
+
+        let params = Params {
+            dir: Some(root.path().into()),
+            min_size: Some("0b".to_string()),
+            defer_large: Some("1KiB".to_string()),
+            ..Default::default()
+        };
+
+        let scanned_names = |params: Params| {
+            let (sender, scanlist) = crossbeam_channel::unbounded();
+            Scanner::new(Arc::new(params))
+                .expect("scanner initialization failed")
+                .scan(sender, Arc::new(MultiProgress::new()))
+                .expect("scanning failed.");
+            let names = scanlist
+                .iter()
+                .map(|f| f.path.file_name().unwrap().to_string_lossy().to_string())
+                .collect::<Vec<String>>();
+            names
+        };
+
+        assert_eq!(scanned_names(params.clone()), vec!["small.bin"]);
+        assert_eq!(
+            scanned_names(params.deferred_pass().unwrap()),
+            vec!["large.bin"]
+        );
+    }
+
+    #[test]
+    fn archive_roots_are_scanned_with_the_same_filters() {
+        let root =
+            TempDir::with_prefix("deduplicator_test_root").expect("unable to create tempdir");
+        let tarball = root.path().join("backup.tar");
+        let mut builder = tar::Builder::new(File::create(&tarball).unwrap());
+        for name in ["top.js", "nested/app.js", "nested/style.css"] {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(4);
+            header.set_cksum();
+            builder.append_data(&mut header, name, &b"test"[..]).unwrap();
+        }
+        builder.into_inner().unwrap();
+
+        let params = Params {
+            types: Some(String::from("js")),
+            dir: Some(tarball.clone()),
+            min_size: Some("0b".to_string()),
+            max_depth: Some(1),
+            ..Default::default()
+        };
+
+        let (sender, scanlist) = crossbeam_channel::unbounded();
+        Scanner::new(Arc::new(params))
+            .expect("scanner initialization failed")
+            .scan(sender, Arc::new(MultiProgress::new()))
+            .expect("scanning failed.");
+
+        let paths = scanlist.iter().map(|f| f.path.to_path_buf()).collect::<Vec<_>>();
+        let tarball = std::fs::canonicalize(tarball).unwrap();
+        assert_eq!(paths, vec![tarball.join("top.js")]);
+    }
+
+    #[test]
+    fn archives_found_while_scanning_are_opened_with_scan_archives() {
+        let root =
+            TempDir::with_prefix("deduplicator_test_root").expect("unable to create tempdir");
+        let root_path = std::fs::canonicalize(root.path()).unwrap();
+        File::create(root_path.join("loose.txt"))
+            .and_then(|mut file| file.write_all(b"kept twice"))
+            .unwrap();
+        let tarball = root_path.join("backup.tar");
+        let mut builder = tar::Builder::new(File::create(&tarball).unwrap());
+        let members = [("docs/other.txt", &b"unrelated"[..]), ("docs/copy.txt", b"kept twice")];
+        for (name, content) in members {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_cksum();
+            builder.append_data(&mut header, name, content).unwrap();
+        }
+        builder.into_inner().unwrap();
+        // NOTE: a gzipped tarball is not opened, only compared as a whole.
+        let gzipped = root_path.join("backup.tar.gz");
+        let encoder = flate2::write::GzEncoder::new(
+            File::create(&gzipped).unwrap(),
+            flate2::Compression::default(),
+        );
+        tar::Builder::new(encoder).into_inner().unwrap().finish().unwrap();
+
+        let scan = |scan_archives: bool| {
+            let params = Params {
+                dir: Some(root_path.clone()),
+                min_size: Some("0b".to_string()),
+                scan_archives,
+                ..Default::default()
+            };
+            let (sender, scanlist) = crossbeam_channel::unbounded();
+            Scanner::new(Arc::new(params))
+                .expect("scanner initialization failed")
+                .scan(sender, Arc::new(MultiProgress::new()))
+                .expect("scanning failed.");
+            let mut files = scanlist.iter().collect::<Vec<Arc<FileInfo>>>();
+            files.sort_by(|a, b| a.path.cmp(&b.path));
+            files
+        };
+
+        assert_eq!(scan(false).len(), 3);
+        let files = scan(true);
+        let paths = files.iter().map(|f| f.path.to_path_buf()).collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec![
+                tarball.clone(),
+                tarball.join("docs/copy.txt"),
+                tarball.join("docs/other.txt"),
+                gzipped,
+                root_path.join("loose.txt"),
+            ]
+        );
+        let seed = 7;
+        assert_eq!(files[1].hash(seed.into()).unwrap(), files[4].hash(seed.into()).unwrap());
+        assert!(crate::archive::Archive::contains(&files[1].path));
+
+        let group = [Arc::clone(&files[1]), Arc::clone(&files[4])];
+        assert_eq!(Params::default().keeper(&group), Some(1));
+    }
+
+    #[test]
+    fn include_and_exclude_globs_match_relative_paths() {
+        let root =
+            TempDir::with_prefix("deduplicator_test_root").expect("unable to create tempdir");
+        for name in [
+            "photo.jpg",
+            "album/photo.jpg",
+            "album/notes.txt",
+            "node_modules/pkg/logo.jpg",
+            "build/out.jpg",
+            "src/build/keep.jpg",
+        ] {
+            let path = root.path().join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            File::create_new(path).unwrap().write_all(b"test").unwrap();
+        }
+
+        let params = Params {
+            include: vec![String::from("*.jpg")],
+            exclude: vec![String::from("node_modules"), String::from("/build")],
+            dir: Some(root.path().into()),
+            min_size: Some("0b".to_string()),
+            ..Default::default()
+        };
+
+        let (sender, scanlist) = crossbeam_channel::unbounded();
+        Scanner::new(Arc::new(params))
+            .expect("scanner initialization failed")
+            .scan(sender, Arc::new(MultiProgress::new()))
+            .expect("scanning failed.");
+
+        let root = std::fs::canonicalize(root.path()).unwrap();
+        let mut paths = scanlist
+            .iter()
+            .map(|f| f.path.strip_prefix(&root).unwrap().to_path_buf())
+            .collect::<Vec<_>>();
+        paths.sort();
+        assert_eq!(
+            paths,
+            ["album/photo.jpg", "photo.jpg", "src/build/keep.jpg"]
+                .map(std::path::PathBuf::from)
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn followed_symlinks_walk_each_directory_once() {
+        let root = TempDir::with_prefix("deduplicator_test_root").unwrap();
+        std::fs::create_dir_all(root.path().join("real")).unwrap();
+        File::create_new(root.path().join("real/a.txt"))
+            .unwrap()
+            .write_all(b"test")
+            .unwrap();
+        std::os::unix::fs::symlink(root.path().join("real"), root.path().join("alias")).unwrap();
+        std::os::unix::fs::symlink(root.path(), root.path().join("real/loop")).unwrap();
+        std::os::unix::fs::symlink(root.path().join("real/a.txt"), root.path().join("b.txt"))
+            .unwrap();
+
+        let scan = |symlinks| {
+            let params = Params {
+                dir: Some(root.path().into()),
+                min_size: Some("0b".to_string()),
+                follow_links: true,
+                symlinks,
+                ..Default::default()
+            };
+            let (sender, scanlist) = crossbeam_channel::unbounded();
+            Scanner::new(Arc::new(params))
+                .expect("scanner initialization failed")
+                .scan(sender, Arc::new(MultiProgress::new()))
+                .expect("scanning failed.");
+            let mut names = scanlist
+                .iter()
+                .map(|f| f.path.file_name().unwrap().to_string_lossy().into_owned())
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+        };
+
+        assert_eq!(scan(SymlinkMode::File), ["a.txt", "b.txt"]);
+        assert_eq!(scan(SymlinkMode::Skip), ["a.txt"]);
+    }
+
+    #[test]
+    fn hidden_files_and_directories_are_left_out_on_request() {
+        let root = TempDir::with_prefix("deduplicator_test_root").unwrap();
+        for name in ["a.txt", ".b.txt", ".cache/c.txt", "dir/.d.txt", "dir/e.txt"] {
+            let path = root.path().join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            File::create_new(path).unwrap().write_all(b"test").unwrap();
+        }
+
+        let scan = |no_hidden, gitignore| {
+            let params = Params {
+                dir: Some(root.path().into()),
+                min_size: Some("0b".to_string()),
+                no_hidden,
+                gitignore,
+                ..Default::default()
+            };
+            let (sender, scanlist) = crossbeam_channel::unbounded();
+            Scanner::new(Arc::new(params))
+                .expect("scanner initialization failed")
+                .scan(sender, Arc::new(MultiProgress::new()))
+                .expect("scanning failed.");
+            let root = std::fs::canonicalize(root.path()).unwrap();
+            let mut paths = scanlist
+                .iter()
+                .map(|f| f.path.strip_prefix(&root).unwrap().to_string_lossy().into_owned())
+                .collect::<Vec<_>>();
+            paths.sort();
+            paths
+        };
+
+        assert_eq!(scan(false, false).len(), 5);
+        assert_eq!(scan(true, false), ["a.txt", "dir/e.txt"]);
+        assert_eq!(scan(true, true), ["a.txt", "dir/e.txt"]);
+    }
+
+    #[test]
+    fn listed_files_are_scanned_instead_of_the_tree() {
+        let root = TempDir::with_prefix("deduplicator_test_root").unwrap();
+        for name in ["a.txt", "b.txt", "c.jpg", "unlisted.txt"] {
+            File::create_new(root.path().join(name))
+                .unwrap()
+                .write_all(b"test")
+                .unwrap();
+        }
+        let list = root.path().join("list");
+        let listed = ["a.txt", "b.txt", "c.jpg", "missing.txt", "a.txt", "./b.txt"]
+            .map(|name| root.path().join(name).to_string_lossy().into_owned());
+        std::fs::write(&list, listed.join("\0")).unwrap();
+
+        let params = Params {
+            dir: Some(root.path().into()),
+            min_size: Some("0b".to_string()),
+            types: Some(String::from("txt")),
+            files_from: Some(list),
+            null_delimited: true,
+            ..Default::default()
+        };
+        let (sender, scanlist) = crossbeam_channel::unbounded();
+        Scanner::new(Arc::new(params))
+            .expect("scanner initialization failed")
+            .scan(sender, Arc::new(MultiProgress::new()))
+            .expect("scanning failed.");
+
+        let mut names = scanlist
+            .iter()
+            .map(|f| f.path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["a.txt", "b.txt"]);
+    }
+
+    #[test]
+    fn each_root_is_scanned_with_its_own_excludes() {
+        let (first, second) = (
+            TempDir::with_prefix("deduplicator_test_root").unwrap(),
+            TempDir::with_prefix("deduplicator_test_root").unwrap(),
+        );
+        for (root, name) in [
+            (&first, "a.txt"),
+            (&first, "tmp/b.txt"),
+            (&second, "c.txt"),
+            (&second, "tmp/d.txt"),
+        ] {
+            let path = root.path().join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            File::create_new(path).unwrap().write_all(b"test").unwrap();
+        }
+
+        let roots = [
+            format!("{}:exclude=tmp/**,threads=1", first.path().display()),
+            second.path().display().to_string(),
+        ];
+        let params = Params {
+            min_size: Some("0b".to_string()),
+            roots: roots.iter().map(|root| root.parse().unwrap()).collect(),
+            ..Default::default()
+        };
+        let (sender, scanlist) = crossbeam_channel::unbounded();
+        Scanner::new(Arc::new(params))
+            .expect("scanner initialization failed")
+            .scan(sender, Arc::new(MultiProgress::new()))
+            .expect("scanning failed.");
+
+        let mut names = scanlist
+            .iter()
+            .map(|f| f.path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(names, ["a.txt", "c.txt", "d.txt"]);
+    }
 }
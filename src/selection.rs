@@ -0,0 +1,73 @@
+use anyhow::Result;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use regex_automata::meta::Regex;
+use std::path::Path;
+
+const REGEX_PREFIX: &str = "re:";
+
+/// Files picked by a pattern across the groups of the triage screen (see `Interactive`), to
+/// mark or keep them all at once. A glob matches the way `--include` does, but against the whole
+/// path: one without a `/` (e.g., `*.RAW`) at any depth, one starting with `/` from the root,
+/// and one matching a directory (e.g., `/mnt/backup`) every file below it. A pattern starting
+/// with `re:` is a regular expression, found anywhere in the path.
+pub enum Selection {
+    Glob(GlobSet),
+    Regex(Box<Regex>),
+}
+
+impl Selection {
+    pub fn parse(pattern: &str) -> Result<Self> {
+        if let Some(regex) = pattern.strip_prefix(REGEX_PREFIX) {
+            return Ok(Self::Regex(Box::new(Regex::new(regex)?)));
+        }
+
+        let glob = pattern.trim().trim_end_matches('/');
+        anyhow::ensure!(!glob.is_empty(), "no pattern given");
+        let glob = match glob.starts_with('/') {
+            true => glob.to_string(),
+            false => format!("**/{glob}"),
+        };
+        let mut set = GlobSetBuilder::new();
+        for pattern in [glob.clone(), format!("{glob}/**")] {
+            set.add(GlobBuilder::new(&pattern).literal_separator(true).build()?);
+        }
+        Ok(Self::Glob(set.build()?))
+    }
+
+    pub fn matches(&self, path: &Path) -> bool {
+        match self {
+            Self::Glob(set) => set.is_match(path),
+            Self::Regex(regex) => regex.is_match(path.to_string_lossy().as_ref()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Selection;
+    use std::path::Path;
+
+    #[test]
+    fn globs_pick_names_and_directories_and_regexes_whole_paths() {
+        let picked = |pattern: &str| {
+            let selection = Selection::parse(pattern).unwrap();
+            [
+                "/mnt/backup/2024/IMG_1.RAW",
+                "/mnt/backup/2024/IMG_1.jpg",
+                "/home/me/Pictures/IMG_1.RAW",
+                "/home/me/backup.txt",
+            ]
+            .into_iter()
+            .filter(|path| selection.matches(Path::new(path)))
+            .collect::<Vec<&str>>()
+        };
+
+        assert_eq!(picked("*.RAW"), ["/mnt/backup/2024/IMG_1.RAW", "/home/me/Pictures/IMG_1.RAW"]);
+        assert_eq!(picked("/mnt/backup"), ["/mnt/backup/2024/IMG_1.RAW", "/mnt/backup/2024/IMG_1.jpg"]);
+        assert_eq!(picked("backup/*/*.jpg"), ["/mnt/backup/2024/IMG_1.jpg"]);
+        assert_eq!(picked("Pictures/"), ["/home/me/Pictures/IMG_1.RAW"]);
+        assert_eq!(picked(r"re:/(home|mnt)/.*\.txt$"), ["/home/me/backup.txt"]);
+        assert!(Selection::parse("re:(").is_err());
+        assert!(Selection::parse(" ").is_err());
+    }
+}
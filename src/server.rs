@@ -1,9 +1,17 @@
+use std::collections::HashMap;
 use std::sync::atomic::{AtomicBool, AtomicU64};
+use std::sync::mpsc::{self, Receiver, Sender};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
-use crate::processor::Processor;
+use crate::cache::HashCache;
+use crate::checkpoint::Checkpoint;
+use crate::events::Event;
+use crate::heartbeat::{Heartbeat, Stage};
+use crate::modes::ModeComparison;
+use crate::processor::{ConfirmedGroup, Hashing, Processor, StagingCopies};
 use crate::scanner::Scanner;
-use anyhow::Result;
+use anyhow::{Context, Result};
 use dashmap::DashMap;
 use indicatif::{MultiProgress, ProgressDrawTarget};
 use rand::Rng;
@@ -12,48 +20,142 @@ use threadpool::ThreadPool;
 use crate::fileinfo::{FileInfo, FileSource};
 use crate::params::Params;
 
+/// Files scanned ahead of the size bucketer, and buckets announced ahead of the hasher, before
+/// the producing stage blocks.
+const FILE_QUEUE_CAPACITY: usize = 4096;
+const BUCKET_QUEUE_CAPACITY: usize = 1024;
+/// One thread per pipeline stage (scanner, size bucketer, hasher & group streaming): each blocks
+/// on the one before it, so they must all run at once. The work within a stage is spread over
+/// pools sized by `--threads` and the per-stage options instead.
+const PIPELINE_STAGES: usize = 4;
+
+/// Raises a stage's finished flag once the stage is over, whether it returned, failed or
+/// panicked, so that the stages polling the flag never wait on a stage that is gone.
+struct Finished(Arc<AtomicBool>);
+
+impl Drop for Finished {
+    fn drop(&mut self) {
+        self.0.store(true, std::sync::atomic::Ordering::Relaxed);
+    }
+}
+
 pub struct Server {
-    filequeue: Arc<Mutex<Vec<FileInfo>>>,
-    sw_duplicate_set: Arc<DashMap<u64, Vec<FileInfo>>>,
-    pub hw_duplicate_set: Arc<DashMap<u128, Vec<FileInfo>>>,
+    /// Every file scanned, by size.
+    pub sw_duplicate_set: Arc<DashMap<u64, Vec<Arc<FileInfo>>>>,
+    pub hw_duplicate_set: Arc<DashMap<u128, Vec<Arc<FileInfo>>>>,
     threadpool: ThreadPool,
     app_args: Arc<Params>,
     pub max_file_path_len: Arc<AtomicU64>,
+    pub staging_files: Mutex<Vec<Arc<FileInfo>>>,
+    pub skipped_staging: AtomicU64,
+    pub staging_copies: Mutex<StagingCopies>,
+    pub verification_warnings: Mutex<Vec<String>>,
+    pub mode_comparison: Mutex<ModeComparison>,
+    group_sender: Mutex<Option<Sender<ConfirmedGroup>>>,
 }
 
 impl Server {
     pub fn new(opts: Params) -> Self {
         Self {
-            filequeue: Arc::new(Mutex::new(Vec::new())),
             sw_duplicate_set: Arc::new(DashMap::new()),
             hw_duplicate_set: Arc::new(DashMap::new()),
-            threadpool: ThreadPool::new(4),
+            threadpool: ThreadPool::new(PIPELINE_STAGES),
             app_args: Arc::new(opts),
             max_file_path_len: Arc::new(AtomicU64::new(0)),
+            staging_files: Mutex::new(Vec::new()),
+            skipped_staging: AtomicU64::new(0),
+            staging_copies: Mutex::new(HashMap::new()),
+            verification_warnings: Mutex::new(Vec::new()),
+            mode_comparison: Mutex::new(ModeComparison::default()),
+            group_sender: Mutex::new(None),
         }
     }
 
-    pub fn start(&self) -> Result<()> {
+    /// Makes the next `start` publish duplicate groups as soon as they are confirmed, while the
+    /// scan is still running. The channel closes once hashing has finished.
+    pub fn stream_groups(&self) -> Receiver<ConfirmedGroup> {
+        let (sender, receiver) = mpsc::channel();
+        *self.group_sender.lock().unwrap() = Some(sender);
+        receiver
+    }
+
+    /// Runs the scan, reporting its stages & progress to `heartbeat`.
+    pub fn start(&self, heartbeat: &Heartbeat) -> Result<()> {
+        // NOTE: taken up front so that an early error drops it and closes the stream.
+        let group_sender = self.group_sender.lock().unwrap().take();
+        let mut seed = self.app_args.seed.unwrap_or_else(|| rand::rng().random());
+        let resumed = match (&self.app_args.checkpoint, self.app_args.resume) {
+            (Some(path), true) => Some(Checkpoint::resume(path, &Checkpoint::fingerprint())?),
+            _ => None,
+        };
+        if let Some(resumed) = &resumed {
+            anyhow::ensure!(
+                self.app_args.seed.is_none_or(|pinned| pinned == resumed.seed()),
+                "--seed differs from the seed of the run to resume ({})",
+                resumed.seed()
+            );
+            seed = resumed.seed();
+        }
+        let pinned = self.app_args.seed.is_some() || resumed.is_some();
+        let cache = match (&self.app_args.cache, pinned) {
+            (Some(path), true) => Some(HashCache::open_seeded(
+                path,
+                self.app_args.cache_invalidate,
+                seed,
+                self.app_args.algorithm,
+            )?),
+            (Some(path), false) => Some(HashCache::open(
+                path,
+                self.app_args.cache_invalidate,
+                seed,
+                self.app_args.algorithm,
+            )?),
+            (None, _) => None,
+        }
+        .map(Arc::new);
+        // NOTE: cached hashes are only comparable when computed with the seed stored alongside.
+        let seed: i64 = match &cache {
+            Some(cache) => cache.seed(),
+            None => seed,
+        };
+        let checkpoint = match (resumed, &self.app_args.checkpoint) {
+            (Some(resumed), _) => Some(resumed),
+            (None, Some(path)) => Some(Checkpoint::create(path, seed, &Checkpoint::fingerprint())?),
+            (None, None) => None,
+        }
+        .map(Arc::new);
+
+        let (file_sender, file_receiver) = crossbeam_channel::bounded(FILE_QUEUE_CAPACITY);
+        let (bucket_sender, bucket_receiver) = crossbeam_channel::bounded(BUCKET_QUEUE_CAPACITY);
+
         // In comparison mode, scan both staging and target directories first
-        if self.app_args.comparison_mode {
-            let staging_dir = self.app_args.get_staging_directory()?;
-            let target_dir = self.app_args.get_target_directory()?;
-
-            let scanner = Scanner::build(&self.app_args)?;
-            
-            let mut staging_files = scanner.scan_with_source(staging_dir, FileSource::Staging)?;
-            let mut target_files = scanner.scan_with_source(target_dir, FileSource::Target)?;
-
-            // Combine all files and populate the queue
-            staging_files.append(&mut target_files);
-            {
-                let mut queue = self.filequeue.lock().unwrap();
-                *queue = staging_files;
+        let comparison_files = match self.app_args.comparison_mode {
+            true => {
+                let staging_dir = self.app_args.get_staging_directory()?;
+                let target_dir = self.app_args.get_target_directory()?;
+
+                let scanner = Scanner::build(&self.app_args)?;
+
+                let staging_files = scanner.scan_with_source(staging_dir, FileSource::Staging)?;
+                let target_files = scanner.scan_with_source(target_dir, FileSource::Target)?;
+                *self.staging_files.lock().unwrap() = staging_files.clone();
+
+                // Only sizes found in both trees can hold a match, nothing else gets hashed
+                let (staging_files, mut target_files, skipped) =
+                    Processor::retain_shared_sizes(staging_files, target_files);
+                self.skipped_staging.store(skipped, std::sync::atomic::Ordering::Relaxed);
+
+                // Only one copy of each staging content is compared against target
+                let (mut staging_files, staging_copies) =
+                    Processor::collapse_staging(staging_files, &self.app_args, seed);
+                *self.staging_copies.lock().unwrap() = staging_copies;
+
+                staging_files.append(&mut target_files);
+                Some(staging_files)
             }
-        }
+            false => None,
+        };
         let progbarbox = Arc::new(MultiProgress::new());
-        let mut rng = rand::rng();
-        let seed: i64 = rng.random();
 
         if !self.app_args.progress {
             progbarbox.set_draw_target(ProgressDrawTarget::hidden());
@@ -64,21 +166,15 @@ impl Server {
             Arc::clone(&self.app_args),
             Arc::clone(&self.app_args),
         );
-        let (file_queue_sc, file_queue_pr) = (
-            Arc::clone(&self.filequeue),
-            Arc::clone(&self.filequeue),
-        );
-        
-        let scanner_finished = Arc::new(AtomicBool::new(self.app_args.comparison_mode));
         let sw_sort_finished = Arc::new(AtomicBool::new(false));
-        let (sfin_sc, sfin_pr) = (
-            Arc::clone(&scanner_finished),
-            Arc::clone(&scanner_finished),
-        );
-        let (swfin_pr_sw, swfin_pr_hw) = (
-            Arc::clone(&sw_sort_finished),
+        heartbeat.watch(
+            Arc::clone(&self.sw_duplicate_set),
+            Arc::clone(&self.hw_duplicate_set),
             Arc::clone(&sw_sort_finished),
         );
+        heartbeat.stage(Stage::Scanning);
+        self.app_args.events.emit(|| Event::Stage(Stage::Scanning));
+        let swfin_pr_sw = Arc::clone(&sw_sort_finished);
         let (store_sw, store_sw2, store_hw) = (
             Arc::clone(&self.sw_duplicate_set),
             Arc::clone(&self.sw_duplicate_set),
@@ -91,46 +187,130 @@ impl Server {
             Arc::clone(&progbarbox),
         );
 
-        if !self.app_args.comparison_mode {
-            self.threadpool.execute(move || {
-                Scanner::new(app_args_sc)
-                    .expect("unable to initialize scanner.")
-                    .scan(file_queue_sc, prog_sc)
-                    .expect("scanner failed.");
+        // NOTE: a resumed run whose walk was complete lists the files it found instead.
+        let listed = checkpoint.as_ref().and_then(|checkpoint| checkpoint.scanned());
+        let checkpoint_hw = checkpoint.clone();
 
-                sfin_sc.store(true, std::sync::atomic::Ordering::Relaxed);
-            });
-        }
+        // NOTE: stage errors, the first of which is handed back once every stage has stopped.
+        let failures: Arc<Mutex<Vec<anyhow::Error>>> = Arc::new(Mutex::new(Vec::new()));
+        let (failures_sc, failures_sw, failures_st, failures_hw) = (
+            Arc::clone(&failures),
+            Arc::clone(&failures),
+            Arc::clone(&failures),
+            Arc::clone(&failures),
+        );
+
+        // Each stage hangs up once done, which ends the stage after it.
+        self.threadpool.execute(move || {
+            let scanned = match comparison_files {
+                Some(files) => {
+                    let _ = files.into_iter().try_for_each(|file| file_sender.send(file));
+                    Ok(())
+                }
+                None => Scanner::new(app_args_sc)
+                    .context("unable to initialize scanner")
+                    .and_then(|scanner| scanner.with_listed(listed).scan(file_sender, prog_sc))
+                    .context("scanner failed"),
+            };
+            if let Err(error) = scanned {
+                failures_sc.lock().unwrap().push(error);
+            }
+        });
 
         self.threadpool.execute(move || {
-            Processor::sizewise(
+            let _finished = Finished(swfin_pr_sw);
+            if let Err(error) = Processor::sizewise(
                 app_args_sw,
-                sfin_pr,
+                file_receiver,
                 store_sw,
-                file_queue_pr,
+                bucket_sender,
                 prog_sw,
-            )
-            .expect("sizewise scanner failed.");
-
-            swfin_pr_sw.store(true, std::sync::atomic::Ordering::Relaxed);
+            ) {
+                failures_sw.lock().unwrap().push(error.context("sizewise scanner failed"));
+            }
         });
 
+        let hw_sort_finished = Arc::new(AtomicBool::new(false));
+        let hwfin_pr = Arc::clone(&hw_sort_finished);
+
+        // NOTE: groups are announced to event subscribers as they are found, streamed or not.
+        if group_sender.is_some() || self.app_args.events.active() {
+            let (app_args_st, store_sw_st, store_hw_st, swfin_st, hwfin_st) = (
+                Arc::clone(&self.app_args),
+                Arc::clone(&self.sw_duplicate_set),
+                Arc::clone(&self.hw_duplicate_set),
+                Arc::clone(&sw_sort_finished),
+                Arc::clone(&hw_sort_finished),
+            );
+            self.threadpool.execute(move || {
+                if let Err(error) = Processor::stream_confirmed(
+                    app_args_st,
+                    store_sw_st,
+                    store_hw_st,
+                    swfin_st,
+                    hwfin_st,
+                    group_sender,
+                ) {
+                    failures_st.lock().unwrap().push(error.context("group streaming failed"));
+                }
+            });
+        }
+
         self.threadpool.execute(move || {
-            Processor::hashwise(
+            // NOTE: raised on unwind too, or group streaming would poll for hashing forever.
+            let _finished = Finished(hwfin_pr);
+            if let Err(error) = Processor::hashwise(
                 app_args_hw,
                 store_sw2,
                 store_hw,
                 prog_hw,
                 max_file_path_len,
-                seed,
-                swfin_pr_hw,
-            )
-            .expect("hashwise scanner failed.");
+                Hashing {
+                    seed,
+                    cache,
+                    checkpoint: checkpoint_hw,
+                },
+                bucket_receiver,
+            ) {
+                failures_hw.lock().unwrap().push(error.context("hashwise scanner failed"));
+            }
+        });
+
+        let (stop_saving, stop) = crossbeam_channel::bounded::<()>(0);
+        let saver = checkpoint.map(|checkpoint| {
+            let (sizes, scanned) = (
+                Arc::clone(&self.sw_duplicate_set),
+                Arc::clone(&sw_sort_finished),
+            );
+            let interval = Duration::from_secs(self.app_args.checkpoint_interval);
+            std::thread::spawn(move || checkpoint.keep_saving(interval, &sizes, &scanned, stop))
         });
 
         progbarbox.clear()?;
 
         self.threadpool.join();
+        drop(stop_saving);
+        if let Some(saver) = saver {
+            let _ = saver.join();
+        }
+        if let Some(failure) = failures.lock().unwrap().drain(..).next() {
+            return Err(failure);
+        }
+        anyhow::ensure!(
+            self.threadpool.panic_count() == 0,
+            "a stage of the scan panicked, the scan is incomplete"
+        );
+
+        let (warnings, comparison) = Processor::verify_groups(
+            Arc::clone(&self.app_args),
+            Arc::clone(&self.hw_duplicate_set),
+            Arc::clone(&progbarbox),
+        )?;
+        *self.verification_warnings.lock().unwrap() = warnings;
+        *self.mode_comparison.lock().unwrap() = comparison;
+        progbarbox.clear()?;
+        heartbeat.stage(Stage::Reporting);
+        self.app_args.events.emit(|| Event::Stage(Stage::Reporting));
 
         Ok(())
     }
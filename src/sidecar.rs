@@ -0,0 +1,156 @@
+use anyhow::Result;
+use clap::ValueEnum;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+const SIDECAR_EXTENSIONS: [&str; 4] = ["xmp", "srt", "nfo", "cue"];
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum SidecarAction {
+    /// Leave sidecar files untouched
+    #[default]
+    Keep,
+    /// Delete sidecar files together with the media file they describe (moved along with --move-to)
+    Delete,
+}
+
+pub struct Sidecar;
+
+impl Sidecar {
+    pub fn is_sidecar(path: &Path) -> bool {
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| {
+                SIDECAR_EXTENSIONS
+                    .iter()
+                    .any(|known| known.eq_ignore_ascii_case(ext))
+            })
+    }
+
+    /// Sidecars of `media` are siblings with a sidecar extension whose name starts with the media
+    /// file's stem, e.g. `IMG_01.xmp`, `IMG_01.jpg.xmp` or `movie.en.srt`.
+    pub fn find(media: &Path) -> Vec<PathBuf> {
+        let (Some(parent), Some(stem)) = (media.parent(), media.file_stem()) else {
+            return vec![];
+        };
+
+        let prefix = format!("{}.", stem.to_string_lossy());
+
+        fs::read_dir(parent)
+            .map(|entries| {
+                entries
+                    .filter_map(Result::ok)
+                    .map(|entry| entry.path())
+                    .filter(|path| path.as_path() != media && Self::is_sidecar(path))
+                    .filter(|path| {
+                        path.file_name()
+                            .is_some_and(|name| name.to_string_lossy().starts_with(&prefix))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// A sidecar is only orphaned once no other media file sharing the stem remains, so that
+    /// `IMG_01.xmp` survives as long as `IMG_01.raw` is still around.
+    fn is_orphaned(sidecar: &Path, media: &Path) -> bool {
+        let (Some(parent), Some(stem)) = (media.parent(), media.file_stem()) else {
+            return false;
+        };
+
+        let prefix = format!("{}.", stem.to_string_lossy());
+
+        fs::read_dir(parent)
+            .map(|entries| {
+                !entries
+                    .filter_map(Result::ok)
+                    .map(|entry| entry.path())
+                    .filter(|path| path.as_path() != media && path.as_path() != sidecar)
+                    .filter(|path| !Self::is_sidecar(path))
+                    .any(|path| {
+                        path.file_name()
+                            .is_some_and(|name| name.to_string_lossy().starts_with(&prefix))
+                    })
+            })
+            .unwrap_or(false)
+    }
+
+    /// Disposes of the sidecars of a media file that has just been removed as a duplicate, the
+    /// same way the media file itself was (deleted or moved). Returns the outcome per sidecar.
+    pub fn follow<F>(media: &Path, action: SidecarAction, dispose: F) -> Vec<(PathBuf, Result<()>)>
+    where
+        F: Fn(&Path) -> Result<()>,
+    {
+        match action {
+            SidecarAction::Keep => vec![],
+            SidecarAction::Delete => Self::find(media)
+                .into_iter()
+                .filter(|sidecar| Self::is_orphaned(sidecar, media))
+                .map(|sidecar| {
+                    let outcome = dispose(&sidecar);
+                    (sidecar, outcome)
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Sidecar, SidecarAction};
+    use crate::{params::Params, removal::Removal};
+    use anyhow::Result;
+    use std::fs::File;
+    use tempfile::TempDir;
+
+    #[test]
+    fn finds_sidecars_sharing_the_media_stem() -> Result<()> {
+        let root = TempDir::new()?;
+        for name in ["IMG_01.jpg", "IMG_01.xmp", "IMG_01.jpg.xmp", "IMG_02.xmp", "IMG_01.txt"] {
+            File::create_new(root.path().join(name))?;
+        }
+
+        let mut found = Sidecar::find(&root.path().join("IMG_01.jpg"));
+        found.sort();
+
+        assert_eq!(
+            found,
+            vec![
+                root.path().join("IMG_01.jpg.xmp"),
+                root.path().join("IMG_01.xmp")
+            ]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_sidecars_still_referenced_by_another_media_file() -> Result<()> {
+        let root = TempDir::new()?;
+        for name in ["IMG_01.jpg", "IMG_01.raw", "IMG_01.xmp", "movie.mkv", "movie.en.srt"] {
+            File::create_new(root.path().join(name))?;
+        }
+
+        std::fs::remove_file(root.path().join("IMG_01.jpg"))?;
+        let touched = Sidecar::follow(
+            &root.path().join("IMG_01.jpg"),
+            SidecarAction::Delete,
+            |sidecar| Removal::Delete.remove(sidecar, &Params::default()),
+        );
+        assert!(touched.is_empty());
+        assert!(root.path().join("IMG_01.xmp").exists());
+
+        std::fs::remove_file(root.path().join("movie.mkv"))?;
+        let touched = Sidecar::follow(
+            &root.path().join("movie.mkv"),
+            SidecarAction::Delete,
+            |sidecar| Removal::Delete.remove(sidecar, &Params::default()),
+        );
+        assert_eq!(touched.len(), 1);
+        assert!(!root.path().join("movie.en.srt").exists());
+
+        Ok(())
+    }
+}
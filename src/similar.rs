@@ -0,0 +1,244 @@
+use dashmap::DashMap;
+use png::{ColorType, Transformations};
+use rayon::prelude::*;
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::Read,
+    path::Path,
+    sync::Arc,
+};
+use zune_jpeg::{
+    zune_core::{colorspace::ColorSpace, options::DecoderOptions},
+    JpegDecoder,
+};
+
+use crate::{archive::Archive, fileinfo::FileInfo};
+
+/// Extensions of the images compared, which are decoded in full.
+const IMAGE_EXTENSIONS: [&str; 5] = ["jpg", "jpeg", "jpe", "jfif", "png"];
+/// Largest side decoded, in pixels; larger images are left out rather than held in memory.
+const MAX_SIDE: usize = 16_384;
+/// Rows of the grid an image is shrunk to, each a column wider as every bit compares a cell
+/// with the one to its right.
+const ROWS: usize = 8;
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// Images that look alike, each with the distance of its perceptual hash to the first one's.
+pub type SimilarGroup = Vec<(Arc<FileInfo>, u32)>;
+
+/// Images that look the same although their bytes differ: another format, resolution,
+/// compression or metadata (see `--images-similar`). Each image gets a 64-bit difference hash
+/// (dHash) of its brightness shrunk to 9x8 cells, and images whose hashes are at most
+/// `--similarity-threshold` bits apart end up in one group, with the images close to any of
+/// its members. Only JPEG & PNG files are decoded.
+pub struct SimilarImages;
+
+impl SimilarImages {
+    /// Groups of similar images among `scanned`, by path of their first image. Groups whose
+    /// images all hold the same bytes are left out, `exact` reports them already.
+    pub fn find(
+        scanned: &DashMap<u64, Vec<Arc<FileInfo>>>,
+        exact: &DashMap<u128, Vec<Arc<FileInfo>>>,
+        threshold: u32,
+    ) -> Vec<SimilarGroup> {
+        let images = scanned
+            .iter()
+            .flat_map(|bucket| bucket.value().clone())
+            .filter(|file| Self::is_image(&file.path))
+            .collect::<Vec<Arc<FileInfo>>>();
+        let mut hashed = images
+            .into_par_iter()
+            .filter_map(|file| Self::dhash(&file.path).map(|hash| (file, hash)))
+            .collect::<Vec<(Arc<FileInfo>, u64)>>();
+        hashed.sort_by(|(a, _), (b, _)| a.path.cmp(&b.path));
+
+        let contents = exact
+            .iter()
+            .filter(|group| group.value().len() > 1)
+            .flat_map(|group| {
+                let hash = *group.key();
+                group
+                    .value()
+                    .iter()
+                    .map(|file| (file.path.clone(), hash))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<HashMap<_, u128>>();
+
+        Self::clusters(&hashed.iter().map(|(_, hash)| *hash).collect::<Vec<u64>>(), threshold)
+            .into_iter()
+            .filter(|members| {
+                let first = contents.get(&hashed[members[0]].0.path);
+                first.is_none() || members.iter().any(|&i| contents.get(&hashed[i].0.path) != first)
+            })
+            .map(|members| {
+                let first = hashed[members[0]].1;
+                members
+                    .into_iter()
+                    .map(|i| (Arc::clone(&hashed[i].0), (hashed[i].1 ^ first).count_ones()))
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Indices of the hashes linked by distances of at most `threshold`, in order of their
+    /// first index, groups of one left out.
+    fn clusters(hashes: &[u64], threshold: u32) -> Vec<Vec<usize>> {
+        let mut parent = (0..hashes.len()).collect::<Vec<usize>>();
+        fn root(parent: &mut [usize], mut i: usize) -> usize {
+            while parent[i] != i {
+                parent[i] = parent[parent[i]];
+                i = parent[i];
+            }
+            i
+        }
+        for i in 0..hashes.len() {
+            for j in i + 1..hashes.len() {
+                if (hashes[i] ^ hashes[j]).count_ones() <= threshold {
+                    let (a, b) = (root(&mut parent, i), root(&mut parent, j));
+                    parent[a.max(b)] = a.min(b);
+                }
+            }
+        }
+
+        let mut clusters = BTreeMap::<usize, Vec<usize>>::new();
+        for i in 0..hashes.len() {
+            let cluster = root(&mut parent, i);
+            clusters.entry(cluster).or_default().push(i);
+        }
+        clusters.into_values().filter(|members| members.len() > 1).collect()
+    }
+
+    fn is_image(path: &Path) -> bool {
+        path.extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| IMAGE_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str()))
+    }
+
+    /// The difference hash of the image at `path`, if it decodes.
+    pub fn dhash(path: &Path) -> Option<u64> {
+        let mut bytes = vec![];
+        Archive::open(path).ok()?.read_to_end(&mut bytes).ok()?;
+        let (luma, width, height) = match bytes.get(..8)? {
+            [0xff, 0xd8, ..] => Self::jpeg_luma(&bytes)?,
+            magic if magic == PNG_SIGNATURE => Self::png_luma(&bytes)?,
+            _ => return None,
+        };
+        if width == 0 || height == 0 {
+            return None;
+        }
+
+        let cells = Self::shrink(&luma, width, height);
+        Some((0..ROWS).flat_map(|row| (0..ROWS).map(move |column| (row, column))).fold(
+            0u64,
+            |hash, (row, column)| (hash << 1) | (cells[row][column] < cells[row][column + 1]) as u64,
+        ))
+    }
+
+    /// Mean brightness of each of the 9x8 cells `luma` is split into.
+    fn shrink(luma: &[u8], width: usize, height: usize) -> [[u64; ROWS + 1]; ROWS] {
+        let span = |cell: usize, cells: usize, side: usize| {
+            let start = cell * side / cells;
+            start..((cell + 1) * side / cells).max(start + 1).min(side)
+        };
+        let mut cells = [[0u64; ROWS + 1]; ROWS];
+        for (row, cells) in cells.iter_mut().enumerate() {
+            for (column, cell) in cells.iter_mut().enumerate() {
+                let (rows, columns) = (span(row, ROWS, height), span(column, ROWS + 1, width));
+                let count = (rows.len() * columns.len()).max(1) as u64;
+                let sum = rows
+                    .flat_map(|y| columns.clone().map(move |x| luma[y * width + x] as u64))
+                    .sum::<u64>();
+                *cell = sum / count;
+            }
+        }
+        cells
+    }
+
+    fn jpeg_luma(bytes: &[u8]) -> Option<(Vec<u8>, usize, usize)> {
+        let options = DecoderOptions::default()
+            .jpeg_set_out_colorspace(ColorSpace::Luma)
+            .set_max_width(MAX_SIDE)
+            .set_max_height(MAX_SIDE);
+        let mut decoder = JpegDecoder::new_with_options(bytes, options);
+        let luma = decoder.decode().ok()?;
+        let (width, height) = decoder.dimensions()?;
+        (luma.len() == width * height).then_some((luma, width, height))
+    }
+
+    fn png_luma(bytes: &[u8]) -> Option<(Vec<u8>, usize, usize)> {
+        let mut decoder = png::Decoder::new(bytes);
+        decoder.set_transformations(Transformations::EXPAND | Transformations::STRIP_16);
+        let mut reader = decoder.read_info().ok()?;
+        if reader.info().width.max(reader.info().height) as usize > MAX_SIDE {
+            return None;
+        }
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let frame = reader.next_frame(&mut buffer).ok()?;
+        let (width, height) = (frame.width as usize, frame.height as usize);
+
+        let channels = frame.color_type.samples();
+        let luma = buffer[..frame.buffer_size()]
+            .chunks_exact(channels)
+            .map(|pixel| match frame.color_type {
+                ColorType::Rgb | ColorType::Rgba => {
+                    ((pixel[0] as u32 * 299 + pixel[1] as u32 * 587 + pixel[2] as u32 * 114) / 1000) as u8
+                }
+                _ => pixel[0],
+            })
+            .collect::<Vec<u8>>();
+        (luma.len() == width * height).then_some((luma, width, height))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SimilarImages;
+    use crate::fileinfo::FileInfo;
+    use anyhow::Result;
+    use dashmap::DashMap;
+    use std::{fs, path::Path, sync::Arc};
+    use tempfile::TempDir;
+
+    /// A grayscale gradient of `width` x `height` pixels, brightest at `bright_x`.
+    fn png(path: &Path, width: u32, height: u32, bright_x: u32) -> Result<()> {
+        let mut encoder = png::Encoder::new(fs::File::create(path)?, width, height);
+        encoder.set_color(png::ColorType::Grayscale);
+        let pixels = (0..height)
+            .flat_map(|_| (0..width).map(move |x| 255 - (x.abs_diff(bright_x) * 255 / width) as u8))
+            .collect::<Vec<u8>>();
+        encoder.write_header()?.write_image_data(&pixels)?;
+        Ok(())
+    }
+
+    #[test]
+    fn resized_images_are_similar_and_others_are_not() -> Result<()> {
+        let root = TempDir::new()?;
+        png(&root.path().join("photo.png"), 180, 120, 40)?;
+        png(&root.path().join("photo-small.png"), 90, 60, 20)?;
+        png(&root.path().join("other.png"), 180, 120, 150)?;
+        fs::write(root.path().join("broken.png"), b"not an image")?;
+        let scanned = DashMap::new();
+        for name in ["photo.png", "photo-small.png", "other.png", "broken.png"] {
+            let file = Arc::new(FileInfo::new(root.path().join(name))?);
+            scanned.entry(file.size).or_insert_with(Vec::new).push(file);
+        }
+
+        let groups = SimilarImages::find(&scanned, &DashMap::new(), 4);
+
+        assert_eq!(groups.len(), 1);
+        let names = groups[0]
+            .iter()
+            .map(|(file, _)| file.path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect::<Vec<String>>();
+        assert_eq!(names, ["photo-small.png", "photo.png"]);
+        assert!(groups[0][1].1 <= 4);
+        assert_eq!(SimilarImages::dhash(&root.path().join("broken.png")), None);
+
+        let exact = DashMap::new();
+        exact.insert(1u128, groups[0].iter().map(|(file, _)| Arc::clone(file)).collect());
+        assert!(SimilarImages::find(&scanned, &exact, 4).is_empty());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,317 @@
+use anyhow::Result;
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    ffi::OsString,
+    fs,
+    io::{self, BufReader, BufWriter, Read, Write},
+    iter::Peekable,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use crate::{
+    fileinfo::{FileInfo, FileSource, FileState},
+    rundir::Scratch,
+};
+
+/// Memory a file held for sorting takes besides its path: the shared `FileInfo`, its state &
+/// the allocator's bookkeeping.
+const FILE_OVERHEAD: usize =
+    std::mem::size_of::<(u64, Arc<FileInfo>)>() + std::mem::size_of::<FileInfo>() + 64;
+
+/// Files with their bucket key, in order of key then path.
+type Sorted = Box<dyn Iterator<Item = Result<(u64, Arc<FileInfo>)>>>;
+
+/// Files grouped by size within a fixed memory budget (see `--max-memory`): once the files held
+/// exceed it, they are sorted by size bucket and written out as a run of their own, and the
+/// runs are merged back in the end. Only buckets of more than one file ever come back into
+/// memory, one after the other as they are hashed, whereas without a budget every file scanned
+/// stays there.
+pub struct SizeSpill {
+    dir: Scratch,
+    budget: usize,
+    held: Vec<(u64, Arc<FileInfo>)>,
+    held_bytes: usize,
+    runs: Vec<PathBuf>,
+}
+
+impl SizeSpill {
+    /// Spills to `dir`, removed once the buckets are merged back.
+    pub fn new(dir: Scratch, budget: usize) -> Result<Self> {
+        Ok(Self {
+            dir,
+            budget,
+            held: vec![],
+            held_bytes: 0,
+            runs: vec![],
+        })
+    }
+
+    /// Adds `file` to the bucket `key`.
+    pub fn push(&mut self, key: u64, file: Arc<FileInfo>) -> Result<()> {
+        self.held_bytes += Self::held_bytes(&file);
+        self.held.push((key, file));
+        if self.held_bytes > self.budget {
+            self.spill()?;
+        }
+        Ok(())
+    }
+
+    /// Memory `file` takes while held.
+    pub fn held_bytes(file: &FileInfo) -> usize {
+        file.path.as_os_str().len() + FILE_OVERHEAD
+    }
+
+    /// Every bucket of more than one file, in order of their keys, each file once.
+    pub fn buckets(mut self) -> Result<impl Iterator<Item = Result<(u64, Vec<Arc<FileInfo>>)>>> {
+        let files: Sorted = match self.runs.is_empty() {
+            true => {
+                Self::sort(&mut self.held);
+                Box::new(std::mem::take(&mut self.held).into_iter().map(Ok))
+            }
+            false => {
+                self.spill()?;
+                let runs = self
+                    .runs
+                    .iter()
+                    .map(|run| Ok(Run::open(run)?.peekable()))
+                    .collect::<Result<Vec<Peekable<Run>>>>()?;
+                Box::new(Merge::new(runs))
+            }
+        };
+        Ok(Buckets {
+            files: files.peekable(),
+            _dir: self.dir,
+        })
+    }
+
+    fn spill(&mut self) -> Result<()> {
+        Self::sort(&mut self.held);
+        let path = self.dir.path().join(format!("sizes-{}.bin", self.runs.len()));
+        let mut writer = BufWriter::new(fs::File::create(&path)?);
+        for (key, file) in self.held.drain(..) {
+            Run::write(&mut writer, key, &file)?;
+        }
+        writer.flush()?;
+        self.runs.push(path);
+        self.held_bytes = 0;
+        Ok(())
+    }
+
+    fn sort(files: &mut [(u64, Arc<FileInfo>)]) {
+        files.sort_unstable_by(|(a_key, a), (b_key, b)| a_key.cmp(b_key).then(a.path.cmp(&b.path)));
+    }
+}
+
+/// The files of a spilled run, in the order they were written.
+struct Run(BufReader<fs::File>);
+
+impl Run {
+    fn open(path: &Path) -> Result<Self> {
+        Ok(Self(BufReader::new(fs::File::open(path)?)))
+    }
+
+    /// Key, size, mtime, source, payload & path of `file`, which is all the hasher needs of it.
+    fn write(writer: &mut impl Write, key: u64, file: &FileInfo) -> Result<()> {
+        let mtime = file
+            .modified
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        let source = match file.source {
+            None => 0u8,
+            Some(FileSource::Staging) => 1,
+            Some(FileSource::Target) => 2,
+        };
+        let path = file.path.as_os_str().as_encoded_bytes();
+        writer.write_all(&key.to_le_bytes())?;
+        writer.write_all(&file.size.to_le_bytes())?;
+        writer.write_all(&mtime.to_le_bytes())?;
+        writer.write_all(&[source, file.payload.is_some() as u8])?;
+        writer.write_all(&file.payload.unwrap_or_default().to_le_bytes())?;
+        writer.write_all(&(path.len() as u64).to_le_bytes())?;
+        writer.write_all(path)?;
+        Ok(())
+    }
+
+    fn read(&mut self) -> Result<Option<(u64, Arc<FileInfo>)>> {
+        let mut key = [0u8; 8];
+        match self.0.read_exact(&mut key) {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            read => read?,
+        }
+        let (size, mtime) = (self.u64()?, u128::from_le_bytes(self.bytes::<16>()?));
+        let [source, has_payload] = self.bytes::<2>()?;
+        let payload = self.u64()?;
+        let mut path = vec![0u8; self.u64()? as usize];
+        self.0.read_exact(&mut path)?;
+        // SAFETY: written by `write` in this very run, from the encoded bytes of an OsStr.
+        let path = unsafe { OsString::from_encoded_bytes_unchecked(path) };
+
+        Ok(Some((
+            u64::from_le_bytes(key),
+            Arc::new(FileInfo {
+                path: PathBuf::from(path).into_boxed_path(),
+                size,
+                modified: UNIX_EPOCH + Duration::from_nanos(mtime as u64),
+                state: Arc::new(Mutex::new(FileState::Unprocessed)),
+                source: match source {
+                    1 => Some(FileSource::Staging),
+                    2 => Some(FileSource::Target),
+                    _ => None,
+                },
+                payload: (has_payload == 1).then_some(payload),
+            }),
+        )))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.bytes::<8>()?))
+    }
+
+    fn bytes<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let mut bytes = [0u8; N];
+        self.0.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+impl Iterator for Run {
+    type Item = Result<(u64, Arc<FileInfo>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read().transpose()
+    }
+}
+
+/// Sorted runs merged into one sorted sequence.
+struct Merge {
+    runs: Vec<Peekable<Run>>,
+    heads: BinaryHeap<Reverse<(u64, Box<Path>, usize)>>,
+}
+
+impl Merge {
+    fn new(runs: Vec<Peekable<Run>>) -> Self {
+        let mut merge = Self {
+            runs,
+            heads: BinaryHeap::new(),
+        };
+        (0..merge.runs.len()).for_each(|run| merge.queue(run));
+        merge
+    }
+
+    /// Queues the next file of `run`, if any; a read error is queued first so it comes out.
+    fn queue(&mut self, run: usize) {
+        match self.runs[run].peek() {
+            Some(Ok((key, file))) => self.heads.push(Reverse((*key, file.path.clone(), run))),
+            Some(Err(_)) => self.heads.push(Reverse((0, PathBuf::new().into_boxed_path(), run))),
+            None => {}
+        }
+    }
+}
+
+impl Iterator for Merge {
+    type Item = Result<(u64, Arc<FileInfo>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse((_, _, run)) = self.heads.pop()?;
+        let next = self.runs[run].next();
+        self.queue(run);
+        next
+    }
+}
+
+/// Files of the same key gathered, lone ones and repeated paths dropped.
+struct Buckets {
+    files: Peekable<Sorted>,
+    _dir: Scratch,
+}
+
+impl Iterator for Buckets {
+    type Item = Result<(u64, Vec<Arc<FileInfo>>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (key, file) = match self.files.next()? {
+                Ok(first) => first,
+                Err(e) => return Some(Err(e)),
+            };
+            let mut bucket = vec![file];
+            while let Some(Ok((next, _))) = self.files.peek() {
+                if *next != key {
+                    break;
+                }
+                if let Some(Ok((_, file))) = self.files.next() {
+                    if bucket.last().is_none_or(|last| last.path != file.path) {
+                        bucket.push(file);
+                    }
+                }
+            }
+            if bucket.len() > 1 {
+                return Some(Ok((key, bucket)));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SizeSpill;
+    use crate::{
+        fileinfo::{FileInfo, FileSource},
+        fixture::Fixture,
+        rundir::RunDir,
+    };
+    use anyhow::Result;
+    use std::sync::Arc;
+
+    #[test]
+    fn spilled_runs_merge_back_into_the_buckets_of_several_files() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let file = |name: &str, size: usize| {
+            fixture.sourced(name, &vec![1u8; size], FileSource::Target)
+        };
+        let files = [
+            file("c.bin", 3)?,
+            file("a.bin", 3)?,
+            file("lone.bin", 5)?,
+            file("x.bin", 1)?,
+            file("y.bin", 1)?,
+        ];
+
+        let run_dir = Arc::new(RunDir::create(Some(fixture.root()))?);
+        for budget in [1, usize::MAX] {
+            let mut spill = SizeSpill::new(run_dir.scratch("spill")?, budget)?;
+            for file in files.iter().chain(&files[..1]) {
+                spill.push(file.size, file.clone())?;
+            }
+            assert_eq!(spill.runs.len(), if budget == 1 { 6 } else { 0 });
+
+            let buckets = spill.buckets()?.collect::<Result<Vec<(u64, Vec<Arc<FileInfo>>)>>>()?;
+            let names = buckets
+                .iter()
+                .map(|(key, bucket)| {
+                    let names = bucket
+                        .iter()
+                        .map(|file| file.path.file_name().unwrap().to_string_lossy().into_owned())
+                        .collect::<Vec<String>>();
+                    (*key, names)
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(
+                names,
+                [
+                    (1, vec!["x.bin".to_string(), "y.bin".to_string()]),
+                    (3, vec!["a.bin".to_string(), "c.bin".to_string()]),
+                ]
+            );
+            let spilled = &buckets[1].1[1];
+            assert_eq!(spilled.source, Some(FileSource::Target));
+            assert_eq!(spilled.modified, files[0].modified);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,202 @@
+use anyhow::Result;
+use clap::Args;
+use colored::Colorize;
+use rand::seq::SliceRandom;
+use std::path::PathBuf;
+
+use crate::{
+    export::{Export, ExportGroup},
+    verify::Verifier,
+};
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct SpotCheckArgs {
+    /// Export written by an earlier run with --export
+    #[arg(value_hint = clap::ValueHint::FilePath, value_name = "export_path")]
+    pub export: PathBuf,
+    /// Number of groups to sample
+    #[arg(long, short = 'n', default_value_t = 20)]
+    pub samples: usize,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum GroupVerdict {
+    /// Every file was re-hashed and byte-compared to the first one.
+    Identical,
+    /// At least one file differs from the first one.
+    Different(PathBuf),
+    /// A file no longer exists or cannot be read.
+    Unreadable(PathBuf),
+}
+
+#[derive(Debug, Default)]
+pub struct SpotCheckReport {
+    pub population: usize,
+    pub sampled: usize,
+    pub identical: usize,
+    pub different: Vec<(String, PathBuf)>,
+    pub unreadable: Vec<(String, PathBuf)>,
+}
+
+impl SpotCheckReport {
+    /// Upper bound on the share of bad groups at 95% confidence. Without failures this is the
+    /// "rule of three" (3/n); otherwise a normal approximation around the observed rate.
+    pub fn error_bound(&self) -> f64 {
+        let checked = (self.identical + self.different.len()) as f64;
+        if checked == 0.0 {
+            return 1.0;
+        }
+
+        let rate = self.different.len() as f64 / checked;
+        let bound = match self.different.is_empty() {
+            true => 3.0 / checked,
+            false => rate + 1.96 * (rate * (1.0 - rate) / checked).sqrt(),
+        };
+        bound.min(1.0)
+    }
+}
+
+/// Audits an export before acting on it: re-reads a random sample of its groups in full and
+/// estimates how trustworthy the rest is.
+pub struct SpotCheck;
+
+impl SpotCheck {
+    pub fn run(args: &SpotCheckArgs) -> Result<()> {
+        let export = Export::read(&args.export)?;
+        let report = Self::check(&export, args.samples);
+
+        report
+            .different
+            .iter()
+            .for_each(|(hash, path)| println!("{} {hash}: {}", "DIFFERS:".red(), path.display()));
+        report.unreadable.iter().for_each(|(hash, path)| {
+            println!("{} {hash}: {}", "UNREADABLE:".yellow(), path.display())
+        });
+
+        println!(
+            "\nSampled {} of {} group(s): {} identical, {} different, {} unreadable.",
+            report.sampled,
+            report.population,
+            report.identical,
+            report.different.len(),
+            report.unreadable.len()
+        );
+
+        let line = format!(
+            "With 95% confidence at most {:.1}% of the exported groups are not true duplicates.",
+            report.error_bound() * 100.0
+        );
+        match report.different.is_empty() {
+            true => println!("{}", line.green()),
+            false => println!("{}", line.red()),
+        }
+
+        Ok(())
+    }
+
+    pub fn check(export: &Export, samples: usize) -> SpotCheckReport {
+        let mut sampled = export.groups.iter().collect::<Vec<&ExportGroup>>();
+        sampled.shuffle(&mut rand::rng());
+        sampled.truncate(samples);
+
+        let mut report = SpotCheckReport {
+            population: export.groups.len(),
+            sampled: sampled.len(),
+            ..Default::default()
+        };
+
+        sampled
+            .into_iter()
+            .for_each(|group| match Self::verdict(group) {
+                GroupVerdict::Identical => report.identical += 1,
+                GroupVerdict::Different(path) => report.different.push((group.hash.clone(), path)),
+                GroupVerdict::Unreadable(path) => {
+                    report.unreadable.push((group.hash.clone(), path))
+                }
+            });
+
+        report
+    }
+
+    /// Full re-hash with the independent verification hash, confirmed by a byte comparison.
+    fn verdict(group: &ExportGroup) -> GroupVerdict {
+        let Some(first) = group.files.first() else {
+            return GroupVerdict::Identical;
+        };
+        let Ok(reference) = Verifier::secondary_hash(first) else {
+            return GroupVerdict::Unreadable(first.clone());
+        };
+
+        for file in group.files.iter().skip(1) {
+            match Verifier::secondary_hash(file) {
+                Ok(fingerprint) if fingerprint != reference => {
+                    return GroupVerdict::Different(file.clone())
+                }
+                Ok(_) => match Verifier::same_bytes(first, file) {
+                    Ok(true) => {}
+                    Ok(false) => return GroupVerdict::Different(file.clone()),
+                    Err(_) => return GroupVerdict::Unreadable(file.clone()),
+                },
+                Err(_) => return GroupVerdict::Unreadable(file.clone()),
+            }
+        }
+
+        GroupVerdict::Identical
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GroupVerdict, SpotCheck};
+    use crate::export::{Export, ExportGroup};
+    use anyhow::Result;
+    use std::fs;
+    use tempfile::TempDir;
+
+    #[test]
+    fn flags_groups_whose_files_changed_since_the_export() -> Result<()> {
+        let root = TempDir::new()?;
+        for (name, content) in [("a", "same"), ("b", "same"), ("c", "same"), ("d", "diff")] {
+            fs::write(root.path().join(name), content)?;
+        }
+        let group = |names: &[&str]| ExportGroup {
+            hash: names.join(""),
+            size: 4,
+            content_type: None,
+            files: names.iter().map(|name| root.path().join(name)).collect(),
+            labels: vec![],
+            note: None,
+            tags: vec![],
+            keep: None,
+            actions: vec![],
+        };
+
+        assert_eq!(
+            SpotCheck::verdict(&group(&["a", "b"])),
+            GroupVerdict::Identical
+        );
+        assert_eq!(
+            SpotCheck::verdict(&group(&["c", "d"])),
+            GroupVerdict::Different(root.path().join("d"))
+        );
+        assert_eq!(
+            SpotCheck::verdict(&group(&["a", "missing"])),
+            GroupVerdict::Unreadable(root.path().join("missing"))
+        );
+
+        let export = Export {
+            version: 1,
+            created: String::new(),
+            run_id: String::new(),
+            groups: vec![group(&["a", "b"]), group(&["c", "d"])],
+            unique: vec![],
+            similar: vec![],
+        };
+        let report = SpotCheck::check(&export, 10);
+        assert_eq!(report.sampled, 2);
+        assert_eq!(report.identical, 1);
+        assert_eq!(report.different.len(), 1);
+
+        Ok(())
+    }
+}
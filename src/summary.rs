@@ -0,0 +1,83 @@
+use crate::{fileinfo::FileInfo, protection::Protection, runid::RunId};
+use dashmap::DashMap;
+use std::{fmt, ops::AddAssign, sync::Arc};
+
+/// Totals printed as the final `DEDUP_RESULT` line so scripts can pick up results without
+/// parsing the human readable report.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RunSummary {
+    pub groups: u64,
+    pub files: u64,
+    pub wasted: u64,
+    pub deleted: u64,
+    /// The part of `wasted` held by copies the filesystem will not let go (see `Protection`).
+    pub locked: u64,
+}
+
+impl RunSummary {
+    pub fn from_store(store: &DashMap<u128, Vec<Arc<FileInfo>>>) -> Self {
+        store
+            .iter()
+            .filter(|group| group.value().len() > 1)
+            .fold(Self::default(), |mut summary, group| {
+                let copies = group.value().len() as u64;
+                let size = group.value().first().map(|f| f.size).unwrap_or_default();
+                summary.groups += 1;
+                summary.files += copies;
+                let locked = group
+                    .value()
+                    .iter()
+                    .filter(|file| Protection::of(&file.path).is_some())
+                    .count() as u64;
+                summary.wasted += (copies - 1) * size;
+                summary.locked += (copies - 1 - Protection::removable_copies(copies, locked)) * size;
+                summary
+            })
+    }
+}
+
+impl AddAssign for RunSummary {
+    fn add_assign(&mut self, other: Self) {
+        self.groups += other.groups;
+        self.files += other.files;
+        self.wasted += other.wasted;
+        self.deleted += other.deleted;
+        self.locked += other.locked;
+    }
+}
+
+impl fmt::Display for RunSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "DEDUP_RESULT run_id={} groups={} files={} wasted={} deleted={} locked={}",
+            RunId::get(),
+            self.groups, self.files, self.wasted, self.deleted, self.locked
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RunSummary;
+    use crate::runid::RunId;
+
+    #[test]
+    fn renders_a_single_parseable_line() {
+        let summary = RunSummary {
+            groups: 123,
+            files: 456,
+            wasted: 789012345,
+            deleted: 0,
+            locked: 4096,
+        };
+
+        assert_eq!(
+            summary.to_string(),
+            format!(
+                "DEDUP_RESULT run_id={} groups=123 files=456 wasted=789012345 deleted=0 locked=4096",
+                RunId::get()
+            )
+        );
+    }
+}
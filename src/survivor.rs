@@ -0,0 +1,98 @@
+use anyhow::{Context, Result};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+};
+
+/// The one promise every removal keeps, whatever a plan, a review or the filesystem did in
+/// between: a group never loses its last copy. Removals are checked twice, once for all of them
+/// before the first one, as their kept copies may be removed in turn, and again on disk right
+/// before each, as a kept copy may have vanished since it was hashed.
+pub struct Survivor;
+
+impl Survivor {
+    /// Paths among `removals` that would take the last copy of their content with them: every
+    /// copy they are kept by is removed too, and so on, without one that stays in the end (a
+    /// plan where two files keep each other, or a file kept by itself).
+    pub fn orphaned<'a>(removals: &HashMap<&'a Path, Vec<&'a Path>>) -> HashSet<&'a Path> {
+        // NOTE: a removal is covered by a copy that stays, or by one covered in turn; repeated
+        // until nothing changes, so that cycles are never covered.
+        let mut covered = HashSet::new();
+        loop {
+            let newly = removals
+                .iter()
+                .filter(|(path, _)| !covered.contains(*path))
+                .filter(|(path, kept)| {
+                    kept.iter().any(|kept| {
+                        kept != *path && (!removals.contains_key(kept) || covered.contains(kept))
+                    })
+                })
+                .map(|(path, _)| *path)
+                .collect::<Vec<&Path>>();
+            if newly.is_empty() {
+                break;
+            }
+            covered.extend(newly);
+        }
+        removals.keys().filter(|path| !covered.contains(*path)).copied().collect()
+    }
+
+    /// The first of `kept` still on disk, right before `path` is removed, as a file of its own:
+    /// a kept copy that turns out to be `path` under another name (a symlink to it) goes with it.
+    /// Fails when there is none left.
+    pub fn ensure<'a>(path: &Path, kept: impl IntoIterator<Item = &'a Path>) -> Result<&'a Path> {
+        let removed = fs::canonicalize(path).ok();
+        kept.into_iter()
+            .find(|kept| {
+                fs::metadata(kept).is_ok_and(|metadata| metadata.is_file())
+                    && fs::canonicalize(kept).ok() != removed
+            })
+            .context("no copy would be left, the kept copy is gone")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Survivor;
+    use anyhow::Result;
+    use std::{
+        collections::{HashMap, HashSet},
+        fs,
+        path::Path,
+    };
+    use tempfile::TempDir;
+
+    #[test]
+    fn no_removal_takes_the_last_copy() -> Result<()> {
+        let path = Path::new;
+        let removals = HashMap::from([
+            (path("/a"), vec![path("/b")]),
+            (path("/b"), vec![path("/c")]),
+            (path("/x"), vec![path("/y")]),
+            (path("/y"), vec![path("/x")]),
+            (path("/self"), vec![path("/self")]),
+            (path("/staged"), vec![path("/y"), path("/target")]),
+        ]);
+        assert_eq!(
+            Survivor::orphaned(&removals),
+            HashSet::from([path("/x"), path("/y"), path("/self")])
+        );
+
+        let root = TempDir::new()?;
+        let (duplicate, kept) = (root.path().join("b.txt"), root.path().join("a.txt"));
+        fs::write(&duplicate, b"same")?;
+        assert!(Survivor::ensure(&duplicate, [kept.as_path()]).is_err());
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(&duplicate, &kept)?;
+            assert!(Survivor::ensure(&duplicate, [kept.as_path()]).is_err());
+            fs::remove_file(&kept)?;
+        }
+        fs::write(&kept, b"same")?;
+        let gone = root.path().join("gone.txt");
+        assert_eq!(Survivor::ensure(&duplicate, [gone.as_path(), kept.as_path()])?, kept);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,222 @@
+use anyhow::{Context, Result};
+use clap::Args;
+use colored::Colorize;
+use dashmap::DashMap;
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use crate::{
+    archive::Archive, fileinfo::FileInfo, heartbeat::Heartbeat, params::Params, rundir::RunDir, server::Server,
+};
+
+#[derive(Args, Debug, Clone, Default)]
+pub struct SymlinkViewArgs {
+    /// Directory to deduplicate
+    #[arg(value_hint = clap::ValueHint::DirPath, value_name = "scan_dir_path")]
+    pub dir: PathBuf,
+    /// Empty or missing directory the view is laid out in, as symbolic links
+    #[arg(value_hint = clap::ValueHint::DirPath, value_name = "view_path")]
+    pub view: PathBuf,
+    /// Show removed duplicates as links to the kept copy instead of leaving them out
+    #[arg(long)]
+    pub links: bool,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ViewSummary {
+    pub files: u64,
+    pub hidden: u64,
+    pub linked: u64,
+}
+
+/// Experimental preview of a cleanup: lays out the scanned tree as it would look with every
+/// duplicate group reduced to one copy. This is not a mounted filesystem: the view is a regular
+/// directory of subdirectories and symbolic links to the scanned files, built once and left for
+/// the user to remove. Nothing in the scanned tree is touched. Of each group the copy chosen by
+/// `--keep` (and `--library`) is the one kept.
+pub struct SymlinkView;
+
+impl SymlinkView {
+    pub fn run(args: &SymlinkViewArgs, app_args: &Params) -> Result<()> {
+        let params = Params {
+            dir: Some(args.dir.clone()),
+            command: None,
+            run_dir: Some(Arc::new(RunDir::create(app_args.tmpdir.as_deref())?)),
+            ..app_args.clone()
+        };
+        let root = params.get_directory()?;
+        if Archive::is_archive(&root) {
+            anyhow::bail!("{} is an archive, the view needs a directory", root.display());
+        }
+        let view = Self::prepare_view(&args.view, &root)?;
+
+        let server = Server::new(params);
+        server.start(&Heartbeat::default())?;
+        for warning in server.verification_warnings.lock().unwrap().iter() {
+            eprintln!("{}", warning.yellow());
+        }
+
+        let redundant = Self::redundant(&server.hw_duplicate_set, app_args);
+        let summary = Self::lay_out(&root, &view, &redundant, args.links)?;
+
+        println!(
+            "{} files in the merged view at {}, {} duplicate(s) {}.",
+            summary.files,
+            view.display(),
+            summary.hidden + summary.linked,
+            match args.links {
+                true => "shown as links to the kept copy",
+                false => "left out",
+            }
+        );
+        println!(
+            "{}",
+            format!(
+                "Remove {} once done, the scanned files stay as they are.",
+                view.display()
+            )
+            .dimmed()
+        );
+
+        Ok(())
+    }
+
+    fn prepare_view(view: &Path, root: &Path) -> Result<PathBuf> {
+        fs::create_dir_all(view)
+            .with_context(|| format!("unable to create view {}", view.display()))?;
+        let view = fs::canonicalize(view)?;
+
+        if view.starts_with(root) {
+            anyhow::bail!(
+                "the view {} must be outside the scanned directory",
+                view.display()
+            );
+        }
+        if fs::read_dir(&view)?.next().is_some() {
+            anyhow::bail!("the view {} is not empty", view.display());
+        }
+
+        Ok(view)
+    }
+
+    /// Every duplicate that would be removed, mapped to the copy that is kept.
+    fn redundant(
+        store: &DashMap<u128, Vec<Arc<FileInfo>>>,
+        app_args: &Params,
+    ) -> HashMap<PathBuf, PathBuf> {
+        store
+            .iter()
+            .filter(|group| group.value().len() > 1)
+            .flat_map(|group| {
+                let group = group.value();
+                let kept = app_args
+                    .keeper(group)
+                    .map(|index| group[index].path.to_path_buf())
+                    .expect("duplicate groups are never empty.");
+                group
+                    .iter()
+                    .filter(|file| *file.path != *kept)
+                    .map(|file| (file.path.to_path_buf(), kept.clone()))
+                    .collect::<Vec<(PathBuf, PathBuf)>>()
+            })
+            .collect()
+    }
+
+    fn lay_out(
+        source: &Path,
+        view: &Path,
+        redundant: &HashMap<PathBuf, PathBuf>,
+        links: bool,
+    ) -> Result<ViewSummary> {
+        let mut summary = ViewSummary::default();
+
+        for entry in fs::read_dir(source)? {
+            let entry = entry?;
+            let (path, destination) = (entry.path(), view.join(entry.file_name()));
+
+            if entry.file_type()?.is_dir() {
+                fs::create_dir(&destination)?;
+                let nested = Self::lay_out(&path, &destination, redundant, links)?;
+                summary.files += nested.files;
+                summary.hidden += nested.hidden;
+                summary.linked += nested.linked;
+                continue;
+            }
+
+            match (redundant.get(&path), links) {
+                (Some(kept), true) => {
+                    Self::link(kept, &destination)?;
+                    summary.linked += 1;
+                }
+                (Some(_), false) => summary.hidden += 1,
+                (None, _) => {
+                    Self::link(&path, &destination)?;
+                    summary.files += 1;
+                }
+            }
+        }
+
+        Ok(summary)
+    }
+
+    #[cfg(unix)]
+    fn link(original: &Path, link: &Path) -> Result<()> {
+        std::os::unix::fs::symlink(original, link)
+            .with_context(|| format!("unable to link {}", link.display()))
+    }
+
+    #[cfg(windows)]
+    fn link(original: &Path, link: &Path) -> Result<()> {
+        std::os::windows::fs::symlink_file(original, link)
+            .with_context(|| format!("unable to link {}", link.display()))
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::{SymlinkView, ViewSummary};
+    use anyhow::Result;
+    use std::{collections::HashMap, fs};
+    use tempfile::TempDir;
+
+    #[test]
+    fn duplicates_are_left_out_or_linked_to_the_kept_copy() -> Result<()> {
+        let (source, view) = (TempDir::new()?, TempDir::new()?);
+        fs::create_dir(source.path().join("nested"))?;
+        for name in ["a.txt", "nested/b.txt", "unique.txt"] {
+            fs::write(source.path().join(name), name)?;
+        }
+        let redundant = HashMap::from([(
+            source.path().join("nested/b.txt"),
+            source.path().join("a.txt"),
+        )]);
+
+        let hidden = view.path().join("hidden");
+        fs::create_dir(&hidden)?;
+        let summary = SymlinkView::lay_out(source.path(), &hidden, &redundant, false)?;
+        assert_eq!(
+            summary,
+            ViewSummary {
+                files: 2,
+                hidden: 1,
+                linked: 0
+            }
+        );
+        assert!(hidden.join("nested").is_dir());
+        assert!(!hidden.join("nested/b.txt").exists());
+
+        let linked = view.path().join("linked");
+        fs::create_dir(&linked)?;
+        SymlinkView::lay_out(source.path(), &linked, &redundant, true)?;
+        assert_eq!(
+            fs::read_link(linked.join("nested/b.txt"))?,
+            source.path().join("a.txt")
+        );
+
+        Ok(())
+    }
+}
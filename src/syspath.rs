@@ -0,0 +1,118 @@
+use anyhow::Result;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Directories the OS and installed software live in, where removing or linking "duplicates"
+/// breaks the system: packages ship identical files on purpose.
+#[cfg(unix)]
+const SYSTEM_DIRECTORIES: &[&str] = &[
+    "/bin",
+    "/boot",
+    "/dev",
+    "/etc",
+    "/lib",
+    "/lib32",
+    "/lib64",
+    "/proc",
+    "/sbin",
+    "/sys",
+    "/usr",
+    "/var/lib",
+    "/Applications",
+    "/Library",
+    "/System",
+];
+
+#[cfg(windows)]
+const SYSTEM_DIRECTORIES: &[&str] = &[
+    r"C:\Windows",
+    r"C:\Program Files",
+    r"C:\Program Files (x86)",
+    r"C:\ProgramData",
+];
+
+#[cfg(not(any(unix, windows)))]
+const SYSTEM_DIRECTORIES: &[&str] = &[];
+
+/// Guards destructive runs against a scan root that is, holds or lies within a system directory,
+/// e.g. `/` or `C:\Windows` typed by mistake in a cron job (see `--allow-system-paths`).
+pub struct SystemPaths;
+
+impl SystemPaths {
+    pub fn guard(root: &Path) -> Result<()> {
+        let Some(system) = Self::overlapping(root, &Self::directories()) else {
+            return Ok(());
+        };
+        let relation = match root.starts_with(&system) {
+            true => "lies within",
+            false => "holds",
+        };
+        anyhow::bail!(
+            "{} {relation} the system directory {}, refusing to delete, move or link files there; \
+             pass --allow-system-paths if this is intended",
+            root.display(),
+            system.display()
+        )
+    }
+
+    /// The system directory `path` lies within, for files that come from a plan rather than a
+    /// scan root.
+    pub fn containing(path: &Path) -> Option<PathBuf> {
+        Self::directories()
+            .into_iter()
+            .find(|system| path.starts_with(system))
+    }
+
+    /// The system directories, resolved like scan roots are (e.g. `/bin` to `/usr/bin`, `/etc`
+    /// to `/private/etc` on macOS).
+    fn directories() -> Vec<PathBuf> {
+        SYSTEM_DIRECTORIES
+            .iter()
+            .map(PathBuf::from)
+            .chain(Self::from_environment())
+            .map(|directory| fs::canonicalize(&directory).unwrap_or(directory))
+            .collect()
+    }
+
+    /// Where Windows is actually installed, which may not be drive C:.
+    #[cfg(windows)]
+    fn from_environment() -> Vec<PathBuf> {
+        ["SystemRoot", "ProgramFiles", "ProgramFiles(x86)", "ProgramData"]
+            .iter()
+            .filter_map(std::env::var_os)
+            .map(PathBuf::from)
+            .collect()
+    }
+
+    #[cfg(not(windows))]
+    fn from_environment() -> Vec<PathBuf> {
+        Vec::new()
+    }
+
+    fn overlapping(root: &Path, system: &[PathBuf]) -> Option<PathBuf> {
+        system
+            .iter()
+            .find(|system| root.starts_with(system) || system.starts_with(root))
+            .cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SystemPaths;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn roots_overlapping_a_system_directory_are_caught() {
+        let system = [PathBuf::from("/usr"), PathBuf::from("/etc")];
+        let overlapping = |root: &str| SystemPaths::overlapping(Path::new(root), &system);
+
+        assert_eq!(overlapping("/"), Some(PathBuf::from("/usr")));
+        assert_eq!(overlapping("/usr"), Some(PathBuf::from("/usr")));
+        assert_eq!(overlapping("/etc/nginx"), Some(PathBuf::from("/etc")));
+        assert_eq!(overlapping("/home/alice/Photos"), None);
+        assert_eq!(overlapping("/usrdata"), None);
+    }
+}
@@ -0,0 +1,198 @@
+use crate::{fileinfo::FileInfo, params::Params};
+use anyhow::Result;
+use dashmap::DashMap;
+use prettytable::{format, row, Table};
+use std::{
+    collections::BTreeMap,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+const BAR_WIDTH: usize = 20;
+
+/// Redundant bytes attributed to every file and directory below the scan root. Within a group the
+/// oldest copy is treated as the original; every other copy counts as waste.
+#[derive(Debug, Default)]
+pub struct UsageTree {
+    root: PathBuf,
+    wasted: BTreeMap<PathBuf, u64>,
+}
+
+impl UsageTree {
+    pub fn build(store: &DashMap<u128, Vec<Arc<FileInfo>>>, root: &Path) -> Self {
+        let mut wasted: BTreeMap<PathBuf, u64> = BTreeMap::new();
+
+        store
+            .iter()
+            .filter(|group| group.value().len() > 1)
+            .for_each(|group| {
+                let original = group
+                    .value()
+                    .iter()
+                    .min_by_key(|file| file.modified)
+                    .map(|file| file.path.clone());
+
+                group
+                    .value()
+                    .iter()
+                    .filter(|file| Some(&file.path) != original.as_ref())
+                    .for_each(|file| {
+                        file.path
+                            .ancestors()
+                            .take_while(|path| path.starts_with(root))
+                            .for_each(|path| {
+                                *wasted.entry(path.to_path_buf()).or_default() += file.size;
+                            });
+                    });
+            });
+
+        Self {
+            root: root.to_path_buf(),
+            wasted,
+        }
+    }
+
+    pub fn total(&self) -> u64 {
+        self.wasted.get(&self.root).copied().unwrap_or_default()
+    }
+
+    pub fn wasted(&self, path: &Path) -> u64 {
+        self.wasted.get(path).copied().unwrap_or_default()
+    }
+
+    /// Direct children of `dir` that carry waste, largest first.
+    pub fn children(&self, dir: &Path) -> Vec<(PathBuf, u64)> {
+        let mut children = self
+            .wasted
+            .iter()
+            .filter(|(path, _)| path.parent() == Some(dir))
+            .map(|(path, bytes)| (path.clone(), *bytes))
+            .collect::<Vec<(PathBuf, u64)>>();
+
+        children.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        children
+    }
+}
+
+pub struct UsageView;
+
+impl UsageView {
+    fn bar(bytes: u64, total: u64) -> String {
+        let filled = match total {
+            0 => 0,
+            _ => ((bytes as f64 / total as f64) * BAR_WIDTH as f64).round() as usize,
+        };
+        format!("{}{}", "#".repeat(filled), " ".repeat(BAR_WIDTH - filled))
+    }
+
+    pub fn init(result: Arc<DashMap<u128, Vec<Arc<FileInfo>>>>, app_args: &Params) -> Result<()> {
+        let root = app_args.get_directory()?;
+        let tree = UsageTree::build(&result, &root);
+
+        if tree.total() == 0 {
+            println!("No duplicates found matching your search criteria.");
+            return Ok(());
+        }
+
+        let mut current = root.clone();
+        loop {
+            let children = tree.children(&current);
+            let mut itable = Table::new();
+            itable.set_format(*format::consts::FORMAT_NO_BORDER_LINE_SEPARATOR);
+            itable.set_titles(row!["index", "wasted", "", "name"]);
+
+            children.iter().enumerate().for_each(|(index, (path, bytes))| {
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                let suffix = if tree.children(path).is_empty() { "" } else { "/" };
+                itable.add_row(row![
+                    index,
+                    bytesize::ByteSize::b(*bytes),
+                    format!("[{}]", Self::bar(*bytes, tree.wasted(&current))),
+                    format!("{name}{suffix}")
+                ]);
+            });
+
+            print!("{esc}[2J{esc}[1;1H", esc = 27 as char);
+            println!(
+                "\n--- {} ({} redundant) ---\n",
+                current.display(),
+                bytesize::ByteSize::b(tree.wasted(&current))
+            );
+            itable.printstd();
+
+            match Self::scan_navigation()?.trim() {
+                "q" | "" => break Ok(()),
+                ".." => {
+                    if current != root {
+                        current.pop();
+                    }
+                }
+                input => match input.parse::<usize>().ok().and_then(|i| children.get(i)) {
+                    Some((path, _)) if !tree.children(path).is_empty() => current = path.clone(),
+                    _ => continue,
+                },
+            }
+        }
+    }
+
+    fn scan_navigation() -> Result<String> {
+        println!("\nEnter an index to open a directory, '..' to go up or 'q' to quit.");
+        print!("\n> ");
+        io::stdout().flush()?;
+        let mut user_input = String::new();
+        io::stdin().read_line(&mut user_input)?;
+
+        Ok(user_input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UsageTree;
+    use crate::fileinfo::FileInfo;
+    use dashmap::DashMap;
+    use std::{
+        path::{Path, PathBuf},
+        sync::{Arc, Mutex},
+        time::{Duration, SystemTime},
+    };
+
+    fn file(path: &str, size: u64, age: u64) -> Arc<FileInfo> {
+        Arc::new(FileInfo {
+            path: PathBuf::from(path).into_boxed_path(),
+            size,
+            modified: SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000 - age),
+            state: Arc::new(Mutex::new(crate::fileinfo::FileState::Unprocessed)),
+            source: None,
+            payload: None,
+        })
+    }
+
+    #[test]
+    fn attributes_waste_to_every_copy_but_the_oldest() {
+        let store = DashMap::new();
+        store.insert(
+            1u128,
+            vec![
+                file("/root/photos/a.jpg", 100, 50),
+                file("/root/backup/2023/a.jpg", 100, 10),
+                file("/root/backup/2024/a.jpg", 100, 5),
+            ],
+        );
+        store.insert(2u128, vec![file("/root/photos/b.jpg", 7, 1)]);
+
+        let tree = UsageTree::build(&store, Path::new("/root"));
+
+        assert_eq!(tree.total(), 200);
+        assert_eq!(tree.wasted(Path::new("/root/photos")), 0);
+        assert_eq!(tree.wasted(Path::new("/root/backup")), 200);
+        assert_eq!(
+            tree.children(Path::new("/root/backup"))
+                .into_iter()
+                .map(|(_, bytes)| bytes)
+                .collect::<Vec<u64>>(),
+            vec![100, 100]
+        );
+    }
+}
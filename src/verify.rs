@@ -0,0 +1,252 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use std::{
+    hash::{DefaultHasher, Hasher},
+    io::{BufReader, Read},
+    path::Path,
+    sync::Arc,
+};
+
+use crate::{archive::Archive, fileinfo::FileInfo, payload::Payload};
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum VerifyMode {
+    /// Trust the hash used for grouping
+    #[default]
+    None,
+    /// Re-hash full contents with an independent algorithm (SipHash)
+    Rehash,
+    /// Compare file contents byte by byte
+    Bytes,
+}
+
+/// How the copy kept in a group is checked before the others are removed, see `--verify-keeper`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum KeeperCheck {
+    /// Same size & modification time as when it was scanned
+    Stat,
+    /// Same contents too, hashed again in full
+    Hash,
+}
+
+impl KeeperCheck {
+    /// Fails unless `keep` is as it was scanned, right before `duplicate` is removed in its
+    /// favour: still there, readable, of the same size & modification time and, hashed again,
+    /// holding the contents of `duplicate`.
+    pub fn verify(self, keep: &FileInfo, duplicate: &FileInfo) -> Result<()> {
+        // NOTE: members of an archive have no stat of their own, only their contents tell.
+        if !Archive::contains(&keep.path) {
+            let current = FileInfo::new(keep.path.to_path_buf()).context("the kept copy is gone")?;
+            if current.size != keep.size || current.modified != keep.modified {
+                anyhow::bail!("the kept copy was modified since the scan");
+            }
+        }
+        let content = Verifier::content(keep).context("the kept copy cannot be read")?;
+        if self == Self::Hash && Verifier::hash_content(content)? != Verifier::hash_content(Verifier::content(duplicate)?)? {
+            anyhow::bail!("the contents of the kept copy changed since the scan");
+        }
+        Ok(())
+    }
+
+    /// `verify` against each of `kept` until one passes, failing with the first reason if none
+    /// does.
+    pub fn verify_any(self, kept: &[Arc<FileInfo>], duplicate: &FileInfo) -> Result<()> {
+        let mut first = None;
+        for keep in kept {
+            match self.verify(keep, duplicate) {
+                Ok(()) => return Ok(()),
+                Err(e) => {
+                    first.get_or_insert(e);
+                }
+            }
+        }
+        Err(first.unwrap_or_else(|| anyhow::anyhow!("no copy is kept")))
+    }
+}
+
+pub struct Verifier;
+
+impl Verifier {
+    /// Full-content hash with an algorithm unrelated to the gxhash used for grouping, so a
+    /// collision in one is not mirrored in the other.
+    pub fn secondary_hash(path: &Path) -> Result<u64> {
+        Self::hash_content(Archive::open(path)?)
+    }
+
+    /// What is compared of a file: its image data alone for photos compared without their
+    /// metadata, every byte otherwise.
+    fn content(file: &FileInfo) -> Result<Box<dyn Read + Send>> {
+        match file.payload {
+            Some(_) => Payload::open(&file.path),
+            None => Archive::open(&file.path),
+        }
+    }
+
+    fn hash_content(content: Box<dyn Read + Send>) -> Result<u64> {
+        let mut reader = BufReader::with_capacity(CHUNK_SIZE, content);
+        let mut hasher = DefaultHasher::new();
+        let mut buffer = vec![0u8; CHUNK_SIZE];
+
+        loop {
+            match reader.read(&mut buffer)? {
+                0 => break,
+                read => hasher.write(&buffer[..read]),
+            }
+        }
+
+        Ok(hasher.finish())
+    }
+
+    pub fn same_bytes(left: &Path, right: &Path) -> Result<bool> {
+        if Archive::size(left)? != Archive::size(right)? {
+            return Ok(false);
+        }
+
+        Self::same_content(Archive::open(left)?, Archive::open(right)?)
+    }
+
+    fn same_files(left: &FileInfo, right: &FileInfo) -> Result<bool> {
+        match (left.payload, right.payload) {
+            (None, None) => Self::same_bytes(&left.path, &right.path),
+            _ if left.content_size() != right.content_size() => Ok(false),
+            _ => Self::same_content(Self::content(left)?, Self::content(right)?),
+        }
+    }
+
+    fn same_content(left: Box<dyn Read + Send>, right: Box<dyn Read + Send>) -> Result<bool> {
+        let mut left = BufReader::with_capacity(CHUNK_SIZE, left);
+        let mut right = BufReader::with_capacity(CHUNK_SIZE, right);
+        let mut lbuf = vec![0u8; CHUNK_SIZE];
+        let mut rbuf = vec![0u8; CHUNK_SIZE];
+
+        loop {
+            let read = left.read(&mut lbuf)?;
+            if read == 0 {
+                return Ok(right.read(&mut rbuf)? == 0);
+            }
+            right.read_exact(&mut rbuf[..read])?;
+            if lbuf[..read] != rbuf[..read] {
+                return Ok(false);
+            }
+        }
+    }
+
+    pub fn identical(left: &FileInfo, right: &FileInfo, mode: VerifyMode) -> Result<bool> {
+        match mode {
+            VerifyMode::None => Ok(true),
+            VerifyMode::Rehash => Ok(
+                Self::hash_content(Self::content(left)?)? == Self::hash_content(Self::content(right)?)?
+            ),
+            VerifyMode::Bytes => Self::same_files(left, right),
+        }
+    }
+
+    /// Splits a candidate group into subgroups whose members are confirmed identical. Files that
+    /// can no longer be read are dropped from the result.
+    pub fn split_group(group: &[Arc<FileInfo>], mode: VerifyMode) -> Vec<Vec<Arc<FileInfo>>> {
+        if mode == VerifyMode::None {
+            return vec![group.to_vec()];
+        }
+
+        let mut subgroups: Vec<Vec<Arc<FileInfo>>> = vec![];
+        let mut fingerprints: Vec<u64> = vec![];
+
+        group.iter().for_each(|file| {
+            let position = match mode {
+                VerifyMode::Rehash => match Self::content(file).and_then(Self::hash_content) {
+                    Ok(fingerprint) => match fingerprints.iter().position(|f| *f == fingerprint) {
+                        Some(position) => Some(position),
+                        None => {
+                            fingerprints.push(fingerprint);
+                            None
+                        }
+                    },
+                    Err(_) => return,
+                },
+                _ => subgroups.iter().position(|subgroup| {
+                    Self::same_files(&subgroup[0], file).unwrap_or(false)
+                }),
+            };
+
+            match position {
+                Some(position) => subgroups[position].push(file.clone()),
+                None => subgroups.push(vec![file.clone()]),
+            }
+        });
+
+        subgroups
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{KeeperCheck, Verifier, VerifyMode};
+    use crate::{fileinfo::FileInfo, fixture::Fixture};
+    use anyhow::Result;
+    use std::fs::File;
+    use std::io::Write;
+    use std::sync::Arc;
+
+    fn group_with_shared_header(fixture: &Fixture) -> Result<Vec<Arc<FileInfo>>> {
+        let header = vec![7u8; 16384];
+        [("one.bin", 1u8), ("two.bin", 1u8), ("three.bin", 2u8)]
+            .iter()
+            .map(|(name, tail)| {
+                fixture.scanned(name, &[header.as_slice(), &[*tail; 4096]].concat())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn rehash_splits_partial_hash_collisions() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let group = group_with_shared_header(&fixture)?;
+
+        let subgroups = Verifier::split_group(&group, VerifyMode::Rehash);
+        let mut sizes = subgroups.iter().map(Vec::len).collect::<Vec<usize>>();
+        sizes.sort();
+
+        assert_eq!(sizes, vec![1, 2]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn byte_compare_splits_partial_hash_collisions() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let group = group_with_shared_header(&fixture)?;
+
+        let subgroups = Verifier::split_group(&group, VerifyMode::Bytes);
+
+        assert_eq!(subgroups.len(), 2);
+        assert!(Verifier::identical(&group[0], &group[1], VerifyMode::Bytes)?);
+        assert!(!Verifier::identical(&group[0], &group[2], VerifyMode::Bytes)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn kept_copies_are_checked_by_stat_or_contents() -> Result<()> {
+        let fixture = Fixture::new()?;
+        let group = group_with_shared_header(&fixture)?;
+        let (keep, duplicate) = (&group[0], &group[1]);
+        assert!(KeeperCheck::Hash.verify(keep, duplicate).is_ok());
+
+        // NOTE: rewritten with its modification time put back, only the contents tell.
+        let mut contents = std::fs::read(&keep.path)?;
+        contents[0] ^= 1;
+        std::fs::write(&keep.path, contents)?;
+        File::options().write(true).open(&keep.path)?.set_modified(keep.modified)?;
+        assert!(KeeperCheck::Stat.verify(keep, duplicate).is_ok());
+        assert!(KeeperCheck::Hash.verify(keep, duplicate).is_err());
+        assert!(KeeperCheck::Hash.verify_any(&group[..2], duplicate).is_ok());
+
+        File::options().append(true).open(&keep.path)?.write_all(b"more")?;
+        File::options().write(true).open(&keep.path)?.set_modified(keep.modified)?;
+        assert!(KeeperCheck::Stat.verify(keep, duplicate).is_err());
+
+        Ok(())
+    }
+}